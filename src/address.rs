@@ -0,0 +1,118 @@
+//! The address other machines reach this one at (`--host`).
+//!
+//! URLs and proxy upstreams default to localhost, which a phone or a VM on
+//! the same network can't use. `--host lan` swaps in the machine's LAN
+//! address instead, and `--host <name>` any address or hostname.
+
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+
+use crate::error::{Error, Result};
+
+/// Where `--host` points URLs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Localhost,
+    /// The LAN address of this machine.
+    Lan,
+    Custom(String),
+}
+
+/// Parses `--host`: "localhost", "lan", or an address or hostname.
+pub fn parse_address(s: &str) -> std::result::Result<Address, String> {
+    match s {
+        "localhost" => Ok(Address::Localhost),
+        "lan" => Ok(Address::Lan),
+        _ if s.is_empty() || s.contains(|c: char| c.is_whitespace() || c == '/') => {
+            Err(format!("'{s}' is not an address or hostname"))
+        }
+        _ => Ok(Address::Custom(s.to_string())),
+    }
+}
+
+impl Address {
+    /// Returns the host to put in URLs, with IPv6 addresses in brackets.
+    pub fn resolve(&self) -> Result<String> {
+        let host = match self {
+            Address::Localhost => return Ok("localhost".to_string()),
+            Address::Lan => lan_ip()?.to_string(),
+            Address::Custom(host) => host.clone(),
+        };
+        Ok(match host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(ip)) => format!("[{ip}]"),
+            _ => host,
+        })
+    }
+}
+
+/// Returns the address this machine reaches the network from.
+///
+/// Connecting a UDP socket sends nothing; it only makes the OS pick the
+/// interface it would route through. 192.0.2.1 is reserved for
+/// documentation, so no real host is involved.
+fn lan_ip() -> Result<IpAddr> {
+    let no_address = |e: std::io::Error| Error::NoLanAddress(e.to_string());
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(no_address)?;
+    socket
+        .connect((Ipv4Addr::new(192, 0, 2, 1), 9))
+        .map_err(no_address)?;
+    let ip = socket.local_addr().map_err(no_address)?.ip();
+    if ip.is_loopback() || ip.is_unspecified() {
+        return Err(Error::NoLanAddress(
+            "no network interface is up".to_string(),
+        ));
+    }
+    Ok(ip)
+}
+
+/// Replaces localhost in `url` with `host`, leaving other hosts alone.
+pub fn substitute_localhost(url: &str, host: &str) -> String {
+    for local in ["localhost", "127.0.0.1", "[::1]"] {
+        if let Some((scheme, rest)) = url.split_once(&format!("://{local}")) {
+            if rest.is_empty() || rest.starts_with([':', '/', '?', '#']) {
+                return format!("{scheme}://{host}{rest}");
+            }
+        }
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(parse_address("lan"), Ok(Address::Lan));
+        assert_eq!(parse_address("localhost"), Ok(Address::Localhost));
+        assert_eq!(
+            parse_address("10.0.0.5"),
+            Ok(Address::Custom("10.0.0.5".to_string()))
+        );
+        assert!(parse_address("").is_err());
+        assert!(parse_address("http://x").is_err());
+
+        let custom = Address::Custom("fe80::1".to_string());
+        assert_eq!(custom.resolve().unwrap(), "[fe80::1]");
+        assert_eq!(Address::Localhost.resolve().unwrap(), "localhost");
+    }
+
+    #[test]
+    fn test_substitute_localhost() {
+        assert_eq!(
+            substitute_localhost("http://localhost:3000/api", "10.0.0.5"),
+            "http://10.0.0.5:3000/api"
+        );
+        assert_eq!(
+            substitute_localhost("postgres://127.0.0.1:5432/db", "box.lan"),
+            "postgres://box.lan:5432/db"
+        );
+        assert_eq!(
+            substitute_localhost("http://localhost.test:3000", "10.0.0.5"),
+            "http://localhost.test:3000"
+        );
+        assert_eq!(
+            substitute_localhost("https://example.com", "10.0.0.5"),
+            "https://example.com"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Read-only port detection over TCP (`pm agent`).
+//!
+//! `pm agent` answers every connection with its machine's listeners, in the
+//! format of `pm status --json`, and closes it. It never reads from the
+//! client, so there is nothing a client can change. `pm status --agent host`
+//! collects listeners from agents and shows them next to the local ones.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::display::build_status_port_list;
+use crate::error::{Error, Result};
+use crate::model::Registry;
+use crate::ports::{get_listening_ports, ListeningPort};
+use crate::remote::parse_pm_status;
+
+/// The port agents listen on unless told otherwise.
+const DEFAULT_PORT: u16 = 7683;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Detection takes a moment, especially on a busy machine.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves detection on `listen` until the process is stopped.
+pub fn serve(listen: &str) -> Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    println!("pm agent listening on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let result = stream.and_then(answer);
+        if let Err(e) = result {
+            debug!("agent connection failed: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Writes this machine's listeners, or why they couldn't be detected.
+fn answer(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    if let Ok(peer) = stream.peer_addr() {
+        debug!("answering {peer}");
+    }
+    // Process owners and directories stay private; only ports and processes go out
+    let response = match get_listening_ports() {
+        Ok(listening) => json!({
+            "apiVersion": "v1",
+            "data": build_status_port_list(&listening, &Registry::default(), false),
+        }),
+        Err(e) => json!({
+            "apiVersion": "v1",
+            "error": { "code": e.code(), "message": e.to_string() },
+        }),
+    };
+    writeln!(stream, "{response}")
+}
+
+/// Adds the default port to an agent address that has none
+/// (`devbox`, `10.0.0.5`, `::1`, `[::1]`).
+fn agent_address(host: &str) -> String {
+    if host.parse::<SocketAddr>().is_ok() {
+        return host.to_string();
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') && port.parse::<u16>().is_ok() => {
+            host.to_string()
+        }
+        _ if host.starts_with('[') => format!("{host}:{DEFAULT_PORT}"),
+        _ if host.contains(':') => format!("[{host}]:{DEFAULT_PORT}"),
+        _ => format!("{host}:{DEFAULT_PORT}"),
+    }
+}
+
+/// Asks the agent at `host` (`host[:port]`) for its listeners, each tagged
+/// with `host`.
+pub fn agent_listening_ports(host: &str) -> Result<Vec<ListeningPort>> {
+    let address = agent_address(host);
+    let failed = |reason: String| Error::CommandFailed {
+        command: format!("pm agent at {address}"),
+        reason,
+    };
+
+    let mut last_error = failed("no addresses".to_string());
+    let mut connected = None;
+    for addr in address
+        .to_socket_addrs()
+        .map_err(|e| failed(e.to_string()))?
+    {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                connected = Some(stream);
+                break;
+            }
+            Err(e) => last_error = failed(e.to_string()),
+        }
+    }
+    let mut stream = connected.ok_or(last_error)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .map_err(|e| failed(e.to_string()))?;
+
+    let value: Value = serde_json::from_str(&response).map_err(|e| failed(e.to_string()))?;
+    if let Some(message) = value.pointer("/error/message").and_then(Value::as_str) {
+        return Err(failed(message.to_string()));
+    }
+    let mut ports =
+        parse_pm_status(&response).ok_or_else(|| failed("unreadable response".to_string()))?;
+    for lp in &mut ports {
+        lp.host = Some(host.to_string());
+    }
+    Ok(ports)
+}
+
+/// Combines this machine's listeners with those of each agent, sorted by
+/// port with local listeners first. Sources that fail are left out with a
+/// warning, so one unreachable agent doesn't hide the rest.
+pub fn combined_listening_ports(
+    local: Result<Vec<ListeningPort>>,
+    agents: &[String],
+) -> Vec<ListeningPort> {
+    let mut listening = local.unwrap_or_else(|e| {
+        warn!("Not showing this machine's listeners: {e}");
+        Vec::new()
+    });
+    for host in agents {
+        match agent_listening_ports(host) {
+            Ok(ports) => listening.extend(ports),
+            Err(e) => warn!("Not showing listeners on {host}: {e}"),
+        }
+    }
+    listening.sort_by(|a, b| (a.port, &a.host).cmp(&(b.port, &b.host)));
+    listening
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_address() {
+        assert_eq!(agent_address("devbox"), "devbox:7683");
+        assert_eq!(agent_address("devbox:9000"), "devbox:9000");
+        assert_eq!(agent_address("10.0.0.5"), "10.0.0.5:7683");
+        assert_eq!(agent_address("::1"), "[::1]:7683");
+        assert_eq!(agent_address("[::1]"), "[::1]:7683");
+        assert_eq!(agent_address("[::1]:9000"), "[::1]:9000");
+    }
+
+    #[test]
+    fn test_agent_listening_ports() {
+        // A one-shot agent answering with a canned listener
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let response =
+                r#"{"apiVersion": "v1", "data": [{"port": 8080, "pid": 7, "process": "nginx"}]}"#;
+            writeln!(stream, "{response}").unwrap();
+        });
+
+        let ports = agent_listening_ports(&address).unwrap();
+        server.join().unwrap();
+        assert_eq!(ports.len(), 1);
+        assert_eq!(ports[0].port.as_u16(), 8080);
+        assert_eq!(ports[0].process_name.as_deref(), Some("nginx"));
+        assert_eq!(ports[0].host.as_deref(), Some(address.as_str()));
+    }
+}
@@ -0,0 +1,356 @@
+//! Bug-report bundles (`pm debug-bundle`).
+//!
+//! Detection problems rarely reproduce away from the machine that has them,
+//! so a bundle gathers what it takes to look at one elsewhere: the registry,
+//! a `pm snapshot`, platform details, the raw `lsof` listing, and the
+//! registry's recent commits when git sync is on. With `--redact`, projects
+//! become `project-1`, `project-2`, ..., and paths, users, and free-form
+//! text are left out, so the bundle can go on a public issue.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::Command;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tar::{Builder, Header};
+
+use crate::error::{ConfigError, Result};
+use crate::gitsync::recent_changes;
+use crate::model::{PortMeta, Registry};
+use crate::name::{is_glob, ProjectName};
+use crate::persistence::{load_registry, registry_path};
+use crate::ports::{ephemeral_range, get_listening_ports};
+use crate::snapshot::{capture, Snapshot};
+use crate::subprocess::{self, Bounded};
+
+/// What redacted text is replaced with.
+const REDACTED: &str = "redacted";
+
+/// How many registry commits a bundle includes.
+const RECENT_CHANGES: usize = 20;
+
+/// Hands out stand-in project names, the same one each time a name comes up.
+#[derive(Default)]
+struct Redactor {
+    projects: BTreeMap<String, String>,
+}
+
+impl Redactor {
+    fn project(&mut self, name: &str) -> String {
+        let next = self.projects.len() + 1;
+        self.projects
+            .entry(name.to_string())
+            .or_insert_with(|| format!("project-{next}"))
+            .clone()
+    }
+
+    fn project_name(&mut self, name: &ProjectName) -> ProjectName {
+        ProjectName::new(self.project(name)).expect("stand-in project names are valid")
+    }
+}
+
+/// Replaces `text`, if there is any.
+fn redact_text(text: &mut Option<String>) {
+    if text.is_some() {
+        *text = Some(REDACTED.to_string());
+    }
+}
+
+fn redact_meta(meta: &mut PortMeta) {
+    for text in [
+        &mut meta.group,
+        &mut meta.host,
+        &mut meta.hostname,
+        &mut meta.command,
+        &mut meta.expect_process,
+        &mut meta.note,
+        &mut meta.url_template,
+        &mut meta.owner,
+    ] {
+        redact_text(text);
+    }
+}
+
+/// Returns `registry` with projects renamed and paths and text replaced.
+/// Ports, ranges, and settings are kept as they are, except that the
+/// projects a range is reserved for are renamed too, and globs replaced.
+fn redact_registry(registry: &Registry, redactor: &mut Redactor) -> Registry {
+    let mut redacted = registry.clone();
+    redacted.projects = registry
+        .projects
+        .iter()
+        .map(|(name, project)| (redactor.project_name(name), project.clone()))
+        .collect();
+    redacted.archived = registry
+        .archived
+        .iter()
+        .map(|(name, project)| (redactor.project_name(name), project.clone()))
+        .collect();
+    redacted.history = registry
+        .history
+        .iter()
+        .map(|(name, ports)| (redactor.project_name(name), ports.clone()))
+        .collect();
+    redacted.dependencies = registry
+        .dependencies
+        .iter()
+        .map(|(name, deps)| {
+            let deps = deps
+                .iter()
+                .map(|(on, names)| (redactor.project_name(on), names.clone()))
+                .collect();
+            (redactor.project_name(name), deps)
+        })
+        .collect();
+    redacted.links = registry
+        .links
+        .values()
+        .enumerate()
+        .map(|(i, project)| (format!("/{REDACTED}/{}", i + 1), redactor.project(project)))
+        .collect();
+    for range in redacted.defaults.ranges.values_mut() {
+        for pattern in &mut range.projects {
+            *pattern = if is_glob(pattern) {
+                REDACTED.to_string()
+            } else {
+                redactor.project(pattern)
+            };
+        }
+    }
+    if redacted.defaults.system_registry.is_some() {
+        redacted.defaults.system_registry = Some(REDACTED.into());
+    }
+    for project in redacted
+        .projects
+        .values_mut()
+        .chain(redacted.archived.values_mut())
+    {
+        for allocation in project.ports.values_mut() {
+            redact_meta(&mut allocation.meta);
+        }
+    }
+    redacted
+}
+
+/// Renames projects in `snapshot` and drops listeners' users and directories.
+fn redact_snapshot(snapshot: &mut Snapshot, redactor: &mut Redactor) {
+    for listener in &mut snapshot.listeners {
+        listener.project = listener.project.as_deref().map(|p| redactor.project(p));
+        listener.cwd = None;
+        listener.uid = None;
+        listener.user = None;
+    }
+    for conflict in &mut snapshot.conflicts {
+        conflict.project = redactor.project(&conflict.project);
+        conflict.listener_project = conflict
+            .listener_project
+            .as_deref()
+            .map(|p| redactor.project(p));
+        for text in [
+            &mut conflict.host,
+            &mut conflict.group,
+            &mut conflict.expect_process,
+            &mut conflict.note,
+            &mut conflict.url,
+        ] {
+            redact_text(text);
+        }
+    }
+}
+
+/// Replaces the USER column of `lsof` output.
+fn redact_lsof(listing: &str) -> String {
+    let mut redacted = String::new();
+    for (i, line) in listing.lines().enumerate() {
+        let mut fields: Vec<&str> = line.split_whitespace().collect();
+        if i > 0 && fields.len() > 2 {
+            fields[2] = REDACTED;
+        }
+        redacted.push_str(&fields.join(" "));
+        redacted.push('\n');
+    }
+    redacted
+}
+
+/// Runs `lsof` for TCP listeners, returning its output or why it didn't run.
+fn lsof_listing() -> String {
+    match subprocess::output(Command::new("lsof").args(["-nP", "-iTCP", "-sTCP:LISTEN"])) {
+        // lsof exits 1 when nothing is listening, so keep whatever it said
+        Ok(Bounded { output, timed_out }) => format!(
+            "{}{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+            if timed_out {
+                "lsof timed out; the listing above may be incomplete\n"
+            } else {
+                ""
+            }
+        ),
+        Err(e) => format!("lsof didn't run: {e}\n"),
+    }
+}
+
+/// Shows `path` relative to the home directory when redacting.
+fn shown_path(path: &Path, redact: bool) -> String {
+    if !redact {
+        return path.display().to_string();
+    }
+    dirs::home_dir()
+        .and_then(|home| path.strip_prefix(home).ok())
+        .map_or_else(
+            || REDACTED.to_string(),
+            |rest| format!("~/{}", rest.display()),
+        )
+}
+
+/// Writes a bundle to `output`, redacted if `redact` is set.
+///
+/// A registry that doesn't load or detection that fails is recorded in the
+/// bundle rather than stopping it, since those are what bundles are for.
+pub fn write_bundle(output: &Path, redact: bool) -> Result<()> {
+    let path = registry_path()?;
+    let loaded = load_registry();
+    let registry = loaded.as_ref().ok().cloned().unwrap_or_default();
+    let detected = get_listening_ports();
+    let listening = detected.as_ref().ok().cloned().unwrap_or_default();
+
+    let mut redactor = Redactor::default();
+    let redacted = redact.then(|| redact_registry(&registry, &mut redactor));
+    let mut snapshot = capture(&registry, &listening);
+    if let Some(redacted) = &redacted {
+        snapshot.registry = redacted;
+        redact_snapshot(&mut snapshot, &mut redactor);
+    }
+
+    let mut platform = format!(
+        "pm {}\nPlatform: {} {} ({})\nCaptured: {}\nRegistry: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::consts::FAMILY,
+        snapshot.captured_at.to_rfc3339(),
+        shown_path(&path, redact),
+    );
+    match &loaded {
+        Ok(registry) => {
+            let ports: usize = registry.projects.values().map(|p| p.ports.len()).sum();
+            let projects = registry.projects.len();
+            let _ = writeln!(
+                platform,
+                "Registry load: ok ({projects} projects, {ports} ports)"
+            );
+        }
+        Err(e) => {
+            let _ = writeln!(platform, "Registry load: failed: {e}");
+        }
+    }
+    match &detected {
+        Ok(ports) => {
+            let _ = writeln!(platform, "Port detection: ok ({} listeners)", ports.len());
+        }
+        Err(e) => {
+            let _ = writeln!(platform, "Port detection: failed: {e}");
+        }
+    }
+    let [low, high] = ephemeral_range();
+    let _ = writeln!(platform, "Ephemeral range: {low}-{high}");
+    for (name, value) in std::env::vars().filter(|(name, _)| name.starts_with("PM_")) {
+        let value = if redact { REDACTED } else { &value };
+        let _ = writeln!(platform, "{name}={value}");
+    }
+
+    let registry_file = match &redacted {
+        Some(redacted) => toml::to_string_pretty(redacted).map_err(ConfigError::SerializeFailed)?,
+        None => fs::read_to_string(&path).unwrap_or_else(|e| format!("# Not readable: {e}\n")),
+    };
+    let lsof = lsof_listing();
+    let changes = match recent_changes(&path, RECENT_CHANGES) {
+        _ if redact => "Left out with --redact\n".to_string(),
+        Ok(Some(log)) => log,
+        Ok(None) => "The registry isn't tracked with git sync\n".to_string(),
+        Err(e) => format!("{e}\n"),
+    };
+    let files = [
+        ("registry.toml", registry_file),
+        (
+            "snapshot.json",
+            serde_json::to_string_pretty(&snapshot).expect("Failed to serialize to JSON") + "\n",
+        ),
+        ("platform.txt", platform),
+        ("lsof.txt", if redact { redact_lsof(&lsof) } else { lsof }),
+        ("changes.txt", changes),
+    ];
+
+    let mut tar = Builder::new(GzEncoder::new(
+        File::create(output)?,
+        Compression::default(),
+    ));
+    let mtime = snapshot.captured_at.timestamp().max(0) as u64;
+    for (name, content) in files {
+        let mut header = Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        tar.append_data(&mut header, format!("pm-debug/{name}"), content.as_bytes())?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Allocation, PortRange, Project};
+    use crate::name::PortName;
+    use crate::port::Port;
+
+    #[test]
+    fn test_redact_registry() {
+        let mut registry = Registry::default();
+        let meta = PortMeta {
+            note: Some("payments sandbox".to_string()),
+            ..PortMeta::default()
+        };
+        let mut project = Project::default();
+        project.ports.insert(
+            PortName::new("web").unwrap(),
+            Allocation::new(Port::new(8000).unwrap(), meta),
+        );
+        registry
+            .projects
+            .insert(ProjectName::new("acme-shop").unwrap(), project);
+        registry
+            .links
+            .insert("/home/alice/acme".to_string(), "acme-shop".to_string());
+        let mut range = PortRange::new(9000, 9099);
+        range.projects = vec!["acme-*".to_string(), "acme-shop".to_string()];
+        registry.defaults.ranges.insert("acme".to_string(), range);
+
+        let redacted = redact_registry(&registry, &mut Redactor::default());
+        let project = &redacted.projects["project-1"];
+        assert_eq!(project.ports["web"].port.as_u16(), 8000);
+        assert_eq!(project.ports["web"].meta.note.as_deref(), Some(REDACTED));
+        assert_eq!(redacted.links["/redacted/1"], "project-1");
+        assert_eq!(
+            redacted.defaults.ranges["acme"].projects,
+            vec![REDACTED.to_string(), "project-1".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_redact_lsof() {
+        let listing = "\
+COMMAND   PID  USER   FD   TYPE DEVICE SIZE/OFF NODE NAME
+node    12345 alice   23u  IPv4 0x1234      0t0  TCP *:3000 (LISTEN)
+";
+        assert_eq!(
+            redact_lsof(listing),
+            "COMMAND PID USER FD TYPE DEVICE SIZE/OFF NODE NAME\n\
+             node 12345 redacted 23u IPv4 0x1234 0t0 TCP *:3000 (LISTEN)\n"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Checking allocations against another machine's (`pm check-against`).
+//!
+//! Registries only know their own machine's ports. When two laptops publish
+//! ports on one docker host, or two people pair by running each other's
+//! services, a port each side allocated separately can clash. A teammate's
+//! `pm snapshot` (or `pm export registry` output) says what they allocated
+//! and had listening, and this compares it with the registry in use.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::model::Registry;
+use crate::port::Port;
+use crate::ports::ListeningPort;
+
+/// One of our allocations whose port the other machine also uses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Clash {
+    pub project: String,
+    pub name: String,
+    pub port: Port,
+    /// Their allocation of the port, as "project.name".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allocated_by: Option<String>,
+    /// The process they had listening on the port when the snapshot was taken.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listener: Option<String>,
+}
+
+/// Returns our allocations whose port `theirs` allocates or `listening`
+/// shows in use, sorted by port. Allocations on other hosts (`--host`) are
+/// left out on both sides, since they are neither machine's ports.
+pub fn find_clashes(ours: &Registry, theirs: &Registry, listening: &[ListeningPort]) -> Vec<Clash> {
+    let mut clashes: Vec<Clash> = ours
+        .projects
+        .iter()
+        .flat_map(|(project, p)| p.ports.iter().map(move |(name, a)| (project, name, a)))
+        .filter(|(_, _, allocation)| allocation.meta.host.is_none())
+        .filter_map(|(project, name, allocation)| {
+            let port = allocation.port;
+            let allocated_by = theirs
+                .find_port_owner_on(None, port)
+                .map(|(project, name)| format!("{project}.{name}"));
+            let listener = listening
+                .iter()
+                .find(|listener| listener.port == port && listener.host.is_none())
+                .map(|listener| match (&listener.process_name, listener.pid) {
+                    (Some(process), Some(pid)) => format!("{process} (PID {pid})"),
+                    (Some(process), None) => process.clone(),
+                    (None, Some(pid)) => format!("PID {pid}"),
+                    (None, None) => "unknown".to_string(),
+                });
+            (allocated_by.is_some() || listener.is_some()).then(|| Clash {
+                project: project.to_string(),
+                name: name.to_string(),
+                port,
+                allocated_by,
+                listener,
+            })
+        })
+        .collect();
+    clashes.sort_by_key(|clash| clash.port);
+    clashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Allocation, PortMeta, Project};
+    use crate::name::{PortName, ProjectName};
+
+    fn registry_with(ports: &[(&str, &str, u16)]) -> Registry {
+        let mut registry = Registry::default();
+        for &(project, name, port) in ports {
+            registry
+                .projects
+                .entry(ProjectName::new(project).unwrap())
+                .or_insert_with(Project::default)
+                .ports
+                .insert(
+                    PortName::new(name).unwrap(),
+                    Allocation::new(Port::new(port).unwrap(), PortMeta::default()),
+                );
+        }
+        registry
+    }
+
+    #[test]
+    fn test_find_clashes() {
+        let ours = registry_with(&[
+            ("webapp", "web", 8000),
+            ("webapp", "api", 3000),
+            ("webapp", "db", 5432),
+        ]);
+        let theirs = registry_with(&[("shop", "web", 8000), ("shop", "api", 3001)]);
+        let listening = [ListeningPort {
+            port: Port::new(5432).unwrap(),
+            pid: Some(42),
+            process_name: Some("postgres".to_string()),
+            process_cwd: None,
+            process_command: None,
+            uid: None,
+            user: None,
+            host: None,
+        }];
+
+        let clashes = find_clashes(&ours, &theirs, &listening);
+        assert_eq!(clashes.len(), 2);
+        assert_eq!(clashes[0].name, "db");
+        assert_eq!(clashes[0].listener.as_deref(), Some("postgres (PID 42)"));
+        assert_eq!(clashes[1].allocated_by.as_deref(), Some("shop.web"));
+        assert_eq!(clashes[1].listener, None);
+
+        assert!(find_clashes(&ours, &Registry::default(), &[]).is_empty());
+    }
+}
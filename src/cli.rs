@@ -1,45 +1,468 @@
 //! CLI command definitions using clap.
 
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, FixedOffset};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::ArgValueCompleter;
 
-use crate::port::Port;
+use crate::address::{parse_address, Address};
+use crate::complete::{port_candidates, project_candidates, ShellType};
+use crate::display::{HostFilter, JsonVersion, OutputFormat};
+use crate::hook::Shell;
+use crate::model::parse_host;
+use crate::name::PortName;
+use crate::preset::Preset;
+use crate::schedule::{parse_after, parse_at};
+use crate::template::Template;
 
 /// Port Manager - manage port allocations across projects.
 #[derive(Parser, Debug)]
 #[command(name = "pm")]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Log what pm is doing to stderr (-v for debug, -vv for trace)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only log errors. `allocate`, `free`, and `config --set` print just the
+    /// port (or range) on stdout and their messages on stderr
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print tables directly instead of through $PM_PAGER or $PAGER
+    #[arg(long, global = true)]
+    pub no_pager: bool,
+
+    /// Registry file to use, overriding PM_CONFIG_PATH and PM_CONFIG_DIR
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub config: Option<std::path::PathBuf>,
+
+    /// Use this profile's registry instead of the one chosen with `pm profile switch`
+    #[arg(long, global = true, value_name = "NAME", conflicts_with = "config")]
+    pub profile: Option<String>,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+/// The `--json[=VERSION]` flag of commands with JSON output.
+///
+/// A bare `--json` prints the v0 payload, as it did before output was
+/// versioned, and keeps doing so until the next major version; newer formats
+/// are opt-in.
+#[derive(Args, Debug, Clone, Copy, Default)]
+pub struct JsonArgs {
+    /// Output as JSON for scripting (bare --json is the unversioned v0 format;
+    /// pass --json=v1 for the versioned envelope)
+    #[arg(
+        id = "json",
+        long = "json",
+        value_name = "VERSION",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "v0"
+    )]
+    pub version: Option<JsonVersion>,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
+    /// Set pm up: create the registry, add range presets, link a directory,
+    /// and allocate a template's ports.
+    ///
+    /// Safe to rerun: anything already in place is kept.
+    Init {
+        /// Range presets to add (e.g., "node,databases")
+        #[arg(long, value_enum, value_delimiter = ',', value_name = "PRESET")]
+        ranges: Vec<Preset>,
+
+        /// Directory to link to the project (e.g., ".")
+        #[arg(long, value_name = "DIR")]
+        link: Option<std::path::PathBuf>,
+
+        /// Record the link in the repository's .pm.toml, creating it if needed
+        #[arg(long, requires = "link")]
+        repo: bool,
+
+        /// Allocate this template's ports to the project
+        #[arg(long, value_enum)]
+        template: Option<Template>,
+
+        /// Project for --link and --template (defaults to the name of the
+        /// --link directory, or of the current directory)
+        #[arg(long)]
+        project: Option<String>,
+    },
+
     /// Allocate a named port to a project.
     ///
     /// If no port is specified, one will be auto-suggested based on the port type.
     #[command(visible_alias = "a")]
     Allocate {
-        /// Project name (e.g., "webapp", "backend")
+        /// Project name (e.g., "webapp", "backend"), or "." for the linked project.
+        /// When only one argument is given, it is the port name and the project
+        /// is inferred from the current directory.
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
         project: String,
 
-        /// Port name/type (e.g., "web", "api", "db")
-        name: String,
+        /// Port name (e.g., "web", "api", "db"); also the type whose range the
+        /// port comes from, unless --type is given
+        name: Option<String>,
 
         /// Specific port number to allocate (optional - auto-suggest if omitted)
-        port: Option<Port>,
+        #[arg(add = ArgValueCompleter::new(port_candidates))]
+        port: Option<String>,
+
+        /// Port type whose range the port comes from (e.g., "web" for an
+        /// "admin" port); kept for later reallocation
+        #[arg(long, short = 't')]
+        r#type: Option<String>,
+
+        /// Group label for releasing related allocations together (e.g., "sprint-42")
+        #[arg(long)]
+        group: Option<String>,
+
+        /// Local hostname for reverse proxy exports (default: <name>.<project>.localhost)
+        #[arg(long)]
+        hostname: Option<String>,
+
+        /// Allocate on another machine (e.g., a shared server) instead of this
+        /// one; the port only has to be free among that host's allocations
+        #[arg(long, value_parser = parse_host, conflicts_with = "pid")]
+        host: Option<String>,
+
+        /// Command that starts the service, for Procfile export (e.g., "npm run dev -- --port {port}")
+        #[arg(long)]
+        command: Option<String>,
+
+        /// Regex the listening process's command line should match (e.g., "node.*vite");
+        /// any other listener is reported as a conflict
+        #[arg(long, value_name = "REGEX")]
+        expect_process: Option<String>,
+
+        /// What the port is for (e.g., "payments sandbox"), shown by `pm list --long`
+        #[arg(long)]
+        note: Option<String>,
+
+        /// URL `pm open` and `pm urls` show; {port} is replaced with the port and
+        /// {host} with --host or localhost (e.g., "https://{host}:{port}/admin")
+        #[arg(long, value_name = "TEMPLATE")]
+        url_template: Option<String>,
+
+        /// Free the allocation on the first `pm gc` run after this long (e.g., 8h, 3d)
+        #[arg(long, value_name = "DURATION", value_parser = parse_after)]
+        ttl: Option<DateTime<FixedOffset>>,
+
+        /// Bind the allocation to a process; `pm gc` frees it once the process exits
+        #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+        pid: Option<i32>,
+
+        /// Replace an existing allocation for this name with a new port in one step.
+        /// Options not given are kept from the old allocation
+        #[arg(long)]
+        replace: bool,
+
+        /// Succeed with the existing port if this name is already allocated
+        /// (and matches PORT, when given), for idempotent setup scripts
+        #[arg(long, conflicts_with = "replace")]
+        if_absent: bool,
+
+        /// Fail if listening ports can't be detected, instead of warning and
+        /// allocating without checking for processes already on the port
+        /// (always on with `pm config --strict-detection true`)
+        #[arg(long)]
+        require_detection: bool,
+
+        /// Also treat ports with connections in TIME_WAIT or CLOSE_WAIT as in
+        /// use, now or within the last SECONDS, since binding them right after
+        /// a restart often fails
+        #[arg(long, value_name = "SECONDS", conflicts_with = "host")]
+        avoid_lingering: Option<u64>,
+
+        /// Allocate even if the arguments look mistyped (a port with a leading
+        /// zero or spaces, or a project and name given the wrong way round)
+        #[arg(long)]
+        no_lint: bool,
     },
 
     /// Free port(s) from a project.
     ///
     /// If no name is specified, frees all ports from the project.
+    /// With --group, frees every allocation tagged with that group across projects.
     #[command(visible_alias = "f")]
     Free {
-        /// Project name
-        project: String,
+        /// Project name, or "." for the current directory's linked project
+        #[arg(
+            required_unless_present_any = ["group", "orphans"],
+            add = ArgValueCompleter::new(project_candidates)
+        )]
+        project: Option<String>,
 
         /// Port name to free (optional - frees all if omitted)
         name: Option<String>,
+
+        /// Free all allocations in this group, across projects
+        #[arg(long, conflicts_with_all = ["project", "name"])]
+        group: Option<String>,
+
+        /// Free allocations whose port is outside every configured range,
+        /// asking about each one
+        #[arg(long, conflicts_with_all = ["project", "name", "group", "at", "after"])]
+        orphans: bool,
+
+        /// With --orphans, move each one to a free port in its range instead
+        /// (locked allocations can't be moved)
+        // `requires` alone lets these through when a project is given, since
+        // clap drops it for arguments that conflict with what's present
+        #[arg(
+            long,
+            requires = "orphans",
+            conflicts_with_all = ["project", "name", "group"]
+        )]
+        migrate: bool,
+
+        /// With --orphans, act on every one without asking
+        #[arg(
+            short,
+            long,
+            requires = "orphans",
+            conflicts_with_all = ["project", "name", "group"]
+        )]
+        yes: bool,
+
+        /// With --orphans, only list them
+        #[arg(
+            long,
+            requires = "orphans",
+            conflicts_with_all = ["yes", "project", "name", "group"]
+        )]
+        dry_run: bool,
+
+        /// Free locked allocations too
+        #[arg(long)]
+        force: bool,
+
+        /// Refuse to free anything whose port is currently listening
+        #[arg(long)]
+        if_idle: bool,
+
+        /// Don't remember the freed ports, so allocating the names again
+        /// picks ports as for new names
+        #[arg(long, conflicts_with_all = ["group", "orphans", "at", "after"])]
+        forget: bool,
+
+        /// Free at this time instead of now, on the next `pm gc` run
+        /// (HH:MM, YYYY-MM-DD HH:MM, or RFC 3339)
+        #[arg(
+            long,
+            value_name = "TIME",
+            value_parser = parse_at,
+            conflicts_with_all = ["group", "force", "if_idle"]
+        )]
+        at: Option<DateTime<FixedOffset>>,
+
+        /// Free after this long instead of now, on the next `pm gc` run (e.g., 2h, 1h30m, 3d)
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_after,
+            conflicts_with_all = ["at", "group", "force", "if_idle"]
+        )]
+        after: Option<DateTime<FixedOffset>>,
+    },
+
+    /// Free a project's ports, remembering them for `pm unarchive`.
+    ///
+    /// The project disappears from list and query output, and its ports are
+    /// free for others. With no project, lists archived projects.
+    Archive {
+        /// Project name, or "." for the current directory's linked project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+
+        /// Archive even if the project has locked ports
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Restore an archived project, reclaiming its old ports where still free.
+    ///
+    /// Ports taken in the meantime are replaced with suggested ones.
+    Unarchive {
+        /// Archived project name, or "." for the current directory's linked project
+        project: String,
+    },
+
+    /// Free allocations that a cleanup policy applies to.
+    ///
+    /// Frees allocations whose scheduled time (`pm free --at/--after`,
+    /// `pm allocate --ttl`) has passed, whose bound process (`--pid`) has
+    /// exited, or that have not been seen listening for the configured
+    /// `stale_after_days`. Meant to run periodically, e.g., from cron or launchd.
+    Gc {
+        /// Report what would be freed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Show allocation churn, lifetimes, and peaks from the local usage log.
+    ///
+    /// Every registry change is recorded in `.usage` beside the registry,
+    /// and nothing is sent anywhere. Helps size ranges and choose
+    /// `stale_after_days`.
+    Usage {
+        #[command(flatten)]
+        json: JsonArgs,
+    },
+
+    /// Install a per-user service that runs `pm gc` periodically.
+    ///
+    /// Writes a launchd agent (macOS) or a systemd user service and timer
+    /// (Linux) for the current pm binary, and starts it.
+    InstallService {
+        /// Minutes between runs
+        #[arg(
+            long,
+            value_name = "MINUTES",
+            default_value_t = 15,
+            value_parser = clap::value_parser!(u32).range(1..)
+        )]
+        interval: u32,
+
+        /// Write the service files without starting the service
+        #[arg(long)]
+        no_start: bool,
+
+        /// Print the service files instead of installing them
+        #[arg(long, conflicts_with = "no_start")]
+        print: bool,
+    },
+
+    /// Stop and remove the service installed by `pm install-service`.
+    UninstallService,
+
+    /// Protect allocations from free and bulk operations.
+    ///
+    /// If no name is specified, locks all ports in the project.
+    Lock {
+        /// Project name, or "." for the current directory's linked project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Port name to lock (optional - locks all if omitted)
+        name: Option<String>,
+    },
+
+    /// Remove protection from allocations.
+    ///
+    /// If no name is specified, unlocks all ports in the project.
+    Unlock {
+        /// Project name, or "." for the current directory's linked project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Port name to unlock (optional - unlocks all if omitted)
+        name: Option<String>,
+    },
+
+    /// Link a directory to a project.
+    ///
+    /// Commands run inside a linked directory (or below it) accept "." or an
+    /// omitted project argument. With no arguments, lists existing links.
+    Link {
+        /// Directory to link (e.g., ".")
+        dir: Option<std::path::PathBuf>,
+
+        /// Project name (defaults to the directory name)
+        project: Option<String>,
+
+        /// Link the enclosing git repository root instead of the directory itself
+        #[arg(long)]
+        git_root: bool,
+
+        /// Record the link in the repository's .pm.toml (relative to the git root)
+        /// so it is shared with everyone using the repo
+        #[arg(long, conflicts_with = "git_root")]
+        repo: bool,
+    },
+
+    /// Remove a directory link.
+    Unlink {
+        /// Linked directory (e.g., ".")
+        dir: std::path::PathBuf,
+    },
+
+    /// Add an alias so a port can also be queried under another name.
+    Alias {
+        /// Project name, or "." for the current directory's linked project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Existing port name
+        name: String,
+
+        /// Additional name resolving to the same port
+        alias: PortName,
+    },
+
+    /// Remove a port alias.
+    Unalias {
+        /// Project name, or "." for the current directory's linked project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Alias to remove
+        alias: String,
+    },
+
+    /// Rename a port, keeping its number and metadata.
+    ///
+    /// Aliases, port history, and other projects' dependencies on the port
+    /// follow it to the new name.
+    RenamePort {
+        /// Project name, or "." for the current directory's linked project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Current port name
+        old_name: String,
+
+        /// New port name
+        new_name: PortName,
+
+        /// Rename the port even if it is locked
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Declare that a project uses another project's port.
+    ///
+    /// `pm query <project> --with-deps` then lists the port alongside the
+    /// project's own, e.g. for wiring service URLs in local development.
+    Depend {
+        /// Project that uses the port
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Project the port belongs to
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        on: String,
+
+        /// Port name (optional - depends on every port of the project if omitted)
+        name: Option<String>,
+    },
+
+    /// Remove a dependency declared with `pm depend`.
+    Undepend {
+        /// Project that uses the port
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Project the port belongs to
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        on: String,
+
+        /// Port name (optional - removes the dependency on the whole project if omitted)
+        name: Option<String>,
     },
 
     /// List allocated ports with their status.
@@ -53,9 +476,41 @@ pub enum Command {
         #[arg(long)]
         unassigned: bool,
 
-        /// Output as JSON for scripting
-        #[arg(long)]
-        json: bool,
+        /// Only show projects matching this glob (e.g., "web*"); repeat to select several
+        #[arg(long, value_name = "GLOB", conflicts_with = "unassigned")]
+        project: Vec<String>,
+
+        /// Only show allocations in this group (see `pm allocate --group`)
+        #[arg(long, visible_alias = "tag", conflicts_with = "unassigned")]
+        group: Option<String>,
+
+        /// Also show each allocation's note and URL
+        #[arg(long, short = 'l', conflicts_with = "unassigned")]
+        long: bool,
+
+        /// Show allocations on this host instead of this machine's, or on
+        /// every host with "all"
+        #[arg(
+            long,
+            value_name = "HOST",
+            value_parser = HostFilter::parse,
+            default_value = "local",
+            conflicts_with = "unassigned"
+        )]
+        host: HostFilter,
+
+        /// Output format: a table, or one line per port for grep pipelines
+        #[arg(
+            short = 'o',
+            long,
+            value_name = "FORMAT",
+            default_value = "table",
+            conflicts_with_all = ["json", "long"]
+        )]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        json: JsonArgs,
     },
 
     /// Query port(s) for a project (for scripting).
@@ -63,15 +518,113 @@ pub enum Command {
     /// Outputs in key=value format for easy parsing.
     #[command(visible_alias = "q")]
     Query {
-        /// Project name
-        project: String,
+        /// Project name, or "." (default) for the current directory's linked project.
+        /// A glob (e.g., "team-a-*") queries every matching project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
 
         /// Port name (optional - shows all if omitted)
         name: Option<String>,
 
-        /// Output as JSON for scripting
+        /// Query every project (no status probing)
+        #[arg(long, conflicts_with_all = ["project", "name"])]
+        all: bool,
+
+        /// Also list the ports of other projects this one depends on (see `pm depend`),
+        /// as project.name=port
+        #[arg(long, conflicts_with = "all")]
+        with_deps: bool,
+
+        /// Only show ports in this group (see `pm allocate --group`)
+        #[arg(long, visible_alias = "tag")]
+        group: Option<String>,
+
+        #[command(flatten)]
+        json: JsonArgs,
+    },
+
+    /// Print a port, allocating it first if it doesn't exist yet.
+    ///
+    /// Prints only the port number, so it can be used directly in scripts
+    /// (e.g., PORT=$(pm get webapp web)). The lookup and allocation happen
+    /// under the registry lock, so concurrent calls agree on one port.
+    Get {
+        /// Project name, or "." for the linked project. When only one argument
+        /// is given, it is the port name and the project is inferred from the
+        /// current directory.
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Port name (e.g., "web", "api", "db")
+        name: Option<String>,
+
+        /// Port type for range selection when allocating (defaults to the name)
+        #[arg(long, short = 't')]
+        r#type: Option<String>,
+    },
+
+    /// Keep a port bound until its service is ready to start.
+    ///
+    /// Allocates the port if needed, then listens on it so no other program
+    /// can take it, until Ctrl-C or `pm hold --release`. Release it right
+    /// before starting the service. Connections to the port are closed
+    /// immediately.
+    Hold {
+        /// Project name, or "." for the linked project. When only one argument
+        /// is given, it is the port name and the project is inferred from the
+        /// current directory.
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Port name (e.g., "web", "api", "db")
+        name: Option<String>,
+
+        /// Release a running hold, returning once the port is free
+        #[arg(long)]
+        release: bool,
+    },
+
+    /// Open an allocation's URL in the browser.
+    ///
+    /// Uses the allocation's --url-template, or http://<host>:<port> where the
+    /// host is the allocation's --host or localhost.
+    /// The browser is $BROWSER if set, otherwise the system default.
+    Open {
+        /// Project name, or "." for the linked project. When only one argument
+        /// is given, it is the port name and the project is inferred from the
+        /// current directory.
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: String,
+
+        /// Port name (e.g., "web", "api")
+        name: Option<String>,
+
+        /// Print the URL instead of opening it
         #[arg(long)]
-        json: bool,
+        print: bool,
+    },
+
+    /// Print the URL of each of a project's ports.
+    ///
+    /// URLs come from each allocation's --url-template, or are
+    /// http://<host>:<port> where the host is the allocation's --host or
+    /// localhost. Use --host lan to hand URLs to a phone or VM on the same
+    /// network.
+    Urls {
+        /// Project name, or "." (default) for the current directory's linked project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+
+        /// Port name (optional - prints only that port's URL)
+        name: Option<String>,
+
+        /// Reach this machine at "lan" (its LAN address), an address or
+        /// hostname, or "localhost" (default)
+        #[arg(long, value_name = "HOST", value_parser = parse_address)]
+        host: Option<Address>,
+
+        #[command(flatten)]
+        json: JsonArgs,
     },
 
     /// Show all listening ports on the system.
@@ -79,16 +632,47 @@ pub enum Command {
     /// Displays both assigned and unassigned ports.
     #[command(visible_alias = "s")]
     Status {
-        /// Output as JSON for scripting
-        #[arg(long)]
-        json: bool,
+        #[command(flatten)]
+        json: JsonArgs,
 
         /// Show full process information including working directory
         #[arg(long)]
         full: bool,
+
+        /// Only show listeners owned by the current user
+        #[arg(long)]
+        mine: bool,
+
+        /// Show another machine's listeners, detected over SSH by pm if it is
+        /// installed there, or by ss or lsof. Allocations are this registry's
+        #[arg(long, value_name = "[USER@]HOST", conflicts_with = "mine")]
+        remote: Option<String>,
+
+        /// Also show the listeners of the `pm agent` at HOST[:PORT], next to
+        /// this machine's (repeatable)
+        #[arg(long, value_name = "HOST[:PORT]", conflicts_with_all = ["remote", "mine"])]
+        agent: Vec<String>,
+
+        /// Show the registry and listeners captured by `pm snapshot` in FILE
+        /// ("-" for stdin) instead of this machine's
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["remote", "agent"])]
+        snapshot: Option<std::path::PathBuf>,
+
+        /// Output format: a table, or one line per port for grep pipelines
+        #[arg(
+            short = 'o',
+            long,
+            value_name = "FORMAT",
+            default_value = "table",
+            conflicts_with_all = ["json", "full"]
+        )]
+        output: OutputFormat,
     },
 
     /// Suggest available ports.
+    ///
+    /// --json prints bare port numbers; --json=v2 lists each port with the
+    /// type and range it came from.
     #[command(visible_alias = "sg")]
     Suggest {
         /// Port type for range selection (e.g., "web", "api", "db")
@@ -99,24 +683,580 @@ pub enum Command {
         #[arg(default_value = "1")]
         count: usize,
 
-        /// Output as JSON for scripting
+        /// Suggest for this project ("." for the current directory's linked
+        /// project), skipping ranges reserved for other projects
+        #[arg(long, add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+
+        /// Show every port looked at and why the skipped ones were skipped
         #[arg(long)]
-        json: bool,
+        explain: bool,
+
+        /// Also skip ports with connections in TIME_WAIT or CLOSE_WAIT, now
+        /// or within the last SECONDS
+        #[arg(long, value_name = "SECONDS")]
+        avoid_lingering: Option<u64>,
+
+        #[command(flatten)]
+        json: JsonArgs,
     },
 
     /// Show or edit configuration.
     #[command(visible_alias = "c")]
+    #[command(args_conflicts_with_subcommands = true)]
     Config {
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+
         /// Show the config file path
         #[arg(long)]
         path: bool,
 
-        /// Set a port range for a type (format: type=start-end, e.g., "web=8000-8999")
+        /// Set a port range for a type (format: type=start-end[,start-end...][:policy], e.g., "web=8000-8999").
+        /// Policies: lowest-free (default), sequential, random
         #[arg(long)]
         set: Option<String>,
 
-        /// Output as JSON for scripting
+        /// With --set, move allocations the new range no longer covers to free
+        /// ports inside it, printing each old and new port
+        #[arg(long, requires = "set")]
+        migrate: bool,
+
+        /// Add a bundle of named ranges; existing ranges are kept (repeatable)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        preset: Vec<Preset>,
+
+        /// Match project and port names ignoring case in query, free, and allocate
+        #[arg(long, value_name = "BOOL")]
+        ignore_case: Option<bool>,
+
+        /// Let `pm gc` free allocations not seen listening for this many days (0 turns it off)
+        #[arg(long, value_name = "DAYS")]
+        stale_after_days: Option<u32>,
+
+        /// Commit every registry change to a git repository in its directory (see `pm sync`)
+        #[arg(long, value_name = "BOOL")]
+        git_sync: Option<bool>,
+
+        /// Make allocate, get, hold, suggest, and other commands that check for
+        /// listening processes fail when ports can't be detected
+        #[arg(long, value_name = "BOOL")]
+        strict_detection: Option<bool>,
+
+        #[command(flatten)]
+        json: JsonArgs,
+    },
+
+    /// Print the JSON Schema for a command's --json output.
+    Schema {
+        /// Output format to describe
+        #[arg(value_enum)]
+        format: SchemaFormat,
+
+        /// JSON format version to describe
+        #[arg(long, value_enum, default_value = "v1")]
+        api_version: JsonVersion,
+    },
+
+    /// Manage profiles: separate named registries.
+    ///
+    /// The `default` profile is the usual registry; others live in
+    /// `profiles/<name>/` beside it. Pick one per command with --profile.
+    #[command(subcommand)]
+    Profile(ProfileAction),
+
+    /// Pull and push the registry's git repository (needs `pm config --git-sync true`).
+    ///
+    /// Local commits are rebased onto the remote's. A pull that conflicts, or
+    /// that leaves the registry invalid, stops before pushing.
+    Sync {
+        /// Set the remote to sync with (stored as the repository's `origin`)
+        #[arg(long, value_name = "URL")]
+        remote: Option<String>,
+    },
+
+    /// Check that pm can read its registry and detect listening ports.
+    ///
+    /// Reports why port detection fails, if it does; other commands only
+    /// warn and carry on without it.
+    Doctor,
+
+    /// Capture the registry, listeners, and conflicts as one JSON document.
+    ///
+    /// The capture is timestamped, for attaching to bug reports or keeping
+    /// around. `pm status --snapshot FILE` shows it again later, on any
+    /// machine.
+    Snapshot,
+
+    /// Collect what it takes to reproduce a problem into a tarball for an issue.
+    ///
+    /// The bundle holds the registry, a `pm snapshot`, platform details, the
+    /// raw `lsof` listing, and the registry's recent commits under git sync.
+    /// Look it over before sharing it, or pass --redact.
+    DebugBundle {
+        /// Where to write the bundle (default: pm-debug-<time>.tar.gz here)
+        #[arg(long, short = 'o', value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+
+        /// Rename projects and leave out paths, users, notes, and commands
+        #[arg(long)]
+        redact: bool,
+    },
+
+    /// Serve this machine's listening ports to `pm status --agent`.
+    ///
+    /// Answers each connection with the listeners `pm status --json` would
+    /// show and closes it; nothing can be changed through the agent. There
+    /// is no authentication, so listen on a public address only on a
+    /// trusted network, or reach a loopback agent through an SSH tunnel.
+    Agent {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:7683")]
+        listen: String,
+    },
+
+    /// Explain an error code and how to fix it.
+    ///
+    /// Without a code, lists every code. With --json, failing commands print
+    /// their error to stderr as JSON, including its code.
+    Explain {
+        /// Error code, e.g. E_PORT_IN_USE
+        code: Option<String>,
+    },
+
+    /// Merge another registry file into this one.
+    ///
+    /// Allocations that don't collide are added. For each collision (the same
+    /// name with another port, or a port taken by another name) pm asks which
+    /// side wins, unless --ours, --theirs, or --reassign decides for all.
+    Merge {
+        /// Registry file to merge in
+        file: std::path::PathBuf,
+
+        /// Keep this registry's allocation on every collision
+        #[arg(long, conflicts_with_all = ["theirs", "reassign"])]
+        ours: bool,
+
+        /// Take theirs on every collision, moving ours to a free port
+        #[arg(long, conflicts_with = "reassign")]
+        theirs: bool,
+
+        /// Keep both sides of port collisions, moving theirs to a free port
+        /// (name collisions keep ours)
+        #[arg(long)]
+        reassign: bool,
+    },
+
+    /// Show what changed between two registries.
+    ///
+    /// Lists allocations added, removed, or moved to another port, and port
+    /// ranges that changed. Each side is a registry file or a JSON export from
+    /// `pm export registry`; without a second file, or with "current", the
+    /// first is compared with the registry in use.
+    Diff {
+        /// The older registry
+        old: std::path::PathBuf,
+
+        /// The newer registry (default: the registry in use)
+        new: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        json: JsonArgs,
+    },
+
+    /// Report allocations whose ports another machine uses too.
+    ///
+    /// Reads a teammate's `pm snapshot` (or `pm export registry` output) and
+    /// lists this registry's allocations whose port they allocated or had
+    /// listening, for pairing or sharing a docker host. Exits with an error
+    /// when there are collisions.
+    CheckAgainst {
+        /// Snapshot or exported registry JSON ("-" for stdin)
+        snapshot: std::path::PathBuf,
+
+        #[command(flatten)]
+        json: JsonArgs,
+    },
+
+    /// Render allocations into configuration for other tools.
+    #[command(subcommand)]
+    Export(ExportTarget),
+
+    /// Register existing ports from other sources.
+    #[command(subcommand)]
+    Import(ImportSource),
+
+    /// Print a project's ports as environment variables (WEB_PORT=8080).
+    ///
+    /// Port names are uppercased with '-', '.' and other characters that can't
+    /// be in a variable name replaced by '_'. Fails if two names map to the
+    /// same variable.
+    Env {
+        /// Project name, or "." (default) for the current directory's linked project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+
+        /// Prefix each line with `export` for use with `eval "$(pm env)"`
+        #[arg(long)]
+        export: bool,
+
+        /// Also print each port's URL (WEB_URL=...), reaching this machine at
+        /// "lan" (its LAN address), an address or hostname, or "localhost"
+        #[arg(long, value_name = "HOST", value_parser = parse_address)]
+        host: Option<Address>,
+    },
+
+    /// Run a command with a project's ports in its environment.
+    ///
+    /// Sets the variables `pm env` prints (WEB_PORT=8080, ...) for the
+    /// command, e.g. `pm run -- npm run dev` or `pm run webapp -- make serve`,
+    /// and exits with its exit code.
+    Run {
+        /// Project name, or "." (default) for the current directory's linked project
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+
+        /// Command to run, after `--`
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+
+    /// Print a shell hook that exports the linked project's ports on cd.
+    ///
+    /// Whenever the working directory changes, the hook exports the ports of
+    /// the directory's linked project as in `pm env` (WEB_PORT=8000, ...), and
+    /// unsets them again on leaving. Add `eval "$(pm hook bash)"` to ~/.bashrc,
+    /// `eval "$(pm hook zsh)"` to ~/.zshrc, or `pm hook fish | source` to
+    /// ~/.config/fish/config.fish.
+    Hook {
+        /// Shell to print the hook for
+        #[arg(value_enum)]
+        shell: Shell,
+
+        /// Print the commands that update the environment for the current
+        /// directory (run by the hook)
+        #[arg(long, hide = true)]
+        apply: bool,
+    },
+
+    /// Print allocation changes as the registry is modified, until interrupted.
+    ///
+    /// Uses filesystem notifications, so editor plugins and dashboards can
+    /// refresh as soon as any pm command (or `pm config edit`) saves.
+    WatchRegistry {
+        #[command(flatten)]
+        json: JsonArgs,
+    },
+
+    /// Serve editor extensions over stdin/stdout until stdin closes.
+    ///
+    /// Speaks the JSON-RPC methods from docs/RPC_API.md, one message per
+    /// line, and pushes `v1.changed` (registry) and `v1.status` (listener)
+    /// notifications as they happen.
+    Ide {
+        /// Seconds between listener status checks
+        #[arg(long, value_name = "SECS", default_value_t = 2)]
+        interval: u64,
+    },
+
+    /// Print a one-line port summary for the current directory's project.
+    ///
+    /// Designed for shell prompts (PS1, starship): prints nothing when the
+    /// directory is not linked to a project.
+    Prompt,
+
+    /// Print a tmux status line segment for a project's ports.
+    ///
+    /// Add `#(pm tmux webapp)` to status-right; active ports are shown in
+    /// green. With --set-env, sets the project's port variables (as in
+    /// `pm env`) in the tmux session instead, so new panes inherit them.
+    Tmux {
+        /// Project name (defaults to the current directory's project; prints
+        /// nothing if there is none)
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+
+        /// Set the port variables in the tmux session instead of printing
+        #[arg(long)]
+        set_env: bool,
+
+        /// Session for --set-env (defaults to the current session)
+        #[arg(long, short = 't', value_name = "SESSION", requires = "set_env")]
+        target: Option<String>,
+
+        /// Print without tmux style codes (e.g., for zellij status bar plugins)
+        #[arg(long, conflicts_with = "set_env")]
+        plain: bool,
+    },
+
+    /// Update pm to the latest GitHub release.
+    ///
+    /// Verifies the download's SHA-256 checksum before replacing the binary.
+    /// Refuses to run for Homebrew, cargo, and system package installs.
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Only report whether a newer version is available
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Generate the CLI reference as man pages or markdown.
+    ///
+    /// Without --out-dir, prints the top-level man page or the full markdown
+    /// reference to stdout.
+    #[command(group(ArgGroup::new("format").required(true).args(["man", "markdown"])))]
+    GenDocs {
+        /// Render man pages (one per subcommand with --out-dir)
+        #[arg(long)]
+        man: bool,
+
+        /// Render a markdown reference
+        #[arg(long)]
+        markdown: bool,
+
+        /// Directory to write the generated files into
+        #[arg(long)]
+        out_dir: Option<std::path::PathBuf>,
+    },
+
+    /// Print completions for a command line, for shells without a
+    /// `COMPLETE=<shell> pm` script.
+    ///
+    /// Completes word CWORD (counting `pm` as 0) of the words after `--`,
+    /// e.g. `pm _complete bash 2 -- pm allocate web`.
+    #[command(name = "_complete", hide = true)]
+    Complete {
+        /// Output format: one value per line (bash), `value:description`
+        /// (zsh), `value<TAB>description` (fish), or JSON records (nu)
+        #[arg(value_enum)]
+        shell: ShellType,
+
+        /// Index of the word to complete
+        cword: usize,
+
+        /// The command line, starting with `pm`
+        #[arg(last = true)]
+        words: Vec<std::ffi::OsString>,
+    },
+}
+
+/// Export formats.
+#[derive(Subcommand, Debug)]
+pub enum ExportTarget {
+    /// nginx `server` blocks proxying each port's hostname
+    Nginx {
+        /// Project name, or "." for the linked project (all projects if omitted)
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+
+        /// Proxy to this machine at "lan" (its LAN address) or an address or
+        /// hostname, for a proxy running elsewhere (default 127.0.0.1)
+        #[arg(long, value_name = "HOST", value_parser = parse_address)]
+        host: Option<Address>,
+    },
+
+    /// Caddyfile sites proxying each port's hostname
+    Caddy {
+        /// Project name, or "." for the linked project (all projects if omitted)
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+
+        /// Proxy to this machine at "lan" (its LAN address) or an address or
+        /// hostname, for a proxy running elsewhere (default 127.0.0.1)
+        #[arg(long, value_name = "HOST", value_parser = parse_address)]
+        host: Option<Address>,
+    },
+
+    /// Add the project's ports to a devcontainer.json's forwardPorts and portsAttributes
+    Devcontainer {
+        /// Project name, or "." for the linked project (inferred if omitted)
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+
+        /// devcontainer.json to update (created if missing)
+        #[arg(long, default_value = ".devcontainer/devcontainer.json")]
+        file: std::path::PathBuf,
+    },
+
+    /// Procfile entries for ports with a --command (foreman, overmind, honcho)
+    #[command(visible_alias = "overmind")]
+    Procfile {
+        /// Project name, or "." for the linked project (all projects if omitted)
+        #[arg(add = ArgValueCompleter::new(project_candidates))]
+        project: Option<String>,
+    },
+
+    /// The whole registry as JSON, for 'pm import registry' on another machine
+    Registry {
+        /// Wrap the registry in a versioned envelope (default: v1)
+        #[arg(
+            long,
+            value_name = "VERSION",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "v1"
+        )]
+        json: Option<JsonVersion>,
+    },
+}
+
+/// Import sources.
+#[derive(Subcommand, Debug)]
+pub enum ImportSource {
+    /// Find hardcoded ports in package.json, vite.config, docker-compose, and Makefiles
+    Scan {
+        /// Directory to scan
+        #[arg(default_value = ".")]
+        dir: std::path::PathBuf,
+
+        /// Project to register ports under (default: linked project or directory name)
+        #[arg(long)]
+        project: Option<String>,
+
+        /// Register every finding without asking
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Only list findings
+        #[arg(long, conflicts_with = "yes")]
+        dry_run: bool,
+    },
+
+    /// Replace the registry with one from 'pm export registry'
+    ///
+    /// The file is validated first. With --merge, its entries are added to the
+    /// registry instead; any port, alias, range, or link that disagrees with
+    /// the registry is a conflict, and nothing is imported.
+    Registry {
+        /// JSON file to import, or "-" for stdin
+        file: std::path::PathBuf,
+
+        /// Add to the registry instead of replacing it
+        #[arg(long)]
+        merge: bool,
+    },
+
+    /// Adopt listeners from saved `ss -tlnp`, `netstat -an`, or `lsof` output
+    ///
+    /// The format is detected from the text. Each listening port is offered as
+    /// an allocation named after its process (or well-known service), as in
+    /// `pm import scan`. With --as-status the listing is shown as `pm status`
+    /// would show it instead, for checking a machine pm can't detect ports on.
+    /// --json goes with --as-status.
+    #[command(group(ArgGroup::new("status_json").args(["json"]).requires("as_status")))]
+    Netstat {
+        /// Saved output, or "-" for stdin
+        file: std::path::PathBuf,
+
+        /// Project to register ports under (default: linked project or directory name)
+        #[arg(long, conflicts_with = "as_status")]
+        project: Option<String>,
+
+        /// Register every listener without asking
+        #[arg(short, long, conflicts_with = "as_status")]
+        yes: bool,
+
+        /// Only list listeners
+        #[arg(long, conflicts_with_all = ["yes", "as_status"])]
+        dry_run: bool,
+
+        /// Show the listeners against the registry, as `pm status` does
+        #[arg(long)]
+        as_status: bool,
+
+        #[command(flatten)]
+        json: JsonArgs,
+    },
+}
+
+/// JSON output formats with a published schema.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SchemaFormat {
+    /// `pm list --json`
+    List,
+    /// `pm status --json` and `pm list --unassigned --json`
+    Status,
+    /// `pm query --json`
+    Query,
+    /// `pm query --all --json` and `pm query --with-deps --json`
+    QueryAll,
+    /// `pm query <glob> --json`
+    QueryGlob,
+    /// `pm suggest --json`
+    Suggest,
+    /// `pm suggest --explain --json`
+    SuggestExplain,
+    /// `pm config --json`
+    Config,
+    /// `pm config validate --json`
+    Validate,
+    /// `pm watch-registry --json` (one object per line)
+    WatchRegistry,
+    /// `pm diff --json`
+    Diff,
+    /// `pm check-against --json`
+    CheckAgainst,
+    /// `pm usage --json`
+    Usage,
+    /// `pm urls --json`
+    Urls,
+}
+
+/// Profile subcommands.
+#[derive(Subcommand, Debug)]
+pub enum ProfileAction {
+    /// List profiles, marking the one in use with '*'
+    List {
+        #[command(flatten)]
+        json: JsonArgs,
+    },
+
+    /// Create a profile with an empty registry
+    Create {
+        /// Profile name
+        name: String,
+
+        /// Also switch to the new profile
+        #[arg(long)]
+        switch: bool,
+    },
+
+    /// Use a profile from now on ("default" returns to the usual registry)
+    Switch {
+        /// Profile name
+        name: String,
+    },
+}
+
+/// Configuration subcommands.
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Open the registry in $VISUAL / $EDITOR, saving only if it validates.
+    ///
+    /// Checks that the file parses, ports are valid, no port is assigned
+    /// twice, and ranges don't overlap. Invalid edits are never saved.
+    Edit,
+
+    /// Check a registry file for problems without changing it.
+    ///
+    /// Reports parse and schema errors, ports outside 1-65535, invalid names,
+    /// inverted or overlapping ranges, and ports assigned twice.
+    Validate {
+        /// Registry file to check (defaults to the active registry)
+        file: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        json: JsonArgs,
+    },
+
+    /// Recover a registry that doesn't parse.
+    ///
+    /// Copies the broken file aside as <file>.broken-<time>, then rewrites
+    /// the registry with every section and line that still parses, and
+    /// reports what was dropped.
+    Repair {
+        /// Only report what would be dropped
         #[arg(long)]
-        json: bool,
+        dry_run: bool,
     },
 }
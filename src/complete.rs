@@ -0,0 +1,240 @@
+//! Dynamic shell completion.
+//!
+//! Shells set up with `source <(COMPLETE=bash pm)` call pm back on every TAB
+//! press, so completions can come from the registry. Completing `pm allocate
+//! <project> <name> <TAB>` offers the ports `pm suggest` would, as if the
+//! suggestion ran inline.
+//!
+//! Shells clap has no script for call `pm _complete <shell> <cword> -- <words>`
+//! from their own completion hook instead, and get the same candidates printed
+//! in a format they can read.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+
+use clap::{ArgMatches, CommandFactory, ValueEnum};
+use clap_complete::CompletionCandidate;
+use serde_json::json;
+
+use crate::cli::Cli;
+use crate::error::Result;
+use crate::model::Registry;
+use crate::name::ProjectName;
+use crate::persistence::{
+    load_registry, registry_path, set_profile_override, set_registry_path_override,
+};
+use crate::ports::get_listening_ports;
+use crate::registry::explain_suggestions;
+use crate::workspace::resolve_project_arg;
+
+/// How many suggested ports to offer.
+const PORT_CANDIDATES: usize = 5;
+
+/// Returns the command line up to the word being completed.
+fn preceding_words() -> Vec<OsString> {
+    // The shell runs `pm -- pm allocate ...` with the line after the `--`
+    let mut words: Vec<OsString> = env::args_os().skip_while(|a| a != "--").skip(1).collect();
+    let index = env::var("_CLAP_COMPLETE_INDEX")
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(words.len().saturating_sub(1));
+    words.truncate(index);
+    words
+}
+
+/// Parses the command line up to the word being completed.
+fn preceding_matches() -> Option<ArgMatches> {
+    Cli::command()
+        .ignore_errors(true)
+        .try_get_matches_from(preceding_words())
+        .ok()
+}
+
+/// Loads the registry named by `--config` and `--profile` in `matches` (a
+/// subcommand's, where global options end up).
+fn completion_registry(matches: &ArgMatches) -> Result<Registry> {
+    if let Some(path) = matches.get_one::<PathBuf>("config") {
+        set_registry_path_override(path)?;
+    }
+    if let Some(profile) = matches.get_one::<String>("profile") {
+        set_profile_override(profile);
+    }
+    // Don't create a registry just to complete a command line
+    if registry_path()?.exists() {
+        load_registry()
+    } else {
+        Ok(Registry::default())
+    }
+}
+
+/// Completes a project argument with the registry's project names.
+pub fn project_candidates(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return Vec::new();
+    };
+    let Some(matches) = preceding_matches() else {
+        return Vec::new();
+    };
+    // `export nginx <TAB>` is two subcommands deep
+    let mut command = &matches;
+    while let Some((_, sub)) = command.subcommand() {
+        command = sub;
+    }
+    let Ok(registry) = completion_registry(command) else {
+        return Vec::new();
+    };
+    registry
+        .projects
+        .iter()
+        .filter(|(name, _)| name.starts_with(current))
+        .map(|(name, project)| {
+            let help = match project.ports.len() {
+                1 => "1 port".to_string(),
+                n => format!("{n} ports"),
+            };
+            CompletionCandidate::new(name.as_str()).help(Some(help.into()))
+        })
+        .collect()
+}
+
+/// Completes `allocate`'s port with free ports suggested for the name's type.
+pub fn port_candidates(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return Vec::new();
+    };
+    let Some(matches) = preceding_matches() else {
+        return Vec::new();
+    };
+    let Some(allocate) = matches.subcommand_matches("allocate") else {
+        return Vec::new();
+    };
+    // Completion has nowhere to report errors; offer nothing instead
+    suggested_ports(allocate)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(port, _)| port.starts_with(current))
+        .map(|(port, help)| CompletionCandidate::new(port).help(Some(help.into())))
+        .collect()
+}
+
+/// Returns the ports to suggest for `allocate <project> <name>`, with the
+/// range each came from.
+fn suggested_ports(allocate: &ArgMatches) -> Result<Vec<(String, String)>> {
+    let (Some(project), Some(name)) = (
+        allocate.get_one::<String>("project"),
+        allocate.get_one::<String>("name"),
+    ) else {
+        return Ok(Vec::new());
+    };
+    let registry = completion_registry(allocate)?;
+    let cwd = env::current_dir()?;
+    let project = ProjectName::new(resolve_project_arg(&registry, Some(project), &cwd)?)?;
+    let active_ports = get_listening_ports().unwrap_or_default();
+    let report = explain_suggestions(
+        &registry,
+        name,
+        Some(&project),
+        PORT_CANDIDATES,
+        &active_ports,
+    )?;
+    let help = format!("free in {} range {}", report.range_type, report.range);
+    Ok(report
+        .suggestions()
+        .map(|port| (port.to_string(), help.clone()))
+        .collect())
+}
+
+/// How `pm _complete` prints candidates, named after the shell that reads them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ShellType {
+    /// One value per line
+    Bash,
+    /// `value:description` lines, as `_describe` takes them
+    Zsh,
+    /// `value<TAB>description` lines
+    Fish,
+    /// A JSON list of `{value, description}` records, for nushell's external completer
+    Nu,
+}
+
+/// Completes word `cword` of `words`, a command line starting with `pm`, and
+/// returns the candidates formatted for `shell`.
+pub fn complete_words(shell: ShellType, cword: usize, mut words: Vec<OsString>) -> Result<String> {
+    // Word 0 is pm itself
+    if cword == 0 {
+        return Ok(String::new());
+    }
+    // A cursor past the last word is starting a new one
+    if words.len() <= cword {
+        words.resize(cword + 1, OsString::new());
+    }
+    // The completers re-read the words after `--` as they do when called back
+    // under COMPLETE=<shell>, and need to know which one is being completed
+    env::set_var("_CLAP_COMPLETE_INDEX", cword.to_string());
+    let cwd = env::current_dir().ok();
+    let candidates =
+        clap_complete::engine::complete(&mut Cli::command(), words, cword, cwd.as_deref())?;
+    let candidates: Vec<(String, String)> = candidates
+        .iter()
+        .filter(|c| !c.is_hide_set())
+        .map(|c| {
+            let value = c.get_value().to_string_lossy().into_owned();
+            let help = c.get_help().map(ToString::to_string).unwrap_or_default();
+            (value, help)
+        })
+        .collect();
+    Ok(format_candidates(shell, &candidates))
+}
+
+/// Formats `(value, description)` candidates for `shell`.
+fn format_candidates(shell: ShellType, candidates: &[(String, String)]) -> String {
+    if shell == ShellType::Nu {
+        let records: Vec<_> = candidates
+            .iter()
+            .map(|(value, help)| json!({ "value": value, "description": help }))
+            .collect();
+        return format!("{}\n", json!(records));
+    }
+    candidates
+        .iter()
+        .map(|(value, help)| match (shell, help.is_empty()) {
+            // zsh splits on the first unescaped ':'
+            (ShellType::Zsh, true) => format!("{}\n", value.replace(':', "\\:")),
+            (ShellType::Zsh, false) => format!("{}:{help}\n", value.replace(':', "\\:")),
+            (ShellType::Fish, false) => format!("{value}\t{help}\n"),
+            _ => format!("{value}\n"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_candidates() {
+        let candidates = [
+            ("webapp".to_string(), "2 ports".to_string()),
+            ("a:b".to_string(), String::new()),
+        ];
+        assert_eq!(
+            format_candidates(ShellType::Bash, &candidates),
+            "webapp\na:b\n"
+        );
+        assert_eq!(
+            format_candidates(ShellType::Zsh, &candidates),
+            "webapp:2 ports\na\\:b\n"
+        );
+        assert_eq!(
+            format_candidates(ShellType::Fish, &candidates),
+            "webapp\t2 ports\na:b\n"
+        );
+        assert_eq!(
+            format_candidates(ShellType::Nu, &candidates),
+            r#"[{"value":"webapp","description":"2 ports"},{"value":"a:b","description":""}]"#
+                .to_string()
+                + "\n"
+        );
+    }
+}
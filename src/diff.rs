@@ -0,0 +1,155 @@
+//! Comparing two registries (`pm diff`).
+//!
+//! Reports allocations added, removed, or moved to another port, as
+//! `pm watch-registry` does for live changes, plus port ranges that were
+//! added, removed, or changed. Either side can be a registry file, an export
+//! from `pm export registry`, or the registry in use.
+
+use std::path::Path;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::error::{ConfigError, Result};
+use crate::model::Registry;
+use crate::transfer::parse_registry_json;
+use crate::watch::{registry_change, RegistryChange};
+
+/// Differences between two registries.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct RegistryDiff {
+    #[serde(flatten)]
+    pub allocations: RegistryChange,
+    pub ranges: Vec<RangeChange>,
+}
+
+impl RegistryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.allocations.is_empty() && self.ranges.is_empty()
+    }
+}
+
+/// A port range added (no `old`), removed (no `new`), or changed.
+#[derive(Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct RangeChange {
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Reads a registry to compare: a JSON export if the file ends in `.json`,
+/// TOML otherwise. It isn't validated, so broken registries can still be
+/// compared.
+pub fn read_registry_file(path: &Path) -> Result<Registry> {
+    let content = std::fs::read_to_string(path).map_err(|source| ConfigError::ReadFailed {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+        parse_registry_json(&content).map_err(|source| {
+            ConfigError::JsonParseFailed {
+                path: path.to_path_buf(),
+                source,
+            }
+            .into()
+        })
+    } else {
+        toml::from_str(&content).map_err(|source| {
+            ConfigError::ParseFailed {
+                path: path.to_path_buf(),
+                source,
+            }
+            .into()
+        })
+    }
+}
+
+/// Returns what changed from `before` to `after`.
+pub fn diff_registries(before: &Registry, after: &Registry) -> RegistryDiff {
+    let (old_ranges, new_ranges) = (before.ranges(), after.ranges());
+    let mut types: Vec<&str> = old_ranges
+        .keys()
+        .chain(new_ranges.keys())
+        .copied()
+        .collect();
+    types.sort_unstable();
+    types.dedup();
+
+    let ranges = types
+        .into_iter()
+        .filter_map(|type_name| {
+            let (old, new) = (old_ranges.get(type_name), new_ranges.get(type_name));
+            (old != new).then(|| RangeChange {
+                type_name: type_name.to_string(),
+                old: old.map(ToString::to_string),
+                new: new.map(ToString::to_string),
+            })
+        })
+        .collect();
+
+    RegistryDiff {
+        allocations: registry_change(before, after),
+        ranges,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Allocation, PortMeta, PortRange, Project};
+    use crate::name::{PortName, ProjectName};
+    use crate::port::Port;
+
+    fn registry_with(ports: &[(&str, &str, u16)]) -> Registry {
+        let mut registry = Registry::default();
+        for (project, name, port) in ports {
+            registry
+                .projects
+                .entry(ProjectName::new(*project).unwrap())
+                .or_insert_with(Project::default)
+                .ports
+                .insert(
+                    PortName::new(*name).unwrap(),
+                    Allocation::new(Port::new(*port).unwrap(), PortMeta::default()),
+                );
+        }
+        registry
+    }
+
+    #[test]
+    fn test_diff_registries() {
+        let before = registry_with(&[("webapp", "web", 8000), ("webapp", "api", 3000)]);
+        let mut after = registry_with(&[("webapp", "web", 8001), ("shop", "web", 8002)]);
+        after
+            .defaults
+            .ranges
+            .insert("web".to_string(), PortRange::new(8000, 8099));
+        after
+            .defaults
+            .ranges
+            .insert("grpc".to_string(), PortRange::new(50051, 50099));
+
+        let diff = diff_registries(&before, &after);
+        assert_eq!(diff.allocations.added.len(), 1);
+        assert_eq!(diff.allocations.removed[0].name, "api");
+        assert_eq!(diff.allocations.changed[0].new.as_u16(), 8001);
+        assert_eq!(
+            diff.ranges,
+            vec![
+                RangeChange {
+                    type_name: "grpc".to_string(),
+                    old: None,
+                    new: Some("50051-50099".to_string()),
+                },
+                RangeChange {
+                    type_name: "web".to_string(),
+                    old: Some("8000-8999".to_string()),
+                    new: Some("8000-8099".to_string()),
+                },
+            ]
+        );
+
+        assert!(diff_registries(&after, &after).is_empty());
+    }
+}
@@ -1,82 +1,368 @@
 //! Output formatting and display utilities.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
 
+use chrono::{DateTime, FixedOffset, Local};
+use clap::ValueEnum;
 use comfy_table::modifiers::UTF8_ROUND_CORNERS;
-use comfy_table::presets::UTF8_FULL_CONDENSED;
-use comfy_table::{Cell, Color, ContentArrangement, Table, TableComponent};
+use comfy_table::presets::{ASCII_FULL_CONDENSED, UTF8_FULL_CONDENSED};
+use comfy_table::{Cell, ColumnConstraint, ContentArrangement, Table, TableComponent};
+use regex::Regex;
+use schemars::schema::RootSchema;
+use schemars::{schema_for, JsonSchema};
 use serde::Serialize;
 
-use crate::model::Registry;
+use crate::check::Clash;
+use crate::diff::RegistryDiff;
+use crate::error::Error;
+use crate::explain::{explain, Explanation};
+use crate::gc::{Collected, Reason, HISTORY_DAYS};
+use crate::merge::MergeStep;
+use crate::model::{
+    parse_host, AllocationPolicy, Borders, DisplayConfig, PortMeta, Quotas, Registry, Theme,
+    TypeRule,
+};
+use crate::name::glob_match;
+use crate::pager;
 use crate::port::Port;
-use crate::ports::ListeningPort;
+use crate::ports::{group_by_port, ListeningPort};
+use crate::preset::PresetChange;
+use crate::registry::{SuggestionReport, Verdict};
+use crate::repair::Salvage;
+use crate::snapshot::Snapshot;
+use crate::theme::{self, paint, Tone};
+use crate::usage::UsageReport;
+use crate::validate::Diagnostic;
+use crate::watch::RegistryChange;
+use crate::wellknown::service_name;
+use crate::workspace::infer_project;
+
+/// Columns that keep their full width when a table is squeezed to fit the
+/// terminal.
+const WHOLE_COLUMNS: [&str; 3] = ["PORT", "PROJECT", "STATUS"];
 
 /// Creates a table with clean styling: solid borders, no row separators.
+/// With `borders = "ascii"` under `[display]`, they are drawn in ASCII.
+///
+/// Commands that print tables call [`pager::start`] before printing anything,
+/// so long output is paged, and print with [`print_table`].
 fn create_table() -> Table {
     let mut table = Table::new();
-    table
-        .load_preset(UTF8_FULL_CONDENSED)
-        .apply_modifier(UTF8_ROUND_CORNERS)
-        .set_content_arrangement(ContentArrangement::Dynamic);
-    // Use solid vertical bars instead of dotted
-    table.set_style(TableComponent::VerticalLines, '│');
-    // Use single-line header separator instead of double
-    table.set_style(TableComponent::MiddleHeaderIntersections, '┼');
-    table.set_style(TableComponent::HeaderLines, '─');
-    table.set_style(TableComponent::LeftHeaderIntersection, '├');
-    table.set_style(TableComponent::RightHeaderIntersection, '┤');
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    match theme::current().borders {
+        Borders::Unicode => {
+            table
+                .load_preset(UTF8_FULL_CONDENSED)
+                .apply_modifier(UTF8_ROUND_CORNERS);
+            // Use solid vertical bars instead of dotted
+            table.set_style(TableComponent::VerticalLines, '│');
+            // Use single-line header separator instead of double
+            table.set_style(TableComponent::MiddleHeaderIntersections, '┼');
+            table.set_style(TableComponent::HeaderLines, '─');
+            table.set_style(TableComponent::LeftHeaderIntersection, '├');
+            table.set_style(TableComponent::RightHeaderIntersection, '┤');
+            table.set_truncation_indicator("…");
+        }
+        Borders::Ascii => {
+            table.load_preset(ASCII_FULL_CONDENSED);
+            table.set_style(TableComponent::HeaderLines, '-');
+            table.set_style(TableComponent::MiddleHeaderIntersections, '+');
+            table.set_truncation_indicator("...");
+        }
+    }
+    pager::prepare_table(&mut table);
     table
 }
 
+/// Fits a table to the terminal's width: the PORT, PROJECT, and STATUS
+/// columns stay whole, and cells that don't fit elsewhere (long process
+/// paths, notes) are cut short with an ellipsis instead of wrapping the row
+/// over several lines. Output that isn't a terminal has no width to fit, so
+/// it keeps everything.
+fn fit_to_width(table: &mut Table) {
+    let whole: Vec<usize> = table
+        .header()
+        .map(|header| {
+            header
+                .cell_iter()
+                .enumerate()
+                .filter(|(_, cell)| WHOLE_COLUMNS.contains(&cell.content().as_str()))
+                .map(|(index, _)| index)
+                .collect()
+        })
+        .unwrap_or_default();
+    for index in whole {
+        if let Some(column) = table.column_mut(index) {
+            column.set_constraint(ColumnConstraint::ContentWidth);
+        }
+    }
+    for row in table.row_iter_mut() {
+        row.max_height(1);
+    }
+}
+
+/// Prints a table made by [`create_table`], fitted to the terminal.
+fn print_table(mut table: Table) {
+    fit_to_width(&mut table);
+    println!("{table}");
+}
+
+/// Version of the `--json` output format.
+///
+/// Within a version, fields may be added but are never removed, renamed, or
+/// retyped. Breaking changes ship as a new version, and earlier versions stay
+/// selectable with `--json=<version>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum JsonVersion {
+    /// Bare payload, as printed before output was versioned.
+    V0,
+    /// Payload wrapped as `{"apiVersion": "v1", "data": ...}`.
+    V1,
+    /// Like v1, except that `pm suggest` lists each port with the type and
+    /// range it came from rather than bare numbers.
+    V2,
+}
+
+impl JsonVersion {
+    /// Returns the version label used in `apiVersion`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JsonVersion::V0 => "v0",
+            JsonVersion::V1 => "v1",
+            JsonVersion::V2 => "v2",
+        }
+    }
+}
+
+/// How `pm list` and `pm status` print ports (`-o`); `--json` is separate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A table, fitted to the terminal.
+    #[default]
+    Table,
+    /// One line per port (`webapp.web 8080 ACTIVE node(4242)`), for grep and awk.
+    Short,
+}
+
+/// Versioned wrapper around JSON output.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct JsonEnvelope<T> {
+    #[serde(rename = "apiVersion")]
+    pub api_version: &'static str,
+    pub data: T,
+}
+
+/// Prints `data` as pretty JSON in the requested format version.
+fn print_json<T: Serialize>(data: &T, version: JsonVersion) {
+    let json = match version {
+        JsonVersion::V0 => serde_json::to_string_pretty(data),
+        JsonVersion::V1 | JsonVersion::V2 => serde_json::to_string_pretty(&JsonEnvelope {
+            api_version: version.as_str(),
+            data,
+        }),
+    }
+    .expect("Failed to serialize to JSON");
+    println!("{json}");
+}
+
+/// Prints `data` as single-line JSON in the requested format version, for
+/// streams with one object per line.
+fn print_json_line<T: Serialize>(data: &T, version: JsonVersion) {
+    let json = match version {
+        JsonVersion::V0 => serde_json::to_string(data),
+        JsonVersion::V1 | JsonVersion::V2 => serde_json::to_string(&JsonEnvelope {
+            api_version: version.as_str(),
+            data,
+        }),
+    }
+    .expect("Failed to serialize to JSON");
+    println!("{json}");
+}
+
+/// Returns the JSON Schema for output of type `T` in the given format version.
+pub fn versioned_schema<T: JsonSchema>(version: JsonVersion) -> RootSchema {
+    match version {
+        JsonVersion::V0 => schema_for!(T),
+        JsonVersion::V1 | JsonVersion::V2 => schema_for!(JsonEnvelope<T>),
+    }
+}
+
 /// Status of an allocated port.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum PortStatus {
     /// Port is allocated but not currently listening.
     Idle,
     /// Port is allocated and currently listening.
     Active,
+    /// Port is listening, but the process belongs to a different project
+    /// (judged by the directory it runs in).
+    Conflict,
+    /// The allocation is on another host, which isn't checked.
+    Remote,
+}
+
+/// Whose allocations `pm list` shows (`--host`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HostFilter {
+    /// This machine's (the default, or `--host local`).
+    #[default]
+    Local,
+    /// Every host's (`--host all`).
+    All,
+    /// One host's.
+    Named(String),
+}
+
+impl HostFilter {
+    /// Parses `--host`: "all", "local", or a host name.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "all" => Ok(Self::All),
+            "local" => Ok(Self::Local),
+            _ => parse_host(s).map(Self::Named),
+        }
+    }
+
+    fn includes(&self, host: Option<&str>) -> bool {
+        match self {
+            Self::Local => host.is_none(),
+            Self::All => true,
+            Self::Named(name) => host == Some(name.as_str()),
+        }
+    }
+}
+
+/// Which allocations `pm list` shows. The default shows all of this
+/// machine's.
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    /// Only allocations with a listener (`--active`).
+    pub active: bool,
+    /// Only projects matching one of these globs; all when empty (`--project`).
+    pub projects: Vec<String>,
+    /// Only allocations with this group label (`--group`/`--tag`).
+    pub group: Option<String>,
+    /// Only allocations on the hosts this selects (`--host`).
+    pub hosts: HostFilter,
 }
 
 /// Information about an allocated port for display.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct AllocatedPortInfo {
     pub project: String,
     pub name: String,
     pub port: Port,
+    /// The host the allocation is on; absent for this machine's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
     pub status: PortStatus,
+    /// The first process listening on the port.
     pub pid: Option<i32>,
+    /// Every process listening on the port.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pids: Vec<i32>,
     #[serde(rename = "process")]
     pub process_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// Project the listening process appears to belong to, when it differs
+    /// from the registry owner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listener_project: Option<String>,
+    /// Pattern the listening process is expected to match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect_process: Option<String>,
+    /// Reserved by the machine-wide registry (read-only).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub system: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// URL from the allocation's URL template.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// When `pm gc` will free the allocation (RFC 3339).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_at: Option<String>,
 }
 
 /// Information about a listening port for JSON status output.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct StatusPortInfo {
     pub port: Port,
     pub project: Option<String>,
     pub name: Option<String>,
+    /// The `pm agent` that reported the port; absent for this machine's ports.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// The first process listening on the port.
     pub pid: Option<i32>,
+    /// Every process listening on the port.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pids: Vec<i32>,
     pub process: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cwd: Option<String>,
+    /// The user ID the process runs as (`--full` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    /// The name of the user the process runs as (`--full` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// The service conventionally on this port, for listeners without an allocation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    /// The process does not match the allocation's expected process pattern.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unexpected: bool,
+}
+
+/// Returns the STATUS cell for `status`, marked with a glyph when
+/// `status_glyphs` is set under `[display]`.
+fn status_cell(status: PortStatus) -> Cell {
+    let (label, tone, glyph, ascii_glyph) = match status {
+        PortStatus::Active => ("ACTIVE", Tone::Good, "●", "+"),
+        PortStatus::Idle => ("IDLE", Tone::Muted, "○", "-"),
+        PortStatus::Conflict => ("CONFLICT", Tone::Bad, "✗", "!"),
+        PortStatus::Remote => ("REMOTE", Tone::Muted, "◌", "~"),
+    };
+    let config = theme::current();
+    let cell = match (config.status_glyphs, config.borders) {
+        (false, _) => Cell::new(label),
+        (true, Borders::Unicode) => Cell::new(format!("{glyph} {label}")),
+        (true, Borders::Ascii) => Cell::new(format!("{ascii_glyph} {label}")),
+    };
+    paint(cell, tone)
 }
 
 /// Displays the allocated ports table.
-pub fn display_allocated_ports(ports: &[AllocatedPortInfo]) {
+pub fn display_allocated_ports(ports: &[AllocatedPortInfo], long: bool) {
     if ports.is_empty() {
         println!("No ports allocated.");
         return;
     }
 
+    pager::start();
     let mut table = create_table();
-    table.set_header(vec!["PROJECT", "NAME", "PORT", "STATUS", "PID", "PROCESS"]);
+    let mut header = vec!["PROJECT", "NAME", "PORT", "STATUS", "PID", "PROCESS"];
+    // Only shown once another host's allocations are listed (`--host`)
+    let show_host = ports.iter().any(|port| port.host.is_some());
+    if show_host {
+        header.insert(2, "HOST");
+    }
+    if long {
+        header.extend(["NOTE", "URL", "FREES AT"]);
+    }
+    table.set_header(header);
 
     for port in ports {
-        let status_cell = match port.status {
-            PortStatus::Active => Cell::new("ACTIVE").fg(Color::Green),
-            PortStatus::Idle => Cell::new("IDLE").fg(Color::DarkGrey),
-        };
+        let status_cell = status_cell(port.status);
 
         let pid_str = port
             .pid
@@ -88,19 +374,239 @@ pub fn display_allocated_ports(ports: &[AllocatedPortInfo]) {
             .clone()
             .unwrap_or_else(|| "---".to_string());
 
-        table.add_row(vec![
-            Cell::new(&port.project),
+        let project_cell = if port.system {
+            paint(Cell::new(format!("{} (system)", port.project)), Tone::Muted)
+        } else {
+            Cell::new(&port.project)
+        };
+
+        let mut row = vec![
+            project_cell,
             Cell::new(&port.name),
             Cell::new(port.port),
             status_cell,
             Cell::new(&pid_str),
             Cell::new(&process_str),
-        ]);
+        ];
+        if show_host {
+            row.insert(2, Cell::new(port.host.as_deref().unwrap_or("local")));
+        }
+        if long {
+            row.push(Cell::new(port.note.as_deref().unwrap_or("---")));
+            row.push(Cell::new(port.url.as_deref().unwrap_or("---")));
+            let frees_at = port
+                .free_at
+                .as_deref()
+                .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+                .map_or_else(|| "---".to_string(), format_local_time);
+            row.push(Cell::new(frees_at));
+        }
+        table.add_row(row);
+    }
+
+    print_table(table);
+}
+
+/// Formats a listener's process as `name(pid)` for short output, or `-` when
+/// nothing is known about it.
+fn short_process(process: Option<&str>, pid: Option<i32>) -> String {
+    match (process, pid) {
+        (Some(process), Some(pid)) => format!("{process}({pid})"),
+        (Some(process), None) => process.to_string(),
+        (None, Some(pid)) => format!("-({pid})"),
+        (None, None) => "-".to_string(),
+    }
+}
+
+/// Prints lines for `-o short`, stopping quietly once the reader goes away
+/// (e.g., `pm list -o short | head -1`).
+fn print_lines(lines: impl Iterator<Item = String>) {
+    let mut out = std::io::stdout().lock();
+    for line in lines {
+        if writeln!(out, "{line}").is_err() {
+            return;
+        }
     }
+}
+
+/// Prints allocated ports one per line (`-o short`):
+/// `project.name [host:]port STATUS process(pid)`.
+pub fn display_allocated_ports_short(ports: &[AllocatedPortInfo]) {
+    print_lines(ports.iter().map(|port| {
+        let status = match port.status {
+            PortStatus::Active => "ACTIVE",
+            PortStatus::Idle => "IDLE",
+            PortStatus::Conflict => "CONFLICT",
+            PortStatus::Remote => "REMOTE",
+        };
+        let port_str = match &port.host {
+            Some(host) => format!("{host}:{}", port.port),
+            None => port.port.to_string(),
+        };
+        format!(
+            "{}.{} {port_str} {status} {}",
+            port.project,
+            port.name,
+            short_process(port.process_name.as_deref(), port.pid)
+        )
+    }));
+}
 
+/// Prints listening ports one per line (`-o short`):
+/// `project.name port process(pid)`, with `-` for unassigned listeners.
+pub fn display_status_short(ports: &[StatusPortInfo]) {
+    print_lines(ports.iter().map(|port| {
+        let owner = match (&port.project, &port.name) {
+            (Some(project), Some(name)) => format!("{project}.{name}"),
+            _ => "-".to_string(),
+        };
+        let port_str = match &port.host {
+            Some(host) => format!("{host}:{}", port.port),
+            None => port.port.to_string(),
+        };
+        format!(
+            "{owner} {port_str} {}",
+            short_process(port.process.as_deref(), port.pid)
+        )
+    }));
+}
+
+/// Formats a timestamp in local time for tables and messages.
+pub fn format_local_time(at: DateTime<FixedOffset>) -> String {
+    at.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Formats a duration in seconds by its two largest units (e.g., "2h 5m").
+fn format_lifetime(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{secs}s"),
+        (0, 0, minutes) => format!("{minutes}m"),
+        (0, hours, minutes) => format!("{hours}h {minutes}m"),
+        (days, hours, _) => format!("{days}d {hours}h"),
+    }
+}
+
+/// Formats an RFC 3339 time from a report in local time.
+fn format_report_time(at: &str) -> String {
+    DateTime::parse_from_rfc3339(at).map_or_else(|_| at.to_string(), format_local_time)
+}
+
+/// Displays the usage log's statistics.
+pub fn display_usage(report: &UsageReport) {
+    let Some(since) = &report.since else {
+        println!("No allocation changes recorded yet");
+        return;
+    };
+
+    println!(
+        "Since {}: {} allocated, {} freed, {} moved",
+        format_report_time(since),
+        report.allocated,
+        report.freed,
+        report.moved
+    );
+    if let Some(secs) = report.average_lifetime_secs {
+        println!(
+            "Freed allocations lasted {} on average",
+            format_lifetime(secs)
+        );
+    }
+    match &report.peak_at {
+        Some(at) => println!(
+            "At most {} ports were allocated at once ({})",
+            report.peak,
+            format_report_time(at)
+        ),
+        None => println!("At most {} ports were allocated at once", report.peak),
+    }
+    println!();
+
+    pager::start();
+    let mut table = create_table();
+    table.set_header(vec![
+        "PROJECT",
+        "ALLOCATED",
+        "FREED",
+        "MOVED",
+        "AVG LIFETIME",
+        "PEAK",
+    ]);
+    for usage in &report.projects {
+        table.add_row(vec![
+            Cell::new(&usage.project),
+            Cell::new(usage.allocated),
+            Cell::new(usage.freed),
+            Cell::new(usage.moved),
+            Cell::new(
+                usage
+                    .average_lifetime_secs
+                    .map_or_else(|| "---".to_string(), format_lifetime),
+            ),
+            Cell::new(usage.peak),
+        ]);
+    }
     println!("{table}");
 }
 
+/// Displays the usage log's statistics as JSON.
+pub fn display_usage_json(report: &UsageReport, version: JsonVersion) {
+    print_json(report, version);
+}
+
+/// Displays what `pm gc` freed (or, for a dry run, would free) and why, and
+/// how many remembered ports of freed names it forgot.
+pub fn display_gc_report(collected: &[Collected], forgotten: usize, dry_run: bool) {
+    if forgotten > 0 {
+        let verb = if dry_run { "Would forget" } else { "Forgot" };
+        println!(
+            "{verb} the previous ports of {forgotten} name(s) freed over {HISTORY_DAYS} days ago"
+        );
+    }
+    if collected.is_empty() {
+        println!("Nothing to free");
+        return;
+    }
+
+    let verb = if dry_run { "Would free" } else { "Freed" };
+    let (mut scheduled, mut exited, mut stale) = (0, 0, 0);
+    for c in collected {
+        let why = match c.reason {
+            Reason::Scheduled(at) => {
+                scheduled += 1;
+                format!("scheduled for {}", format_local_time(at))
+            }
+            Reason::ProcessExited(pid) => {
+                exited += 1;
+                format!("process {pid} exited")
+            }
+            Reason::Stale(since) => {
+                stale += 1;
+                format!("idle since {}", format_local_time(since))
+            }
+        };
+        println!("{verb} {}.{} (was {}, {why})", c.project, c.name, c.port);
+    }
+
+    let counts: Vec<String> = [
+        (scheduled, "scheduled"),
+        (exited, "process exited"),
+        (stale, "stale"),
+    ]
+    .iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, what)| format!("{n} {what}"))
+    .collect();
+    let noun = if collected.len() == 1 {
+        "allocation"
+    } else {
+        "allocations"
+    };
+    println!("{verb} {} {noun}: {}", collected.len(), counts.join(", "));
+}
+
 /// Displays the status table (all listening ports).
 pub fn display_status(listening: &[ListeningPort], registry: &Registry, full: bool) {
     if listening.is_empty() {
@@ -108,60 +614,106 @@ pub fn display_status(listening: &[ListeningPort], registry: &Registry, full: bo
         return;
     }
 
+    pager::start();
     let mut table = create_table();
+    // Only combined views (`pm status --agent`) say which machine a port is on
+    let show_host = listening.iter().any(|lp| lp.host.is_some());
+    let mut header = Vec::new();
+    if show_host {
+        header.push("HOST");
+    }
     if full {
-        table.set_header(vec![
+        header.extend([
             "PORT",
             "PROJECT",
             "NAME",
             "PID",
+            "USER",
             "PROCESS",
             "DIRECTORY",
         ]);
     } else {
-        table.set_header(vec!["PORT", "PROJECT", "NAME", "PID", "PROCESS"]);
+        header.extend(["PORT", "PROJECT", "NAME", "PID", "PROCESS"]);
     }
+    table.set_header(header);
 
-    for lp in listening {
-        let (project, name) = registry
-            .find_port_owner(lp.port)
-            .map(|(p, n)| (p.to_string(), n.to_string()))
-            .unwrap_or_else(|| ("---".to_string(), "---".to_string()));
+    // A port shared by several processes is one row listing all of them
+    for group in group_by_port(listening) {
+        let lp = &group[0];
+        // Unassigned listeners are named after the port's usual service
+        let (project, name_cell) = match registry.find_port_owner_on(lp.host.as_deref(), lp.port) {
+            Some((p, n)) => (p.to_string(), Cell::new(n)),
+            None => match service_name(lp.port) {
+                Some(service) => ("---".to_string(), paint(Cell::new(service), Tone::Muted)),
+                None => ("---".to_string(), Cell::new("---")),
+            },
+        };
 
-        let pid_str = lp
-            .pid
-            .map(|p| p.to_string())
-            .unwrap_or_else(|| "---".to_string());
+        let pid_str = listener_cell(group.iter().map(|lp| lp.pid.map(|p| p.to_string())));
+        let process_str = listener_cell(group.iter().map(|lp| lp.process_name.clone()));
+        // Highlight processes squatting on a port reserved for something else
+        let process_cell = if group.iter().any(|lp| is_unexpected_listener(registry, lp)) {
+            paint(Cell::new(&process_str), Tone::Bad)
+        } else {
+            Cell::new(&process_str)
+        };
 
-        let process_str = lp.process_name.clone().unwrap_or_else(|| "---".to_string());
+        let mut row = Vec::new();
+        if show_host {
+            row.push(Cell::new(lp.host.as_deref().unwrap_or("local")));
+        }
 
         if full {
-            let cwd_str = lp
-                .process_cwd
-                .as_ref()
-                .map(|p| p.display().to_string())
-                .unwrap_or_else(|| "---".to_string());
+            let cwd_str = listener_cell(
+                group
+                    .iter()
+                    .map(|lp| lp.process_cwd.as_ref().map(|p| p.display().to_string())),
+            );
+            // Fall back to the bare UID when it has no user database entry
+            let user_str = listener_cell(group.iter().map(|lp| {
+                lp.user
+                    .clone()
+                    .or_else(|| lp.uid.map(|uid| uid.to_string()))
+            }));
 
-            table.add_row(vec![
+            row.extend([
                 Cell::new(lp.port),
                 Cell::new(&project),
-                Cell::new(&name),
+                name_cell,
                 Cell::new(&pid_str),
-                Cell::new(&process_str),
+                Cell::new(&user_str),
+                process_cell,
                 Cell::new(&cwd_str),
             ]);
         } else {
-            table.add_row(vec![
+            row.extend([
                 Cell::new(lp.port),
                 Cell::new(&project),
-                Cell::new(&name),
+                name_cell,
                 Cell::new(&pid_str),
-                Cell::new(&process_str),
+                process_cell,
             ]);
         }
+        table.add_row(row);
     }
 
-    println!("{table}");
+    print_table(table);
+}
+
+/// Joins the distinct values a port's listeners have for one cell, or
+/// "---" when none is known.
+fn listener_cell(values: impl Iterator<Item = Option<String>>) -> String {
+    let mut distinct: Vec<String> = Vec::new();
+    for value in values.flatten() {
+        if !distinct.contains(&value) {
+            distinct.push(value);
+        }
+    }
+    if distinct.is_empty() {
+        "---".to_string()
+    } else {
+        distinct.join(", ")
+    }
 }
 
 /// Displays suggested ports.
@@ -180,6 +732,51 @@ pub fn display_suggestions(ports: &[Port], port_type: &str) {
     }
 }
 
+/// Displays every port a suggestion looked at and why it was skipped
+/// (`pm suggest --explain`). Suggested ports in the OS ephemeral range are
+/// flagged, since outgoing connections may take them briefly.
+pub fn display_suggestion_report(report: &SuggestionReport, ephemeral: [u16; 2]) {
+    pager::start();
+    println!(
+        "Suggesting from the '{}' range {}",
+        report.range_type, report.range
+    );
+    println!();
+
+    let mut table = create_table();
+    table.set_header(vec!["PORT", "RESULT", "WHY"]);
+    for candidate in &report.candidates {
+        let info = CandidateInfo::new(candidate.port, &candidate.verdict, ephemeral);
+        let (result, tone) = if info.suggested {
+            ("suggested", Tone::Good)
+        } else {
+            ("skipped", Tone::Muted)
+        };
+        let why = match &candidate.verdict {
+            Verdict::Suggested if info.ephemeral => format!(
+                "in the ephemeral range {}-{}; outgoing connections may take it briefly",
+                ephemeral[0], ephemeral[1]
+            ),
+            Verdict::Suggested => String::new(),
+            Verdict::Allocated { owner } => format!("allocated to {owner}"),
+            Verdict::Active { pid, process } => {
+                let process = process.as_deref().unwrap_or("unknown process");
+                match pid {
+                    Some(pid) => format!("in use by {process} (PID {pid})"),
+                    None => format!("in use by {process}"),
+                }
+            }
+            Verdict::Excluded => "excluded by the range".to_string(),
+        };
+        table.add_row(vec![
+            Cell::new(candidate.port),
+            paint(Cell::new(result), tone),
+            Cell::new(why),
+        ]);
+    }
+    print_table(table);
+}
+
 /// Displays query output for scripting.
 pub fn display_query(ports: &[(String, Port)], single_value: bool) {
     if single_value && ports.len() == 1 {
@@ -192,59 +789,336 @@ pub fn display_query(ports: &[(String, Port)], single_value: bool) {
     }
 }
 
+/// Displays a compact one-line summary for shell prompts (e.g., `web:8080✓ api:3000✗`).
+pub fn display_prompt(entries: &[(String, Port, bool)]) {
+    if entries.is_empty() {
+        return;
+    }
+
+    let segments: Vec<String> = entries
+        .iter()
+        .map(|(name, port, active)| {
+            let mark = if *active { '✓' } else { '✗' };
+            format!("{name}:{port}{mark}")
+        })
+        .collect();
+    println!("{}", segments.join(" "));
+}
+
+/// Displays a project's ports as a tmux status line segment, active ports in
+/// green. `plain` drops the tmux style codes and marks ports like
+/// [`display_prompt`] instead.
+pub fn display_tmux_segment(project: &str, entries: &[(String, Port, bool)], plain: bool) {
+    if entries.is_empty() {
+        return;
+    }
+
+    let segments: Vec<String> = entries
+        .iter()
+        .map(|(name, port, active)| match (plain, active) {
+            (true, true) => format!("{name}:{port}✓"),
+            (true, false) => format!("{name}:{port}✗"),
+            (false, true) => format!("#[fg=green]{name}:{port}#[default]"),
+            (false, false) => format!("#[dim]{name}:{port}#[default]"),
+        })
+        .collect();
+    println!("{project} {}", segments.join(" "));
+}
+
+/// Displays the ranges a preset added and the existing ones it left alone.
+pub fn display_preset_changes(changes: &[PresetChange]) {
+    if changes.is_empty() {
+        println!("All preset ranges are already set");
+        return;
+    }
+    for change in changes {
+        match change {
+            PresetChange::Added(name, [start, end]) => {
+                println!("Added {name} range {start}-{end}")
+            }
+            PresetChange::Kept(name, range) => {
+                println!("Kept existing {name} range {range}")
+            }
+            PresetChange::Overlaps(name, [start, end], other) => {
+                println!("Skipped {name} range {start}-{end}: it overlaps the {other} range")
+            }
+        }
+    }
+}
+
+/// A profile, for `pm profile list`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ProfileInfo {
+    pub name: String,
+    /// The profile's registry file.
+    pub path: String,
+    /// Whether this invocation uses the profile.
+    pub active: bool,
+}
+
+/// Displays profiles, marking the one in use.
+pub fn display_profiles(profiles: &[ProfileInfo]) {
+    let width = profiles.iter().map(|p| p.name.len()).max().unwrap_or(0);
+    for profile in profiles {
+        let marker = if profile.active { '*' } else { ' ' };
+        println!("{marker} {:width$}  {}", profile.name, profile.path);
+    }
+}
+
+/// Displays profiles as JSON.
+pub fn display_profiles_json(profiles: &[ProfileInfo], version: JsonVersion) {
+    print_json(&profiles, version);
+}
+
 /// Displays configuration information.
 pub fn display_config(registry: &Registry, path: Option<&std::path::Path>) {
+    pager::start();
     if let Some(p) = path {
         println!("Config file: {}", p.display());
         println!();
     }
 
+    if let Some(system) = &registry.system {
+        println!("System registry: {}", system.path.display());
+        println!();
+    }
+
+    if registry.defaults.ignore_case {
+        println!("Names are matched ignoring case");
+        println!();
+    }
+
+    if let Some(days) = registry.defaults.stale_after_days {
+        println!("pm gc frees allocations idle for {days} days");
+        println!();
+    }
+
+    if registry.defaults.git_sync {
+        println!("Registry changes are committed to git (pm sync)");
+        println!();
+    }
+
+    if registry.defaults.strict_detection {
+        println!("Commands fail when listening ports can't be detected");
+        println!();
+    }
+
+    let type_rules = registry.type_rules();
+    if !type_rules.is_empty() {
+        println!("Type rules (first match wins):");
+        for rule in type_rules {
+            println!("  {} -> {}", rule.pattern(), rule.type_name);
+        }
+        println!();
+    }
+
+    let quotas = registry.quotas();
+    if !quotas.is_empty() {
+        println!("Quotas (most ports allocated):");
+        if let Some(limit) = quotas.per_project {
+            println!("  per project: {limit}");
+        }
+        if let Some(limit) = quotas.per_user {
+            println!("  per user: {limit}");
+        }
+        for (range_type, limit) in &quotas.ranges {
+            println!("  {range_type} range: {limit}");
+        }
+        println!();
+    }
+
+    let display = registry.display;
+    if display != DisplayConfig::default() {
+        let theme = match display.theme {
+            Theme::Default => "default colors",
+            Theme::Colorblind => "colorblind colors",
+            Theme::None => "no colors",
+        };
+        let borders = match display.borders {
+            Borders::Unicode => "unicode borders",
+            Borders::Ascii => "ascii borders",
+        };
+        let glyphs = if display.status_glyphs {
+            ", and status glyphs"
+        } else {
+            ""
+        };
+        println!("Tables use {theme}, {borders}{glyphs}");
+        println!();
+    }
+
     println!("Default port ranges:");
     let mut table = create_table();
     table.set_header(vec!["TYPE", "RANGE"]);
 
-    for (name, range) in &registry.defaults.ranges {
-        table.add_row(vec![
-            Cell::new(name),
-            Cell::new(format!("{}-{}", range[0], range[1])),
-        ]);
+    for (name, range) in registry.ranges() {
+        table.add_row(vec![Cell::new(name), Cell::new(range.to_string())]);
     }
 
-    println!("{table}");
+    print_table(table);
+}
+
+/// Checks a listener against an allocation's `expect_process` pattern,
+/// trying the command line and then the process name.
+///
+/// Returns `None` when there is no pattern or nothing is known about the process.
+fn matches_expected_process(meta: &PortMeta, listening: &ListeningPort) -> Option<bool> {
+    let pattern = Regex::new(meta.expect_process.as_deref()?).ok()?;
+    let candidates: Vec<&str> = [&listening.process_command, &listening.process_name]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    Some(candidates.iter().any(|c| pattern.is_match(c)))
+}
+
+/// Returns true if `listening` is on an allocated port whose `expect_process`
+/// pattern it does not match.
+fn is_unexpected_listener(registry: &Registry, listening: &ListeningPort) -> bool {
+    let Some((project, name)) =
+        registry.find_port_owner_on(listening.host.as_deref(), listening.port)
+    else {
+        return false;
+    };
+    let meta = &registry.projects[project].ports[name].meta;
+    matches_expected_process(meta, listening) == Some(false)
+}
+
+/// Infers which project a listening process belongs to from its working
+/// directory, using the same links and repository names as `pm query .`.
+/// Answers are kept in `known` by directory, so processes sharing one don't
+/// each read its repository's `.pm.toml`.
+fn listener_project(
+    registry: &Registry,
+    listening: &ListeningPort,
+    known: &mut HashMap<PathBuf, Option<String>>,
+) -> Option<String> {
+    let cwd = listening.process_cwd.as_deref()?;
+    known
+        .entry(cwd.to_path_buf())
+        .or_insert_with(|| infer_project(registry, cwd).ok().flatten())
+        .clone()
 }
 
 /// Builds the list of allocated ports with their status.
+///
+/// Only allocations `filter` selects are included. `listening` is this
+/// machine's listeners; other hosts' allocations are [`PortStatus::Remote`].
 pub fn build_allocated_port_list(
     registry: &Registry,
     listening: &[ListeningPort],
-    filter_active: bool,
+    filter: &ListFilter,
 ) -> Vec<AllocatedPortInfo> {
-    let listening_map: HashMap<Port, &ListeningPort> =
-        listening.iter().map(|lp| (lp.port, lp)).collect();
+    let listening_map: HashMap<Port, &[ListeningPort]> = group_by_port(listening)
+        .into_iter()
+        .map(|group| (group[0].port, group))
+        .collect();
 
     let mut result = Vec::new();
+    let mut known_dirs = HashMap::new();
 
-    for (project_name, project) in &registry.projects {
-        for (port_name, &port) in &project.ports {
-            let (status, pid, process_name) = if let Some(lp) = listening_map.get(&port) {
-                (PortStatus::Active, lp.pid, lp.process_name.clone())
-            } else {
-                (PortStatus::Idle, None, None)
-            };
-
-            if filter_active && status != PortStatus::Active {
-                continue;
-            }
+    let ignore_case = registry.defaults.ignore_case;
+    let user_allocations = registry
+        .projects
+        .iter()
+        .flat_map(|(project_name, project)| {
+            project.ports.iter().map(move |(port_name, allocation)| {
+                let aliases = project
+                    .aliases_for(port_name)
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                (project_name, port_name, allocation, aliases, false)
+            })
+        });
+    let system_allocations =
+        registry
+            .system_allocations()
+            .into_iter()
+            .map(|(project_name, port_name, allocation)| {
+                (project_name, port_name, allocation, Vec::new(), true)
+            });
+    let allocations = user_allocations
+        .chain(system_allocations)
+        .filter(|(_, _, allocation, ..)| {
+            filter.hosts.includes(allocation.meta.host.as_deref())
+                && (filter.group.is_none() || allocation.meta.group == filter.group)
+        })
+        .filter(|(project_name, ..)| {
+            filter.projects.is_empty()
+                || filter
+                    .projects
+                    .iter()
+                    .any(|glob| glob_match(glob, project_name, ignore_case))
+        });
 
-            result.push(AllocatedPortInfo {
-                project: project_name.clone(),
-                name: port_name.clone(),
-                port,
-                status,
-                pid,
-                process_name,
+    for (project_name, port_name, allocation, aliases, system) in allocations {
+        let port = allocation.port;
+        let host = allocation.meta.host.clone();
+        let group = match host {
+            Some(_) => &[],
+            None => listening_map.get(&port).copied().unwrap_or_default(),
+        };
+        let listener = group.first();
+        let (status, pid, process_name) = if let Some(lp) = listener {
+            (PortStatus::Active, lp.pid, lp.process_name.clone())
+        } else {
+            (PortStatus::Idle, None, None)
+        };
+        // An explicit process pattern is more reliable than the directory
+        // guess; every process sharing the port has to match it
+        let expected = group
+            .iter()
+            .filter_map(|lp| matches_expected_process(&allocation.meta, lp))
+            .reduce(|a, b| a && b);
+        let listener_project = listener
+            .filter(|_| expected.is_none())
+            .and_then(|lp| listener_project(registry, lp, &mut known_dirs))
+            .filter(|owner| {
+                if ignore_case {
+                    !owner.eq_ignore_ascii_case(project_name)
+                } else {
+                    owner != project_name.as_str()
+                }
             });
+        let status = if host.is_some() {
+            PortStatus::Remote
+        } else if expected == Some(false) || listener_project.is_some() {
+            PortStatus::Conflict
+        } else {
+            status
+        };
+
+        if filter.active && status == PortStatus::Idle {
+            continue;
         }
+
+        result.push(AllocatedPortInfo {
+            project: project_name.to_string(),
+            name: port_name.to_string(),
+            port,
+            host,
+            status,
+            pid,
+            pids: all_pids(group),
+            process_name,
+            group: allocation.meta.group.clone(),
+            aliases,
+            locked: allocation.meta.locked,
+            system,
+            listener_project,
+            expect_process: allocation.meta.expect_process.clone(),
+            note: allocation.meta.note.clone(),
+            url: allocation
+                .meta
+                .url_template
+                .as_ref()
+                .map(|_| allocation.meta.url(port)),
+            free_at: allocation.meta.free_at.map(|at| at.to_rfc3339()),
+        });
     }
 
     // Sort by project, then by name
@@ -259,91 +1133,173 @@ pub fn build_status_port_list(
     registry: &Registry,
     full: bool,
 ) -> Vec<StatusPortInfo> {
-    listening
-        .iter()
-        .map(|lp| {
+    group_by_port(listening)
+        .into_iter()
+        .map(|group| {
+            let lp = &group[0];
             let (project, name) = registry
-                .find_port_owner(lp.port)
+                .find_port_owner_on(lp.host.as_deref(), lp.port)
                 .map(|(p, n)| (Some(p.to_string()), Some(n.to_string())))
                 .unwrap_or((None, None));
 
-            let cwd = if full {
-                lp.process_cwd.as_ref().map(|p| p.display().to_string())
+            let (cwd, uid, user) = if full {
+                (
+                    lp.process_cwd.as_ref().map(|p| p.display().to_string()),
+                    lp.uid,
+                    lp.user.clone(),
+                )
             } else {
-                None
+                (None, None, None)
+            };
+            let service = match project {
+                Some(_) => None,
+                None => service_name(lp.port).map(String::from),
             };
 
             StatusPortInfo {
                 port: lp.port,
                 project,
                 name,
+                host: lp.host.clone(),
                 pid: lp.pid,
+                pids: all_pids(group),
                 process: lp.process_name.clone(),
                 cwd,
+                uid,
+                user,
+                service,
+                unexpected: group.iter().any(|lp| is_unexpected_listener(registry, lp)),
             }
         })
         .collect()
 }
 
+/// Returns the PIDs of a port's listeners.
+fn all_pids(group: &[ListeningPort]) -> Vec<i32> {
+    group.iter().filter_map(|lp| lp.pid).collect()
+}
+
 /// Displays allocated ports as JSON.
-pub fn display_allocated_ports_json(ports: &[AllocatedPortInfo]) {
-    let json = serde_json::to_string_pretty(ports).expect("Failed to serialize to JSON");
-    println!("{json}");
+pub fn display_allocated_ports_json(ports: &[AllocatedPortInfo], version: JsonVersion) {
+    print_json(&ports, version);
 }
 
 /// Displays status (listening ports) as JSON.
-pub fn display_status_json(ports: &[StatusPortInfo]) {
-    let json = serde_json::to_string_pretty(ports).expect("Failed to serialize to JSON");
-    println!("{json}");
+pub fn display_status_json(ports: &[StatusPortInfo], version: JsonVersion) {
+    print_json(&ports, version);
+}
+
+/// Displays a `pm snapshot` capture, always in the v1 envelope.
+pub fn display_snapshot_json(snapshot: &Snapshot) {
+    print_json(snapshot, JsonVersion::V1);
 }
 
 /// Configuration info for JSON output.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct ConfigInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub config_file: Option<String>,
+    /// Whether project and port names are matched ignoring case.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_case: bool,
+    /// Machine-wide registry layered underneath, when one was loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_registry: Option<String>,
+    /// Days without a listener after which `pm gc` frees an allocation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale_after_days: Option<u32>,
+    /// Whether registry changes are committed to git.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub git_sync: bool,
+    /// Whether commands fail when listening ports can't be detected.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict_detection: bool,
+    /// Rules picking range types from port names, in the order they are tried.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub type_rules: Vec<TypeRule>,
+    /// Allocation limits in effect, from this and the system registry.
+    #[serde(default, skip_serializing_if = "Quotas::is_empty")]
+    pub quotas: Quotas,
     pub ranges: Vec<RangeInfo>,
 }
 
 /// Port range info for JSON output.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct RangeInfo {
     pub name: String,
+    /// Lowest port of the range.
     pub start: u16,
+    /// Highest port of the range.
     pub end: u16,
+    /// The range's segments, when it has more than one.
+    #[serde(skip_serializing_if = "has_one_segment")]
+    pub segments: Vec<[u16; 2]>,
+    /// How automatic allocation picks ports from the range.
+    #[serde(skip_serializing_if = "is_default_policy")]
+    pub policy: AllocationPolicy,
+    /// Ports inside the range that are never handed out.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<u16>,
+    /// Project globs the range is reserved for.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub projects: Vec<String>,
+}
+
+fn is_default_policy(policy: &AllocationPolicy) -> bool {
+    *policy == AllocationPolicy::default()
+}
+
+fn has_one_segment(segments: &[[u16; 2]]) -> bool {
+    segments.len() == 1
 }
 
 /// Displays configuration as JSON.
-pub fn display_config_json(registry: &Registry, path: Option<&std::path::Path>) {
+pub fn display_config_json(
+    registry: &Registry,
+    path: Option<&std::path::Path>,
+    version: JsonVersion,
+) {
     let ranges: Vec<RangeInfo> = registry
-        .defaults
-        .ranges
-        .iter()
+        .ranges()
+        .into_iter()
         .map(|(name, range)| RangeInfo {
-            name: name.clone(),
-            start: range[0],
-            end: range[1],
+            name: name.to_string(),
+            start: range.start(),
+            end: range.end(),
+            segments: range.segments,
+            policy: range.policy,
+            exclude: range.exclude,
+            projects: range.projects,
         })
         .collect();
 
     let config = ConfigInfo {
         config_file: path.map(|p| p.display().to_string()),
+        ignore_case: registry.defaults.ignore_case,
+        stale_after_days: registry.defaults.stale_after_days,
+        git_sync: registry.defaults.git_sync,
+        strict_detection: registry.defaults.strict_detection,
+        type_rules: registry.type_rules().into_iter().cloned().collect(),
+        quotas: registry.quotas(),
+        system_registry: registry
+            .system
+            .as_ref()
+            .map(|s| s.path.display().to_string()),
         ranges,
     };
 
-    let json = serde_json::to_string_pretty(&config).expect("Failed to serialize to JSON");
-    println!("{json}");
+    print_json(&config, version);
 }
 
 /// Query result for JSON output.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct QueryResult {
     pub name: String,
     pub port: Port,
 }
 
 /// Displays query results as JSON.
-pub fn display_query_json(ports: &[(String, Port)]) {
+pub fn display_query_json(ports: &[(String, Port)], version: JsonVersion) {
     let results: Vec<QueryResult> = ports
         .iter()
         .map(|(name, port)| QueryResult {
@@ -352,12 +1308,667 @@ pub fn display_query_json(ports: &[(String, Port)]) {
         })
         .collect();
 
-    let json = serde_json::to_string_pretty(&results).expect("Failed to serialize to JSON");
-    println!("{json}");
+    print_json(&results, version);
 }
 
 /// Displays suggested ports as JSON.
-pub fn display_suggestions_json(ports: &[Port]) {
-    let json = serde_json::to_string_pretty(ports).expect("Failed to serialize to JSON");
+/// A suggested port and the range it came from, for JSON output (v2).
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SuggestedPort {
+    pub port: Port,
+    /// The type whose range was used: the one asked for, or "default" when
+    /// it has no range or its range is reserved for other projects.
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// First and last port of the range.
+    pub range: [u16; 2],
+}
+
+/// Displays suggestions as JSON: bare port numbers before v2, and objects
+/// naming each port's range from v2 on.
+pub fn display_suggestions_json(report: &SuggestionReport, version: JsonVersion) {
+    if version != JsonVersion::V2 {
+        let ports: Vec<Port> = report.suggestions().collect();
+        print_json(&ports, version);
+        return;
+    }
+    let ports: Vec<SuggestedPort> = report
+        .suggestions()
+        .map(|port| SuggestedPort {
+            port,
+            type_name: report.range_type.clone(),
+            range: [report.range.start(), report.range.end()],
+        })
+        .collect();
+    print_json(&ports, version);
+}
+
+/// Why a suggestion skipped a port, for JSON output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SkipReason {
+    /// Allocated in the registry.
+    Allocated,
+    /// Listening on the system.
+    Active,
+    /// Excluded by the range.
+    Excluded,
+}
+
+/// A port a suggestion looked at, for JSON output.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CandidateInfo {
+    pub port: Port,
+    pub suggested: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<SkipReason>,
+    /// The allocation holding the port ("project.name").
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+    /// The port is in the OS ephemeral range, where outgoing connections
+    /// may take it briefly.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub ephemeral: bool,
+}
+
+impl CandidateInfo {
+    fn new(port: Port, verdict: &Verdict, ephemeral: [u16; 2]) -> Self {
+        let mut info = CandidateInfo {
+            port,
+            suggested: false,
+            reason: None,
+            owner: None,
+            pid: None,
+            process: None,
+            ephemeral: (ephemeral[0]..=ephemeral[1]).contains(&port.as_u16()),
+        };
+        match verdict {
+            Verdict::Suggested => info.suggested = true,
+            Verdict::Allocated { owner } => {
+                info.reason = Some(SkipReason::Allocated);
+                info.owner = Some(owner.clone());
+            }
+            Verdict::Active { pid, process } => {
+                info.reason = Some(SkipReason::Active);
+                info.pid = *pid;
+                info.process = process.clone();
+            }
+            Verdict::Excluded => info.reason = Some(SkipReason::Excluded),
+        }
+        info
+    }
+}
+
+/// `pm suggest --explain` output for JSON.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SuggestionExplanation {
+    /// The type whose range was used: the one asked for, or "default".
+    #[serde(rename = "type")]
+    pub range_type: String,
+    pub range: String,
+    /// Every port looked at, in order.
+    pub candidates: Vec<CandidateInfo>,
+}
+
+/// Displays `pm suggest --explain` output as JSON.
+pub fn display_suggestion_report_json(
+    report: &SuggestionReport,
+    ephemeral: [u16; 2],
+    version: JsonVersion,
+) {
+    let explanation = SuggestionExplanation {
+        range_type: report.range_type.clone(),
+        range: report.range.to_string(),
+        candidates: report
+            .candidates
+            .iter()
+            .map(|c| CandidateInfo::new(c.port, &c.verdict, ephemeral))
+            .collect(),
+    };
+    print_json(&explanation, version);
+}
+
+/// Displays a registry change as `+`/`-`/`~` lines.
+pub fn display_registry_change(change: &RegistryChange) {
+    for entry in &change.added {
+        println!("+ {}.{} = {}", entry.project, entry.name, entry.port);
+    }
+    for entry in &change.removed {
+        println!("- {}.{} = {}", entry.project, entry.name, entry.port);
+    }
+    for entry in &change.changed {
+        println!(
+            "~ {}.{}: {} -> {}",
+            entry.project, entry.name, entry.old, entry.new
+        );
+    }
+}
+
+/// Displays a registry change as one line of JSON.
+pub fn display_registry_change_json(change: &RegistryChange, version: JsonVersion) {
+    print_json_line(change, version);
+}
+
+/// Displays all allocations as `project.name=port` lines for scripting.
+pub fn display_query_all(ports: &[(String, String, Port)]) {
+    for (project, name, port) in ports {
+        println!("{project}.{name}={port}");
+    }
+}
+
+/// Query result across projects for JSON output.
+#[derive(Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct ProjectQueryResult {
+    pub project: String,
+    pub name: String,
+    pub port: Port,
+}
+
+/// Displays all allocations as JSON.
+pub fn display_query_all_json(ports: &[(String, String, Port)], version: JsonVersion) {
+    let results: Vec<ProjectQueryResult> = ports
+        .iter()
+        .map(|(project, name, port)| ProjectQueryResult {
+            project: project.clone(),
+            name: name.clone(),
+            port: *port,
+        })
+        .collect();
+
+    print_json(&results, version);
+}
+
+/// Displays service URLs, one `name=url` line each, or only the URL for a
+/// single port asked for by name.
+pub fn display_urls(urls: &[(String, Port, String)], single_value: bool) {
+    if single_value && urls.len() == 1 {
+        println!("{}", urls[0].2);
+        return;
+    }
+    for (name, _, url) in urls {
+        println!("{name}={url}");
+    }
+}
+
+/// A port's URL for JSON output.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct UrlResult {
+    pub name: String,
+    pub port: Port,
+    pub url: String,
+}
+
+/// Displays service URLs as JSON.
+pub fn display_urls_json(urls: &[(String, Port, String)], version: JsonVersion) {
+    let results: Vec<UrlResult> = urls
+        .iter()
+        .map(|(name, port, url)| UrlResult {
+            name: name.clone(),
+            port: *port,
+            url: url.clone(),
+        })
+        .collect();
+
+    print_json(&results, version);
+}
+
+/// Displays ports of several projects as JSON, grouped by project
+/// (`{"team-a-api": [{"name": "web", "port": 8000}], ...}`).
+pub fn display_query_projects_json(ports: &[(String, String, Port)], version: JsonVersion) {
+    let mut results: BTreeMap<&str, Vec<QueryResult>> = BTreeMap::new();
+    for (project, name, port) in ports {
+        results.entry(project).or_default().push(QueryResult {
+            name: name.clone(),
+            port: *port,
+        });
+    }
+
+    print_json(&results, version);
+}
+
+/// Displays a JSON Schema document.
+pub fn display_schema(schema: &RootSchema) {
+    let json = serde_json::to_string_pretty(schema).expect("Failed to serialize to JSON");
     println!("{json}");
 }
+
+/// An error as printed to stderr by commands run with `--json`.
+#[derive(Debug, Serialize)]
+struct ErrorInfo {
+    /// Stable code; `pm explain <code>` describes it.
+    code: &'static str,
+    /// Failure category, as in the JSON-RPC API's `data.kind`.
+    kind: &'static str,
+    message: String,
+    hints: &'static [&'static str],
+}
+
+/// Prints an error to stderr as JSON: `{"error": ...}`, with `apiVersion`
+/// alongside it in v1.
+pub fn display_error_json(error: &Error, version: JsonVersion) {
+    let info = ErrorInfo {
+        code: error.code(),
+        kind: error.kind(),
+        message: error.to_string(),
+        hints: explain(error.code()).map_or(&[], |e| e.hints),
+    };
+    let json = match version {
+        JsonVersion::V0 => serde_json::json!({ "error": info }),
+        JsonVersion::V1 | JsonVersion::V2 => {
+            serde_json::json!({ "apiVersion": version.as_str(), "error": info })
+        }
+    };
+    eprintln!("{json}");
+}
+
+/// Displays one error code's explanation.
+pub fn display_explanation(explanation: &Explanation) {
+    println!("{}: {}", explanation.code, explanation.summary);
+    println!();
+    println!("{}", explanation.details);
+    println!();
+    println!("To fix:");
+    for hint in explanation.hints {
+        println!("  - {hint}");
+    }
+}
+
+/// Lists error codes with their summaries.
+pub fn display_explanations(explanations: &[Explanation]) {
+    let width = explanations.iter().map(|e| e.code.len()).max().unwrap_or(0);
+    for explanation in explanations {
+        println!("{:width$}  {}", explanation.code, explanation.summary);
+    }
+}
+
+/// Registry validation result for JSON output.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ValidationReport {
+    pub file: String,
+    pub valid: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Displays what `pm merge` did, one line per step.
+pub fn display_merge_steps(steps: &[MergeStep]) {
+    if steps.is_empty() {
+        println!("Nothing to merge");
+        return;
+    }
+    for step in steps {
+        match step {
+            MergeStep::Added { key, port } => println!("+ {key} = {port}"),
+            MergeStep::Moved { key, from, to } => println!("~ {key}: {from} -> {to}"),
+            MergeStep::KeptOurs { key, reason } => println!("= kept ours for {key}: {reason}"),
+        }
+    }
+}
+
+/// Displays the differences between two registries as a table.
+pub fn display_registry_diff(diff: &RegistryDiff) {
+    if diff.is_empty() {
+        println!("No differences");
+        return;
+    }
+
+    pager::start();
+    let mut table = create_table();
+    table.set_header(vec!["CHANGE", "ENTRY", "OLD", "NEW"]);
+    let change = &diff.allocations;
+    for entry in &change.added {
+        table.add_row(vec![
+            paint(Cell::new("added"), Tone::Good),
+            Cell::new(format!("{}.{}", entry.project, entry.name)),
+            Cell::new("---"),
+            Cell::new(entry.port),
+        ]);
+    }
+    for entry in &change.removed {
+        table.add_row(vec![
+            paint(Cell::new("removed"), Tone::Bad),
+            Cell::new(format!("{}.{}", entry.project, entry.name)),
+            Cell::new(entry.port),
+            Cell::new("---"),
+        ]);
+    }
+    for entry in &change.changed {
+        table.add_row(vec![
+            paint(Cell::new("changed"), Tone::Changed),
+            Cell::new(format!("{}.{}", entry.project, entry.name)),
+            Cell::new(entry.old),
+            Cell::new(entry.new),
+        ]);
+    }
+    for range in &diff.ranges {
+        let (label, tone) = match (&range.old, &range.new) {
+            (None, _) => ("added", Tone::Good),
+            (_, None) => ("removed", Tone::Bad),
+            _ => ("changed", Tone::Changed),
+        };
+        table.add_row(vec![
+            paint(Cell::new(label), tone),
+            Cell::new(format!("range {}", range.type_name)),
+            Cell::new(range.old.as_deref().unwrap_or("---")),
+            Cell::new(range.new.as_deref().unwrap_or("---")),
+        ]);
+    }
+
+    print_table(table);
+}
+
+/// Displays the differences between two registries as JSON.
+pub fn display_registry_diff_json(diff: &RegistryDiff, version: JsonVersion) {
+    print_json(diff, version);
+}
+
+/// Displays allocations that collide with another machine's ports.
+pub fn display_clashes(clashes: &[Clash]) {
+    if clashes.is_empty() {
+        println!("No collisions");
+        return;
+    }
+
+    let mut table = create_table();
+    table.set_header(vec!["PORT", "OURS", "THEIR ALLOCATION", "THEIR LISTENER"]);
+    for clash in clashes {
+        table.add_row(vec![
+            paint(Cell::new(clash.port), Tone::Bad),
+            Cell::new(format!("{}.{}", clash.project, clash.name)),
+            Cell::new(clash.allocated_by.as_deref().unwrap_or("---")),
+            Cell::new(clash.listener.as_deref().unwrap_or("---")),
+        ]);
+    }
+    print_table(table);
+}
+
+/// Displays clashes as JSON.
+pub fn display_clashes_json(clashes: &[Clash], version: JsonVersion) {
+    print_json(&clashes, version);
+}
+
+/// Displays the whole registry as JSON, for `pm export registry`.
+pub fn display_registry_json(registry: &Registry, version: JsonVersion) {
+    print_json(registry, version);
+}
+
+/// Displays registry validation results.
+pub fn display_validation(path: &std::path::Path, diagnostics: &[Diagnostic]) {
+    if diagnostics.is_empty() {
+        println!("{}: ok", path.display());
+        return;
+    }
+    for diagnostic in diagnostics {
+        println!("{}: {diagnostic}", path.display());
+    }
+}
+
+/// Displays what `pm config repair` dropped from the registry at `path`
+/// and what it kept.
+pub fn display_repair(path: &std::path::Path, salvage: &Salvage) {
+    for dropped in &salvage.dropped {
+        let lines = match dropped.lines {
+            (first, last) if first == last => format!("line {first}"),
+            (first, last) => format!("lines {first}-{last}"),
+        };
+        let section = match dropped.section.as_str() {
+            "" => String::new(),
+            section => format!(" in {section}"),
+        };
+        println!(
+            "{}: dropped {lines}{section}: {} ({})",
+            path.display(),
+            dropped.text,
+            dropped.reason
+        );
+    }
+    let projects = salvage.registry.projects.len();
+    let ports: usize = salvage
+        .registry
+        .projects
+        .values()
+        .map(|p| p.ports.len())
+        .sum();
+    println!(
+        "Kept {projects} project{} with {ports} port{}",
+        if projects == 1 { "" } else { "s" },
+        if ports == 1 { "" } else { "s" }
+    );
+}
+
+/// Displays registry validation results as JSON.
+pub fn display_validation_json(
+    path: &std::path::Path,
+    diagnostics: &[Diagnostic],
+    version: JsonVersion,
+) {
+    let report = ValidationReport {
+        file: path.display().to_string(),
+        valid: diagnostics.is_empty(),
+        diagnostics: diagnostics.to_vec(),
+    };
+    print_json(&report, version);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Allocation, PortMeta, Project};
+    use crate::name::{PortName, ProjectName};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_format_lifetime() {
+        assert_eq!(format_lifetime(42), "42s");
+        assert_eq!(format_lifetime(300), "5m");
+        assert_eq!(format_lifetime(7500), "2h 5m");
+        assert_eq!(format_lifetime(3 * 86400 + 4 * 3600 + 60), "3d 4h");
+    }
+
+    fn registry_with(project: &str, port: u16) -> Registry {
+        let mut proj = Project::default();
+        proj.ports.insert(
+            PortName::new("web").unwrap(),
+            Allocation::new(Port::new(port).unwrap(), PortMeta::default()),
+        );
+        let mut registry = Registry::default();
+        registry
+            .projects
+            .insert(ProjectName::new(project).unwrap(), proj);
+        registry
+    }
+
+    fn listener(port: u16, cwd: Option<&std::path::Path>) -> ListeningPort {
+        ListeningPort {
+            port: Port::new(port).unwrap(),
+            pid: Some(42),
+            process_name: Some("node".to_string()),
+            process_cwd: cwd.map(|p| p.to_path_buf()),
+            process_command: None,
+            uid: None,
+            user: None,
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_short_process() {
+        assert_eq!(short_process(Some("node"), Some(4242)), "node(4242)");
+        assert_eq!(short_process(Some("node"), None), "node");
+        assert_eq!(short_process(None, Some(4242)), "-(4242)");
+        assert_eq!(short_process(None, None), "-");
+    }
+
+    #[test]
+    fn test_fit_to_width_truncates_instead_of_wrapping() {
+        let mut table = create_table();
+        table.set_width(48);
+        table.set_header(vec!["PROJECT", "PORT", "STATUS", "PROCESS"]);
+        table.add_row(vec![
+            "storefront-admin",
+            "8080",
+            "ACTIVE",
+            "/usr/local/lib/node_modules/.bin/next-server --port 8080",
+        ]);
+        fit_to_width(&mut table);
+
+        let lines: Vec<String> = table.lines().collect();
+        assert_eq!(lines.len(), 5, "{lines:#?}");
+        let row = &lines[3];
+        assert!(row.contains("storefront-admin"));
+        assert!(row.contains("ACTIVE"));
+        assert!(row.contains("/usr/"), "{lines:#?}");
+        assert!(row.trim_end_matches(['│', ' ']).ends_with('…'));
+    }
+
+    #[test]
+    fn test_shared_port_lists_every_pid() {
+        let registry = registry_with("webapp", 8080);
+        let mut worker = listener(8080, None);
+        worker.pid = Some(43);
+        let listening = [listener(8080, None), worker, listener(9090, None)];
+
+        let ports = build_status_port_list(&listening, &registry, false);
+        assert_eq!(ports.len(), 2);
+        assert_eq!(ports[0].pid, Some(42));
+        assert_eq!(ports[0].pids, [42, 43]);
+        assert_eq!(ports[1].pids, [42]);
+
+        let ports = build_allocated_port_list(&registry, &listening, &ListFilter::default());
+        assert_eq!(ports[0].status, PortStatus::Active);
+        assert_eq!(ports[0].pids, [42, 43]);
+    }
+
+    #[test]
+    fn test_listener_cell() {
+        let values = [Some("nginx".to_string()), None, Some("nginx".to_string())];
+        assert_eq!(listener_cell(values.into_iter()), "nginx");
+        let values = [Some("42".to_string()), Some("43".to_string())];
+        assert_eq!(listener_cell(values.into_iter()), "42, 43");
+        assert_eq!(listener_cell([None].into_iter()), "---");
+    }
+
+    #[test]
+    fn test_listener_from_other_project_is_conflict() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().canonicalize().unwrap();
+        let mut registry = registry_with("webapp", 8080);
+        registry
+            .links
+            .insert(dir.to_string_lossy().into_owned(), "api".to_string());
+
+        let ports = build_allocated_port_list(
+            &registry,
+            &[listener(8080, Some(&dir))],
+            &ListFilter::default(),
+        );
+        assert_eq!(ports[0].status, PortStatus::Conflict);
+        assert_eq!(ports[0].listener_project.as_deref(), Some("api"));
+
+        // Conflicts are still listening, so --active keeps them
+        let ports = build_allocated_port_list(
+            &registry,
+            &[listener(8080, Some(&dir))],
+            &ListFilter {
+                active: true,
+                ..ListFilter::default()
+            },
+        );
+        assert_eq!(ports.len(), 1);
+    }
+
+    #[test]
+    fn test_expect_process() {
+        let mut registry = registry_with("webapp", 8080);
+        registry
+            .projects
+            .get_mut("webapp")
+            .unwrap()
+            .ports
+            .get_mut("web")
+            .unwrap()
+            .meta
+            .expect_process = Some("node.*vite".to_string());
+
+        let mut vite = listener(8080, None);
+        vite.process_command = Some("node /app/node_modules/.bin/vite".to_string());
+        let ports = build_allocated_port_list(&registry, &[vite.clone()], &ListFilter::default());
+        assert_eq!(ports[0].status, PortStatus::Active);
+        assert!(!is_unexpected_listener(&registry, &vite));
+
+        let mut other = listener(8080, None);
+        other.process_name = Some("python".to_string());
+        other.process_command = Some("python -m http.server 8080".to_string());
+        let ports = build_allocated_port_list(&registry, &[other.clone()], &ListFilter::default());
+        assert_eq!(ports[0].status, PortStatus::Conflict);
+        assert!(is_unexpected_listener(&registry, &other));
+
+        // Nothing known about the process: can't tell, so trust it
+        let mut unknown = listener(8080, None);
+        unknown.process_name = None;
+        let ports = build_allocated_port_list(&registry, &[unknown], &ListFilter::default());
+        assert_eq!(ports[0].status, PortStatus::Active);
+    }
+
+    #[test]
+    fn test_listener_project_read_once_per_directory() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().canonicalize().unwrap();
+        std::fs::create_dir(dir.join(".git")).unwrap();
+        std::fs::write(dir.join(".pm.toml"), "[links]\n\".\" = \"api\"\n").unwrap();
+        let registry = Registry::default();
+        let mut known = HashMap::new();
+
+        let lp = listener(8080, Some(&dir));
+        assert_eq!(
+            listener_project(&registry, &lp, &mut known).as_deref(),
+            Some("api")
+        );
+        // A second listener in the same directory uses the first answer
+        std::fs::remove_file(dir.join(".pm.toml")).unwrap();
+        let other = listener(8081, Some(&dir));
+        assert_eq!(
+            listener_project(&registry, &other, &mut known).as_deref(),
+            Some("api")
+        );
+        assert_eq!(known.len(), 1);
+    }
+
+    #[test]
+    fn test_listener_from_owner_or_unknown_is_active() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().canonicalize().unwrap();
+        let mut registry = registry_with("webapp", 8080);
+        registry
+            .links
+            .insert(dir.to_string_lossy().into_owned(), "webapp".to_string());
+
+        let ports = build_allocated_port_list(
+            &registry,
+            &[listener(8080, Some(&dir))],
+            &ListFilter::default(),
+        );
+        assert_eq!(ports[0].status, PortStatus::Active);
+        assert_eq!(ports[0].listener_project, None);
+
+        let ports =
+            build_allocated_port_list(&registry, &[listener(8080, None)], &ListFilter::default());
+        assert_eq!(ports[0].status, PortStatus::Active);
+    }
+    #[test]
+    fn test_unassigned_listeners_name_service() {
+        let registry = registry_with("webapp", 5432);
+        let listening = [
+            listener(5432, None),
+            listener(6379, None),
+            listener(6380, None),
+        ];
+        let ports = build_status_port_list(&listening, &registry, false);
+        let services: Vec<_> = ports.iter().map(|p| p.service.as_deref()).collect();
+        // Allocated ports are named by their allocation instead
+        assert_eq!(services, vec![None, Some("redis"), None]);
+    }
+}
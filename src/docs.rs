@@ -0,0 +1,170 @@
+//! CLI reference generation.
+//!
+//! Renders man pages and a markdown reference straight from the clap command
+//! definitions, so packaged documentation never drifts from the binary.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use clap::{Arg, Command};
+
+use crate::error::Result;
+
+/// Writes a man page for `cmd` and every subcommand into `dir`
+/// (`pm.1`, `pm-allocate.1`, `pm-export-nginx.1`, ...).
+pub fn write_man_pages(cmd: Command, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    clap_mangen::generate_to(cmd, dir)?;
+    Ok(())
+}
+
+/// Renders the top-level man page.
+pub fn render_man(cmd: Command) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    clap_mangen::Man::new(cmd).render(&mut buf)?;
+    Ok(buf)
+}
+
+/// Renders a markdown reference covering every (non-hidden) subcommand.
+pub fn render_markdown(mut cmd: Command) -> String {
+    // Building propagates bin names so subcommand usage reads `pm allocate ...`
+    cmd.build();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# {} command reference", cmd.get_name());
+    let _ = writeln!(out);
+    if let Some(about) = cmd.get_about() {
+        let _ = writeln!(out, "{about}");
+        let _ = writeln!(out);
+    }
+    let _ = writeln!(out, "```text\n{}\n```", cmd.render_usage());
+
+    for sub in cmd.get_subcommands().filter(|s| !s.is_hide_set()) {
+        write_command(&mut out, sub, cmd.get_name(), 2);
+    }
+    out
+}
+
+fn write_command(out: &mut String, cmd: &Command, parent: &str, level: usize) {
+    let path = format!("{parent} {}", cmd.get_name());
+    let _ = writeln!(out);
+    let _ = writeln!(out, "{} `{path}`", "#".repeat(level));
+    let _ = writeln!(out);
+
+    if let Some(about) = cmd.get_long_about().or_else(|| cmd.get_about()) {
+        let _ = writeln!(out, "{about}");
+        let _ = writeln!(out);
+    }
+
+    let aliases: Vec<_> = cmd.get_visible_aliases().collect();
+    if !aliases.is_empty() {
+        let _ = writeln!(out, "Aliases: `{}`", aliases.join("`, `"));
+        let _ = writeln!(out);
+    }
+
+    let _ = writeln!(out, "```text\n{}\n```", cmd.clone().render_usage());
+
+    let args: Vec<_> = cmd
+        .get_arguments()
+        .filter(|a| !a.is_hide_set() && !matches!(a.get_id().as_str(), "help" | "version"))
+        .collect();
+
+    let positionals: Vec<_> = args.iter().filter(|a| a.is_positional()).collect();
+    if !positionals.is_empty() {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "Arguments:");
+        let _ = writeln!(out);
+        for arg in positionals {
+            write_arg(out, arg);
+        }
+    }
+
+    let options: Vec<_> = args.iter().filter(|a| !a.is_positional()).collect();
+    if !options.is_empty() {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "Options:");
+        let _ = writeln!(out);
+        for arg in options {
+            write_arg(out, arg);
+        }
+    }
+
+    for sub in cmd.get_subcommands().filter(|s| !s.is_hide_set()) {
+        write_command(out, sub, &path, level + 1);
+    }
+}
+
+fn write_arg(out: &mut String, arg: &Arg) {
+    let value_name = arg
+        .get_value_names()
+        .and_then(|names| names.first())
+        .map(|n| n.to_string())
+        .unwrap_or_else(|| arg.get_id().as_str().to_uppercase());
+
+    let mut flag = String::new();
+    if arg.is_positional() {
+        let _ = write!(flag, "<{value_name}>");
+    } else {
+        if let Some(short) = arg.get_short() {
+            let _ = write!(flag, "-{short}, ");
+        }
+        if let Some(long) = arg.get_long() {
+            let _ = write!(flag, "--{long}");
+        }
+        if arg.get_action().takes_values() {
+            let _ = write!(flag, " <{value_name}>");
+        }
+    }
+
+    let mut line = format!("- `{flag}`");
+    if let Some(help) = arg.get_long_help().or_else(|| arg.get_help()) {
+        let help = help.to_string().replace('\n', " ");
+        let _ = write!(line, ": {help}");
+    }
+
+    let possible: Vec<_> = arg
+        .get_possible_values()
+        .into_iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect();
+    if !possible.is_empty() && arg.get_action().takes_values() {
+        let _ = write!(line, " [possible values: {}]", possible.join(", "));
+    }
+
+    let defaults: Vec<_> = arg
+        .get_default_values()
+        .iter()
+        .map(|v| v.to_string_lossy().into_owned())
+        .collect();
+    if !defaults.is_empty() && arg.get_action().takes_values() {
+        let _ = write!(line, " (default: {})", defaults.join(", "));
+    }
+
+    let _ = writeln!(out, "{line}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_markdown_covers_subcommands() {
+        let md = render_markdown(Cli::command());
+
+        assert!(md.starts_with("# pm command reference"));
+        assert!(md.contains("## `pm allocate`"));
+        assert!(md.contains("Aliases: `a`"));
+        assert!(md.contains("- `--group <GROUP>`: Group label"));
+        assert!(md.contains("### `pm export nginx`"));
+        assert!(md.contains("(default: default)"));
+    }
+
+    #[test]
+    fn test_render_man() {
+        let man = String::from_utf8(render_man(Cli::command()).unwrap()).unwrap();
+        assert!(man.contains(".TH pm 1"));
+    }
+}
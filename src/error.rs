@@ -4,6 +4,7 @@ use std::path::PathBuf;
 
 use thiserror::Error;
 
+use crate::name::InvalidNameError;
 use crate::port::Port;
 
 /// Main error type for port manager operations.
@@ -20,6 +21,155 @@ pub enum Error {
 
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("Invalid name '{}': {0}", .0.name)]
+    InvalidName(#[from] InvalidNameError),
+
+    #[error("Watch error: {0}")]
+    Watch(#[from] notify::Error),
+
+    #[error("Failed to run '{command}': {reason}")]
+    CommandFailed { command: String, reason: String },
+
+    #[error("Port {port}: {reason}")]
+    HoldFailed { port: Port, reason: String },
+
+    #[error("Not supported on this platform: {0}")]
+    Unsupported(&'static str),
+
+    #[error("Couldn't find this machine's LAN address: {0}. Pass --host <address> instead")]
+    NoLanAddress(String),
+
+    #[error("Unknown error code '{0}'. Run 'pm explain' to list codes")]
+    UnknownCode(String),
+
+    #[error("{}. Pass --no-lint if that's what you meant", .0.join("; "))]
+    Linted(Vec<String>),
+
+    #[cfg(feature = "self-update")]
+    #[error("Update error: {0}")]
+    Update(#[from] UpdateError),
+}
+
+impl Error {
+    /// Machine-readable failure category, reported as `data.kind` by `pm ide`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Registry(e) => match e {
+                RegistryError::ProjectNotFound(_)
+                | RegistryError::ProjectNotArchived(_)
+                | RegistryError::NoLinkedProject(_)
+                | RegistryError::LinkNotFound(_)
+                | RegistryError::PortNameNotFound { .. }
+                | RegistryError::AliasNotFound { .. }
+                | RegistryError::DependencyNotFound { .. }
+                | RegistryError::GroupNotFound(_) => "not_found",
+                RegistryError::NameIsAlias { .. }
+                | RegistryError::PortAlreadyAllocated { .. }
+                | RegistryError::AmbiguousName { .. }
+                | RegistryError::EnvVarCollision { .. }
+                | RegistryError::PortsCollide { .. }
+                | RegistryError::PortNameExists { .. }
+                | RegistryError::ProjectExists(_)
+                | RegistryError::PortInUse { .. }
+                | RegistryError::PortActive { .. } => "conflict",
+                RegistryError::PortLocked { .. } => "locked",
+                RegistryError::InvalidProcessPattern { .. }
+                | RegistryError::InvalidHostname { .. }
+                | RegistryError::InvalidRangeFormat
+                | RegistryError::UnknownPolicy(_)
+                | RegistryError::InvalidPortNumber(_)
+                | RegistryError::PortTypedTwice { .. }
+                | RegistryError::InvalidPortRange { .. }
+                | RegistryError::OverlappingSegments { .. } => "invalid",
+                RegistryError::NoAvailablePorts { .. }
+                | RegistryError::RangeReserved { .. }
+                | RegistryError::QuotaExceeded { .. } => "exhausted",
+            },
+            Error::Config(ConfigError::ProfileNotFound(_)) => "not_found",
+            Error::Config(ConfigError::ProfileExists(_) | ConfigError::ImportConflicts(_)) => {
+                "conflict"
+            }
+            Error::InvalidName(_) | Error::UnknownCode(_) | Error::Linted(_) => "invalid",
+            Error::HoldFailed { .. } => "conflict",
+            _ => "internal",
+        }
+    }
+
+    /// Stable error code for scripts, explained by `pm explain <code>`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Config(e) => match e {
+                ConfigError::NoConfigDir => "E_NO_CONFIG_DIR",
+                ConfigError::ReadFailed { .. } => "E_CONFIG_READ",
+                ConfigError::WriteFailed { .. } | ConfigError::SerializeFailed(_) => {
+                    "E_CONFIG_WRITE"
+                }
+                ConfigError::ParseFailed { .. } => "E_CONFIG_PARSE",
+                ConfigError::RegistryCorrupt { .. } => "E_REGISTRY_CORRUPT",
+                ConfigError::LockFailed { .. } => "E_LOCK_FAILED",
+                ConfigError::InvalidRegistry(_) | ConfigError::ValidationFailed { .. } => {
+                    "E_INVALID_REGISTRY"
+                }
+                ConfigError::ImportConflicts(_) => "E_IMPORT_CONFLICT",
+                ConfigError::ModifiedDuringEdit(_) => "E_CONCURRENT_EDIT",
+                ConfigError::EditorFailed { .. } | ConfigError::EditorExited { .. } => "E_EDITOR",
+                ConfigError::JsonParseFailed { .. } => "E_JSON_PARSE",
+                ConfigError::ProfileNotFound(_) => "E_PROFILE_NOT_FOUND",
+                ConfigError::ProfileExists(_) => "E_PROFILE_EXISTS",
+                ConfigError::GitSyncOff | ConfigError::NoSyncRemote => "E_SYNC_NOT_SET_UP",
+                ConfigError::ForeignRepo(_) => "E_FOREIGN_REPO",
+            },
+            Error::Registry(e) => match e {
+                RegistryError::ProjectNotFound(_) => "E_PROJECT_NOT_FOUND",
+                RegistryError::ProjectNotArchived(_) => "E_PROJECT_NOT_ARCHIVED",
+                RegistryError::ProjectExists(_) => "E_PROJECT_EXISTS",
+                RegistryError::NoLinkedProject(_) => "E_NO_LINKED_PROJECT",
+                RegistryError::LinkNotFound(_) => "E_LINK_NOT_FOUND",
+                RegistryError::PortNameNotFound { .. } => "E_PORT_NAME_NOT_FOUND",
+                RegistryError::PortLocked { .. } => "E_PORT_LOCKED",
+                RegistryError::AliasNotFound { .. } => "E_ALIAS_NOT_FOUND",
+                RegistryError::DependencyNotFound { .. } => "E_DEPENDENCY_NOT_FOUND",
+                RegistryError::NameIsAlias { .. } => "E_NAME_IS_ALIAS",
+                RegistryError::PortAlreadyAllocated { .. } => "E_PORT_ALLOCATED",
+                RegistryError::GroupNotFound(_) => "E_GROUP_NOT_FOUND",
+                RegistryError::AmbiguousName { .. } => "E_AMBIGUOUS_NAME",
+                RegistryError::EnvVarCollision { .. } => "E_ENV_VAR_COLLISION",
+                RegistryError::PortNameExists { .. } => "E_PORT_NAME_EXISTS",
+                RegistryError::NoAvailablePorts { .. } | RegistryError::RangeReserved { .. } => {
+                    "E_NO_AVAILABLE_PORTS"
+                }
+                RegistryError::QuotaExceeded { .. } => "E_QUOTA_EXCEEDED",
+                RegistryError::PortInUse { .. } => "E_PORT_IN_USE",
+                RegistryError::PortsCollide { .. } => "E_PORTS_COLLIDE",
+                RegistryError::PortActive { .. } => "E_PORT_ACTIVE",
+                RegistryError::InvalidProcessPattern { .. } => "E_INVALID_PATTERN",
+                RegistryError::InvalidHostname { .. } => "E_INVALID_HOSTNAME",
+                RegistryError::InvalidRangeFormat
+                | RegistryError::UnknownPolicy(_)
+                | RegistryError::InvalidPortRange { .. }
+                | RegistryError::OverlappingSegments { .. } => "E_INVALID_RANGE",
+                RegistryError::InvalidPortNumber(_) | RegistryError::PortTypedTwice { .. } => {
+                    "E_INVALID_PORT"
+                }
+            },
+            Error::PortDetection(PortDetectionError::ProcessEnumFailed(_)) => "E_DETECTION_FAILED",
+            Error::PortDetection(PortDetectionError::PlatformNotSupported) => {
+                "E_DETECTION_UNSUPPORTED"
+            }
+            Error::Io(_) => "E_IO",
+            Error::InvalidName(_) => "E_INVALID_NAME",
+            Error::Watch(_) => "E_WATCH",
+            Error::CommandFailed { .. } => "E_COMMAND_FAILED",
+            Error::HoldFailed { .. } => "E_HOLD_FAILED",
+            Error::Unsupported(_) => "E_UNSUPPORTED",
+            Error::NoLanAddress(_) => "E_NO_LAN_ADDRESS",
+            Error::UnknownCode(_) => "E_UNKNOWN_CODE",
+            Error::Linted(_) => "E_LINT",
+            #[cfg(feature = "self-update")]
+            Error::Update(_) => "E_UPDATE",
+        }
+    }
 }
 
 /// Errors related to configuration file operations.
@@ -49,6 +199,13 @@ pub enum ConfigError {
         source: toml::de::Error,
     },
 
+    #[error("The registry at {path} doesn't parse (run 'pm config repair' to recover what does): {source}")]
+    RegistryCorrupt {
+        path: PathBuf,
+        #[source]
+        source: Box<toml::de::Error>,
+    },
+
     #[error("Failed to serialize config: {0}")]
     SerializeFailed(#[from] toml::ser::Error),
 
@@ -58,6 +215,50 @@ pub enum ConfigError {
         #[source]
         source: std::io::Error,
     },
+
+    #[error("Registry not saved: {0} problem(s) found")]
+    InvalidRegistry(usize),
+
+    #[error("{path} has {count} problem(s)")]
+    ValidationFailed { path: PathBuf, count: usize },
+
+    #[error("Registry not imported: {0} conflict(s) found")]
+    ImportConflicts(usize),
+
+    #[error("Registry at {0} changed while it was being edited. Re-run 'pm config edit'")]
+    ModifiedDuringEdit(PathBuf),
+
+    #[error("Failed to run editor '{editor}': {source}")]
+    EditorFailed {
+        editor: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Editor '{editor}' failed ({status}); registry not changed")]
+    EditorExited { editor: String, status: String },
+
+    #[error("Failed to parse JSON file at {path}: {source}")]
+    JsonParseFailed {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Profile '{0}' not found. Run 'pm profile list' or 'pm profile create {0}'")]
+    ProfileNotFound(String),
+
+    #[error("Profile '{0}' already exists")]
+    ProfileExists(String),
+
+    #[error("Git sync is off. Turn it on with 'pm config --git-sync true'")]
+    GitSyncOff,
+
+    #[error("No remote to sync with. Run 'pm sync --remote <url>'")]
+    NoSyncRemote,
+
+    #[error("{0} is a git repository pm didn't create, so git-sync won't commit to it. Move the registry elsewhere with PM_CONFIG_PATH, or turn git-sync off")]
+    ForeignRepo(PathBuf),
 }
 
 /// Errors related to port registry operations.
@@ -66,9 +267,37 @@ pub enum RegistryError {
     #[error("Project '{0}' not found. Run 'pm list' to see allocated projects")]
     ProjectNotFound(String),
 
+    #[error("Project '{0}' is not archived. Run 'pm archive' to see archived projects")]
+    ProjectNotArchived(String),
+
+    #[error("Project '{0}' already has allocations. Free or archive them before unarchiving")]
+    ProjectExists(String),
+
+    #[error("No project linked to {0}. Run 'pm link . <project>' or pass a project name")]
+    NoLinkedProject(PathBuf),
+
+    #[error("Directory {0} is not linked to a project. Run 'pm link' to see links")]
+    LinkNotFound(PathBuf),
+
     #[error("Port name '{name}' not found in project '{project}'. Run 'pm query {project}' to see available ports")]
     PortNameNotFound { project: String, name: String },
 
+    #[error("Port {project}.{name} is locked. Run 'pm unlock {project} {name}' or pass --force")]
+    PortLocked { project: String, name: String },
+
+    #[error("Alias '{alias}' not found in project '{project}'")]
+    AliasNotFound { project: String, alias: String },
+
+    #[error("Project '{project}' doesn't depend on {dependency}. Run 'pm query {project} --with-deps' to see its dependencies")]
+    DependencyNotFound { project: String, dependency: String },
+
+    #[error("'{alias}' is an alias for '{target}' in project '{project}'. Run 'pm unalias {project} {alias}' to remove the alias or 'pm free {project} {target}' to free the port")]
+    NameIsAlias {
+        project: String,
+        alias: String,
+        target: String,
+    },
+
     #[error("Port {port} is already allocated to {project}.{name}. Run 'pm list' to see all allocations")]
     PortAlreadyAllocated {
         port: Port,
@@ -76,11 +305,41 @@ pub enum RegistryError {
         name: String,
     },
 
+    #[error("No allocations found in group '{0}'. Run 'pm list --json' to see allocation groups")]
+    GroupNotFound(String),
+
+    #[error("'{name}' matches {matches} when ignoring case. Rename one with 'pm config edit' or run 'pm config --ignore-case false'")]
+    AmbiguousName { name: String, matches: String },
+
+    #[error("Port names {names} in project '{project}' all map to ${var}. Rename one with 'pm config edit'")]
+    EnvVarCollision {
+        project: String,
+        var: String,
+        names: String,
+    },
+
     #[error("Port name '{name}' already exists in project '{project}'")]
     PortNameExists { project: String, name: String },
 
-    #[error("No available ports in range {start}-{end}. Try 'pm free <project>' to release ports or expand the range with 'pm config'")]
-    NoAvailablePorts { start: u16, end: u16 },
+    #[error("No available ports in range {range}. Try 'pm free <project>' to release ports or expand the range with 'pm config'")]
+    NoAvailablePorts { range: String },
+
+    #[error("The '{port_type}' range is reserved for {projects}, and so is the default range. Pick another type or pass a port for '{project}'")]
+    RangeReserved {
+        port_type: String,
+        project: String,
+        projects: String,
+    },
+
+    #[error("Quota exceeded: {holder} already has {limit} ports allocated, the most quotas.{quota} allows")]
+    QuotaExceeded {
+        holder: String,
+        limit: usize,
+        quota: String,
+    },
+
+    #[error("{count} allocation(s) collide with ports used in {path}")]
+    PortsCollide { count: usize, path: PathBuf },
 
     #[error("Port {port} is in use by {process_name} (PID {pid})")]
     PortInUse {
@@ -89,20 +348,62 @@ pub enum RegistryError {
         process_name: String,
     },
 
-    #[error("Invalid range format: expected 'type=start-end' (e.g., web=8000-8999)")]
+    #[error("Port {project}.{name} ({port}) is still in use by {process_name}; nothing was freed. Stop it first or drop --if-idle")]
+    PortActive {
+        project: String,
+        name: String,
+        port: Port,
+        process_name: String,
+    },
+
+    #[error("Invalid process pattern '{pattern}': {reason}")]
+    InvalidProcessPattern { pattern: String, reason: String },
+
+    #[error("Invalid hostname for {owner}: {reason}")]
+    InvalidHostname { owner: String, reason: String },
+
+    #[error("Invalid range format: expected 'type=start-end[,start-end...][:policy]' (e.g., web=8000-8999)")]
     InvalidRangeFormat,
 
+    #[error("Unknown allocation policy '{0}': expected lowest-free, sequential, or random")]
+    UnknownPolicy(String),
+
     #[error("Invalid port number: '{0}'. Port must be between 1 and 65535")]
     InvalidPortNumber(String),
 
+    #[error("Invalid port number: '{input}'. It looks like {port} typed twice")]
+    PortTypedTwice { input: String, port: Port },
+
     #[error("Invalid range: start port ({start}) must be less than end port ({end})")]
     InvalidPortRange { start: u16, end: u16 },
+
+    #[error("Invalid range: segments {first} and {second} overlap")]
+    OverlappingSegments { first: String, second: String },
+}
+
+/// Errors related to `pm self-update`.
+#[cfg(feature = "self-update")]
+#[derive(Error, Debug)]
+pub enum UpdateError {
+    #[error("Failed to reach GitHub releases: {0}")]
+    Http(String),
+
+    #[error("Release {version} has no binary for this platform (expected asset '{asset}')")]
+    NoAsset { version: String, asset: String },
+
+    #[error("Checksum mismatch for {asset}; the download was not installed")]
+    ChecksumMismatch { asset: String },
+
+    #[error("pm is managed by a package manager. Run '{0}' instead")]
+    ManagedInstall(&'static str),
 }
 
 /// Errors related to port detection via system calls.
 #[derive(Error, Debug)]
 pub enum PortDetectionError {
     #[error("Failed to enumerate processes: {0}. Try running with elevated privileges (sudo)")]
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    // Only constructed by the macOS backend
     ProcessEnumFailed(String),
 
     #[error("Platform not supported")]
@@ -0,0 +1,531 @@
+//! Error codes and their remediation, for `pm explain` and JSON errors.
+//!
+//! Every `Error` maps to a stable code (see `Error::code`). Codes are part of
+//! the scripting interface: they are never renamed or reused, so scripts can
+//! branch on them instead of on messages.
+
+/// What an error code means and how to get past it.
+#[derive(Debug)]
+pub struct Explanation {
+    pub code: &'static str,
+    /// One line, shown by `pm explain` without arguments.
+    pub summary: &'static str,
+    /// Paragraphs shown by `pm explain <code>`.
+    pub details: &'static str,
+    /// Short remediation steps, also included in JSON errors.
+    pub hints: &'static [&'static str],
+}
+
+/// Every error code, sorted by code.
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "E_ALIAS_NOT_FOUND",
+        summary: "The alias does not exist in the project",
+        details: "Aliases are extra names for an existing allocation, created with \
+                  'pm alias'. The name given is neither an alias nor a port name in the \
+                  project.",
+        hints: &["Run 'pm query <project>' to see the project's names"],
+    },
+    Explanation {
+        code: "E_AMBIGUOUS_NAME",
+        summary: "A name matches several entries when ignoring case",
+        details: "With 'pm config --ignore-case true', names that differ only by case \
+                  resolve to the same entry. When two entries exist that differ only by \
+                  case, pm refuses to guess which one was meant.",
+        hints: &[
+            "Rename one of the entries with 'pm config edit'",
+            "Or turn case-insensitive matching off with 'pm config --ignore-case false'",
+        ],
+    },
+    Explanation {
+        code: "E_COMMAND_FAILED",
+        summary: "An external command pm runs failed",
+        details: "Some commands hand off to other programs: a browser for 'pm open', \
+                  tmux for 'pm tmux --set-env', launchctl or systemctl for \
+                  'pm install-service'. The program could not be started or exited with \
+                  an error; its name and the reason are in the message.",
+        hints: &["Check that the program is installed and on PATH"],
+    },
+    Explanation {
+        code: "E_CONCURRENT_EDIT",
+        summary: "The registry changed while it was being edited",
+        details: "'pm config edit' edits a copy of the registry and only saves it if the \
+                  registry is unchanged when the editor exits. Another pm command \
+                  changed it in the meantime, so the edit was discarded rather than \
+                  overwriting that change.",
+        hints: &["Run 'pm config edit' again"],
+    },
+    Explanation {
+        code: "E_CONFIG_PARSE",
+        summary: "The registry file is not valid TOML for pm",
+        details: "pm could not parse the registry. The message points at the line and \
+                  column of the first problem. 'pm config validate' lists every problem \
+                  without changing anything.",
+        hints: &[
+            "Run 'pm config validate' to see every problem",
+            "Fix the file with 'pm config edit'",
+        ],
+    },
+    Explanation {
+        code: "E_CONFIG_READ",
+        summary: "The registry file could not be read",
+        details: "The registry exists but reading it failed, usually because of file \
+                  permissions.",
+        hints: &["Check the permissions of the file shown by 'pm config --path'"],
+    },
+    Explanation {
+        code: "E_CONFIG_WRITE",
+        summary: "The registry file could not be written",
+        details: "Saving the registry failed, usually because the config directory is \
+                  read-only or the disk is full. The registry was not changed.",
+        hints: &["Check that the directory shown by 'pm config --path' is writable"],
+    },
+    Explanation {
+        code: "E_DEPENDENCY_NOT_FOUND",
+        summary: "The project has no such dependency",
+        details: "'pm undepend' removes a dependency declared with 'pm depend'. The \
+                  project doesn't depend on the project or port given; a dependency on a \
+                  whole project is removed without a port name.",
+        hints: &["Run 'pm query <project> --with-deps' to see what the project depends on"],
+    },
+    Explanation {
+        code: "E_DETECTION_FAILED",
+        summary: "Listening ports could not be detected",
+        details: "pm asks the operating system which ports are listening and which \
+                  processes own them. That query failed, usually for lack of \
+                  permission.",
+        hints: &["Run the command again with elevated privileges (sudo)"],
+    },
+    Explanation {
+        code: "E_DETECTION_UNSUPPORTED",
+        summary: "Port detection is not available on this platform",
+        details: "Commands that need to know which ports are listening (pm status, \
+                  pm list's STATUS column, --if-idle) are not supported here. \
+                  Allocation, queries, and exports work without it.",
+        hints: &["Use commands that only read the registry, such as 'pm query'"],
+    },
+    Explanation {
+        code: "E_EDITOR",
+        summary: "The editor could not be run or exited with an error",
+        details: "'pm config edit' runs $VISUAL, then $EDITOR, then vi. The editor could \
+                  not be started, or it exited with a failure status; the registry was \
+                  not changed.",
+        hints: &["Set EDITOR to an installed editor, e.g. EDITOR=\"code --wait\""],
+    },
+    Explanation {
+        code: "E_ENV_VAR_COLLISION",
+        summary: "Two port names map to the same environment variable",
+        details: "Port names become variables by uppercasing and replacing '-' and '.' \
+                  with '_', so names such as 'web-api' and 'web_api' both become \
+                  WEB_API_PORT. pm refuses to print either rather than silently drop \
+                  one.",
+        hints: &["Rename one of the ports with 'pm config edit'"],
+    },
+    Explanation {
+        code: "E_FOREIGN_REPO",
+        summary: "Git-sync found a repository it didn't create",
+        details: "Git-sync mode commits the registry to a git repository of its own in \
+                  the registry's directory, marked by the .gitignore pm writes there. \
+                  That directory already holds another repository, and pm won't commit \
+                  to it or push its history.",
+        hints: &[
+            "Keep the registry in a directory of its own with PM_CONFIG_PATH",
+            "Turn git-sync off with 'pm config --git-sync false'",
+        ],
+    },
+    Explanation {
+        code: "E_GROUP_NOT_FOUND",
+        summary: "No allocation belongs to the group",
+        details: "Groups are set with 'pm allocate --group'. No allocation in any \
+                  project has the given group.",
+        hints: &["Run 'pm list --json' to see allocation groups"],
+    },
+    Explanation {
+        code: "E_HOLD_FAILED",
+        summary: "A port could not be held, or its hold did not end",
+        details: "'pm hold' binds the allocated port itself so nothing else can take \
+                  it. That fails when another process already listens on the port, \
+                  such as the service itself or another 'pm hold'. 'pm hold --release' \
+                  fails when the holding process doesn't let go of the port in time.",
+        hints: &[
+            "Run 'pm status' to see what is listening on the port",
+            "Stop the holding process (Ctrl-C in its terminal) if a release timed out",
+        ],
+    },
+    Explanation {
+        code: "E_IMPORT_CONFLICT",
+        summary: "An imported registry disagrees with this one",
+        details: "'pm import registry --merge' only adds entries. A port, alias, range, \
+                  or link that already has a different value here, or a port allocated to \
+                  another name, is a conflict. Each conflict is printed and nothing is \
+                  imported.",
+        hints: &[
+            "Resolve the reported entries in either registry and import again",
+            "Import without --merge to replace this registry instead",
+        ],
+    },
+    Explanation {
+        code: "E_INVALID_HOSTNAME",
+        summary: "A proxy hostname has characters a hostname can't have",
+        details: "'pm export nginx' and 'pm export caddy' write each port's hostname \
+                  (--hostname, or <name>.<project>.localhost) into the proxy config, so \
+                  it may only hold letters, digits, and '-' in labels separated by '.'. \
+                  Anything else could change the config's meaning, so it is refused \
+                  when allocating and when exporting.",
+        hints: &[
+            "Pass a hostname like 'app.localhost' to --hostname",
+            "Fix a hostname in the registry with 'pm config edit'",
+        ],
+    },
+    Explanation {
+        code: "E_INVALID_NAME",
+        summary: "A project or port name is not allowed",
+        details: "Names may contain letters, digits, '-', and '_', may not start \
+                  with '-', and are limited in length. '.' is not allowed because it \
+                  separates project and port in 'project.name'. The message says which \
+                  rule the name broke.",
+        hints: &[
+            "Pick a name using only letters, digits, '-', and '_'",
+            "Names from older versions still load; rename ports with 'pm rename-port'",
+        ],
+    },
+    Explanation {
+        code: "E_INVALID_PATTERN",
+        summary: "The --expect-process pattern is not a valid regex",
+        details: "--expect-process takes a regular expression that is matched against \
+                  the listening process's command line. The pattern could not be \
+                  compiled.",
+        hints: &["Quote the pattern in the shell, e.g. --expect-process 'node.*vite'"],
+    },
+    Explanation {
+        code: "E_INVALID_PORT",
+        summary: "A port number is out of range",
+        details: "Ports must be whole numbers from 1 to 65535.",
+        hints: &["Use a port between 1 and 65535"],
+    },
+    Explanation {
+        code: "E_INVALID_RANGE",
+        summary: "A port range is malformed",
+        details: "'pm config --set' takes ranges as type=start-end, where start is less \
+                  than end, for example web=8000-8999. A range in several pieces lists \
+                  non-overlapping segments separated by commas (api=3000-3099,3300-3399). \
+                  The range can be followed by an allocation policy: lowest-free, \
+                  sequential, or random (web=8000-8999:random).",
+        hints: &["Use the form type=start-end, e.g. 'pm config --set web=8000-8999'"],
+    },
+    Explanation {
+        code: "E_INVALID_REGISTRY",
+        summary: "The registry has validation problems",
+        details: "'pm config validate', 'pm config edit', or 'pm import registry' found \
+                  problems such as duplicate ports or ranges that don't parse. Each \
+                  problem is printed with its location.",
+        hints: &["Fix the reported problems with 'pm config edit'"],
+    },
+    Explanation {
+        code: "E_IO",
+        summary: "A file or system operation failed",
+        details: "An operating system call failed outside the registry itself, for \
+                  example writing an exported file or reading the current directory. \
+                  The message includes the system's reason.",
+        hints: &["Check the path and permissions mentioned in the message"],
+    },
+    Explanation {
+        code: "E_JSON_PARSE",
+        summary: "A JSON input file could not be parsed",
+        details: "Commands that read JSON (such as 'pm import' and \
+                  'pm export devcontainer') found a syntax error. Comments and trailing \
+                  commas are allowed in devcontainer.json.",
+        hints: &["Check the file at the line and column in the message"],
+    },
+    Explanation {
+        code: "E_LINK_NOT_FOUND",
+        summary: "The directory is not linked to a project",
+        details: "'pm unlink' removes a link created with 'pm link'. The directory has no \
+                  link.",
+        hints: &["Run 'pm link' to see links"],
+    },
+    Explanation {
+        code: "E_LINT",
+        summary: "The arguments to 'pm allocate' look mistyped",
+        details: "Before changing the registry, 'pm allocate' checks for common slips: a \
+                  port with a leading zero or spaces in it, a new project named like a port \
+                  (all digits), and a project and port name given the wrong way round \
+                  (the port name is an existing project but the project isn't). The error \
+                  says which was found.",
+        hints: &[
+            "Fix the arguments and run the command again",
+            "Pass --no-lint if the arguments are what you meant",
+        ],
+    },
+    Explanation {
+        code: "E_LOCK_FAILED",
+        summary: "The registry lock could not be acquired",
+        details: "pm locks the registry while changing it so concurrent commands don't \
+                  lose each other's changes. Taking the lock failed, usually because the \
+                  lock file can't be created.",
+        hints: &["Check that the directory shown by 'pm config --path' is writable"],
+    },
+    Explanation {
+        code: "E_NAME_IS_ALIAS",
+        summary: "The name is an alias, not an allocation",
+        details: "Freeing or replacing an alias is ambiguous: it could mean removing the \
+                  alias or freeing the port it points to.",
+        hints: &[
+            "Run 'pm unalias <project> <alias>' to remove the alias",
+            "Or free the target port by its own name",
+        ],
+    },
+    Explanation {
+        code: "E_NO_AVAILABLE_PORTS",
+        summary: "Every port in the range is allocated or in use",
+        details: "pm picks ports from the range for the port type (or 'default'). \
+                  Every port in it is either allocated in the registry or currently \
+                  listening. A range reserved for other projects (its 'projects' list) \
+                  is skipped in favor of 'default', and allocation fails when that is \
+                  reserved too.",
+        hints: &[
+            "Free unused allocations with 'pm free' or 'pm gc'",
+            "Widen the range with 'pm config --set type=start-end'",
+        ],
+    },
+    Explanation {
+        code: "E_NO_CONFIG_DIR",
+        summary: "No config directory could be determined",
+        details: "pm keeps its registry in <config dir>/port-manager, where the config \
+                  dir is the platform's (e.g., ~/.config on Linux). It could not be found, \
+                  which happens when HOME is unset.",
+        hints: &["Set PM_CONFIG_DIR to a directory pm can write to"],
+    },
+    Explanation {
+        code: "E_NO_LAN_ADDRESS",
+        summary: "This machine's LAN address could not be found",
+        details: "'--host lan' puts the address this machine reaches the network from \
+                  in URLs. pm asks the OS which interface it would route through, and \
+                  there was none, usually because the machine is offline.",
+        hints: &[
+            "Pass the address or hostname yourself, e.g. '--host 192.168.1.20'",
+            "Check that a network interface is up",
+        ],
+    },
+    Explanation {
+        code: "E_NO_LINKED_PROJECT",
+        summary: "The current directory is not linked to a project",
+        details: "When no project is given (or '.' is), pm infers it from directory \
+                  links, .pm.toml, and the git repository name. None of them matched \
+                  the current directory.",
+        hints: &[
+            "Run 'pm link . <project>' to link the directory",
+            "Or pass the project name explicitly",
+        ],
+    },
+    Explanation {
+        code: "E_PORTS_COLLIDE",
+        summary: "Allocations use ports another machine uses too",
+        details: "'pm check-against' compares this registry with another machine's \
+                  snapshot. Some of this registry's allocations have ports that the \
+                  other registry allocates or that had a listener there, which clash \
+                  when both machines publish ports on one host or reach each other's \
+                  services.",
+        hints: &[
+            "Move an allocation with 'pm allocate <project> <name> --replace'",
+            "Agree with your teammate on which side keeps the port",
+        ],
+    },
+    Explanation {
+        code: "E_PORT_ACTIVE",
+        summary: "--if-idle refused to free a port that is listening",
+        details: "With --if-idle, pm frees nothing if any of the ports is in use, so a \
+                  running service never loses its reservation.",
+        hints: &[
+            "Stop the process first",
+            "Or run the command without --if-idle",
+        ],
+    },
+    Explanation {
+        code: "E_PORT_ALLOCATED",
+        summary: "The port is already allocated to another name",
+        details: "Each port belongs to at most one project and name. The requested port \
+                  is reserved elsewhere, possibly in the read-only system registry.",
+        hints: &[
+            "Run 'pm list' to see who holds the port",
+            "Or omit the port to let pm pick a free one",
+        ],
+    },
+    Explanation {
+        code: "E_PORT_IN_USE",
+        summary: "Something is already listening on the port",
+        details: "pm checks that a port is free before allocating it. A process is \
+                  listening on the requested port; the message names it.",
+        hints: &[
+            "Stop the process, or omit the port to let pm pick a free one",
+            "Run 'pm status' to see what is listening",
+        ],
+    },
+    Explanation {
+        code: "E_PORT_LOCKED",
+        summary: "The allocation is locked",
+        details: "Locked allocations are protected from 'pm free', 'pm gc', and \
+                  --replace so shared or long-lived ports are not released by accident.",
+        hints: &["Run 'pm unlock <project> <name>' first", "Or pass --force"],
+    },
+    Explanation {
+        code: "E_PORT_NAME_EXISTS",
+        summary: "The project already has a port with that name",
+        details: "Port names are unique within a project. To keep the existing port use \
+                  --if-absent; to move it use --replace.",
+        hints: &[
+            "Use 'pm get' or --if-absent to reuse the existing port",
+            "Or pass --replace to move it",
+        ],
+    },
+    Explanation {
+        code: "E_PORT_NAME_NOT_FOUND",
+        summary: "The project has no port with that name",
+        details: "The project exists but has no allocation or alias with the given name.",
+        hints: &["Run 'pm query <project>' to see available ports"],
+    },
+    Explanation {
+        code: "E_PROFILE_EXISTS",
+        summary: "A profile with that name already exists",
+        details: "'pm profile create' only creates new profiles, so an existing \
+                  profile's registry is never replaced.",
+        hints: &["Run 'pm profile switch <name>' to use the existing profile"],
+    },
+    Explanation {
+        code: "E_PROFILE_NOT_FOUND",
+        summary: "No profile with that name exists",
+        details: "Profiles are separate registries created with 'pm profile create'. \
+                  '--profile' and 'pm profile switch' only accept existing profiles, \
+                  plus 'default' for the base registry.",
+        hints: &[
+            "Run 'pm profile list' to see profiles",
+            "Or create it with 'pm profile create <name>'",
+        ],
+    },
+    Explanation {
+        code: "E_PROJECT_EXISTS",
+        summary: "An archived project can't be restored over an existing one",
+        details: "'pm unarchive' brings back a project archived with 'pm archive'. A \
+                  project of the same name has allocations again, and the two would \
+                  have to be merged name by name, so nothing was restored.",
+        hints: &["Free the existing project with 'pm free <project>', then unarchive"],
+    },
+    Explanation {
+        code: "E_PROJECT_NOT_ARCHIVED",
+        summary: "The project is not archived",
+        details: "'pm unarchive' only restores projects put away with 'pm archive'. \
+                  There is no archived project of that name.",
+        hints: &["Run 'pm archive' to list archived projects"],
+    },
+    Explanation {
+        code: "E_PROJECT_NOT_FOUND",
+        summary: "No project with that name has allocations",
+        details: "Projects exist while they have at least one allocation. The name did \
+                  not match any project.",
+        hints: &["Run 'pm list' to see allocated projects"],
+    },
+    Explanation {
+        code: "E_QUOTA_EXCEEDED",
+        summary: "The allocation would go over a quota",
+        details: "[defaults.quotas] limits how many ports one project (per_project), \
+                  one user (per_user), or one range type (ranges) may hold, in this \
+                  registry or the system registry. The holder named in the error \
+                  already has as many as its quota allows.",
+        hints: &[
+            "Free unused allocations with 'pm free' or 'pm gc'",
+            "Raise the quota with 'pm config edit'",
+        ],
+    },
+    Explanation {
+        code: "E_REGISTRY_CORRUPT",
+        summary: "The registry file doesn't parse",
+        details: "The registry has a syntax error or an entry of the wrong type, often \
+                  from a hand edit or a merge conflict. The message points at the line \
+                  and column of the first problem. When only some projects or sections \
+                  are affected, commands that read the registry leave those out with a \
+                  warning, but commands that change it refuse, so nothing is lost. \
+                  'pm config repair' copies the file aside, keeps every section and line \
+                  that still parses, and reports what it dropped.",
+        hints: &[
+            "Run 'pm config repair --dry-run' to see what would be dropped",
+            "Run 'pm config repair' to recover the rest",
+            "Run 'pm config validate' to see every problem",
+        ],
+    },
+    Explanation {
+        code: "E_SYNC_NOT_SET_UP",
+        summary: "Git sync isn't set up for this registry",
+        details: "'pm sync' pulls and pushes the git repository that git-sync mode \
+                  keeps in the registry's directory. Git-sync mode is off, or the \
+                  repository has no remote yet.",
+        hints: &[
+            "Turn git-sync on with 'pm config --git-sync true'",
+            "Set the remote with 'pm sync --remote <url>'",
+        ],
+    },
+    Explanation {
+        code: "E_UNKNOWN_CODE",
+        summary: "pm explain was given a code it doesn't know",
+        details: "Codes are printed in JSON errors as 'code'.",
+        hints: &["Run 'pm explain' to list every code"],
+    },
+    Explanation {
+        code: "E_UNSUPPORTED",
+        summary: "The command is not supported on this platform",
+        details: "Some commands depend on platform services, such as launchd or systemd \
+                  for 'pm install-service'.",
+        hints: &["The message says what the command needs"],
+    },
+    Explanation {
+        code: "E_UPDATE",
+        summary: "pm self-update failed",
+        details: "Downloading or installing a release failed. Nothing was replaced \
+                  unless the message says so. Installs managed by a package manager \
+                  must be updated through it.",
+        hints: &["Follow the instruction in the message, or update with your package manager"],
+    },
+    Explanation {
+        code: "E_WATCH",
+        summary: "Watching the registry for changes failed",
+        details: "'pm watch-registry' and 'pm ide' watch the registry file for changes. \
+                  The operating system's file watcher reported an error.",
+        hints: &["Check that the config directory exists and is readable"],
+    },
+];
+
+/// Looks up a code, ignoring case and an omitted `E_` prefix.
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    let code = code.to_ascii_uppercase();
+    let code = if code.starts_with("E_") {
+        code
+    } else {
+        format!("E_{code}")
+    };
+    EXPLANATIONS.iter().find(|e| e.code == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Error, RegistryError};
+
+    #[test]
+    fn test_explanations_are_sorted_and_unique() {
+        assert!(EXPLANATIONS.windows(2).all(|w| w[0].code < w[1].code));
+        assert!(EXPLANATIONS.iter().all(|e| !e.hints.is_empty()));
+    }
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(explain("E_PORT_IN_USE").unwrap().code, "E_PORT_IN_USE");
+        assert_eq!(explain("port_in_use").unwrap().code, "E_PORT_IN_USE");
+        assert!(explain("E_NOPE").is_none());
+
+        let e = Error::from(RegistryError::PortLocked {
+            project: "webapp".to_string(),
+            name: "web".to_string(),
+        });
+        assert!(explain(e.code()).is_some());
+        assert!(explain(Error::UnknownCode("x".to_string()).code()).is_some());
+    }
+}
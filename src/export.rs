@@ -0,0 +1,345 @@
+//! Export generators for external tools.
+//!
+//! Renders registry allocations into configuration formats for reverse
+//! proxies and other tooling. Generators are pure: they return the rendered
+//! text and leave writing it to the caller.
+
+use std::fmt::Write;
+
+use serde_json::{json, Map, Value};
+
+use crate::error::{RegistryError, Result};
+use crate::model::{hostname_problem, Allocation, Project};
+
+/// A generator that renders allocations of the given projects as text.
+/// Proxies forward to the services at `upstream` (usually 127.0.0.1).
+pub type Renderer = fn(&[(&str, &Project)], upstream: &str) -> String;
+
+/// Returns the hostname a reverse proxy should route to an allocation.
+///
+/// Uses the allocation's `hostname` metadata, defaulting to
+/// `<name>.<project>.localhost`.
+pub fn proxy_hostname(project: &str, name: &str, allocation: &Allocation) -> String {
+    allocation
+        .meta
+        .hostname
+        .clone()
+        .unwrap_or_else(|| format!("{name}.{project}.localhost"))
+}
+
+/// Fails on the first allocation whose proxy hostname isn't a valid
+/// hostname, since [`render_nginx`] and [`render_caddy`] paste it into the
+/// config as is. Default hostnames may have the '_' of port names, which
+/// both proxies accept.
+pub fn check_hostnames(projects: &[(&str, &Project)]) -> Result<()> {
+    for (project_name, project) in projects {
+        for (name, allocation) in &project.ports {
+            let problem = match &allocation.meta.hostname {
+                Some(host) => hostname_problem(host),
+                None => hostname_problem(
+                    &proxy_hostname(project_name, name, allocation).replace('_', "-"),
+                ),
+            };
+            if let Some(reason) = problem {
+                return Err(RegistryError::InvalidHostname {
+                    owner: format!("{project_name}.{name}"),
+                    reason,
+                }
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders an nginx `server` block per allocation.
+pub fn render_nginx(projects: &[(&str, &Project)], upstream: &str) -> String {
+    let mut out = String::new();
+    for (project_name, project) in projects {
+        for (name, allocation) in &project.ports {
+            let host = proxy_hostname(project_name, name, allocation);
+            let _ = writeln!(out, "# {project_name}.{name}");
+            let _ = writeln!(out, "server {{");
+            let _ = writeln!(out, "    listen 80;");
+            let _ = writeln!(out, "    server_name {host};");
+            let _ = writeln!(out);
+            let _ = writeln!(out, "    location / {{");
+            let _ = writeln!(
+                out,
+                "        proxy_pass http://{upstream}:{};",
+                allocation.port
+            );
+            let _ = writeln!(out, "        proxy_set_header Host $host;");
+            let _ = writeln!(
+                out,
+                "        proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;"
+            );
+            let _ = writeln!(out, "        proxy_http_version 1.1;");
+            let _ = writeln!(out, "        proxy_set_header Upgrade $http_upgrade;");
+            let _ = writeln!(out, "        proxy_set_header Connection \"upgrade\";");
+            let _ = writeln!(out, "    }}");
+            let _ = writeln!(out, "}}");
+            let _ = writeln!(out);
+        }
+    }
+    out
+}
+
+/// Renders a Caddyfile site block per allocation.
+pub fn render_caddy(projects: &[(&str, &Project)], upstream: &str) -> String {
+    let mut out = String::new();
+    for (project_name, project) in projects {
+        for (name, allocation) in &project.ports {
+            let host = proxy_hostname(project_name, name, allocation);
+            let _ = writeln!(out, "# {project_name}.{name}");
+            let _ = writeln!(out, "http://{host} {{");
+            let _ = writeln!(out, "    reverse_proxy {upstream}:{}", allocation.port);
+            let _ = writeln!(out, "}}");
+            let _ = writeln!(out);
+        }
+    }
+    out
+}
+
+/// Renders a Procfile (as read by foreman, overmind, and honcho) with one
+/// process per allocation that has `command` metadata.
+///
+/// `{port}` in the command is replaced with the allocated port. Process names
+/// are the port name, prefixed with the project when exporting several.
+pub fn render_procfile(projects: &[(&str, &Project)], _upstream: &str) -> String {
+    let qualify = projects.len() > 1;
+    let mut out = String::new();
+    for (project_name, project) in projects {
+        for (name, allocation) in &project.ports {
+            let Some(command) = &allocation.meta.command else {
+                continue;
+            };
+            let command = command.replace("{port}", &allocation.port.to_string());
+            if qualify {
+                let _ = writeln!(out, "{project_name}-{name}: {command}");
+            } else {
+                let _ = writeln!(out, "{name}: {command}");
+            }
+        }
+    }
+    out
+}
+
+/// Merges a project's allocations into a devcontainer.json object.
+///
+/// Each port is added to `forwardPorts` (existing entries are kept) and gets a
+/// `portsAttributes` label with its name. Other attributes, such as
+/// `onAutoForward`, are preserved.
+pub fn update_devcontainer(config: &mut Map<String, Value>, project: &Project) {
+    let forward = config
+        .entry("forwardPorts")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    if !forward.is_array() {
+        *forward = Value::Array(Vec::new());
+    }
+    if let Value::Array(forward) = forward {
+        for allocation in project.ports.values() {
+            let port = json!(allocation.port);
+            if !forward.contains(&port) {
+                forward.push(port);
+            }
+        }
+    }
+
+    let attributes = config
+        .entry("portsAttributes")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !attributes.is_object() {
+        *attributes = Value::Object(Map::new());
+    }
+    if let Value::Object(attributes) = attributes {
+        for (name, allocation) in &project.ports {
+            let entry = attributes
+                .entry(allocation.port.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !entry.is_object() {
+                *entry = Value::Object(Map::new());
+            }
+            if let Value::Object(entry) = entry {
+                entry.insert("label".to_string(), json!(name));
+            }
+        }
+    }
+}
+
+/// Strips `//` and `/* */` comments and trailing commas from JSONC text
+/// (the dialect devcontainer.json is written in) so it parses as JSON.
+pub fn strip_jsonc(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        out.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            (']' | '}', _) => {
+                // Drop a trailing comma before the closing bracket
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PortMeta;
+    use crate::name::PortName;
+    use crate::port::Port;
+
+    fn project() -> Project {
+        let mut project = Project::default();
+        project.ports.insert(
+            PortName::new("web").unwrap(),
+            Allocation::new(Port::new(8080).unwrap(), PortMeta::default()),
+        );
+        project.ports.insert(
+            PortName::new("admin").unwrap(),
+            Allocation::new(
+                Port::new(8081).unwrap(),
+                PortMeta {
+                    hostname: Some("admin.test".to_string()),
+                    ..Default::default()
+                },
+            ),
+        );
+        project
+    }
+
+    #[test]
+    fn test_render_nginx() {
+        let project = project();
+        let out = render_nginx(&[("webapp", &project)], "127.0.0.1");
+
+        assert!(out.contains("server_name web.webapp.localhost;"));
+        assert!(out.contains("proxy_pass http://127.0.0.1:8080;"));
+        assert!(out.contains("server_name admin.test;"));
+        assert_eq!(out.matches("server {").count(), 2);
+    }
+
+    #[test]
+    fn test_render_caddy() {
+        let project = project();
+        let out = render_caddy(&[("webapp", &project)], "127.0.0.1");
+
+        assert!(out.contains("http://web.webapp.localhost {\n    reverse_proxy 127.0.0.1:8080\n}"));
+        assert!(out.contains("http://admin.test {"));
+
+        let out = render_caddy(&[("webapp", &project)], "10.0.0.5");
+        assert!(out.contains("reverse_proxy 10.0.0.5:8080"));
+    }
+
+    #[test]
+    fn test_check_hostnames() {
+        let mut project = project();
+        project.ports.insert(
+            PortName::new("admin_ui").unwrap(),
+            Allocation::new(Port::new(8082).unwrap(), PortMeta::default()),
+        );
+        assert!(check_hostnames(&[("webapp", &project)]).is_ok());
+
+        project.ports.get_mut("admin").unwrap().meta.hostname =
+            Some("admin.test;\n    root /".to_string());
+        let err = check_hostnames(&[("webapp", &project)]).unwrap_err();
+        assert_eq!(err.code(), "E_INVALID_HOSTNAME");
+        assert!(err.to_string().contains("webapp.admin"));
+    }
+
+    #[test]
+    fn test_update_devcontainer() {
+        let project = project();
+        let mut config: Map<String, Value> = serde_json::from_str(
+            r#"{
+                "name": "webapp",
+                "forwardPorts": [8080, 9229],
+                "portsAttributes": {"8080": {"onAutoForward": "openBrowser"}}
+            }"#,
+        )
+        .unwrap();
+
+        update_devcontainer(&mut config, &project);
+
+        assert_eq!(config["forwardPorts"], json!([8080, 9229, 8081]));
+        assert_eq!(
+            config["portsAttributes"]["8080"],
+            json!({"onAutoForward": "openBrowser", "label": "web"})
+        );
+        assert_eq!(config["portsAttributes"]["8081"], json!({"label": "admin"}));
+        assert_eq!(config["name"], json!("webapp"));
+    }
+
+    #[test]
+    fn test_strip_jsonc() {
+        let input = r#"{
+            // The image to use
+            "image": "mcr.microsoft.com/devcontainers/base", /* inline */
+            "url": "http://example.com//path",
+            "forwardPorts": [3000,],
+        }"#;
+
+        let value: Value = serde_json::from_str(&strip_jsonc(input)).unwrap();
+        assert_eq!(value["url"], json!("http://example.com//path"));
+        assert_eq!(value["forwardPorts"], json!([3000]));
+    }
+
+    #[test]
+    fn test_render_procfile() {
+        let mut project = project();
+        project.ports.get_mut("web").unwrap().meta.command =
+            Some("npm run dev -- --port {port}".to_string());
+
+        let out = render_procfile(&[("webapp", &project)], "127.0.0.1");
+        assert_eq!(out, "web: npm run dev -- --port 8080\n");
+
+        let other = Project::default();
+        let out = render_procfile(&[("webapp", &project), ("other", &other)], "127.0.0.1");
+        assert_eq!(out, "webapp-web: npm run dev -- --port 8080\n");
+    }
+}
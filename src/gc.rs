@@ -0,0 +1,303 @@
+//! `pm gc`: applies every cleanup policy in one pass.
+//!
+//! An allocation is freed when its scheduled time (`pm free --at/--after`,
+//! `pm allocate --ttl`) has passed, when the process it was bound to with
+//! `pm allocate --pid` has exited, or when it has not been seen listening
+//! for `stale_after_days`. Locked allocations are never freed.
+//!
+//! It also forgets the previous ports of names freed more than
+//! [`HISTORY_DAYS`] ago, so the registry's history doesn't grow forever.
+
+use chrono::{DateTime, Duration, FixedOffset};
+
+use crate::model::Registry;
+use crate::name::PortName;
+use crate::port::Port;
+use crate::ports::ListeningPort;
+
+/// Days a freed name's previous port is remembered.
+pub const HISTORY_DAYS: i64 = 90;
+
+/// Why `pm gc` freed an allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// Its scheduled free time passed.
+    Scheduled(DateTime<FixedOffset>),
+    /// The process it was bound to exited.
+    ProcessExited(i32),
+    /// Not seen listening since this time.
+    Stale(DateTime<FixedOffset>),
+}
+
+/// An allocation freed by [`collect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collected {
+    pub project: String,
+    pub name: String,
+    pub port: Port,
+    pub reason: Reason,
+}
+
+/// Frees every allocation a cleanup policy applies to, returning them sorted
+/// by project and name.
+///
+/// `listening` is `None` when port detection failed; staleness is then not
+/// judged, since every port would look idle. `process_alive` returns `None`
+/// when it can't tell, which keeps the allocation.
+pub fn collect(
+    registry: &mut Registry,
+    now: DateTime<FixedOffset>,
+    listening: Option<&[ListeningPort]>,
+    process_alive: impl Fn(i32) -> Option<bool>,
+) -> Vec<Collected> {
+    let stale_after = registry
+        .defaults
+        .stale_after_days
+        .map(|days| Duration::days(days.into()));
+    let mut collected = Vec::new();
+    let mut remembered = Vec::new();
+
+    for (project_name, project) in registry.projects.iter_mut() {
+        let mut freed: Vec<(PortName, Reason)> = Vec::new();
+        for (name, allocation) in project.ports.iter_mut() {
+            let meta = &mut allocation.meta;
+            // Only this machine's ports can be seen listening
+            let active = listening
+                .filter(|_| meta.host.is_none())
+                .map(|ports| ports.iter().any(|lp| lp.port == allocation.port));
+
+            // Start the staleness clock, or restart it while the port is in use
+            if stale_after.is_some()
+                && (active == Some(true) || (active.is_some() && meta.last_active.is_none()))
+            {
+                meta.last_active = Some(now);
+            }
+            if meta.locked {
+                continue;
+            }
+
+            let reason = if let Some(at) = meta.free_at.filter(|at| *at <= now) {
+                Some(Reason::Scheduled(at))
+            } else if let Some(pid) = meta.pid.filter(|pid| process_alive(*pid) == Some(false)) {
+                Some(Reason::ProcessExited(pid))
+            } else {
+                match (stale_after, meta.last_active) {
+                    (Some(after), Some(since)) if active == Some(false) && now - since >= after => {
+                        Some(Reason::Stale(since))
+                    }
+                    _ => None,
+                }
+            };
+            if let Some(reason) = reason {
+                freed.push((name.clone(), reason));
+            }
+        }
+
+        for (name, reason) in freed {
+            if let Some(allocation) = project.ports.remove(&name) {
+                remembered.push((project_name.clone(), name.clone(), allocation.port));
+                collected.push(Collected {
+                    project: project_name.to_string(),
+                    name: name.into(),
+                    port: allocation.port,
+                    reason,
+                });
+            }
+        }
+        project.prune_aliases();
+    }
+    registry
+        .projects
+        .retain(|_, project| !project.ports.is_empty());
+    for (project_name, name, port) in &remembered {
+        registry.remember_port(project_name, name, *port);
+    }
+    collected
+}
+
+/// Forgets the previous ports of names freed more than [`HISTORY_DAYS`]
+/// before `now`, returning how many were forgotten.
+pub fn forget_old_ports(registry: &mut Registry, now: DateTime<FixedOffset>) -> usize {
+    registry.forget_ports_freed_before(now - Duration::days(HISTORY_DAYS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Allocation, PortMeta, PreviousPort, Project};
+    use crate::name::ProjectName;
+
+    fn at(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn port(n: u16) -> Port {
+        Port::new(n).unwrap()
+    }
+
+    fn registry(entries: &[(&str, u16, PortMeta)]) -> Registry {
+        let mut project = Project::default();
+        for (name, n, meta) in entries {
+            project.ports.insert(
+                PortName::new(*name).unwrap(),
+                Allocation::new(port(*n), meta.clone()),
+            );
+        }
+        let mut registry = Registry::default();
+        registry
+            .projects
+            .insert(ProjectName::new("demo").unwrap(), project);
+        registry
+    }
+
+    fn listening(n: u16) -> ListeningPort {
+        ListeningPort {
+            port: port(n),
+            pid: None,
+            process_name: None,
+            process_cwd: None,
+            process_command: None,
+            uid: None,
+            user: None,
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_scheduled_and_bound_pid() {
+        let due = at("2026-06-01T18:00:00+00:00");
+        let mut registry = registry(&[
+            (
+                "web",
+                8080,
+                PortMeta {
+                    free_at: Some(due),
+                    ..Default::default()
+                },
+            ),
+            (
+                "api",
+                3000,
+                PortMeta {
+                    free_at: Some(due),
+                    locked: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "worker",
+                9000,
+                PortMeta {
+                    pid: Some(4242),
+                    ..Default::default()
+                },
+            ),
+            (
+                "db",
+                5432,
+                PortMeta {
+                    pid: Some(1),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let alive = |pid| Some(pid == 1);
+
+        assert!(collect(
+            &mut registry.clone(),
+            due - Duration::minutes(1),
+            None,
+            |_| None
+        )
+        .is_empty());
+
+        let collected = collect(&mut registry, due, None, alive);
+        let reasons: Vec<_> = collected
+            .iter()
+            .map(|c| (c.name.as_str(), c.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("web", Reason::Scheduled(due)),
+                ("worker", Reason::ProcessExited(4242)),
+            ]
+        );
+        // Locked allocations and live processes keep their ports
+        let ports = &registry.projects["demo"].ports;
+        assert!(ports.contains_key("api") && ports.contains_key("db"));
+    }
+
+    #[test]
+    fn test_stale_allocations() {
+        let start = at("2026-06-01T00:00:00+00:00");
+        let mut registry = registry(&[
+            ("web", 8080, PortMeta::default()),
+            ("api", 3000, PortMeta::default()),
+        ]);
+
+        // No threshold: nothing is tracked or freed
+        assert!(collect(&mut registry, start, Some(&[]), |_| None).is_empty());
+        assert_eq!(
+            registry.projects["demo"].ports["web"].meta.last_active,
+            None
+        );
+
+        registry.defaults.stale_after_days = Some(7);
+        collect(&mut registry, start, Some(&[]), |_| None);
+        assert_eq!(
+            registry.projects["demo"].ports["web"].meta.last_active,
+            Some(start)
+        );
+
+        // web keeps listening; api doesn't
+        let later = start + Duration::days(3);
+        collect(&mut registry, later, Some(&[listening(8080)]), |_| None);
+
+        // Detection failures never count as idle
+        let expired = start + Duration::days(8);
+        assert!(collect(&mut registry, expired, None, |_| None).is_empty());
+
+        let collected = collect(&mut registry, expired, Some(&[]), |_| None);
+        assert_eq!(
+            collected,
+            vec![Collected {
+                project: "demo".to_string(),
+                name: "api".to_string(),
+                port: port(3000),
+                reason: Reason::Stale(start),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_forget_old_ports() {
+        let now = at("2026-06-01T12:00:00+00:00");
+        let mut registry = Registry::default();
+        let history = registry
+            .history
+            .entry(ProjectName::new("demo").unwrap())
+            .or_default();
+        for (name, n, freed_at) in [
+            ("web", 8080, "2026-05-20T12:00:00+00:00"),
+            ("api", 3000, "2026-01-01T12:00:00+00:00"),
+        ] {
+            history.insert(
+                PortName::new(name).unwrap(),
+                PreviousPort {
+                    port: port(n),
+                    freed_at: at(freed_at),
+                },
+            );
+        }
+
+        assert_eq!(forget_old_ports(&mut registry, now), 1);
+        assert!(registry.history["demo"].contains_key("web"));
+        assert!(!registry.history["demo"].contains_key("api"));
+        assert_eq!(
+            forget_old_ports(&mut registry, now + Duration::days(365)),
+            1
+        );
+        assert!(registry.history.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+//! Keeping an allocated port bound until its service starts (`pm hold`).
+//!
+//! An allocation only reserves a port among pm users; any other program can
+//! still bind it. `pm hold` binds the port itself and stays in the
+//! foreground, and records its PID and port in a hold file beside the
+//! registry. `pm hold --release` deletes the file; the holder notices, closes
+//! the socket, and exits, and the release returns once the port is free.
+
+use std::fs;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::persistence::config_dir;
+use crate::port::Port;
+use crate::ports::process_alive;
+
+/// How often the holder checks whether it was released.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a release waits for the holder to let go.
+const RELEASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns the hold file for `project.name`.
+fn hold_file(project: &str, name: &str) -> Result<PathBuf> {
+    Ok(config_dir()?
+        .join("holds")
+        .join(format!("{project}.{name}")))
+}
+
+/// Reads a hold file's PID and port.
+fn read_hold(path: &Path) -> Option<(i32, Port)> {
+    let content = fs::read_to_string(path).ok()?;
+    let (pid, port) = content.trim().split_once(' ')?;
+    Some((pid.parse().ok()?, port.parse().ok()?))
+}
+
+/// Returns true if nothing is listening on `port`.
+fn port_free(port: Port) -> bool {
+    TcpListener::bind((Ipv4Addr::UNSPECIFIED, port.as_u16())).is_ok()
+}
+
+/// Binds `port` for `project.name` and blocks until released, calling
+/// `on_hold` once the port is bound.
+pub fn hold(project: &str, name: &str, port: Port, on_hold: impl FnOnce()) -> Result<()> {
+    let failed = |e: io::Error| Error::HoldFailed {
+        port,
+        reason: format!("can't bind it: {e}"),
+    };
+    // The wildcard address blocks binds on every interface, loopback included
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port.as_u16())).map_err(failed)?;
+    // Where IPv6 isn't dual-stack with the bind above, hold it as well
+    let listener_v6 = TcpListener::bind((Ipv6Addr::UNSPECIFIED, port.as_u16())).ok();
+    for listener in std::iter::once(&listener).chain(&listener_v6) {
+        listener.set_nonblocking(true)?;
+    }
+
+    let path = hold_file(project, name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let pid = std::process::id() as i32;
+    fs::write(&path, format!("{pid} {port}\n"))?;
+    on_hold();
+
+    // Held until the hold file goes away or another hold takes it over
+    while read_hold(&path) == Some((pid, port)) {
+        // Close connections right away rather than leaving clients hanging
+        for listener in std::iter::once(&listener).chain(&listener_v6) {
+            while listener.accept().is_ok() {}
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    debug!("released {project}.{name}");
+    Ok(())
+}
+
+/// Releases the hold on `project.name`, waiting until the holder has let go
+/// of the port. Returns the port, or `None` if it wasn't held.
+pub fn release(project: &str, name: &str) -> Result<Option<Port>> {
+    let path = hold_file(project, name)?;
+    let Some((pid, port)) = read_hold(&path) else {
+        return Ok(None);
+    };
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    // A holder killed with Ctrl-C leaves its file behind
+    if process_alive(pid) == Some(false) {
+        return Ok(None);
+    }
+
+    let started = Instant::now();
+    while process_alive(pid) != Some(false) && !port_free(port) {
+        if started.elapsed() > RELEASE_TIMEOUT {
+            return Err(Error::HoldFailed {
+                port,
+                reason: format!(
+                    "PID {pid} still holds it {}s after the release",
+                    RELEASE_TIMEOUT.as_secs()
+                ),
+            });
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(Some(port))
+}
@@ -0,0 +1,164 @@
+//! Shell hooks that export a project's ports on `cd` (`pm hook`).
+//!
+//! `pm hook <shell>` prints a function that runs whenever the working
+//! directory changes. It asks `pm hook <shell> --apply` for the commands to
+//! run: unset the variables the hook exported before, then export the ports
+//! of the directory's linked project, if there is one. The exported names are
+//! kept in `PM_HOOK_VARS`, so variables the user set themselves are never
+//! unset.
+
+use clap::ValueEnum;
+use tracing::warn;
+
+use crate::port::Port;
+
+/// Names of the variables the hook exported, separated by spaces.
+pub const HOOK_VARS: &str = "PM_HOOK_VARS";
+
+/// A shell `pm hook` supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+const BASH_HOOK: &str = r#"_pm_hook() {
+  local previous_exit_status=$?
+  if [[ "${_PM_HOOK_DIR:-}" != "$PWD" ]]; then
+    _PM_HOOK_DIR=$PWD
+    eval "$(command pm hook bash --apply)"
+  fi
+  return $previous_exit_status
+}
+if [[ ";${PROMPT_COMMAND[*]:-};" != *";_pm_hook;"* ]]; then
+  PROMPT_COMMAND="_pm_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
+"#;
+
+const ZSH_HOOK: &str = r#"_pm_hook() {
+  eval "$(command pm hook zsh --apply)"
+}
+typeset -ag chpwd_functions
+if (( ! ${chpwd_functions[(I)_pm_hook]} )); then
+  chpwd_functions=(_pm_hook $chpwd_functions)
+fi
+_pm_hook
+"#;
+
+const FISH_HOOK: &str = r#"function _pm_hook --on-variable PWD
+    command pm hook fish --apply | source
+end
+_pm_hook
+"#;
+
+/// Returns the hook to evaluate in the shell's startup file.
+pub fn hook_script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH_HOOK,
+        Shell::Zsh => ZSH_HOOK,
+        Shell::Fish => FISH_HOOK,
+    }
+}
+
+/// True for a name that is safe to put unquoted in a shell command.
+fn is_var_name(var: &str) -> bool {
+    !var.is_empty() && var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns the commands that unset `previous` (the variables exported last
+/// time) and export `vars`, recording their names in [`HOOK_VARS`].
+///
+/// The shell evaluates the result, so names that aren't plain variable names
+/// are left out of both: `PM_HOOK_VARS` is user-writable, and port names in a
+/// hand-edited or imported registry aren't checked when it loads.
+pub fn apply_commands(shell: Shell, previous: &[&str], vars: &[(String, Port)]) -> String {
+    let mut commands = String::new();
+    let set = |commands: &mut String, var: &str, value: &str| match shell {
+        Shell::Bash | Shell::Zsh => commands.push_str(&format!("export {var}='{value}';\n")),
+        Shell::Fish => commands.push_str(&format!("set -gx {var} '{value}';\n")),
+    };
+    let unset = |commands: &mut String, var: &str| match shell {
+        Shell::Bash | Shell::Zsh => commands.push_str(&format!("unset {var};\n")),
+        Shell::Fish => commands.push_str(&format!("set -e {var};\n")),
+    };
+
+    for var in previous {
+        if is_var_name(var) {
+            unset(&mut commands, var);
+        }
+    }
+    let vars: Vec<&(String, Port)> = vars
+        .iter()
+        .filter(|(var, _)| {
+            let ok = is_var_name(var);
+            if !ok {
+                warn!("Not exporting '{var}': it isn't a valid variable name");
+            }
+            ok
+        })
+        .collect();
+    if vars.is_empty() {
+        if !previous.is_empty() {
+            unset(&mut commands, HOOK_VARS);
+        }
+        return commands;
+    }
+    for (var, port) in &vars {
+        set(&mut commands, var, &port.to_string());
+    }
+    let names: Vec<&str> = vars.iter().map(|(var, _)| var.as_str()).collect();
+    set(&mut commands, HOOK_VARS, &names.join(" "));
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vec<(String, Port)> {
+        vec![
+            ("API_PORT".to_string(), Port::new(3000).unwrap()),
+            ("WEB_PORT".to_string(), Port::new(8000).unwrap()),
+        ]
+    }
+
+    #[test]
+    fn test_apply_commands_entering_a_project() {
+        assert_eq!(
+            apply_commands(Shell::Bash, &["DB_PORT"], &vars()),
+            "unset DB_PORT;\nexport API_PORT='3000';\nexport WEB_PORT='8000';\n\
+             export PM_HOOK_VARS='API_PORT WEB_PORT';\n"
+        );
+        assert_eq!(
+            apply_commands(Shell::Fish, &[], &vars()),
+            "set -gx API_PORT '3000';\nset -gx WEB_PORT '8000';\n\
+             set -gx PM_HOOK_VARS 'API_PORT WEB_PORT';\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_commands_leaving_a_project() {
+        assert_eq!(
+            apply_commands(Shell::Zsh, &["API_PORT", "WEB_PORT"], &[]),
+            "unset API_PORT;\nunset WEB_PORT;\nunset PM_HOOK_VARS;\n"
+        );
+        assert_eq!(
+            apply_commands(Shell::Fish, &["WEB_PORT", "$(rm -rf ~)"], &[]),
+            "set -e WEB_PORT;\nset -e PM_HOOK_VARS;\n"
+        );
+        assert_eq!(apply_commands(Shell::Bash, &[], &[]), "");
+    }
+
+    #[test]
+    fn test_apply_commands_skips_hostile_names() {
+        let mut vars = vars();
+        vars.push(("X$(TOUCH PWNED)_PORT".to_string(), Port::new(4000).unwrap()));
+        let commands = apply_commands(Shell::Bash, &[], &vars);
+        assert!(!commands.contains('$'));
+        assert!(commands.ends_with("export PM_HOOK_VARS='API_PORT WEB_PORT';\n"));
+
+        let hostile = [("A;B_PORT".to_string(), Port::new(4000).unwrap())];
+        assert_eq!(apply_commands(Shell::Fish, &[], &hostile), "");
+    }
+}
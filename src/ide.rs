@@ -0,0 +1,426 @@
+//! `pm ide`: a long-running backend for editor extensions.
+//!
+//! Serves the methods from `docs/RPC_API.md` as JSON-RPC 2.0 over stdin and
+//! stdout, one message per line. Instead of requiring `v1.watch`, it pushes
+//! `v1.changed` notifications for registry changes and `v1.status`
+//! notifications whenever a listener starts or stops on an allocated port,
+//! so an extension can keep a status bar item current without polling.
+
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::display::{
+    build_allocated_port_list, AllocatedPortInfo, ListFilter, PortStatus, ProjectQueryResult,
+    QueryResult,
+};
+use crate::error::{Error, Result};
+use crate::model::PortMeta;
+use crate::name::{PortName, ProjectName};
+use crate::persistence::{load_registry, with_registry_mut};
+use crate::port::Port;
+use crate::ports::{get_listening_ports, listening_ports_for_checks};
+use crate::registry::{
+    allocate_named, free_group, free_port, query_all_ports, query_ports, Allocated, OnExisting,
+};
+use crate::watch::watch_registry;
+use crate::workspace::resolve_project_arg;
+
+/// A JSON-RPC error object.
+#[derive(Debug, PartialEq)]
+struct RpcError {
+    code: i64,
+    message: String,
+    kind: &'static str,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl ToString) -> Self {
+        RpcError {
+            code: -32602,
+            message: message.to_string(),
+            kind: "invalid",
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(e: Error) -> Self {
+        let kind = e.kind();
+        let code = match kind {
+            "not_found" => -32001,
+            "conflict" => -32002,
+            "locked" => -32003,
+            "invalid" => -32004,
+            "exhausted" => -32005,
+            _ => -32000,
+        };
+        RpcError {
+            code,
+            message: e.to_string(),
+            kind,
+        }
+    }
+}
+
+type RpcResult = std::result::Result<Value, RpcError>;
+
+#[derive(Deserialize)]
+struct Request {
+    /// Absent for client notifications, which get no response.
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct AllocateParams {
+    project: String,
+    name: String,
+    port: Option<Port>,
+    #[serde(rename = "type")]
+    port_type: Option<String>,
+    group: Option<String>,
+    hostname: Option<String>,
+    command: Option<String>,
+    expect_process: Option<String>,
+    note: Option<String>,
+    url_template: Option<String>,
+    #[serde(default)]
+    if_absent: bool,
+    #[serde(default)]
+    replace: bool,
+}
+
+#[derive(Deserialize)]
+struct FreeParams {
+    project: Option<String>,
+    name: Option<String>,
+    group: Option<String>,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    if_idle: bool,
+}
+
+#[derive(Deserialize)]
+struct QueryParams {
+    project: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ListParams {
+    #[serde(default)]
+    active: bool,
+    #[serde(default)]
+    project: Vec<String>,
+}
+
+/// Runs until stdin closes, answering requests and pushing notifications.
+///
+/// Listener status is polled every `interval`.
+pub fn run_ide(interval: Duration) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+
+    thread::spawn(|| {
+        let result = watch_registry(|change| {
+            send(&notification("v1.changed", json!({ "change": change })));
+        });
+        if let Err(e) = result {
+            warn!("Registry watch stopped: {e}");
+        }
+    });
+    thread::spawn(move || poll_status(interval));
+
+    for line in io::stdin().lock().lines() {
+        if let Some(response) = handle_line(&line?, &cwd) {
+            send(&response);
+        }
+    }
+    debug!("stdin closed; exiting");
+    Ok(())
+}
+
+/// Writes one message per line. `println!` holds the stdout lock for the
+/// whole line, so messages from different threads never interleave.
+fn send(message: &Value) {
+    println!("{message}");
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Sends `v1.status` with every allocation's status at startup and whenever
+/// a port's status or listening process changes.
+fn poll_status(interval: Duration) {
+    let mut last: Option<Vec<StatusKey>> = None;
+    loop {
+        match load_registry() {
+            Ok(registry) => {
+                let listening = get_listening_ports().unwrap_or_default();
+                let ports =
+                    build_allocated_port_list(&registry, &listening, &ListFilter::default());
+                let keys: Vec<StatusKey> = ports.iter().map(status_key).collect();
+                if last.as_ref() != Some(&keys) {
+                    send(&notification("v1.status", json!({ "ports": ports })));
+                    last = Some(keys);
+                }
+            }
+            Err(e) => warn!("Skipping status poll: {e}"),
+        }
+        thread::sleep(interval);
+    }
+}
+
+type StatusKey = (String, String, Port, PortStatus, Option<i32>);
+
+fn status_key(info: &AllocatedPortInfo) -> StatusKey {
+    (
+        info.project.clone(),
+        info.name.clone(),
+        info.port,
+        info.status,
+        info.pid,
+    )
+}
+
+/// Handles one line of input, returning the response to send, if any.
+fn handle_line(line: &str, cwd: &Path) -> Option<Value> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => return Some(error_response(Value::Null, rpc_error(-32700, e))),
+    };
+    let request: Request = match serde_json::from_value(value) {
+        Ok(request) => request,
+        Err(e) => return Some(error_response(Value::Null, rpc_error(-32600, e))),
+    };
+    let result = dispatch(&request.method, request.params, cwd);
+    let id = request.id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => error_response(id, e),
+    })
+}
+
+fn rpc_error(code: i64, e: impl ToString) -> RpcError {
+    RpcError {
+        code,
+        message: e.to_string(),
+        kind: "invalid",
+    }
+}
+
+fn error_response(id: Value, e: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": e.code, "message": e.message, "data": { "kind": e.kind } },
+    })
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+fn dispatch(method: &str, raw: Value, cwd: &Path) -> RpcResult {
+    match method {
+        "v1.allocate" => allocate(params(raw)?, cwd),
+        "v1.free" => free(params(raw)?, cwd),
+        "v1.query" => query(params(raw)?, cwd),
+        "v1.list" => list(params(raw)?),
+        _ => Err(RpcError {
+            code: -32601,
+            message: format!("Unknown method '{method}'"),
+            kind: "not_found",
+        }),
+    }
+}
+
+fn allocate(p: AllocateParams, cwd: &Path) -> RpcResult {
+    let name = PortName::new(p.name).map_err(Error::from)?;
+    let active_ports = listening_ports_for_checks(false)?;
+    let on_existing = if p.replace {
+        OnExisting::Replace
+    } else if p.if_absent {
+        OnExisting::Keep
+    } else {
+        OnExisting::Fail
+    };
+    let meta = PortMeta {
+        group: p.group,
+        range_type: p.port_type,
+        hostname: p.hostname,
+        command: p.command,
+        expect_process: p.expect_process,
+        note: p.note,
+        url_template: p.url_template,
+        ..Default::default()
+    };
+
+    let (project, allocated) = with_registry_mut(|registry| {
+        let project = ProjectName::new(resolve_project_arg(registry, Some(&p.project), cwd)?)?;
+        let allocated = allocate_named(
+            registry,
+            &project,
+            &name,
+            p.port,
+            meta,
+            on_existing,
+            &active_ports,
+        )?;
+        Ok((project, allocated))
+    })?;
+
+    let previous = match allocated {
+        Allocated::Replaced { old, .. } => Some(old),
+        _ => None,
+    };
+    Ok(json!({
+        "project": project.as_str(),
+        "name": name.as_str(),
+        "port": allocated.port(),
+        "previous": previous,
+    }))
+}
+
+fn free(p: FreeParams, cwd: &Path) -> RpcResult {
+    let active_ports = if p.if_idle {
+        get_listening_ports()?
+    } else {
+        Vec::new()
+    };
+    let freed: Vec<ProjectQueryResult> = match (&p.group, &p.project) {
+        (Some(group), _) => {
+            with_registry_mut(|registry| free_group(registry, group, p.force, &active_ports))?
+                .into_iter()
+                .map(|(project, name, port)| ProjectQueryResult {
+                    project,
+                    name,
+                    port,
+                })
+                .collect()
+        }
+        (None, Some(project)) => {
+            let (project, freed) = with_registry_mut(|registry| {
+                let project = resolve_project_arg(registry, Some(project), cwd)?;
+                let freed = free_port(
+                    registry,
+                    &project,
+                    p.name.as_deref(),
+                    p.force,
+                    &active_ports,
+                )?;
+                Ok((project, freed))
+            })?;
+            freed
+                .into_iter()
+                .map(|(name, port)| ProjectQueryResult {
+                    project: project.clone(),
+                    name,
+                    port,
+                })
+                .collect()
+        }
+        (None, None) => {
+            return Err(RpcError::invalid_params(
+                "v1.free needs 'project' or 'group'",
+            ))
+        }
+    };
+    Ok(json!(freed))
+}
+
+fn query(p: QueryParams, cwd: &Path) -> RpcResult {
+    let registry = load_registry()?;
+    let Some(project) = p.project else {
+        let results: Vec<ProjectQueryResult> = query_all_ports(&registry)
+            .into_iter()
+            .map(|(project, name, port)| ProjectQueryResult {
+                project,
+                name,
+                port,
+            })
+            .collect();
+        return Ok(json!(results));
+    };
+    let project = resolve_project_arg(&registry, Some(&project), cwd)?;
+    let results: Vec<QueryResult> = query_ports(&registry, &project, p.name.as_deref())?
+        .into_iter()
+        .map(|(name, port)| QueryResult { name, port })
+        .collect();
+    Ok(json!(results))
+}
+
+fn list(p: ListParams) -> RpcResult {
+    let registry = load_registry()?;
+    let listening = get_listening_ports().unwrap_or_default();
+    let filter = ListFilter {
+        active: p.active,
+        projects: p.project,
+        ..ListFilter::default()
+    };
+    Ok(json!(build_allocated_port_list(
+        &registry, &listening, &filter
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle(line: &str) -> Option<Value> {
+        handle_line(line, Path::new("."))
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let response = handle("not json").unwrap();
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["error"]["code"], -32700);
+
+        let response = handle(r#"{"jsonrpc":"2.0","id":1}"#).unwrap();
+        assert_eq!(response["error"]["code"], -32600);
+
+        let response = handle(r#"{"jsonrpc":"2.0","id":2,"method":"v1.nope"}"#).unwrap();
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["error"]["code"], -32601);
+
+        let response =
+            handle(r#"{"jsonrpc":"2.0","id":3,"method":"v1.allocate","params":{"port":1}}"#)
+                .unwrap();
+        assert_eq!(response["error"]["code"], -32602);
+
+        // Notifications never get a response, even when they fail
+        assert_eq!(handle(r#"{"jsonrpc":"2.0","method":"v1.nope"}"#), None);
+        assert_eq!(handle("   "), None);
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let e: RpcError = Error::from(crate::error::RegistryError::ProjectNotFound(
+            "webapp".to_string(),
+        ))
+        .into();
+        assert_eq!((e.code, e.kind), (-32001, "not_found"));
+
+        let e: RpcError = Error::from(crate::error::RegistryError::PortLocked {
+            project: "webapp".to_string(),
+            name: "web".to_string(),
+        })
+        .into();
+        assert_eq!((e.code, e.kind), (-32003, "locked"));
+    }
+}
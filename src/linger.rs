@@ -0,0 +1,144 @@
+//! Ports whose connections are still closing down (`--avoid-lingering`).
+//!
+//! After a service stops, connections on its port linger in TIME_WAIT or
+//! CLOSE_WAIT, and binding the port again without SO_REUSEADDR fails until
+//! they are gone. Ports seen in those states are remembered in a file beside
+//! the registry, so they can be avoided for a while afterwards as well.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::debug;
+
+use crate::error::Result;
+use crate::persistence::config_dir;
+use crate::port::Port;
+use crate::ports::{get_closing_ports, ListeningPort};
+
+/// Remembered ports are forgotten after this many seconds, whatever the window.
+const FORGET_AFTER: u64 = 3600;
+
+/// When each port was last seen with closing connections, and in which state.
+type Seen = BTreeMap<Port, (u64, String)>;
+
+fn seen_file() -> Result<PathBuf> {
+    Ok(config_dir()?.join(".lingering"))
+}
+
+/// Reads remembered ports, one `port seconds state` line each.
+fn read_seen(path: &Path) -> Seen {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let port = fields.next()?.parse().ok()?;
+            let seen = fields.next()?.parse().ok()?;
+            Some((port, (seen, fields.next()?.to_string())))
+        })
+        .collect()
+}
+
+fn write_seen(path: &Path, seen: &Seen) -> Result<()> {
+    let content: String = seen
+        .iter()
+        .map(|(port, (at, state))| format!("{port} {at} {state}\n"))
+        .collect();
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Adds the ports closing now to `seen`, drops ports older than
+/// [`FORGET_AFTER`], and returns the ones seen within `within` seconds, as
+/// listeners with no process, described by their state.
+fn update(seen: &mut Seen, closing: &[(Port, &str)], now: u64, within: u64) -> Vec<ListeningPort> {
+    for (port, state) in closing {
+        seen.insert(*port, (now, state.to_string()));
+    }
+    seen.retain(|_, (at, _)| now.saturating_sub(*at) < FORGET_AFTER);
+
+    seen.iter()
+        .filter(|(_, (at, _))| now.saturating_sub(*at) <= within)
+        .map(|(port, (at, state))| {
+            let process_name = match now.saturating_sub(*at) {
+                0 => format!("connections in {state}"),
+                ago => format!("connections in {state} {ago}s ago"),
+            };
+            ListeningPort {
+                port: *port,
+                pid: None,
+                process_name: Some(process_name),
+                process_cwd: None,
+                process_command: None,
+                uid: None,
+                user: None,
+                host: None,
+            }
+        })
+        .collect()
+}
+
+/// Returns ports with connections closing now, or seen closing within the
+/// last `within` seconds, to treat as in use.
+pub fn lingering_ports(within: u64) -> Result<Vec<ListeningPort>> {
+    let closing = get_closing_ports()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let path = seen_file()?;
+    let mut seen = read_seen(&path);
+    let lingering = update(&mut seen, &closing, now, within);
+    // Remembering is a nicety; the ports closing now are still avoided
+    if let Err(e) = write_seen(&path, &seen) {
+        debug!("not remembering lingering ports: {e}");
+    }
+    Ok(lingering)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(n: u16) -> Port {
+        Port::new(n).unwrap()
+    }
+
+    #[test]
+    fn test_update() {
+        let mut seen = Seen::new();
+        seen.insert(port(3000), (900, "CLOSE_WAIT".to_string()));
+        seen.insert(port(4000), (100, "TIME_WAIT".to_string()));
+        let closing = [(port(8080), "TIME_WAIT")];
+
+        let lingering = update(&mut seen, &closing, 1000, 120);
+        let described: Vec<(u16, &str)> = lingering
+            .iter()
+            .map(|lp| (lp.port.as_u16(), lp.process_name.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            described,
+            [
+                (3000, "connections in CLOSE_WAIT 100s ago"),
+                (8080, "connections in TIME_WAIT"),
+            ]
+        );
+        // Port 4000 is out of the window but still remembered
+        assert!(seen.contains_key(&port(4000)));
+
+        update(&mut seen, &[], 100 + FORGET_AFTER, 0);
+        assert!(!seen.contains_key(&port(4000)));
+    }
+
+    #[test]
+    fn test_read_seen() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(".lingering");
+        let mut seen = Seen::new();
+        seen.insert(port(8080), (1000, "TIME_WAIT".to_string()));
+        write_seen(&path, &seen).unwrap();
+        assert_eq!(read_seen(&path), seen);
+        assert!(read_seen(&dir.path().join("missing")).is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+//! Checks on `pm allocate` arguments (`--no-lint` skips them).
+//!
+//! A mistyped port or a swapped project and name goes into the registry as
+//! readily as the right one, and only shows up later as a service on the
+//! wrong port or a project nobody meant to create. These checks catch the
+//! usual slips before anything is saved.
+
+use crate::error::{RegistryError, Result};
+use crate::model::Registry;
+use crate::port::Port;
+
+/// Parses a port argument as typed, ignoring spaces and leading zeros
+/// (which [`lint_port`] reports). A number too large to be a port that is a
+/// port typed twice, like 80808080, is reported as such.
+pub fn parse_port_arg(raw: &str) -> Result<Port> {
+    let digits: String = raw.chars().filter(|c| !c.is_whitespace()).collect();
+    if let Ok(port) = digits.parse() {
+        return Ok(port);
+    }
+    let (first, second) = digits.split_at(digits.len() / 2);
+    match first.parse::<Port>() {
+        Ok(port) if first == second => Err(RegistryError::PortTypedTwice {
+            input: raw.to_string(),
+            port,
+        }
+        .into()),
+        _ => Err(RegistryError::InvalidPortNumber(raw.to_string()).into()),
+    }
+}
+
+/// Returns likely mistakes in a port argument that still parses.
+pub fn lint_port(raw: &str, port: Port) -> Vec<String> {
+    let mut findings = Vec::new();
+    if raw.contains(char::is_whitespace) {
+        findings.push(format!("Port '{raw}' has spaces in it; it would be {port}"));
+    } else if raw.len() > 1 && raw.starts_with('0') {
+        findings.push(format!(
+            "Port '{raw}' has a leading zero; it would be {port}"
+        ));
+    }
+    findings
+}
+
+/// Returns likely mistakes in the project and port name of
+/// `pm allocate <project> <name>`, or of `pm allocate <name>` when
+/// `project` is `None`.
+pub fn lint_names(registry: &Registry, project: Option<&str>, name: &str) -> Vec<String> {
+    let is_project =
+        |s: &str| registry.projects.contains_key(s) || registry.archived.contains_key(s);
+    match project {
+        None if is_project(name) => vec![format!(
+            "'{name}' is a project, not a port name ('pm allocate {name} <name>' allocates in it)"
+        )],
+        Some(project) if project != "." && !is_project(project) && is_project(name) => {
+            vec![format!(
+                "'{project}' isn't a project but '{name}' is, so they may be the wrong \
+                 way round ('pm allocate {name} {project}')"
+            )]
+        }
+        Some(project) if is_port_shaped(project) && !is_project(project) => vec![format!(
+            "Project '{project}' looks like a port; the port goes after the port name \
+             ('pm allocate <project> {name} {project}')"
+        )],
+        _ => Vec::new(),
+    }
+}
+
+/// True for a name that is all digits, like a port number.
+fn is_port_shaped(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Project;
+    use crate::name::ProjectName;
+
+    #[test]
+    fn test_parse_port_arg() {
+        assert_eq!(parse_port_arg("8080").unwrap().as_u16(), 8080);
+        assert_eq!(parse_port_arg(" 80 80").unwrap().as_u16(), 8080);
+        assert_eq!(parse_port_arg("08080").unwrap().as_u16(), 8080);
+        assert_eq!(
+            parse_port_arg("80808080").unwrap_err().code(),
+            "E_INVALID_PORT"
+        );
+        assert!(parse_port_arg("80808080")
+            .unwrap_err()
+            .to_string()
+            .contains("8080 typed twice"));
+        assert!(parse_port_arg("0").is_err());
+        assert!(parse_port_arg("web").is_err());
+    }
+
+    #[test]
+    fn test_lint_port() {
+        let port = Port::new(8080).unwrap();
+        assert!(lint_port("8080", port).is_empty());
+        assert_eq!(lint_port("08080", port).len(), 1);
+        assert!(lint_port("80 80", port)[0].contains("spaces"));
+    }
+
+    #[test]
+    fn test_lint_names() {
+        let mut registry = Registry::default();
+        registry
+            .projects
+            .insert(ProjectName::new("webapp").unwrap(), Project::default());
+
+        assert!(lint_names(&registry, Some("webapp"), "web").is_empty());
+        assert!(lint_names(&registry, Some("shop"), "web").is_empty());
+        assert!(
+            lint_names(&registry, Some("web"), "webapp")[0].contains("('pm allocate webapp web')")
+        );
+        assert_eq!(lint_names(&registry, None, "webapp").len(), 1);
+        assert!(lint_names(&registry, None, "web").is_empty());
+        assert!(lint_names(&registry, Some("."), "webapp").is_empty());
+
+        assert!(lint_names(&registry, Some("08080"), "web")[0].contains("looks like a port"));
+        registry
+            .projects
+            .insert(ProjectName::new("2024").unwrap(), Project::default());
+        assert!(lint_names(&registry, Some("2024"), "web").is_empty());
+    }
+}
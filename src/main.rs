@@ -1,192 +1,2699 @@
 //! Port Manager CLI - manage port allocations across projects.
 
+mod address;
+mod agent;
+mod bundle;
+mod check;
 mod cli;
+mod complete;
+mod diff;
 mod display;
+mod docs;
 mod error;
+mod explain;
+mod export;
+mod gc;
+mod gitsync;
+mod hold;
+mod hook;
+mod ide;
+mod linger;
+mod lint;
+mod merge;
 mod model;
+mod name;
+mod pager;
 mod persistence;
 mod port;
 mod ports;
+mod preset;
+mod profile;
 mod registry;
+mod remote;
+mod repair;
+mod scan;
+mod schedule;
+mod service;
+mod snapshot;
+mod subprocess;
+mod template;
+mod theme;
+mod transfer;
+#[cfg(feature = "self-update")]
+mod update;
+mod usage;
+mod validate;
+mod watch;
+mod wellknown;
+mod workspace;
 
-use clap::Parser;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use cli::{Cli, Command};
+use chrono::{DateTime, FixedOffset};
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use tracing::{debug, warn, Level};
+
+use address::Address;
+use agent::{combined_listening_ports, serve};
+use bundle::write_bundle;
+use check::{find_clashes, Clash};
+use cli::{Cli, Command, ConfigAction, ExportTarget, ImportSource, ProfileAction, SchemaFormat};
+use complete::complete_words;
+use diff::{diff_registries, read_registry_file, RegistryDiff};
 use display::{
     build_allocated_port_list, build_status_port_list, display_allocated_ports,
-    display_allocated_ports_json, display_config, display_config_json, display_query,
-    display_query_json, display_status, display_status_json, display_suggestions,
-    display_suggestions_json,
+    display_allocated_ports_json, display_allocated_ports_short, display_clashes,
+    display_clashes_json, display_config, display_config_json, display_error_json,
+    display_explanation, display_explanations, display_gc_report, display_merge_steps,
+    display_preset_changes, display_profiles, display_profiles_json, display_prompt, display_query,
+    display_query_all, display_query_all_json, display_query_json, display_query_projects_json,
+    display_registry_change, display_registry_change_json, display_registry_diff,
+    display_registry_diff_json, display_registry_json, display_repair, display_schema,
+    display_snapshot_json, display_status, display_status_json, display_status_short,
+    display_suggestion_report, display_suggestion_report_json, display_suggestions,
+    display_suggestions_json, display_tmux_segment, display_urls, display_urls_json, display_usage,
+    display_usage_json, display_validation, display_validation_json, format_local_time,
+    versioned_schema, AllocatedPortInfo, ConfigInfo, JsonVersion, ListFilter, OutputFormat,
+    ProfileInfo, ProjectQueryResult, QueryResult, StatusPortInfo, SuggestedPort,
+    SuggestionExplanation, UrlResult, ValidationReport,
+};
+use docs::{render_man, render_markdown, write_man_pages};
+use error::{ConfigError, Error, PortDetectionError, RegistryError, Result};
+use explain::{explain, EXPLANATIONS};
+use export::{
+    check_hostnames, render_caddy, render_nginx, render_procfile, strip_jsonc, update_devcontainer,
+    Renderer,
+};
+use gitsync::{sync, SyncOutcome};
+use hook::{apply_commands, hook_script, Shell, HOOK_VARS};
+use ide::run_ide;
+use linger::lingering_ports;
+use lint::{lint_names, lint_port, parse_port_arg};
+use merge::{merge_registries, Collision, CollisionKind, Resolution};
+use model::{PortMeta, Project, Registry};
+use name::{is_glob, PortName, ProjectName, MAX_NAME_LEN};
+use persistence::{
+    load_registry, profile_registry_path, read_registry_content, registry_path,
+    replace_broken_registry, replace_registry_content, set_profile_override,
+    set_registry_path_override, switch_profile, with_registry_locked, with_registry_mut,
 };
-use error::Result;
-use persistence::{load_registry, registry_path, with_registry_mut};
 use port::Port;
-use ports::get_listening_ports;
-use registry::{allocate_port, free_port, query_ports, set_port_range, suggest_port};
+use ports::{
+    ephemeral_range, get_listening_ports, listening_ports_for_checks, process_alive, ListeningPort,
+};
+use preset::{apply_presets, Preset};
+use profile::{create_profile, ensure_profile_exists, list_profiles, validate_profile_name};
+use registry::{
+    add_alias, add_dependency, allocate_named, allocate_port, archive_project, dependency_ports,
+    env_urls, env_vars, existing_port, explain_suggestions, find_orphans, free_group, free_port,
+    get_allocation, in_group, migrate_range, query_all_ports, query_matching_ports, query_ports,
+    range_strays, reallocate_port, remove_alias, remove_dependency, rename_port, schedule_free,
+    service_urls, set_locked, set_port_range, suggest_port, unarchive_project, Allocated,
+    OnExisting,
+};
+use remote::{parse_listing, remote_listening_ports};
+use repair::salvage;
+use scan::scan_directory;
+use service::{service_files, service_paths, Manager, SYSTEMD_TIMER};
+use snapshot::{capture, read_snapshot, read_snapshot_or_registry};
+use template::Template;
+use transfer::{merge_registry, parse_registry_json, system_conflicts};
+use usage::{usage_report, UsageReport};
+use validate::{validate_content, validate_parsed, validate_registry};
+use watch::{watch_registry, RegistryChange};
+use wellknown::service_name;
+use workspace::{
+    find_git_root, infer_project, link_directory, load_repo_config, repo_link_key,
+    resolve_project_arg, save_repo_config, unlink_directory, REPO_CONFIG_FILE,
+};
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("Error: {e}");
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
+    let matches = Cli::command().get_matches();
+    let json = json_requested(&matches);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let result = run(cli);
+    pager::finish();
+    if let Err(e) = result {
+        match json {
+            Some(version) => display_error_json(&e, version),
+            None => eprintln!("Error: {e}"),
+        }
         std::process::exit(1);
     }
 }
 
-fn run() -> Result<()> {
-    let cli = Cli::parse();
+/// Returns the version of the invoked command's `--json` flag, if given, so
+/// errors are reported in the format the caller is parsing. Arguments passed
+/// through to other programs (`pm run -- ... --json`) don't count.
+fn json_requested(matches: &ArgMatches) -> Option<JsonVersion> {
+    let mut json = None;
+    let mut matches = Some(matches);
+    while let Some(current) = matches {
+        if let Ok(Some(version)) = current.try_get_one::<JsonVersion>("json") {
+            json = Some(*version);
+        }
+        matches = current.subcommand().map(|(_, sub)| sub);
+    }
+    json
+}
+
+/// Sends log output to stderr at a level picked by -v/-q (warnings by default).
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => Level::ERROR,
+        (false, 0) => Level::WARN,
+        (false, 1) => Level::DEBUG,
+        (false, _) => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+fn run(cli: Cli) -> Result<()> {
+    init_logging(cli.verbose, cli.quiet);
+    if cli.no_pager {
+        pager::disable();
+    }
+    if let Some(path) = &cli.config {
+        set_registry_path_override(path)?;
+    }
+    if let Some(profile) = &cli.profile {
+        // `pm --profile x profile create x` is fine; anything else needs it to exist
+        if matches!(cli.command, Command::Profile(_)) {
+            validate_profile_name(profile)?;
+        } else {
+            ensure_profile_exists(profile)?;
+        }
+        set_profile_override(profile);
+    }
 
     match cli.command {
+        Command::Init {
+            ranges,
+            link,
+            repo,
+            template,
+            project,
+        } => cmd_init(&ranges, link.as_deref(), repo, template, project.as_deref()),
+
         Command::Allocate {
             project,
             name,
             port,
-        } => cmd_allocate(&project, &name, port),
+            r#type,
+            group,
+            hostname,
+            host,
+            command,
+            expect_process,
+            note,
+            url_template,
+            ttl,
+            pid,
+            replace,
+            if_absent,
+            require_detection,
+            avoid_lingering,
+            no_lint,
+        } => {
+            let raw_port = port;
+            let port = raw_port.as_deref().map(parse_port_arg).transpose()?;
+            if !no_lint {
+                let (project, name) = match &name {
+                    Some(name) => (Some(project.as_str()), name),
+                    None => (None, &project),
+                };
+                let registry = load_registry()?;
+                let mut findings = lint_names(&registry, project, name);
+                if let (Some(raw), Some(port)) = (&raw_port, port) {
+                    findings.extend(lint_port(raw, port));
+                }
+                if !findings.is_empty() {
+                    return Err(Error::Linted(findings));
+                }
+            }
+            let meta = PortMeta {
+                group,
+                range_type: r#type,
+                hostname,
+                host,
+                command,
+                expect_process,
+                note,
+                url_template,
+                free_at: ttl,
+                pid,
+                ..Default::default()
+            };
+            let on_existing = if replace {
+                OnExisting::Replace
+            } else if if_absent {
+                OnExisting::Keep
+            } else {
+                OnExisting::Fail
+            };
+            // Another host's listeners can't be seen from here
+            let mut active_ports = if meta.host.is_some() {
+                Vec::new()
+            } else {
+                listening_ports_for_checks(require_detection)?
+            };
+            if let Some(within) = avoid_lingering {
+                active_ports.extend(lingering_ports(within)?);
+            }
+            match name {
+                Some(name) => cmd_allocate(
+                    Some(&project),
+                    &name,
+                    port,
+                    meta,
+                    on_existing,
+                    &active_ports,
+                    cli.quiet,
+                ),
+                // A single argument is the port name; infer the project
+                None => cmd_allocate(
+                    None,
+                    &project,
+                    port,
+                    meta,
+                    on_existing,
+                    &active_ports,
+                    cli.quiet,
+                ),
+            }
+        }
+
+        Command::Free {
+            project,
+            name,
+            group,
+            orphans,
+            migrate,
+            yes,
+            dry_run,
+            force,
+            if_idle,
+            forget,
+            at,
+            after,
+        } => match (group, at.or(after)) {
+            _ if orphans => cmd_free_orphans(migrate, yes, dry_run, force, if_idle),
+            (Some(group), _) => cmd_free_group(&group, force, if_idle, cli.quiet),
+            // clap guarantees project is present when --group is absent
+            (None, Some(at)) => {
+                cmd_schedule_free(project.as_deref().unwrap_or_default(), name.as_deref(), at)
+            }
+            (None, None) => cmd_free(
+                project.as_deref().unwrap_or_default(),
+                name.as_deref(),
+                force,
+                if_idle,
+                forget,
+                cli.quiet,
+            ),
+        },
+
+        Command::Archive { project, force } => match project {
+            Some(project) => cmd_archive(&project, force),
+            None => cmd_archived(),
+        },
+
+        Command::Unarchive { project } => cmd_unarchive(&project),
+
+        Command::Gc { dry_run } => cmd_gc(dry_run),
+
+        Command::Usage { json } => cmd_usage(json.version),
+
+        Command::InstallService {
+            interval,
+            no_start,
+            print,
+        } => cmd_install_service(interval, !no_start, print),
+
+        Command::UninstallService => cmd_uninstall_service(),
+
+        Command::Lock { project, name } => cmd_lock(&project, name.as_deref(), true),
 
-        Command::Free { project, name } => cmd_free(&project, name.as_deref()),
+        Command::Unlock { project, name } => cmd_lock(&project, name.as_deref(), false),
+
+        Command::Link {
+            dir,
+            project,
+            git_root,
+            repo,
+        } => match dir {
+            Some(dir) => cmd_link(&dir, project.as_deref(), git_root, repo),
+            None => cmd_links(),
+        },
+
+        Command::Unlink { dir } => cmd_unlink(&dir),
+
+        Command::Alias {
+            project,
+            name,
+            alias,
+        } => cmd_alias(&project, &name, &alias),
+
+        Command::Unalias { project, alias } => cmd_unalias(&project, &alias),
+
+        Command::RenamePort {
+            project,
+            old_name,
+            new_name,
+            force,
+        } => cmd_rename_port(&project, &old_name, &new_name, force),
+
+        Command::Depend { project, on, name } => cmd_depend(&project, &on, name.as_deref()),
+
+        Command::Undepend { project, on, name } => cmd_undepend(&project, &on, name.as_deref()),
 
         Command::List {
             active,
             unassigned,
+            project,
+            group,
+            long,
+            host,
+            output,
             json,
-        } => cmd_list(active, unassigned, json),
+        } => {
+            let filter = ListFilter {
+                active,
+                projects: project,
+                group,
+                hosts: host,
+            };
+            cmd_list(unassigned, &filter, long, output, json.version)
+        }
 
         Command::Query {
             project,
             name,
+            all,
+            with_deps,
+            group,
+            json,
+        } => {
+            if all {
+                cmd_query_all(group.as_deref(), json.version)
+            } else {
+                cmd_query(
+                    project.as_deref(),
+                    name.as_deref(),
+                    with_deps,
+                    group.as_deref(),
+                    json.version,
+                )
+            }
+        }
+
+        Command::Get {
+            project,
+            name,
+            r#type,
+        } => match name {
+            Some(name) => cmd_get(Some(&project), &name, r#type.as_deref()),
+            // A single argument is the port name; infer the project
+            None => cmd_get(None, &project, r#type.as_deref()),
+        },
+
+        Command::Hold {
+            project,
+            name,
+            release,
+        } => match name {
+            Some(name) => cmd_hold(Some(&project), &name, release),
+            // A single argument is the port name; infer the project
+            None => cmd_hold(None, &project, release),
+        },
+
+        Command::Open {
+            project,
+            name,
+            print,
+        } => match name {
+            Some(name) => cmd_open(Some(&project), &name, print),
+            // A single argument is the port name; infer the project
+            None => cmd_open(None, &project, print),
+        },
+
+        Command::Urls {
+            project,
+            name,
+            host,
             json,
-        } => cmd_query(&project, name.as_deref(), json),
+        } => cmd_urls(
+            project.as_deref(),
+            name.as_deref(),
+            host.as_ref(),
+            json.version,
+        ),
 
-        Command::Status { json, full } => cmd_status(json, full),
+        Command::Status {
+            json,
+            full,
+            mine,
+            remote,
+            agent,
+            snapshot,
+            output,
+        } => cmd_status(
+            json.version,
+            full,
+            mine,
+            remote.as_deref(),
+            &agent,
+            snapshot.as_deref(),
+            output,
+        ),
 
         Command::Suggest {
             r#type,
             count,
+            project,
+            explain,
+            avoid_lingering,
+            json,
+        } => cmd_suggest(
+            &r#type,
+            project.as_deref(),
+            count,
+            explain,
+            avoid_lingering,
+            json.version,
+        ),
+
+        Command::Config {
+            action: Some(ConfigAction::Edit),
+            ..
+        } => cmd_config_edit(),
+
+        Command::Config {
+            action: Some(ConfigAction::Validate { file, json }),
+            ..
+        } => cmd_config_validate(file, json.version),
+
+        Command::Config {
+            action: Some(ConfigAction::Repair { dry_run }),
+            ..
+        } => cmd_config_repair(dry_run),
+
+        Command::Config {
+            action: None,
+            set: Some(range_spec),
+            migrate,
+            ..
+        } => cmd_config_set_range(&range_spec, migrate, cli.quiet),
+
+        Command::Config {
+            action: None,
+            path,
+            preset,
+            ignore_case,
+            stale_after_days,
+            git_sync,
+            strict_detection,
+            json,
+            ..
+        } => cmd_config(
+            path,
+            &preset,
+            ignore_case,
+            stale_after_days,
+            git_sync,
+            strict_detection,
+            json.version,
+        ),
+
+        Command::Schema {
+            format,
+            api_version,
+        } => {
+            cmd_schema(format, api_version);
+            Ok(())
+        }
+
+        Command::Profile(action) => cmd_profile(action),
+
+        Command::Sync { remote } => cmd_sync(remote.as_deref()),
+
+        Command::Doctor => cmd_doctor(),
+
+        Command::Snapshot => cmd_snapshot(),
+
+        Command::DebugBundle { output, redact } => cmd_debug_bundle(output, redact),
+
+        Command::Agent { listen } => serve(&listen),
+
+        Command::Complete {
+            shell,
+            cword,
+            words,
+        } => {
+            print!("{}", complete_words(shell, cword, words)?);
+            Ok(())
+        }
+
+        Command::Explain { code } => cmd_explain(code.as_deref()),
+
+        Command::Merge {
+            file,
+            ours,
+            theirs,
+            reassign,
+        } => {
+            let resolution = if ours {
+                Some(Resolution::Ours)
+            } else if theirs {
+                Some(Resolution::Theirs)
+            } else if reassign {
+                Some(Resolution::Reassign)
+            } else {
+                None
+            };
+            cmd_merge(&file, resolution)
+        }
+
+        Command::Diff { old, new, json } => cmd_diff(&old, new.as_deref(), json.version),
+
+        Command::CheckAgainst { snapshot, json } => cmd_check_against(&snapshot, json.version),
+
+        Command::Export(target) => cmd_export(target),
+
+        Command::Import(ImportSource::Scan {
+            dir,
+            project,
+            yes,
+            dry_run,
+        }) => cmd_import_scan(&dir, project.as_deref(), yes, dry_run),
+
+        Command::Import(ImportSource::Registry { file, merge }) => {
+            cmd_import_registry(&file, merge)
+        }
+
+        Command::Import(ImportSource::Netstat {
+            file,
+            project,
+            yes,
+            dry_run,
+            as_status,
             json,
-        } => cmd_suggest(&r#type, count, json),
+        }) => {
+            if as_status {
+                cmd_import_netstat_status(&file, json.version)
+            } else {
+                cmd_import_netstat(&file, project.as_deref(), yes, dry_run)
+            }
+        }
+
+        Command::Env {
+            project,
+            export,
+            host,
+        } => cmd_env(project.as_deref(), export, host.as_ref()),
+
+        Command::Run { project, command } => cmd_run(project.as_deref(), &command),
 
-        Command::Config { path, set, json } => cmd_config(path, set, json),
+        Command::Hook { shell, apply } => cmd_hook(shell, apply),
+
+        Command::WatchRegistry { json } => cmd_watch_registry(json.version),
+
+        Command::Ide { interval } => run_ide(Duration::from_secs(interval.max(1))),
+
+        Command::Prompt => cmd_prompt(),
+
+        Command::Tmux {
+            project,
+            set_env,
+            target,
+            plain,
+        } => {
+            if set_env {
+                cmd_tmux_set_env(project.as_deref(), target.as_deref())
+            } else {
+                cmd_tmux(project.as_deref(), plain)
+            }
+        }
+
+        #[cfg(feature = "self-update")]
+        Command::SelfUpdate { check } => cmd_self_update(check),
+
+        Command::GenDocs {
+            man,
+            markdown: _,
+            out_dir,
+        } => cmd_gen_docs(man, out_dir.as_deref()),
     }
 }
 
-fn cmd_allocate(project: &str, name: &str, port: Option<Port>) -> Result<()> {
-    let active_ports = get_listening_ports().unwrap_or_default();
+fn cmd_init(
+    presets: &[Preset],
+    link: Option<&Path>,
+    repo: bool,
+    template: Option<Template>,
+    project: Option<&str>,
+) -> Result<()> {
+    let path = registry_path()?;
+    if path.exists() {
+        println!("Using registry {}", path.display());
+    } else {
+        // Loading creates it
+        load_registry()?;
+        println!("Created registry {}", path.display());
+    }
+
+    if !presets.is_empty() {
+        // A first run must not leave a registry that fails `pm config validate`
+        let mut problems = Vec::new();
+        let changes = with_registry_mut(|registry| {
+            let changes = apply_presets(registry, presets);
+            problems = validate_registry(registry);
+            if !problems.is_empty() {
+                return Err(ConfigError::ValidationFailed {
+                    path: path.clone(),
+                    count: problems.len(),
+                }
+                .into());
+            }
+            Ok(changes)
+        });
+        if !problems.is_empty() {
+            display_validation(&path, &problems);
+        }
+        display_preset_changes(&changes?);
+    }
 
-    let allocated =
-        with_registry_mut(|registry| allocate_port(registry, project, name, port, &active_ports))?;
+    let dir = match link {
+        Some(dir) => dir.canonicalize()?,
+        None => std::env::current_dir()?,
+    };
+    let project = match project {
+        Some(p) => p.to_string(),
+        None => dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| RegistryError::NoLinkedProject(dir.clone()))?,
+    };
+    if link.is_some() {
+        cmd_link(&dir, Some(&project), false, repo)?;
+    }
 
-    println!("Allocated {project}.{name} = {allocated}");
+    if let Some(template) = template {
+        let active_ports = listening_ports_for_checks(false)?;
+        for name in template.ports() {
+            // Names allocated by an earlier run keep their ports
+            cmd_allocate(
+                Some(&project),
+                name,
+                None,
+                PortMeta::default(),
+                OnExisting::Keep,
+                &active_ports,
+                false,
+            )?;
+        }
+    }
     Ok(())
 }
 
-fn cmd_free(project: &str, name: Option<&str>) -> Result<()> {
-    let freed = with_registry_mut(|registry| free_port(registry, project, name))?;
-
-    for (port_name, port) in freed {
-        println!("Freed {project}.{port_name} (was {port})");
+/// Prints a message for people: on stdout, or on stderr with `--quiet`, which
+/// leaves stdout to the values scripts capture.
+fn say(quiet: bool, message: &str) {
+    if quiet {
+        eprintln!("{message}");
+    } else {
+        println!("{message}");
     }
+}
+
+fn cmd_allocate(
+    project: Option<&str>,
+    name: &str,
+    port: Option<Port>,
+    meta: PortMeta,
+    on_existing: OnExisting,
+    active_ports: &[ListeningPort],
+    quiet: bool,
+) -> Result<()> {
+    let name = PortName::new(name)?;
+    let cwd = std::env::current_dir()?;
+    let on_host = meta
+        .host
+        .as_ref()
+        .map_or_else(String::new, |host| format!(" on {host}"));
+
+    let (project, allocated) = with_registry_mut(|registry| {
+        let project = ProjectName::new(resolve_project_arg(registry, project, &cwd)?)?;
+        let allocated = allocate_named(
+            registry,
+            &project,
+            &name,
+            port,
+            meta,
+            on_existing,
+            active_ports,
+        )?;
+        Ok((project, allocated))
+    })?;
 
+    let (port, message) = match allocated {
+        Allocated::New(port) => (
+            port,
+            format!("Allocated {project}.{name} = {port}{on_host}"),
+        ),
+        Allocated::Existing(port) => (
+            port,
+            format!("{project}.{name} = {port} (already allocated)"),
+        ),
+        Allocated::Replaced { old, new } => {
+            (new, format!("Reallocated {project}.{name}: {old} -> {new}"))
+        }
+    };
+    say(quiet, &message);
+    if quiet {
+        println!("{port}");
+    }
     Ok(())
 }
 
-fn cmd_list(active_only: bool, unassigned_only: bool, json: bool) -> Result<()> {
-    let registry = load_registry()?;
-    let listening = get_listening_ports().unwrap_or_default();
+fn cmd_get(project: Option<&str>, name: &str, port_type: Option<&str>) -> Result<()> {
+    let name = PortName::new(name)?;
+    let active_ports = listening_ports_for_checks(false)?;
+    let cwd = std::env::current_dir()?;
 
-    if unassigned_only {
-        // Show only unassigned listening ports
-        let unassigned: Vec<_> = listening
-            .iter()
-            .filter(|lp| registry.find_port_owner(lp.port).is_none())
-            .cloned()
-            .collect();
-        if json {
-            let ports = build_status_port_list(&unassigned, &registry, false);
-            display_status_json(&ports);
-        } else {
-            display_status(&unassigned, &registry, false);
+    let port = with_registry_mut(|registry| {
+        let project = ProjectName::new(resolve_project_arg(registry, project, &cwd)?)?;
+        if let Some(port) = existing_port(registry, &project, &name) {
+            return Ok(port);
         }
-    } else {
-        let ports = build_allocated_port_list(&registry, &listening, active_only);
-        if json {
-            display_allocated_ports_json(&ports);
-        } else {
-            display_allocated_ports(&ports);
+        let meta = PortMeta {
+            range_type: port_type.map(str::to_string),
+            ..Default::default()
+        };
+        allocate_port(registry, &project, &name, None, meta, &active_ports)
+    })?;
+
+    println!("{port}");
+    Ok(())
+}
+
+fn cmd_hold(project: Option<&str>, name: &str, release: bool) -> Result<()> {
+    let name = PortName::new(name)?;
+    let cwd = std::env::current_dir()?;
+
+    if release {
+        let registry = load_registry()?;
+        let project = resolve_project_arg(&registry, project, &cwd)?;
+        match hold::release(&project, &name)? {
+            Some(port) => println!("Released {project}.{name} = {port}"),
+            None => println!("{project}.{name} is not held"),
         }
+        return Ok(());
     }
 
+    let active_ports = listening_ports_for_checks(false)?;
+    let (project, allocated) = with_registry_mut(|registry| {
+        let project = ProjectName::new(resolve_project_arg(registry, project, &cwd)?)?;
+        let allocated = allocate_named(
+            registry,
+            &project,
+            &name,
+            None,
+            PortMeta::default(),
+            OnExisting::Keep,
+            &active_ports,
+        )?;
+        Ok((project, allocated))
+    })?;
+    let port = allocated.port();
+    hold::hold(&project, &name, port, || {
+        println!(
+            "Holding {project}.{name} = {port} until Ctrl-C or 'pm hold --release {project} {name}'"
+        )
+    })?;
+    println!("Released {project}.{name} = {port}");
     Ok(())
 }
 
-fn cmd_query(project: &str, name: Option<&str>, json: bool) -> Result<()> {
+fn cmd_urls(
+    project: Option<&str>,
+    name: Option<&str>,
+    host: Option<&Address>,
+    json: Option<JsonVersion>,
+) -> Result<()> {
     let registry = load_registry()?;
+    let cwd = std::env::current_dir()?;
+    let project = resolve_project_arg(&registry, project, &cwd)?;
+    let local_host = host.unwrap_or(&Address::Localhost).resolve()?;
+    let urls = service_urls(&registry, &project, name, &local_host)?;
 
-    let ports = query_ports(&registry, project, name)?;
+    match json {
+        Some(version) => display_urls_json(&urls, version),
+        None => display_urls(&urls, name.is_some()),
+    }
+    Ok(())
+}
 
-    if ports.is_empty() {
-        if json {
-            println!("[]");
-        }
-        // No output for scripting - exit success but empty
+fn cmd_open(project: Option<&str>, name: &str, print: bool) -> Result<()> {
+    let registry = load_registry()?;
+    let cwd = std::env::current_dir()?;
+    let project = resolve_project_arg(&registry, project, &cwd)?;
+    let allocation = get_allocation(&registry, &project, name)?;
+    let url = allocation.meta.url(allocation.port);
+
+    if print {
+        println!("{url}");
         return Ok(());
     }
+    open_url(&url)
+}
+
+/// Opens `url` with $BROWSER, or the platform's default handler.
+fn open_url(url: &str) -> Result<()> {
+    let mut command = match std::env::var("BROWSER") {
+        Ok(browser) if !browser.is_empty() => std::process::Command::new(browser),
+        _ if cfg!(target_os = "macos") => std::process::Command::new("open"),
+        _ if cfg!(windows) => {
+            let mut command = std::process::Command::new("cmd");
+            // The empty argument is start's window title
+            command.args(["/C", "start", ""]);
+            command
+        }
+        _ => std::process::Command::new("xdg-open"),
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    run_checked(command.arg(url), &program)
+}
+
+/// Runs `command`, failing with `CommandFailed` (reported as `name`) if it
+/// can't be started or exits unsuccessfully.
+fn run_checked(command: &mut std::process::Command, name: &str) -> Result<()> {
+    let failed = |reason: String| Error::CommandFailed {
+        command: name.to_string(),
+        reason,
+    };
+    let status = command.status().map_err(|e| failed(e.to_string()))?;
+    if !status.success() {
+        return Err(failed(status.to_string()));
+    }
+    Ok(())
+}
 
-    if json {
-        display_query_json(&ports);
+/// Returns the listening ports that `--if-idle` protects, or none without it.
+///
+/// Unlike most commands, detection failures are errors here: freeing a port
+/// we could not check would defeat the point of the flag.
+fn if_idle_guard(if_idle: bool) -> Result<Vec<ListeningPort>> {
+    if if_idle {
+        get_listening_ports()
     } else {
-        display_query(&ports, name.is_some());
+        Ok(Vec::new())
+    }
+}
+
+fn cmd_free(
+    project: &str,
+    name: Option<&str>,
+    force: bool,
+    if_idle: bool,
+    forget: bool,
+    quiet: bool,
+) -> Result<()> {
+    let active_ports = if_idle_guard(if_idle)?;
+    let cwd = std::env::current_dir()?;
+    let (project, freed) = with_registry_mut(|registry| {
+        let project = resolve_project_arg(registry, Some(project), &cwd)?;
+        let freed = free_port(registry, &project, name, force, &active_ports)?;
+        if forget {
+            for (port_name, _) in &freed {
+                registry.take_previous_port(&project, port_name);
+            }
+        }
+        Ok((project, freed))
+    })?;
+
+    for (port_name, port) in freed {
+        say(quiet, &format!("Freed {project}.{port_name} (was {port})"));
+        if quiet {
+            println!("{port}");
+        }
     }
+
     Ok(())
 }
 
-fn cmd_status(json: bool, full: bool) -> Result<()> {
-    let registry = load_registry()?;
-    let listening = get_listening_ports()?;
+fn cmd_schedule_free(project: &str, name: Option<&str>, at: DateTime<FixedOffset>) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let (project, scheduled) = with_registry_mut(|registry| {
+        let project = resolve_project_arg(registry, Some(project), &cwd)?;
+        let scheduled = schedule_free(registry, &project, name, at)?;
+        Ok((project, scheduled))
+    })?;
 
-    if json {
-        let ports = build_status_port_list(&listening, &registry, full);
-        display_status_json(&ports);
-    } else {
-        display_status(&listening, &registry, full);
+    let at = format_local_time(at);
+    for (port_name, port) in scheduled {
+        println!("Scheduled {project}.{port_name} ({port}) to be freed at {at}");
     }
     Ok(())
 }
 
-fn cmd_suggest(port_type: &str, count: usize, json: bool) -> Result<()> {
+/// Formats ports as `name=port` pairs, sorted by name.
+fn port_pairs<N: std::fmt::Display>(ports: impl IntoIterator<Item = (N, Port)>) -> String {
+    ports
+        .into_iter()
+        .map(|(name, port)| format!("{name}={port}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn cmd_archive(project: &str, force: bool) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let (project, freed) = with_registry_mut(|registry| {
+        let project = resolve_project_arg(registry, Some(project), &cwd)?;
+        let freed = archive_project(registry, &project, force)?;
+        Ok((project, freed))
+    })?;
+
+    println!("Archived {project} ({})", port_pairs(freed));
+    Ok(())
+}
+
+fn cmd_archived() -> Result<()> {
     let registry = load_registry()?;
-    let active_ports = get_listening_ports().unwrap_or_default();
+    for (name, project) in &registry.archived {
+        let ports = project.ports.iter().map(|(n, a)| (n, a.port));
+        println!("{name}: {}", port_pairs(ports));
+    }
+    Ok(())
+}
+
+fn cmd_unarchive(project: &str) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let active_ports = listening_ports_for_checks(false)?;
+    let (project, restored) = with_registry_mut(|registry| {
+        let project = resolve_project_arg(registry, Some(project), &cwd)?;
+        let restored = unarchive_project(registry, &project, &active_ports)?;
+        Ok((project, restored))
+    })?;
+
+    for (name, old, new) in restored {
+        if old == new {
+            println!("Restored {project}.{name} = {new}");
+        } else {
+            println!("Restored {project}.{name} = {new} ({old} is taken)");
+        }
+    }
+    Ok(())
+}
 
-    let suggestions = suggest_port(&registry, port_type, count, &active_ports)?;
+fn cmd_usage(json: Option<JsonVersion>) -> Result<()> {
+    let registry = load_registry()?;
+    let report = usage_report(&registry)?;
+    match json {
+        Some(version) => display_usage_json(&report, version),
+        None => display_usage(&report),
+    }
+    Ok(())
+}
 
-    if json {
-        display_suggestions_json(&suggestions);
+fn cmd_gc(dry_run: bool) -> Result<()> {
+    let now = chrono::Local::now().fixed_offset();
+    let listening = get_listening_ports();
+    let run = |registry: &mut Registry| {
+        let listening = match &listening {
+            Ok(ports) => Some(ports.as_slice()),
+            Err(e) => {
+                if registry.defaults.stale_after_days.is_some() {
+                    warn!("Not checking for idle allocations: {e}");
+                }
+                None
+            }
+        };
+        let collected = gc::collect(registry, now, listening, process_alive);
+        (collected, gc::forget_old_ports(registry, now))
+    };
+    let (collected, forgotten) = if dry_run {
+        run(&mut load_registry()?)
     } else {
-        display_suggestions(&suggestions, port_type);
+        with_registry_mut(|registry| Ok(run(registry)))?
+    };
+
+    display_gc_report(&collected, forgotten, dry_run);
+    Ok(())
+}
+
+fn cmd_install_service(interval: u32, start: bool, print: bool) -> Result<()> {
+    let manager = Manager::current()?;
+    let exe = std::env::current_exe()?;
+    let files = service_files(manager, &exe, interval)?;
+
+    if print {
+        let rendered: Vec<String> = files
+            .iter()
+            .map(|file| format!("# {}\n{}", file.path.display(), file.content))
+            .collect();
+        print!("{}", rendered.join("\n"));
+        return Ok(());
     }
 
+    for file in &files {
+        if let Some(dir) = file.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&file.path, &file.content)?;
+        println!("Wrote {}", file.path.display());
+    }
+    if !start {
+        return Ok(());
+    }
+
+    match manager {
+        Manager::Launchd => run_checked(
+            std::process::Command::new("launchctl")
+                .args(["load", "-w"])
+                .arg(&files[0].path),
+            "launchctl load",
+        )?,
+        Manager::Systemd => {
+            run_checked(
+                &mut systemctl(&["daemon-reload"]),
+                "systemctl --user daemon-reload",
+            )?;
+            run_checked(
+                &mut systemctl(&["enable", "--now", SYSTEMD_TIMER]),
+                "systemctl --user enable",
+            )?;
+        }
+    }
+    println!("pm gc will run every {interval} minutes");
     Ok(())
 }
 
-fn cmd_config(show_path: bool, set_range: Option<String>, json: bool) -> Result<()> {
-    let path = registry_path()?;
+fn cmd_uninstall_service() -> Result<()> {
+    let manager = Manager::current()?;
+    let paths: Vec<PathBuf> = service_paths(manager)?
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect();
+    if paths.is_empty() {
+        println!("No pm service installed");
+        return Ok(());
+    }
+
+    // Stopping fails if the service was never started; removing the files
+    // still uninstalls it
+    let stopped = match manager {
+        Manager::Launchd => run_checked(
+            std::process::Command::new("launchctl")
+                .args(["unload", "-w"])
+                .arg(&paths[0]),
+            "launchctl unload",
+        ),
+        Manager::Systemd => run_checked(
+            &mut systemctl(&["disable", "--now", SYSTEMD_TIMER]),
+            "systemctl --user disable",
+        ),
+    };
+    if let Err(e) = stopped {
+        warn!("{e}");
+    }
+
+    for path in &paths {
+        std::fs::remove_file(path)?;
+        println!("Removed {}", path.display());
+    }
+    if manager == Manager::Systemd {
+        if let Err(e) = run_checked(
+            &mut systemctl(&["daemon-reload"]),
+            "systemctl --user daemon-reload",
+        ) {
+            warn!("{e}");
+        }
+    }
+    Ok(())
+}
+
+/// Returns a `systemctl --user` command with `args`.
+fn systemctl(args: &[&str]) -> std::process::Command {
+    let mut command = std::process::Command::new("systemctl");
+    command.arg("--user").args(args);
+    command
+}
+
+fn cmd_free_group(group: &str, force: bool, if_idle: bool, quiet: bool) -> Result<()> {
+    let active_ports = if_idle_guard(if_idle)?;
+    let freed = with_registry_mut(|registry| free_group(registry, group, force, &active_ports))?;
+
+    for (project, port_name, port) in freed {
+        say(quiet, &format!("Freed {project}.{port_name} (was {port})"));
+        if quiet {
+            println!("{port}");
+        }
+    }
 
-    if let Some(range_spec) = set_range {
-        let (type_name, start, end) =
-            with_registry_mut(|registry| set_port_range(registry, &range_spec))?;
-        println!("Set {type_name} range to {start}-{end}");
+    Ok(())
+}
+
+fn cmd_free_orphans(
+    migrate: bool,
+    yes: bool,
+    dry_run: bool,
+    force: bool,
+    if_idle: bool,
+) -> Result<()> {
+    let orphans = find_orphans(&load_registry()?);
+    if orphans.is_empty() {
+        println!("No allocations outside the configured ranges");
         return Ok(());
     }
 
-    let registry = load_registry()?;
-    if json {
-        if show_path {
-            display_config_json(&registry, Some(&path));
-        } else {
-            display_config_json(&registry, None);
+    let question = if migrate {
+        "  Move it into its range?"
+    } else {
+        "  Free it?"
+    };
+    let mut chosen = Vec::new();
+    for (project, name, port) in orphans {
+        println!("{project}.{name} = {port} is outside every configured range");
+        if !dry_run && (yes || confirm(question)?) {
+            chosen.push((project, name));
         }
-    } else if show_path {
-        display_config(&registry, Some(&path));
+    }
+    if chosen.is_empty() {
+        return Ok(());
+    }
+
+    // Migrated ports must not land on a listener even without --if-idle
+    let active_ports = if if_idle {
+        get_listening_ports()?
+    } else if migrate {
+        listening_ports_for_checks(false)?
     } else {
-        display_config(&registry, None);
+        Vec::new()
+    };
+    let idle_only: &[ListeningPort] = if if_idle { &active_ports } else { &[] };
+    let moves = with_registry_mut(|registry| {
+        // Ranges may have changed while we asked; only touch what is still orphaned
+        let still_orphaned = find_orphans(registry);
+        let mut moves = Vec::new();
+        for (project, name) in &chosen {
+            let Some((_, _, port)) = still_orphaned
+                .iter()
+                .find(|(p, n, _)| p == project && n == name)
+            else {
+                continue;
+            };
+            // Locked ports stay put (moving always, freeing unless --force)
+            // rather than stopping the rest
+            let locked = registry.projects[project].ports[name].meta.locked;
+            if locked && (migrate || !force) {
+                moves.push((project.clone(), name.clone(), *port, OrphanFix::Left));
+            } else if migrate {
+                let (_, to) = reallocate_port(
+                    registry,
+                    project,
+                    name,
+                    None,
+                    PortMeta::default(),
+                    &active_ports,
+                )?;
+                moves.push((project.clone(), name.clone(), *port, OrphanFix::Moved(to)));
+            } else {
+                free_port(registry, project, Some(name), force, idle_only)?;
+                moves.push((project.clone(), name.clone(), *port, OrphanFix::Freed));
+            }
+        }
+        Ok(moves)
+    })?;
+
+    for (project, name, from, fix) in moves {
+        match fix {
+            OrphanFix::Moved(to) => println!("Moved {project}.{name}: {from} -> {to}"),
+            OrphanFix::Freed => println!("Freed {project}.{name} (was {from})"),
+            OrphanFix::Left => println!("Left {project}.{name} on {from} (locked)"),
+        }
+    }
+    Ok(())
+}
+
+/// What `pm free --orphans` did with one orphan.
+enum OrphanFix {
+    Moved(Port),
+    Freed,
+    Left,
+}
+
+fn cmd_lock(project: &str, name: Option<&str>, locked: bool) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let (project, changed) = with_registry_mut(|registry| {
+        let project = resolve_project_arg(registry, Some(project), &cwd)?;
+        let changed = set_locked(registry, &project, name, locked)?;
+        Ok((project, changed))
+    })?;
+
+    let verb = if locked { "Locked" } else { "Unlocked" };
+    for (port_name, port) in changed {
+        println!("{verb} {project}.{port_name} ({port})");
+    }
+
+    Ok(())
+}
+
+/// Returns the git root containing `dir`, or an error if there is none.
+fn require_git_root(dir: &Path) -> Result<PathBuf> {
+    find_git_root(dir).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} is not inside a git repository", dir.display()),
+        )
+        .into()
+    })
+}
+
+fn cmd_link(dir: &Path, project: Option<&str>, git_root: bool, repo: bool) -> Result<()> {
+    let mut dir = dir.canonicalize()?;
+    if git_root {
+        dir = require_git_root(&dir)?;
+    }
+
+    let project = match project {
+        Some(p) => p.to_string(),
+        None => dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| RegistryError::NoLinkedProject(dir.clone()))?,
+    };
+    let project = ProjectName::new(project)?;
+
+    if repo {
+        let root = require_git_root(&dir)?;
+        // dir is inside root, so a relative key always exists
+        let key = repo_link_key(&root, &dir).unwrap_or_default();
+        let mut config = load_repo_config(&root)?.unwrap_or_default();
+        config.links.insert(key.clone(), project.to_string());
+        save_repo_config(&root, &config)?;
+        println!(
+            "Linked {key} -> {project} in {}",
+            root.join(REPO_CONFIG_FILE).display()
+        );
+        return Ok(());
+    }
+
+    let previous = with_registry_mut(|registry| Ok(link_directory(registry, &dir, &project)))?;
+
+    match previous {
+        Some(old) if project != old.as_str() => {
+            println!("Linked {} -> {project} (was {old})", dir.display())
+        }
+        _ => println!("Linked {} -> {project}", dir.display()),
+    }
+    Ok(())
+}
+
+fn cmd_links() -> Result<()> {
+    let registry = load_registry()?;
+
+    for (dir, project) in &registry.links {
+        println!("{dir} -> {project}");
+    }
+    Ok(())
+}
+
+fn cmd_unlink(dir: &Path) -> Result<()> {
+    let dir = dir.canonicalize()?;
+    let project = with_registry_mut(|registry| unlink_directory(registry, &dir))?;
+
+    println!("Unlinked {} (was {project})", dir.display());
+    Ok(())
+}
+
+fn cmd_alias(project: &str, name: &str, alias: &PortName) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let (project, (target, port)) = with_registry_mut(|registry| {
+        let project = resolve_project_arg(registry, Some(project), &cwd)?;
+        let added = add_alias(registry, &project, name, alias)?;
+        Ok((project, added))
+    })?;
+
+    println!("Aliased {project}.{alias} -> {target} ({port})");
+    Ok(())
+}
+
+fn cmd_unalias(project: &str, alias: &str) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let (project, target) = with_registry_mut(|registry| {
+        let project = resolve_project_arg(registry, Some(project), &cwd)?;
+        let target = remove_alias(registry, &project, alias)?;
+        Ok((project, target))
+    })?;
+
+    println!("Removed alias {project}.{alias} (was {target})");
+    Ok(())
+}
+
+fn cmd_rename_port(project: &str, old: &str, new: &PortName, force: bool) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let (project, (old, port)) = with_registry_mut(|registry| {
+        let project = resolve_project_arg(registry, Some(project), &cwd)?;
+        let renamed = rename_port(registry, &project, old, new, force)?;
+        Ok((project, renamed))
+    })?;
+
+    println!("Renamed {project}.{old} to {project}.{new} ({port})");
+    Ok(())
+}
+
+fn cmd_depend(project: &str, on: &str, name: Option<&str>) -> Result<()> {
+    let added = with_registry_mut(|registry| add_dependency(registry, project, on, name))?;
+
+    let dependency = name.map_or_else(|| on.to_string(), |name| format!("{on}.{name}"));
+    if added {
+        println!("{project} depends on {dependency}");
+    } else {
+        println!("{project} already depends on {dependency}");
+    }
+    Ok(())
+}
+
+fn cmd_undepend(project: &str, on: &str, name: Option<&str>) -> Result<()> {
+    with_registry_mut(|registry| remove_dependency(registry, project, on, name))?;
+
+    let dependency = name.map_or_else(|| on.to_string(), |name| format!("{on}.{name}"));
+    println!("{project} no longer depends on {dependency}");
+    Ok(())
+}
+
+fn cmd_list(
+    unassigned_only: bool,
+    filter: &ListFilter,
+    long: bool,
+    output: OutputFormat,
+    json: Option<JsonVersion>,
+) -> Result<()> {
+    let registry = load_registry()?;
+    let listening = get_listening_ports().unwrap_or_default();
+
+    if unassigned_only {
+        // Show only unassigned listening ports
+        let unassigned: Vec<_> = listening
+            .iter()
+            .filter(|lp| registry.find_port_owner(lp.port).is_none())
+            .cloned()
+            .collect();
+        if let Some(version) = json {
+            let ports = build_status_port_list(&unassigned, &registry, false);
+            display_status_json(&ports, version);
+        } else if output == OutputFormat::Short {
+            display_status_short(&build_status_port_list(&unassigned, &registry, false));
+        } else {
+            display_status(&unassigned, &registry, false);
+        }
+    } else {
+        let ports = build_allocated_port_list(&registry, &listening, filter);
+        if let Some(version) = json {
+            display_allocated_ports_json(&ports, version);
+        } else if output == OutputFormat::Short {
+            display_allocated_ports_short(&ports);
+        } else {
+            display_allocated_ports(&ports, long);
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_query(
+    project: Option<&str>,
+    name: Option<&str>,
+    with_deps: bool,
+    group: Option<&str>,
+    json: Option<JsonVersion>,
+) -> Result<()> {
+    let registry = load_registry()?;
+    if let Some(pattern) = project.filter(|p| is_glob(p)) {
+        let mut ports = query_matching_ports(&registry, pattern, name)?;
+        if with_deps {
+            let projects: BTreeSet<String> = ports.iter().map(|(p, _, _)| p.clone()).collect();
+            for project in projects {
+                for dep in dependency_ports(&registry, &project) {
+                    if !ports.contains(&dep) {
+                        ports.push(dep);
+                    }
+                }
+            }
+        }
+        ports.retain(|(project, name, _)| in_group(&registry, project, name, group));
+        match json {
+            Some(version) => display_query_projects_json(&ports, version),
+            None => display_query_all(&ports),
+        }
+        return Ok(());
+    }
+    let cwd = std::env::current_dir()?;
+    let project = resolve_project_arg(&registry, project, &cwd)?;
+
+    let mut ports = query_ports(&registry, &project, name)?;
+    ports.retain(|(name, _)| in_group(&registry, &project, name, group));
+
+    if with_deps {
+        let mut deps = dependency_ports(&registry, &project);
+        deps.retain(|(project, name, _)| in_group(&registry, project, name, group));
+        if let Some(version) = json {
+            let all: Vec<_> = ports
+                .into_iter()
+                .map(|(name, port)| (project.clone(), name, port))
+                .chain(deps)
+                .collect();
+            display_query_all_json(&all, version);
+            return Ok(());
+        }
+        ports.extend(
+            deps.into_iter()
+                .map(|(project, name, port)| (format!("{project}.{name}"), port)),
+        );
+    }
+
+    if let Some(version) = json {
+        display_query_json(&ports, version);
+    } else if ports.is_empty() {
+        // No output for scripting - exit success but empty
+        return Ok(());
+    } else {
+        display_query(&ports, name.is_some() && !with_deps);
+    }
+    Ok(())
+}
+
+fn cmd_env(project: Option<&str>, export: bool, host: Option<&Address>) -> Result<()> {
+    let registry = load_registry()?;
+    let cwd = std::env::current_dir()?;
+    let project = resolve_project_arg(&registry, project, &cwd)?;
+    let urls = match host {
+        Some(host) => env_urls(&registry, &project, &host.resolve()?)?,
+        None => Vec::new(),
+    };
+
+    let prefix = if export { "export " } else { "" };
+    for (var, port) in env_vars(&registry, &project)? {
+        println!("{prefix}{var}={port}");
+    }
+    for (var, url) in urls {
+        println!("{prefix}{var}={url}");
+    }
+    Ok(())
+}
+
+fn cmd_run(project: Option<&str>, command: &[String]) -> Result<()> {
+    let registry = load_registry()?;
+    let cwd = std::env::current_dir()?;
+    let project = resolve_project_arg(&registry, project, &cwd)?;
+    let vars = env_vars(&registry, &project)?;
+
+    let (program, args) = command.split_first().expect("clap requires a command");
+    let status = std::process::Command::new(program)
+        .args(args)
+        .envs(vars.iter().map(|(var, port)| (var, port.to_string())))
+        .status()
+        .map_err(|e| Error::CommandFailed {
+            command: command.join(" "),
+            reason: e.to_string(),
+        })?;
+    if !status.success() {
+        // Killed by a signal has no code; report it as a plain failure
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+fn cmd_hook(shell: Shell, apply: bool) -> Result<()> {
+    if !apply {
+        print!("{}", hook_script(shell));
+        return Ok(());
+    }
+
+    let previous = std::env::var(HOOK_VARS).unwrap_or_default();
+    let previous: Vec<&str> = previous.split_whitespace().collect();
+    // Runs on every cd, so a directory without a project (or a broken
+    // registry) just clears what was exported before
+    let vars = load_registry()
+        .and_then(|registry| {
+            let cwd = std::env::current_dir()?;
+            let project = resolve_project_arg(&registry, None, &cwd)?;
+            env_vars(&registry, &project)
+        })
+        .unwrap_or_else(|e| {
+            debug!("not exporting ports: {e}");
+            Vec::new()
+        });
+    print!("{}", apply_commands(shell, &previous, &vars));
+    Ok(())
+}
+
+fn cmd_watch_registry(json: Option<JsonVersion>) -> Result<()> {
+    watch_registry(|change| match json {
+        Some(version) => display_registry_change_json(change, version),
+        None => display_registry_change(change),
+    })
+}
+
+fn cmd_query_all(group: Option<&str>, json: Option<JsonVersion>) -> Result<()> {
+    let registry = load_registry()?;
+    let mut ports = query_all_ports(&registry);
+    ports.retain(|(project, name, _)| in_group(&registry, project, name, group));
+
+    if let Some(version) = json {
+        display_query_all_json(&ports, version);
+    } else {
+        display_query_all(&ports);
+    }
+    Ok(())
+}
+
+fn cmd_status(
+    json: Option<JsonVersion>,
+    full: bool,
+    mine: bool,
+    remote: Option<&str>,
+    agents: &[String],
+    snapshot: Option<&Path>,
+    output: OutputFormat,
+) -> Result<()> {
+    let (registry, mut listening) = match snapshot {
+        Some(path) => read_snapshot(path)?,
+        None => {
+            let registry = load_registry()?;
+            let listening = match remote {
+                Some(host) => remote_listening_ports(host)?,
+                None if !agents.is_empty() => {
+                    combined_listening_ports(get_listening_ports(), agents)
+                }
+                None => get_listening_ports()?,
+            };
+            (registry, listening)
+        }
+    };
+    if mine {
+        // Listeners whose owner can't be read are usually other users'
+        listening.retain(ListeningPort::is_mine);
+    }
+
+    if let Some(version) = json {
+        let ports = build_status_port_list(&listening, &registry, full);
+        display_status_json(&ports, version);
+    } else if output == OutputFormat::Short {
+        display_status_short(&build_status_port_list(&listening, &registry, false));
+    } else {
+        display_status(&listening, &registry, full);
+    }
+    Ok(())
+}
+
+fn cmd_snapshot() -> Result<()> {
+    let registry = load_registry()?;
+    let listening = get_listening_ports().unwrap_or_else(|e| {
+        warn!("Capturing no listeners: {e} (see `pm doctor`)");
+        Vec::new()
+    });
+    display_snapshot_json(&capture(&registry, &listening));
+    Ok(())
+}
+
+fn cmd_debug_bundle(output: Option<PathBuf>, redact: bool) -> Result<()> {
+    let output = output.unwrap_or_else(|| {
+        let time = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        PathBuf::from(format!("pm-debug-{time}.tar.gz"))
+    });
+    write_bundle(&output, redact)?;
+    println!("Wrote {}", output.display());
+    if !redact {
+        println!("It has your project names and paths; pass --redact to leave them out");
+    }
+    Ok(())
+}
+
+fn cmd_suggest(
+    port_type: &str,
+    project: Option<&str>,
+    count: usize,
+    explain: bool,
+    avoid_lingering: Option<u64>,
+    json: Option<JsonVersion>,
+) -> Result<()> {
+    let registry = load_registry()?;
+    let mut active_ports = listening_ports_for_checks(false)?;
+    if let Some(within) = avoid_lingering {
+        active_ports.extend(lingering_ports(within)?);
+    }
+
+    let project = match project {
+        Some(project) => {
+            let cwd = std::env::current_dir()?;
+            Some(ProjectName::new(resolve_project_arg(
+                &registry,
+                Some(project),
+                &cwd,
+            )?)?)
+        }
+        None => None,
+    };
+    if explain {
+        let report =
+            explain_suggestions(&registry, port_type, project.as_ref(), count, &active_ports)?;
+        let ephemeral = ephemeral_range();
+        match json {
+            Some(version) => display_suggestion_report_json(&report, ephemeral, version),
+            None => display_suggestion_report(&report, ephemeral),
+        }
+        return Ok(());
+    }
+    if let Some(version) = json {
+        let report =
+            explain_suggestions(&registry, port_type, project.as_ref(), count, &active_ports)?;
+        display_suggestions_json(&report, version);
+    } else {
+        let suggestions =
+            suggest_port(&registry, port_type, project.as_ref(), count, &active_ports)?;
+        display_suggestions(&suggestions, port_type);
+    }
+
+    Ok(())
+}
+
+fn cmd_config_set_range(range_spec: &str, migrate: bool, quiet: bool) -> Result<()> {
+    let active_ports = if migrate {
+        listening_ports_for_checks(false)?
+    } else {
+        Vec::new()
+    };
+    let (type_name, range, migrations, strays) = with_registry_mut(|registry| {
+        let before = registry.clone();
+        let (type_name, _, _) = set_port_range(registry, range_spec)?;
+        let old = before.get_range(&type_name);
+        let range = registry.get_range(&type_name);
+        if migrate {
+            let migrations = migrate_range(registry, &type_name, &old, &active_ports)?;
+            Ok((type_name, range, migrations, Vec::new()))
+        } else {
+            let strays = range_strays(registry, &type_name, &old);
+            Ok((type_name, range, Vec::new(), strays))
+        }
+    })?;
+    say(quiet, &format!("Set {type_name} range to {range}"));
+    if quiet {
+        println!("{range}");
+    }
+    for migration in &migrations {
+        let key = format!("{}.{}", migration.project, migration.name);
+        match migration.to {
+            Some(to) => say(quiet, &format!("Moved {key}: {} -> {to}", migration.from)),
+            None => say(quiet, &format!("Left {key} on {} (locked)", migration.from)),
+        }
+    }
+    // Rerunning with --migrate can't find these once the old range is gone
+    for (project, name, port) in strays {
+        warn!(
+            "{project}.{name} ({port}) is outside the new {type_name} range; \
+             move it with `pm allocate {project} {name} --replace`"
+        );
+    }
+    Ok(())
+}
+
+fn cmd_config(
+    show_path: bool,
+    presets: &[Preset],
+    ignore_case: Option<bool>,
+    stale_after_days: Option<u32>,
+    git_sync: Option<bool>,
+    strict_detection: Option<bool>,
+    json: Option<JsonVersion>,
+) -> Result<()> {
+    let path = registry_path()?;
+
+    if !presets.is_empty() {
+        let changes = with_registry_mut(|registry| Ok(apply_presets(registry, presets)))?;
+        display_preset_changes(&changes);
+        return Ok(());
+    }
+
+    if let Some(ignore_case) = ignore_case {
+        with_registry_mut(|registry| {
+            registry.defaults.ignore_case = ignore_case;
+            Ok(())
+        })?;
+        let state = if ignore_case { "on" } else { "off" };
+        println!("Case-insensitive name matching is {state}");
+        return Ok(());
+    }
+
+    if let Some(days) = stale_after_days {
+        let days = Some(days).filter(|d| *d > 0);
+        with_registry_mut(|registry| {
+            registry.defaults.stale_after_days = days;
+            Ok(())
+        })?;
+        match days {
+            Some(days) => println!("pm gc frees allocations idle for {days} days"),
+            None => println!("pm gc no longer frees idle allocations"),
+        }
+        return Ok(());
+    }
+
+    if let Some(git_sync) = git_sync {
+        with_registry_mut(|registry| {
+            registry.defaults.git_sync = git_sync;
+            Ok(())
+        })?;
+        if git_sync {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            println!("Registry changes are committed to git in {}", dir.display());
+        } else {
+            println!("Registry changes are no longer committed to git");
+        }
+        return Ok(());
+    }
+
+    if let Some(strict) = strict_detection {
+        with_registry_mut(|registry| {
+            registry.defaults.strict_detection = strict;
+            Ok(())
+        })?;
+        if strict {
+            println!("Commands fail when listening ports can't be detected");
+        } else {
+            println!(
+                "Commands warn and skip conflict checks when listening ports can't be detected"
+            );
+        }
+        return Ok(());
+    }
+
+    let registry = load_registry()?;
+    if let Some(version) = json {
+        if show_path {
+            display_config_json(&registry, Some(&path), version);
+        } else {
+            display_config_json(&registry, None, version);
+        }
+    } else if show_path {
+        display_config(&registry, Some(&path));
+    } else {
+        display_config(&registry, None);
+    }
+
+    Ok(())
+}
+
+/// Opens `path` in the user's editor and waits for it to exit.
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // Allow editors with arguments, e.g. EDITOR="code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .map_err(|source| ConfigError::EditorFailed {
+            editor: editor.clone(),
+            source,
+        })?;
+
+    if !status.success() {
+        return Err(ConfigError::EditorExited {
+            editor,
+            status: status.to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn cmd_config_edit() -> Result<()> {
+    let path = registry_path()?;
+    let original = read_registry_content()?;
+
+    // Edit a scratch copy so the registry is never left half-written. It
+    // sits next to the registry, where only the user can read it, and is
+    // removed when dropped.
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut scratch = tempfile::Builder::new()
+        .prefix(".registry-edit-")
+        .suffix(".toml")
+        .tempfile_in(dir)?;
+    scratch.write_all(original.as_bytes())?;
+    scratch.flush()?;
+    let edited = edit_until_valid(scratch.path())?;
+
+    if edited == original {
+        println!("No changes");
+        return Ok(());
+    }
+
+    replace_registry_content(&original, &edited)?;
+    println!("Saved {}", path.display());
+    Ok(())
+}
+
+/// Runs the editor on `scratch` until its content validates or the user gives up.
+fn edit_until_valid(scratch: &Path) -> Result<String> {
+    loop {
+        run_editor(scratch)?;
+        let edited = std::fs::read_to_string(scratch)?;
+
+        match validate_content(&edited) {
+            Ok(_) => return Ok(edited),
+            Err(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    eprintln!("{diagnostic}");
+                }
+                if !confirm("Edit again?")? {
+                    return Err(ConfigError::InvalidRegistry(diagnostics.len()).into());
+                }
+            }
+        }
+    }
+}
+
+fn cmd_config_validate(file: Option<PathBuf>, json: Option<JsonVersion>) -> Result<()> {
+    let path = match file {
+        Some(path) => path,
+        None => registry_path()?,
+    };
+    let content = std::fs::read_to_string(&path).map_err(|source| ConfigError::ReadFailed {
+        path: path.clone(),
+        source,
+    })?;
+
+    let diagnostics = validate_content(&content).err().unwrap_or_default();
+
+    if let Some(version) = json {
+        display_validation_json(&path, &diagnostics, version);
+    } else {
+        display_validation(&path, &diagnostics);
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError::ValidationFailed {
+            path,
+            count: diagnostics.len(),
+        }
+        .into())
+    }
+}
+
+fn cmd_config_repair(dry_run: bool) -> Result<()> {
+    let path = registry_path()?;
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("No registry at {}; nothing to repair", path.display());
+            return Ok(());
+        }
+        Err(source) => return Err(ConfigError::ReadFailed { path, source }.into()),
+    };
+    if toml::from_str::<Registry>(&content).is_ok() {
+        println!("{}: ok, nothing to repair", path.display());
+        return Ok(());
+    }
+
+    let salvage = salvage(&content);
+    display_repair(&path, &salvage);
+    if dry_run {
+        return Ok(());
+    }
+    let backup = replace_broken_registry(&content, &salvage.content)?;
+    println!("The broken registry was saved to {}", backup.display());
+    Ok(())
+}
+
+fn cmd_schema(format: SchemaFormat, version: JsonVersion) {
+    let schema = match format {
+        SchemaFormat::List => versioned_schema::<Vec<AllocatedPortInfo>>(version),
+        SchemaFormat::Status => versioned_schema::<Vec<StatusPortInfo>>(version),
+        SchemaFormat::Query => versioned_schema::<Vec<QueryResult>>(version),
+        SchemaFormat::QueryAll => versioned_schema::<Vec<ProjectQueryResult>>(version),
+        SchemaFormat::QueryGlob => versioned_schema::<BTreeMap<String, Vec<QueryResult>>>(version),
+        SchemaFormat::Suggest if version == JsonVersion::V2 => {
+            versioned_schema::<Vec<SuggestedPort>>(version)
+        }
+        SchemaFormat::Suggest => versioned_schema::<Vec<Port>>(version),
+        SchemaFormat::SuggestExplain => versioned_schema::<SuggestionExplanation>(version),
+        SchemaFormat::Config => versioned_schema::<ConfigInfo>(version),
+        SchemaFormat::Validate => versioned_schema::<ValidationReport>(version),
+        SchemaFormat::WatchRegistry => versioned_schema::<RegistryChange>(version),
+        SchemaFormat::Diff => versioned_schema::<RegistryDiff>(version),
+        SchemaFormat::CheckAgainst => versioned_schema::<Vec<Clash>>(version),
+        SchemaFormat::Usage => versioned_schema::<UsageReport>(version),
+        SchemaFormat::Urls => versioned_schema::<Vec<UrlResult>>(version),
+    };
+    display_schema(&schema);
+}
+
+fn cmd_profile(action: ProfileAction) -> Result<()> {
+    match action {
+        ProfileAction::List { json } => {
+            let current = registry_path()?;
+            let profiles = list_profiles()?
+                .into_iter()
+                .map(|name| {
+                    let path = profile_registry_path(&name)?;
+                    Ok(ProfileInfo {
+                        active: path == current,
+                        path: path.display().to_string(),
+                        name,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            match json.version {
+                Some(version) => display_profiles_json(&profiles, version),
+                None => display_profiles(&profiles),
+            }
+        }
+        ProfileAction::Create { name, switch } => {
+            let path = create_profile(&name)?;
+            println!("Created profile {name} ({})", path.display());
+            if switch {
+                switch_profile(&name)?;
+                println!("Switched to profile {name}");
+            }
+        }
+        ProfileAction::Switch { name } => {
+            ensure_profile_exists(&name)?;
+            switch_profile(&name)?;
+            println!("Switched to profile {name}");
+        }
+    }
+    Ok(())
+}
+
+fn cmd_doctor() -> Result<()> {
+    let mut problems: Vec<Error> = Vec::new();
+
+    let path = registry_path()?;
+    if path.exists() {
+        let content = std::fs::read_to_string(&path).map_err(|source| ConfigError::ReadFailed {
+            path: path.clone(),
+            source,
+        })?;
+        match validate_content(&content) {
+            Ok(registry) => {
+                let ports: usize = registry.projects.values().map(|p| p.ports.len()).sum();
+                println!(
+                    "Registry: {} ({} projects, {ports} ports)",
+                    path.display(),
+                    registry.projects.len()
+                );
+            }
+            Err(diagnostics) => {
+                println!("Registry: {}: invalid", path.display());
+                println!("  Run `pm config validate` for details");
+                problems.push(
+                    ConfigError::ValidationFailed {
+                        path,
+                        count: diagnostics.len(),
+                    }
+                    .into(),
+                );
+            }
+        }
+    } else {
+        println!("Registry: {} (not created yet)", path.display());
+    }
+
+    match get_listening_ports() {
+        Ok(ports) => {
+            let listening = ports.iter().map(|lp| lp.port).collect::<HashSet<_>>().len();
+            println!("Port detection: ok ({listening} ports listening)");
+        }
+        Err(Error::PortDetection(PortDetectionError::PlatformNotSupported)) => {
+            println!("Port detection: not supported on this platform");
+            println!("  Allocations aren't checked against processes already listening");
+        }
+        Err(e) => {
+            println!("Port detection: failed");
+            println!("  Allocations go ahead without checking for processes already");
+            println!("  listening; pass --require-detection or set strict_detection");
+            println!("  to refuse instead");
+            problems.push(e);
+        }
+    }
+
+    // Report the first problem as the error, so its code is explained
+    match problems.into_iter().next() {
+        Some(problem) => Err(problem),
+        None => Ok(()),
+    }
+}
+
+fn cmd_explain(code: Option<&str>) -> Result<()> {
+    match code {
+        Some(code) => {
+            let explanation = explain(code).ok_or_else(|| Error::UnknownCode(code.to_string()))?;
+            display_explanation(explanation);
+        }
+        None => display_explanations(EXPLANATIONS),
+    }
+    Ok(())
+}
+
+fn cmd_prompt() -> Result<()> {
+    let registry = load_registry()?;
+    let cwd = std::env::current_dir()?;
+
+    // Print nothing outside known projects so the prompt stays clean
+    let Some(project) = infer_project(&registry, &cwd)? else {
+        return Ok(());
+    };
+    let Some(proj) = registry.projects.get(project.as_str()) else {
+        return Ok(());
+    };
+
+    display_prompt(&port_states(proj));
+    Ok(())
+}
+
+/// Returns each of the project's ports with whether it is listening.
+fn port_states(project: &Project) -> Vec<(String, Port, bool)> {
+    let listening = get_listening_ports().unwrap_or_default();
+    project
+        .ports
+        .iter()
+        .map(|(name, a)| {
+            let active = listening.iter().any(|lp| lp.port == a.port);
+            (name.to_string(), a.port, active)
+        })
+        .collect()
+}
+
+fn cmd_tmux(project: Option<&str>, plain: bool) -> Result<()> {
+    let registry = load_registry()?;
+    let project = match project {
+        Some(project) => {
+            let cwd = std::env::current_dir()?;
+            resolve_project_arg(&registry, Some(project), &cwd)?
+        }
+        // A status line runs this constantly; stay quiet outside projects
+        None => match infer_project(&registry, &std::env::current_dir()?)? {
+            Some(project) => project.to_string(),
+            None => return Ok(()),
+        },
+    };
+    let Some(proj) = registry.projects.get(project.as_str()) else {
+        return Ok(());
+    };
+
+    display_tmux_segment(&project, &port_states(proj), plain);
+    Ok(())
+}
+
+fn cmd_tmux_set_env(project: Option<&str>, target: Option<&str>) -> Result<()> {
+    let registry = load_registry()?;
+    let cwd = std::env::current_dir()?;
+    let project = resolve_project_arg(&registry, project, &cwd)?;
+
+    for (var, port) in env_vars(&registry, &project)? {
+        let mut tmux = std::process::Command::new("tmux");
+        tmux.arg("set-environment");
+        if let Some(target) = target {
+            tmux.args(["-t", target]);
+        }
+        run_checked(
+            tmux.args([var.as_str(), &port.to_string()]),
+            &format!("tmux set-environment {var}"),
+        )?;
+        println!("Set {var}={port} in tmux");
+    }
+    Ok(())
+}
+
+fn cmd_export(target: ExportTarget) -> Result<()> {
+    let registry = load_registry()?;
+    let cwd = std::env::current_dir()?;
+
+    let (project, host, render): (Option<&str>, Option<&Address>, Renderer) = match &target {
+        ExportTarget::Nginx { project, host } => (project.as_deref(), host.as_ref(), render_nginx),
+        ExportTarget::Caddy { project, host } => (project.as_deref(), host.as_ref(), render_caddy),
+        ExportTarget::Procfile { project } => (project.as_deref(), None, render_procfile),
+        ExportTarget::Devcontainer { project, file } => {
+            return cmd_export_devcontainer(&registry, project.as_deref(), file);
+        }
+        ExportTarget::Registry { json } => {
+            display_registry_json(&registry, json.unwrap_or(JsonVersion::V0));
+            return Ok(());
+        }
+    };
+
+    let projects: Vec<(&str, &Project)> = match project {
+        Some(arg) => {
+            let name = resolve_project_arg(&registry, Some(arg), &cwd)?;
+            let (name, project) = registry
+                .projects
+                .get_key_value(name.as_str())
+                .ok_or(RegistryError::ProjectNotFound(name))?;
+            vec![(name.as_str(), project)]
+        }
+        None => registry
+            .projects
+            .iter()
+            .map(|(name, project)| (name.as_str(), project))
+            .collect(),
+    };
+
+    if matches!(
+        target,
+        ExportTarget::Nginx { .. } | ExportTarget::Caddy { .. }
+    ) {
+        check_hostnames(&projects)?;
+    }
+
+    // Proxies reach local services over IPv4 loopback unless told otherwise
+    let upstream = match host {
+        None | Some(Address::Localhost) => "127.0.0.1".to_string(),
+        Some(host) => host.resolve()?,
+    };
+    print!("{}", render(&projects, &upstream));
+    Ok(())
+}
+
+fn cmd_export_devcontainer(registry: &Registry, project: Option<&str>, file: &Path) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let project = resolve_project_arg(registry, project, &cwd)?;
+    let proj = registry
+        .projects
+        .get(project.as_str())
+        .ok_or_else(|| RegistryError::ProjectNotFound(project.clone()))?;
+
+    let mut config = match std::fs::read_to_string(file) {
+        Ok(content) => serde_json::from_str(&strip_jsonc(&content)).map_err(|source| {
+            ConfigError::JsonParseFailed {
+                path: file.to_path_buf(),
+                source,
+            }
+        })?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => serde_json::Map::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    update_devcontainer(&mut config, proj);
+
+    if let Some(parent) = file.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(&config).expect("Failed to serialize to JSON");
+    std::fs::write(file, json + "\n")?;
+
+    println!(
+        "Updated {} with {} port(s) from {project}",
+        file.display(),
+        proj.ports.len()
+    );
+    Ok(())
+}
+
+/// Asks a yes/no question on stdin, defaulting to no.
+fn confirm(question: &str) -> Result<bool> {
+    print!("{question} [y/N] ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn cmd_import_scan(dir: &Path, project: Option<&str>, yes: bool, dry_run: bool) -> Result<()> {
+    let dir = dir.canonicalize()?;
+    let findings = scan_directory(&dir)?;
+
+    if findings.is_empty() {
+        println!("No ports found in {}", dir.display());
+        return Ok(());
+    }
+
+    let registry = load_registry()?;
+    let project = match project {
+        Some(p) => resolve_project_arg(&registry, Some(p), &dir)?,
+        None => match infer_project(&registry, &dir)? {
+            Some(p) => p,
+            None => dir
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .ok_or_else(|| RegistryError::NoLinkedProject(dir.clone()))?,
+        },
+    };
+
+    let project = ProjectName::new(project)?;
+
+    let mut accepted = Vec::new();
+    for finding in findings {
+        let source = format!("{}: {}", finding.file.display(), finding.context);
+
+        if let Some((owner, name)) = registry.find_port_owner(finding.port) {
+            println!(
+                "Skipping {} ({source}): already allocated to {owner}.{name}",
+                finding.port
+            );
+            continue;
+        }
+        let name_taken = registry
+            .projects
+            .get(project.as_str())
+            .is_some_and(|p| p.contains_name(&finding.name));
+        if name_taken {
+            println!(
+                "Skipping {project}.{} ({source}): name already allocated",
+                finding.name
+            );
+            continue;
+        }
+
+        println!("{project}.{} = {} ({source})", finding.name, finding.port);
+        if dry_run {
+            continue;
+        }
+        if yes || confirm("  Register?")? {
+            accepted.push(finding);
+        }
+    }
+
+    if accepted.is_empty() {
+        return Ok(());
+    }
+
+    with_registry_mut(|registry| {
+        for finding in &accepted {
+            // These ports are usually in use by the very server being imported,
+            // so skip the listening-port check
+            allocate_port(
+                registry,
+                &project,
+                &PortName::new(finding.name.as_str())?,
+                Some(finding.port),
+                PortMeta::default(),
+                &[],
+            )?;
+        }
+        Ok(())
+    })?;
+
+    for finding in &accepted {
+        println!("Allocated {project}.{} = {}", finding.name, finding.port);
+    }
+    Ok(())
+}
+
+/// Reads a saved `ss`, `netstat`, or `lsof` listing ("-" for stdin).
+fn read_listing(file: &Path) -> Result<Vec<ListeningPort>> {
+    let text = if file == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        std::fs::read_to_string(file).map_err(|source| ConfigError::ReadFailed {
+            path: file.to_path_buf(),
+            source,
+        })?
+    };
+    Ok(parse_listing(&text))
+}
+
+fn cmd_import_netstat_status(file: &Path, json: Option<JsonVersion>) -> Result<()> {
+    let registry = load_registry()?;
+    let listening = read_listing(file)?;
+    match json {
+        Some(version) => display_status_json(
+            &build_status_port_list(&listening, &registry, false),
+            version,
+        ),
+        None => display_status(&listening, &registry, false),
+    }
+    Ok(())
+}
+
+/// Returns the name to adopt a listener under: its process name made into a
+/// valid port name, else the port's well-known service, else `port-<port>`.
+fn listener_port_name(listener: &ListeningPort) -> String {
+    listener
+        .process_name
+        .as_deref()
+        .map(|process| {
+            process
+                .to_ascii_lowercase()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect::<String>()
+                .trim_matches('-')
+                .chars()
+                .take(MAX_NAME_LEN)
+                .collect::<String>()
+        })
+        .filter(|name| PortName::new(name.as_str()).is_ok())
+        .or_else(|| service_name(listener.port).map(str::to_string))
+        .unwrap_or_else(|| format!("port-{}", listener.port))
+}
+
+fn cmd_import_netstat(file: &Path, project: Option<&str>, yes: bool, dry_run: bool) -> Result<()> {
+    let mut listening = read_listing(file)?;
+    listening.dedup_by_key(|listener| listener.port);
+    if listening.is_empty() {
+        println!(
+            "No listening ports found in {} (expected `ss -tlnp`, `netstat -an`, or `lsof` output)",
+            file.display()
+        );
+        return Ok(());
+    }
+
+    let registry = load_registry()?;
+    let cwd = std::env::current_dir()?;
+    let project = match project {
+        Some(p) => resolve_project_arg(&registry, Some(p), &cwd)?,
+        None => match infer_project(&registry, &cwd)? {
+            Some(p) => p,
+            None => cwd
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .ok_or_else(|| RegistryError::NoLinkedProject(cwd.clone()))?,
+        },
+    };
+    let project = ProjectName::new(project)?;
+
+    let mut accepted: Vec<(PortName, Port)> = Vec::new();
+    for listener in &listening {
+        let port = listener.port;
+        let source = listener
+            .process_name
+            .as_deref()
+            .unwrap_or("unknown process");
+        if let Some((owner, name)) = registry.find_port_owner(port) {
+            println!("Skipping {port} ({source}): already allocated to {owner}.{name}");
+            continue;
+        }
+        // Two processes of the same name get the second one's port appended
+        let mut name = listener_port_name(listener);
+        let taken = |name: &str| {
+            registry
+                .projects
+                .get(project.as_str())
+                .is_some_and(|p| p.contains_name(name))
+                || accepted.iter().any(|(n, _)| n.as_str() == name)
+        };
+        if taken(&name) {
+            name = format!("{name}-{port}");
+        }
+        let Ok(name) = PortName::new(name) else {
+            continue;
+        };
+        if taken(&name) {
+            println!("Skipping {project}.{name} ({source}): name already allocated");
+            continue;
+        }
+
+        println!("{project}.{name} = {port} ({source})");
+        if dry_run {
+            continue;
+        }
+        if yes || confirm("  Register?")? {
+            accepted.push((name, port));
+        }
+    }
+
+    if accepted.is_empty() {
+        return Ok(());
+    }
+
+    with_registry_mut(|registry| {
+        for (name, port) in &accepted {
+            // The listing comes from another machine (or an earlier time), so
+            // nothing here says whether the port is free now
+            allocate_port(
+                registry,
+                &project,
+                name,
+                Some(*port),
+                PortMeta::default(),
+                &[],
+            )?;
+        }
+        Ok(())
+    })?;
+
+    for (name, port) in &accepted {
+        println!("Allocated {project}.{name} = {port}");
+    }
+    Ok(())
+}
+
+fn cmd_merge(file: &Path, resolution: Option<Resolution>) -> Result<()> {
+    let content = std::fs::read_to_string(file).map_err(|source| ConfigError::ReadFailed {
+        path: file.to_path_buf(),
+        source,
+    })?;
+    let theirs = match validate_content(&content) {
+        Ok(theirs) => theirs,
+        Err(diagnostics) => {
+            display_validation(file, &diagnostics);
+            return Err(ConfigError::ValidationFailed {
+                path: file.to_path_buf(),
+                count: diagnostics.len(),
+            }
+            .into());
+        }
+    };
+
+    let active_ports = listening_ports_for_checks(false)?;
+    let steps = with_registry_mut(|registry| {
+        let steps = merge_registries(
+            registry,
+            &theirs,
+            &active_ports,
+            |collision| match resolution {
+                Some(resolution) => Ok(resolution),
+                None => ask_resolution(collision),
+            },
+        )?;
+        let diagnostics = validate_registry(registry);
+        if !diagnostics.is_empty() {
+            for diagnostic in &diagnostics {
+                eprintln!("{diagnostic}");
+            }
+            return Err(ConfigError::InvalidRegistry(diagnostics.len()).into());
+        }
+        Ok(steps)
+    })?;
+
+    display_merge_steps(&steps);
+    Ok(())
+}
+
+fn cmd_diff(old: &Path, new: Option<&Path>, json: Option<JsonVersion>) -> Result<()> {
+    let before = read_registry_file(old)?;
+    let after = match new {
+        Some(new) if new != Path::new("current") => read_registry_file(new)?,
+        // The file alone, without the system registry load_registry attaches
+        _ => {
+            let path = registry_path()?;
+            if path.exists() {
+                read_registry_file(&path)?
+            } else {
+                Registry::default()
+            }
+        }
+    };
+
+    let diff = diff_registries(&before, &after);
+    match json {
+        Some(version) => display_registry_diff_json(&diff, version),
+        None => display_registry_diff(&diff),
+    }
+    Ok(())
+}
+
+fn cmd_check_against(snapshot: &Path, json: Option<JsonVersion>) -> Result<()> {
+    let registry = load_registry()?;
+    let (theirs, listening) = read_snapshot_or_registry(snapshot)?;
+    let clashes = find_clashes(&registry, &theirs, &listening);
+
+    match json {
+        Some(version) => display_clashes_json(&clashes, version),
+        None => display_clashes(&clashes),
+    }
+    if clashes.is_empty() {
+        Ok(())
+    } else {
+        Err(RegistryError::PortsCollide {
+            count: clashes.len(),
+            path: snapshot.to_path_buf(),
+        }
+        .into())
+    }
+}
+
+/// Asks how to settle a merge collision on stdin, defaulting to ours.
+fn ask_resolution(collision: &Collision) -> Result<Resolution> {
+    println!("{collision}");
+    let question = match collision.kind {
+        CollisionKind::Name { .. } => "  Keep [o]urs or take [t]heirs? [O/t] ",
+        CollisionKind::Port { .. } => {
+            "  Keep [o]urs, take [t]heirs, or [r]eassign theirs to a free port? [O/t/r] "
+        }
+    };
+    print!("{question}");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(match answer.trim().to_lowercase().as_str() {
+        "t" | "theirs" => Resolution::Theirs,
+        "r" | "reassign" if matches!(collision.kind, CollisionKind::Port { .. }) => {
+            Resolution::Reassign
+        }
+        _ => Resolution::Ours,
+    })
+}
+
+fn cmd_sync(remote: Option<&str>) -> Result<()> {
+    if !load_registry()?.defaults.git_sync {
+        return Err(ConfigError::GitSyncOff.into());
+    }
+    match with_registry_locked(|path| sync(path, remote))? {
+        SyncOutcome::Synced { remote } => println!("Synced registry with {remote}"),
+        SyncOutcome::NoRemote => return Err(ConfigError::NoSyncRemote.into()),
+    }
+    Ok(())
+}
+
+fn cmd_import_registry(file: &Path, merge: bool) -> Result<()> {
+    let content = if file == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        std::fs::read_to_string(file).map_err(|source| ConfigError::ReadFailed {
+            path: file.to_path_buf(),
+            source,
+        })?
+    };
+    let incoming =
+        parse_registry_json(&content).map_err(|source| ConfigError::JsonParseFailed {
+            path: file.to_path_buf(),
+            source,
+        })?;
+
+    let diagnostics = validate_parsed(&incoming);
+    if !diagnostics.is_empty() {
+        display_validation(file, &diagnostics);
+        return Err(ConfigError::ValidationFailed {
+            path: file.to_path_buf(),
+            count: diagnostics.len(),
+        }
+        .into());
+    }
+
+    let projects = incoming.projects.len();
+    let ports: usize = incoming.projects.values().map(|p| p.ports.len()).sum();
+
+    let summary = with_registry_mut(|registry| {
+        let result = if merge {
+            merge_registry(registry, incoming).map(Some)
+        } else {
+            let conflicts = system_conflicts(registry, &incoming);
+            if conflicts.is_empty() {
+                let system = registry.system.take();
+                *registry = incoming;
+                registry.system = system;
+                Ok(None)
+            } else {
+                Err(conflicts)
+            }
+        };
+        let summary = result.map_err(|conflicts| {
+            for conflict in &conflicts {
+                eprintln!("{conflict}");
+            }
+            ConfigError::ImportConflicts(conflicts.len())
+        })?;
+
+        // Merging can still produce names that differ only by case
+        let diagnostics = validate_registry(registry);
+        if !diagnostics.is_empty() {
+            for diagnostic in &diagnostics {
+                eprintln!("{diagnostic}");
+            }
+            return Err(ConfigError::InvalidRegistry(diagnostics.len()).into());
+        }
+        Ok(summary)
+    })?;
+
+    match summary {
+        Some(summary) => println!(
+            "Imported {} new entries from {} ({} already present)",
+            summary.added,
+            file.display(),
+            summary.unchanged
+        ),
+        None => println!(
+            "Replaced registry with {projects} project(s) and {ports} port(s) from {}",
+            file.display()
+        ),
+    }
+    Ok(())
+}
+
+fn cmd_gen_docs(man: bool, out_dir: Option<&Path>) -> Result<()> {
+    let cmd = Cli::command();
+
+    // clap's ArgGroup guarantees exactly one of --man / --markdown
+    match (man, out_dir) {
+        (true, Some(dir)) => {
+            write_man_pages(cmd, dir)?;
+            println!("Wrote man pages to {}", dir.display());
+        }
+        (true, None) => io::stdout().write_all(&render_man(cmd)?)?,
+        (false, Some(dir)) => {
+            std::fs::create_dir_all(dir)?;
+            let path = dir.join("pm.md");
+            std::fs::write(&path, render_markdown(cmd))?;
+            println!("Wrote {}", path.display());
+        }
+        (false, None) => print!("{}", render_markdown(cmd)),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "self-update")]
+fn cmd_self_update(check: bool) -> Result<()> {
+    let exe = std::env::current_exe()?.canonicalize()?;
+    if let Some(upgrade) = update::managed_by(&exe) {
+        return Err(error::UpdateError::ManagedInstall(upgrade).into());
+    }
+
+    let current = env!("CARGO_PKG_VERSION");
+    let release = update::fetch_latest_release()?;
+    if !update::is_newer(release.version(), current) {
+        println!("pm {current} is up to date");
+        return Ok(());
+    }
+
+    if check {
+        println!(
+            "pm {} is available (installed: {current})",
+            release.version()
+        );
+        return Ok(());
     }
 
+    let bytes = update::download_verified(&release)?;
+    update::replace_executable(&exe, &bytes)?;
+    println!("Updated pm {current} -> {}", release.version());
     Ok(())
 }
@@ -0,0 +1,428 @@
+//! Merging another registry file into this one (`pm merge`).
+//!
+//! Allocations that don't collide are added as they are. A collision is
+//! either the same name with a different port, or a port that belongs to a
+//! different name here; each one is settled by a [`Resolution`], chosen up
+//! front or asked for one collision at a time.
+
+use std::fmt;
+
+use crate::error::Result;
+use crate::model::{Allocation, Registry};
+use crate::name::{PortName, ProjectName};
+use crate::port::Port;
+use crate::ports::ListeningPort;
+use crate::registry::{allocate_port, merge_dependencies, reallocate_port};
+
+/// How to settle a collision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep this registry's allocation and drop theirs.
+    Ours,
+    /// Take their allocation, moving ours to a free port if it held theirs.
+    /// Locked allocations of ours are kept instead.
+    Theirs,
+    /// Keep both, giving their allocation a free port from its range.
+    /// Name collisions can't keep both, so they keep ours.
+    Reassign,
+}
+
+/// Why an allocation from the other registry can't simply be added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CollisionKind {
+    /// The name has a different port here.
+    Name { ours: Port },
+    /// The port belongs to another name here (`project.name`).
+    Port { owner: String },
+}
+
+/// An allocation from the other registry that collides with this one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub project: ProjectName,
+    pub name: PortName,
+    /// Their port.
+    pub port: Port,
+    pub kind: CollisionKind,
+}
+
+impl Collision {
+    /// Describes the collision without naming the allocation.
+    fn reason(&self) -> String {
+        let port = self.port;
+        match &self.kind {
+            CollisionKind::Name { ours } => format!("is {ours} here but {port} in theirs"),
+            CollisionKind::Port { owner } => {
+                format!("is {port} in theirs, but {port} is {owner} here")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{} {}", self.project, self.name, self.reason())
+    }
+}
+
+/// One change a merge made, or an entry it left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeStep {
+    /// Their allocation was added.
+    Added { key: String, port: Port },
+    /// An allocation moved to another port (theirs reassigned, or ours
+    /// moved out of the way of theirs).
+    Moved { key: String, from: Port, to: Port },
+    /// Their entry was left out in favor of ours.
+    KeptOurs { key: String, reason: String },
+}
+
+/// Merges `theirs` into `registry`, calling `resolve` for each collision.
+///
+/// Aliases, ranges, and links are added where this registry has none; ones
+/// that differ keep ours. The registry is only changed if the whole merge
+/// succeeds. Allocations already present with the same port are skipped.
+pub fn merge_registries(
+    registry: &mut Registry,
+    theirs: &Registry,
+    active_ports: &[ListeningPort],
+    mut resolve: impl FnMut(&Collision) -> Result<Resolution>,
+) -> Result<Vec<MergeStep>> {
+    let mut merged = registry.clone();
+    let mut steps = Vec::new();
+
+    for (project, their_project) in &theirs.projects {
+        for (name, allocation) in &their_project.ports {
+            let key = format!("{project}.{name}");
+            let ours = merged
+                .projects
+                .get(project)
+                .and_then(|p| p.ports.get(name))
+                .map(|a| a.port);
+            if ours == Some(allocation.port) {
+                continue;
+            }
+            if let Some(target) = merged
+                .projects
+                .get(project)
+                .and_then(|p| p.aliases.get(name))
+            {
+                steps.push(MergeStep::KeptOurs {
+                    key,
+                    reason: format!("is an alias for '{target}' here"),
+                });
+                continue;
+            }
+
+            let owner = merged
+                .find_port_owner_on(allocation.meta.host.as_deref(), allocation.port)
+                .map(|(p, n)| (p.to_string(), n.to_string()));
+            let kind = match (ours, &owner) {
+                (Some(ours), _) => CollisionKind::Name { ours },
+                (None, Some((p, n))) => CollisionKind::Port {
+                    owner: format!("{p}.{n}"),
+                },
+                (None, None) => {
+                    insert(&mut merged, project, name, allocation.clone());
+                    steps.push(MergeStep::Added {
+                        key,
+                        port: allocation.port,
+                    });
+                    continue;
+                }
+            };
+            let collision = Collision {
+                project: project.clone(),
+                name: name.clone(),
+                port: allocation.port,
+                kind,
+            };
+
+            match (resolve(&collision)?, &collision.kind) {
+                (Resolution::Ours, _) | (Resolution::Reassign, CollisionKind::Name { .. }) => {
+                    steps.push(MergeStep::KeptOurs {
+                        key,
+                        reason: collision.reason(),
+                    });
+                }
+                (Resolution::Theirs, kind) => {
+                    // Taking theirs would replace ours or move the owner of
+                    // their port, and neither may happen to a locked one
+                    let replaced = match kind {
+                        CollisionKind::Name { .. } => Some((project.as_str(), name.as_str())),
+                        CollisionKind::Port { .. } => None,
+                    };
+                    let owner_key = owner.as_ref().map(|(p, n)| (p.as_str(), n.as_str()));
+                    if let Some((p, n)) = replaced
+                        .into_iter()
+                        .chain(owner_key)
+                        .find(|(p, n)| is_locked(&merged, p, n))
+                    {
+                        steps.push(MergeStep::KeptOurs {
+                            key,
+                            reason: format!("{}, and {p}.{n} is locked", collision.reason()),
+                        });
+                        continue;
+                    }
+                    // Move whichever allocation holds their port here out of
+                    // the way; system registry reservations can't move
+                    match owner {
+                        Some((p, n))
+                            if merged
+                                .projects
+                                .get(p.as_str())
+                                .is_some_and(|proj| proj.ports.contains_key(n.as_str())) =>
+                        {
+                            let (p, n) = (ProjectName::new(p)?, PortName::new(n)?);
+                            let (from, to) = reallocate_port(
+                                &mut merged,
+                                &p,
+                                &n,
+                                None,
+                                Default::default(),
+                                active_ports,
+                            )?;
+                            steps.push(MergeStep::Moved {
+                                key: format!("{p}.{n}"),
+                                from: from.unwrap_or(allocation.port),
+                                to,
+                            });
+                        }
+                        Some((p, n)) => {
+                            let step = match kind {
+                                CollisionKind::Port { .. } => {
+                                    reassign(&mut merged, project, name, allocation, active_ports)?
+                                }
+                                CollisionKind::Name { .. } => MergeStep::KeptOurs {
+                                    key,
+                                    reason: format!(
+                                        "{} is reserved for {p}.{n} by the system registry",
+                                        allocation.port
+                                    ),
+                                },
+                            };
+                            steps.push(step);
+                            continue;
+                        }
+                        None => {}
+                    }
+                    insert(&mut merged, project, name, allocation.clone());
+                    steps.push(MergeStep::Added {
+                        key,
+                        port: allocation.port,
+                    });
+                }
+                (Resolution::Reassign, CollisionKind::Port { .. }) => {
+                    steps.push(reassign(
+                        &mut merged,
+                        project,
+                        name,
+                        allocation,
+                        active_ports,
+                    )?);
+                }
+            }
+        }
+
+        for (alias, target) in &their_project.aliases {
+            let Some(proj) = merged.projects.get_mut(project) else {
+                continue;
+            };
+            if !proj.contains_name(alias) && proj.ports.contains_key(target) {
+                proj.aliases.insert(alias.clone(), target.clone());
+            }
+        }
+    }
+
+    for (type_name, range) in &theirs.defaults.ranges {
+        match merged.defaults.ranges.get(type_name) {
+            Some(ours) if ours != range => steps.push(MergeStep::KeptOurs {
+                key: format!("range {type_name}"),
+                reason: format!("is {ours} here but {range} in theirs"),
+            }),
+            Some(_) => {}
+            None => {
+                merged
+                    .defaults
+                    .ranges
+                    .insert(type_name.clone(), range.clone());
+            }
+        }
+    }
+
+    for (path, project) in &theirs.links {
+        match merged.links.get(path) {
+            Some(ours) if ours != project => steps.push(MergeStep::KeptOurs {
+                key: format!("link {path}"),
+                reason: format!("links to '{ours}' here but '{project}' in theirs"),
+            }),
+            Some(_) => {}
+            None => {
+                merged.links.insert(path.clone(), project.clone());
+            }
+        }
+    }
+    merge_dependencies(&mut merged, theirs);
+
+    *registry = merged;
+    Ok(steps)
+}
+
+fn insert(registry: &mut Registry, project: &ProjectName, name: &PortName, allocation: Allocation) {
+    registry
+        .projects
+        .entry(project.clone())
+        .or_default()
+        .ports
+        .insert(name.clone(), allocation);
+}
+
+/// Adds their allocation on a free port from its range instead of theirs.
+fn reassign(
+    registry: &mut Registry,
+    project: &ProjectName,
+    name: &PortName,
+    allocation: &Allocation,
+    active_ports: &[ListeningPort],
+) -> Result<MergeStep> {
+    let to = allocate_port(
+        registry,
+        project,
+        name,
+        None,
+        allocation.meta.clone(),
+        active_ports,
+    )?;
+    Ok(MergeStep::Moved {
+        key: format!("{project}.{name}"),
+        from: allocation.port,
+        to,
+    })
+}
+
+/// Returns true if `project.name` is a locked allocation in `registry`.
+fn is_locked(registry: &Registry, project: &str, name: &str) -> bool {
+    registry
+        .projects
+        .get(project)
+        .and_then(|p| p.ports.get(name))
+        .is_some_and(|a| a.meta.locked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{PortMeta, Project};
+
+    fn registry_with(ports: &[(&str, &str, u16)]) -> Registry {
+        let mut registry = Registry::default();
+        for (project, name, port) in ports {
+            registry
+                .projects
+                .entry(ProjectName::new(*project).unwrap())
+                .or_insert_with(Project::default)
+                .ports
+                .insert(
+                    PortName::new(*name).unwrap(),
+                    Allocation::new(Port::new(*port).unwrap(), PortMeta::default()),
+                );
+        }
+        registry
+    }
+
+    fn port_of(registry: &Registry, project: &str, name: &str) -> Option<u16> {
+        registry
+            .projects
+            .get(project)
+            .and_then(|p| p.ports.get(name))
+            .map(|a| a.port.as_u16())
+    }
+
+    #[test]
+    fn test_merge_adds_non_conflicting() {
+        let mut ours = registry_with(&[("webapp", "web", 8000)]);
+        let theirs = registry_with(&[("webapp", "web", 8000), ("api", "http", 3000)]);
+
+        let steps = merge_registries(&mut ours, &theirs, &[], |_| {
+            panic!("no collisions expected")
+        })
+        .unwrap();
+        assert_eq!(
+            steps,
+            vec![MergeStep::Added {
+                key: "api.http".to_string(),
+                port: Port::new(3000).unwrap()
+            }]
+        );
+        assert_eq!(port_of(&ours, "api", "http"), Some(3000));
+    }
+
+    #[test]
+    fn test_merge_port_collision() {
+        let theirs = registry_with(&[("api", "web", 8000)]);
+
+        let mut ours = registry_with(&[("webapp", "web", 8000)]);
+        merge_registries(&mut ours, &theirs, &[], |_| Ok(Resolution::Ours)).unwrap();
+        assert_eq!(port_of(&ours, "api", "web"), None);
+
+        let mut ours = registry_with(&[("webapp", "web", 8000)]);
+        merge_registries(&mut ours, &theirs, &[], |_| Ok(Resolution::Reassign)).unwrap();
+        assert_eq!(port_of(&ours, "webapp", "web"), Some(8000));
+        assert_eq!(port_of(&ours, "api", "web"), Some(8001));
+
+        let mut ours = registry_with(&[("webapp", "web", 8000)]);
+        merge_registries(&mut ours, &theirs, &[], |_| Ok(Resolution::Theirs)).unwrap();
+        assert_eq!(port_of(&ours, "api", "web"), Some(8000));
+        assert_eq!(port_of(&ours, "webapp", "web"), Some(8001));
+    }
+
+    #[test]
+    fn test_merge_name_collision() {
+        let theirs = registry_with(&[("webapp", "web", 8001)]);
+
+        let mut ours = registry_with(&[("webapp", "web", 8000)]);
+        let mut seen = Vec::new();
+        merge_registries(&mut ours, &theirs, &[], |c| {
+            seen.push(c.kind.clone());
+            Ok(Resolution::Reassign)
+        })
+        .unwrap();
+        assert_eq!(
+            seen,
+            vec![CollisionKind::Name {
+                ours: Port::new(8000).unwrap()
+            }]
+        );
+        assert_eq!(port_of(&ours, "webapp", "web"), Some(8000));
+
+        let mut ours = registry_with(&[("webapp", "web", 8000), ("api", "web", 8001)]);
+        merge_registries(&mut ours, &theirs, &[], |_| Ok(Resolution::Theirs)).unwrap();
+        assert_eq!(port_of(&ours, "webapp", "web"), Some(8001));
+        assert_eq!(port_of(&ours, "api", "web"), Some(8002));
+    }
+
+    #[test]
+    fn test_merge_theirs_keeps_locked_ports() {
+        let lock = |registry: &mut Registry, project: &str, name: &str| {
+            crate::registry::set_locked(registry, project, Some(name), true).unwrap();
+        };
+
+        // Same name, different port
+        let theirs = registry_with(&[("webapp", "web", 8001)]);
+        let mut ours = registry_with(&[("webapp", "web", 8000)]);
+        lock(&mut ours, "webapp", "web");
+        let steps = merge_registries(&mut ours, &theirs, &[], |_| Ok(Resolution::Theirs)).unwrap();
+        assert_eq!(port_of(&ours, "webapp", "web"), Some(8000));
+        assert!(ours.projects["webapp"].ports["web"].meta.locked);
+        assert!(matches!(&steps[0], MergeStep::KeptOurs { reason, .. }
+            if reason.ends_with("webapp.web is locked")));
+
+        // Their port held by a locked allocation of ours
+        let theirs = registry_with(&[("api", "web", 8000)]);
+        let mut ours = registry_with(&[("webapp", "web", 8000)]);
+        lock(&mut ours, "webapp", "web");
+        merge_registries(&mut ours, &theirs, &[], |_| Ok(Resolution::Theirs)).unwrap();
+        assert_eq!(port_of(&ours, "webapp", "web"), Some(8000));
+        assert_eq!(port_of(&ours, "api", "web"), None);
+    }
+}
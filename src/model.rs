@@ -3,11 +3,23 @@
 //! Contains the registry structure and related types for port allocations.
 
 use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
 
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::address::substitute_localhost;
+use crate::name::{glob_match, PortName, ProjectName};
 use crate::port::Port;
 
+/// Most freed ports [`Registry::history`] remembers.
+pub const MAX_HISTORY: usize = 500;
+
 /// The main registry configuration, stored as TOML.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Registry {
@@ -17,74 +29,1004 @@ pub struct Registry {
 
     /// Projects with their named port allocations.
     #[serde(default)]
-    pub projects: BTreeMap<String, Project>,
+    pub projects: BTreeMap<ProjectName, Project>,
+
+    /// Directories linked to projects (absolute path -> project name).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, String>,
+
+    /// Projects put away with `pm archive`, with the ports they had.
+    ///
+    /// Their ports are free for others; `pm unarchive` asks for the same
+    /// ones back.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub archived: BTreeMap<ProjectName, Project>,
+
+    /// The port each freed `project.name` last had, which automatic
+    /// allocation hands it again when it can. At most [`MAX_HISTORY`]
+    /// entries are kept, and `pm gc` forgets old ones.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub history: BTreeMap<ProjectName, BTreeMap<PortName, PreviousPort>>,
+
+    /// Other projects' ports each project uses (`pm depend`): project ->
+    /// the projects it depends on -> their port names, where an empty list
+    /// means every port. `pm query --with-deps` resolves them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<ProjectName, BTreeMap<ProjectName, Vec<PortName>>>,
+
+    /// How tables are drawn.
+    #[serde(default, skip_serializing_if = "DisplayConfig::is_default")]
+    pub display: DisplayConfig,
+
+    /// Read-only machine-wide registry layered underneath this one.
+    ///
+    /// Its allocations are reserved and its ranges fill in types this
+    /// registry doesn't define. Never saved.
+    #[serde(skip)]
+    pub system: Option<Box<SystemRegistry>>,
+}
+
+/// A machine-wide registry loaded from disk, and where it came from.
+#[derive(Debug, Clone)]
+pub struct SystemRegistry {
+    pub path: PathBuf,
+    pub registry: Registry,
+}
+
+/// Repository-local settings, stored in `.pm.toml` at the git root.
+///
+/// Lets a monorepo map its subdirectories to projects in a file that is
+/// committed alongside the code.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepoConfig {
+    /// Paths relative to the repository root mapped to project names
+    /// (e.g., "packages/api" -> "api"). Use "." for the root itself.
+    #[serde(default)]
+    pub links: BTreeMap<String, String>,
 }
 
 /// Default settings including port ranges.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Defaults {
+    /// Match project and port names ignoring ASCII case (e.g., "WebApp" finds "webapp").
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_case: bool,
+
     /// Port ranges by type name (e.g., "web" -> [8000, 8999]).
     #[serde(default = "default_ranges")]
-    pub ranges: BTreeMap<String, [u16; 2]>,
+    pub ranges: BTreeMap<String, PortRange>,
+
+    /// Machine-wide registry to layer underneath this one
+    /// (default: /etc/port-manager/registry.toml).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_registry: Option<PathBuf>,
+
+    /// `pm gc` frees allocations that have not been seen listening for this many days.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_after_days: Option<u32>,
+
+    /// Commit every registry change to a git repository in the registry's
+    /// directory, for sharing with `pm sync`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub git_sync: bool,
+
+    /// Fail commands that check for listening processes when ports can't be
+    /// detected, as if `--require-detection` were always passed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict_detection: bool,
+
+    /// Rules picking the range type for port names allocated without
+    /// `--type`, tried in order (`[[defaults.type_rules]]`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub type_rules: Vec<TypeRule>,
+
+    /// Limits on how many ports can be allocated (`[defaults.quotas]`).
+    #[serde(default, skip_serializing_if = "Quotas::is_empty")]
+    pub quotas: Quotas,
+}
+
+/// The most allocations a project, a user, or a range type may hold, so one
+/// team can't take over a shared registry. Unset limits don't apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Quotas {
+    /// Most ports any one project may hold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_project: Option<usize>,
+    /// Most ports any one user may hold. Allocations record who made them
+    /// (`owner`) while this is set; earlier ones count toward no one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_user: Option<usize>,
+    /// Most ports allocated from each range type, across all projects
+    /// (e.g., `test = 200`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ranges: BTreeMap<String, usize>,
+}
+
+impl Quotas {
+    /// Returns true if no limit is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the stricter of each limit in `self` and `other`.
+    fn stricter(&self, other: &Quotas) -> Quotas {
+        fn min(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+            a.into_iter().chain(b).min()
+        }
+        let mut ranges = self.ranges.clone();
+        for (range_type, limit) in &other.ranges {
+            ranges
+                .entry(range_type.clone())
+                .and_modify(|own| *own = (*own).min(*limit))
+                .or_insert(*limit);
+        }
+        Quotas {
+            per_project: min(self.per_project, other.per_project),
+            per_user: min(self.per_user, other.per_user),
+            ranges,
+        }
+    }
+}
+
+/// Maps port names to a range type, so a naming convention (e.g., every
+/// "frontend*" port is a web port) is set once instead of with each `--type`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TypeRule {
+    /// Glob the port name matches: an exact name, or with `*` for any run of
+    /// characters (e.g., "frontend*", "*-grpc").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Regex the port name matches, instead of a glob (e.g., "^worker-[0-9]+$").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// Range type for matching names.
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+impl TypeRule {
+    /// Returns the rule's pattern as shown to users: the glob, or the regex
+    /// between slashes.
+    pub fn pattern(&self) -> String {
+        match (&self.name, &self.regex) {
+            (Some(glob), _) => glob.clone(),
+            (None, Some(pattern)) => format!("/{pattern}/"),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Returns true if `name` matches the rule. A rule whose regex doesn't
+    /// compile matches nothing; `pm config validate` reports it.
+    pub fn matches(&self, name: &str, ignore_case: bool) -> bool {
+        match (&self.name, &self.regex) {
+            (Some(glob), _) => glob_match(glob, name, ignore_case),
+            (None, Some(pattern)) => Regex::new(pattern).is_ok_and(|re| re.is_match(name)),
+            (None, None) => false,
+        }
+    }
 }
 
 /// A project with its named port allocations.
+///
+/// Stored as a flat table of entries: each key is either a port allocation
+/// or an alias (`frontend = { alias = "web" }`) pointing at another name.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
-#[serde(transparent)]
+#[serde(
+    from = "BTreeMap<PortName, ProjectEntry>",
+    into = "BTreeMap<PortName, ProjectEntry>"
+)]
 pub struct Project {
     /// Named ports (e.g., "web" -> 8080).
-    pub ports: BTreeMap<String, Port>,
+    pub ports: BTreeMap<PortName, Allocation>,
+    /// Alternate names resolving to a port name (e.g., "frontend" -> "web").
+    pub aliases: BTreeMap<PortName, PortName>,
+}
+
+impl Project {
+    /// Returns true if `name` is used by either a port or an alias.
+    pub fn contains_name(&self, name: &str) -> bool {
+        self.ports.contains_key(name) || self.aliases.contains_key(name)
+    }
+
+    /// Resolves a port name or alias to its allocation.
+    pub fn resolve(&self, name: &str) -> Option<&Allocation> {
+        let target = self.aliases.get(name).map(PortName::as_str).unwrap_or(name);
+        self.ports.get(target)
+    }
+
+    /// Returns the aliases pointing at the given port name, in sorted order.
+    pub fn aliases_for(&self, name: &str) -> Vec<&str> {
+        self.aliases
+            .iter()
+            .filter(|(_, target)| target.as_str() == name)
+            .map(|(alias, _)| alias.as_str())
+            .collect()
+    }
+
+    /// Drops aliases whose target port no longer exists.
+    pub fn prune_aliases(&mut self) {
+        let ports = &self.ports;
+        self.aliases.retain(|_, target| ports.contains_key(target));
+    }
+}
+
+/// A single entry in a project table: a port allocation or an alias.
+#[derive(Debug, Clone)]
+enum ProjectEntry {
+    Port(Box<Allocation>),
+    Alias(PortName),
+}
+
+impl From<BTreeMap<PortName, ProjectEntry>> for Project {
+    fn from(entries: BTreeMap<PortName, ProjectEntry>) -> Self {
+        let mut project = Project::default();
+        for (name, entry) in entries {
+            match entry {
+                ProjectEntry::Port(allocation) => {
+                    project.ports.insert(name, *allocation);
+                }
+                ProjectEntry::Alias(target) => {
+                    project.aliases.insert(name, target);
+                }
+            }
+        }
+        project
+    }
+}
+
+impl From<Project> for BTreeMap<PortName, ProjectEntry> {
+    fn from(project: Project) -> Self {
+        let ports = project
+            .ports
+            .into_iter()
+            .map(|(name, a)| (name, ProjectEntry::Port(Box::new(a))));
+        let aliases = project
+            .aliases
+            .into_iter()
+            .map(|(name, target)| (name, ProjectEntry::Alias(target)));
+        ports.chain(aliases).collect()
+    }
+}
+
+/// A single named port allocation.
+///
+/// Serialized as a bare port number when there is no metadata (`web = 8080`),
+/// and as a table otherwise (`web = { port = 8080, group = "sprint-42" }`),
+/// so registries without metadata keep their original format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    /// The allocated port number.
+    pub port: Port,
+    /// Optional metadata attached at allocation time.
+    pub meta: PortMeta,
+}
+
+/// The port a freed `project.name` had, and when it was freed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviousPort {
+    pub port: Port,
+    pub freed_at: DateTime<FixedOffset>,
+}
+
+/// Optional metadata attached to an allocation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortMeta {
+    /// Group label used to release related allocations together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Range type the port is drawn from (`pm allocate --type`), when it
+    /// isn't the one named like the port. Reallocation keeps using it.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub range_type: Option<String>,
+    /// Protected allocations are skipped by free and bulk operations unless forced.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// Machine the service runs on (`pm allocate --host`); unset for this one.
+    /// Ports only collide with allocations on the same machine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Local hostname a reverse proxy routes to this port (e.g., "app.localhost").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Command that starts the service; `{port}` is replaced with the allocated port.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Regex the listening process's command line (or name) should match
+    /// (e.g., "node.*vite"); anything else on the port is a conflict.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect_process: Option<String>,
+    /// Free-form description of what the port is for (e.g., "payments sandbox").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// URL the service is reached at; `{port}` is replaced with the allocated
+    /// port and `{host}` with the allocation's host (or "localhost").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_template: Option<String>,
+    /// When `pm gc` should free the allocation (`pm free --at` / `--after`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_at: Option<DateTime<FixedOffset>>,
+    /// Process the allocation belongs to; `pm gc` frees it once the process exits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+    /// When `pm gc` last saw the port listening (tracked only while
+    /// `stale_after_days` is set).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_active: Option<DateTime<FixedOffset>>,
+    /// User who allocated the port (recorded while a per-user quota is set).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+impl PortMeta {
+    /// Returns true if no metadata is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the URL for the service on `port`: `url_template` with the
+    /// port and host filled in, or `http://<host>:<port>`. The host is the
+    /// machine the allocation is on (`--host`), or localhost.
+    pub fn url(&self, port: Port) -> String {
+        self.url_on(port, "localhost")
+    }
+
+    /// Returns the URL as [`PortMeta::url`] does, reaching services on this
+    /// machine at `local_host` instead of localhost (`--host`). A template
+    /// that spells out localhost or 127.0.0.1 has it replaced too.
+    pub fn url_on(&self, port: Port, local_host: &str) -> String {
+        let host = self.host.as_deref().unwrap_or(local_host);
+        let url = match &self.url_template {
+            Some(template) => template
+                .replace("{port}", &port.to_string())
+                .replace("{host}", host),
+            None => format!("http://{host}:{port}"),
+        };
+        if self.host.is_none() && local_host != "localhost" {
+            substitute_localhost(&url, local_host)
+        } else {
+            url
+        }
+    }
+}
+
+/// Returns why `s` can't be a proxy hostname, or `None` if it can. Hostnames
+/// are pasted into nginx and Caddy configs, so only DNS characters are
+/// allowed: letters, digits, '-', and '.' between non-empty labels.
+pub fn hostname_problem(s: &str) -> Option<String> {
+    let valid = !s.is_empty()
+        && s.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    (!valid).then(|| {
+        format!("'{s}' is not a hostname; use letters, digits, and '-' in labels separated by '.'")
+    })
+}
+
+/// Parses `--host`, the name of the machine an allocation is on. "all" and
+/// "local" are reserved for `pm list --host`.
+pub fn parse_host(s: &str) -> Result<String, String> {
+    if matches!(s, "all" | "local") {
+        return Err(format!("'{s}' is reserved"));
+    }
+    if s.is_empty()
+        || !s
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    {
+        return Err(format!(
+            "'{s}' is not a host name; use letters, digits, '-', '_', and '.'"
+        ));
+    }
+    Ok(s.to_string())
+}
+
+impl Allocation {
+    /// Creates an allocation with the given metadata.
+    pub fn new(port: Port, meta: PortMeta) -> Self {
+        Self { port, meta }
+    }
+}
+
+/// Table form of an allocation, used when metadata is present.
+#[derive(Serialize)]
+struct AllocationTable<'a> {
+    port: Port,
+    #[serde(flatten)]
+    meta: &'a PortMeta,
+}
+
+/// Table form of any project entry, as read from disk.
+#[derive(Deserialize)]
+struct EntryTable {
+    port: Option<Port>,
+    alias: Option<PortName>,
+    #[serde(flatten)]
+    meta: PortMeta,
+}
+
+impl Serialize for Allocation {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.meta.is_empty() {
+            self.port.serialize(serializer)
+        } else {
+            AllocationTable {
+                port: self.port,
+                meta: &self.meta,
+            }
+            .serialize(serializer)
+        }
+    }
+}
+
+impl Serialize for ProjectEntry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            ProjectEntry::Port(allocation) => allocation.serialize(serializer),
+            ProjectEntry::Alias(target) => {
+                let mut map = BTreeMap::new();
+                map.insert("alias", target);
+                map.serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ProjectEntry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = ProjectEntry;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a port number or a table with a `port` or `alias` key")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                let value = u16::try_from(value).map_err(|_| {
+                    E::custom(format!("invalid port number: {value} (must be 1-65535)"))
+                })?;
+                self.visit_u16(value)
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                let value = u16::try_from(value).map_err(|_| {
+                    E::custom(format!("invalid port number: {value} (must be 1-65535)"))
+                })?;
+                self.visit_u16(value)
+            }
+
+            fn visit_u16<E: de::Error>(self, value: u16) -> Result<Self::Value, E> {
+                let port = Port::new(value).map_err(E::custom)?;
+                Ok(ProjectEntry::Port(Box::new(Allocation::new(
+                    port,
+                    PortMeta::default(),
+                ))))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let table = EntryTable::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                match (table.port, table.alias) {
+                    (Some(port), None) => Ok(ProjectEntry::Port(Box::new(Allocation::new(
+                        port, table.meta,
+                    )))),
+                    (None, Some(target)) if table.meta.is_empty() => {
+                        Ok(ProjectEntry::Alias(target))
+                    }
+                    (None, Some(_)) => {
+                        Err(de::Error::custom("alias entries cannot carry metadata"))
+                    }
+                    (Some(_), Some(_)) => Err(de::Error::custom(
+                        "entry cannot have both `port` and `alias`",
+                    )),
+                    (None, None) => Err(de::Error::missing_field("port")),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
 }
 
 impl Default for Defaults {
     fn default() -> Self {
         Self {
+            ignore_case: false,
             ranges: default_ranges(),
+            system_registry: None,
+            stale_after_days: None,
+            git_sync: false,
+            strict_detection: false,
+            type_rules: Vec::new(),
+            quotas: Quotas::default(),
         }
     }
 }
 
+/// Table styling, from the `[display]` section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisplayConfig {
+    /// Colors for statuses and changes.
+    #[serde(default)]
+    pub theme: Theme,
+
+    /// Characters tables are drawn with.
+    #[serde(default)]
+    pub borders: Borders,
+
+    /// Mark statuses with a symbol as well as a color (e.g., "● ACTIVE").
+    #[serde(default)]
+    pub status_glyphs: bool,
+}
+
+impl DisplayConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A set of colors for table cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Green for good, red for bad, yellow for changes.
+    #[default]
+    Default,
+    /// Blue for good, yellow for bad, magenta for changes, which stay apart
+    /// with red-green color blindness.
+    Colorblind,
+    /// No colors.
+    None,
+}
+
+/// Characters tables are drawn with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Borders {
+    /// Box-drawing characters with rounded corners.
+    #[default]
+    Unicode,
+    /// `+`, `-`, and `|`, for terminals and fonts without box drawing.
+    Ascii,
+}
+
 /// Returns the default port ranges for common port types.
-fn default_ranges() -> BTreeMap<String, [u16; 2]> {
+fn default_ranges() -> BTreeMap<String, PortRange> {
     let mut ranges = BTreeMap::new();
-    ranges.insert("web".to_string(), [8000, 8999]);
-    ranges.insert("api".to_string(), [3000, 3999]);
-    ranges.insert("db".to_string(), [5400, 5499]);
-    ranges.insert("cache".to_string(), [6300, 6399]);
-    ranges.insert("default".to_string(), [9000, 9999]);
+    ranges.insert("web".to_string(), PortRange::new(8000, 8999));
+    ranges.insert("api".to_string(), PortRange::new(3000, 3999));
+    ranges.insert("db".to_string(), PortRange::new(5400, 5499));
+    ranges.insert("cache".to_string(), PortRange::new(6300, 6399));
+    ranges.insert("default".to_string(), PortRange::new(9000, 9999));
     ranges
 }
 
+/// A type's port range and how ports are picked from it.
+///
+/// A range is one or more segments, tried in the order listed. Serialized as
+/// `[start, end]` (or `[[start, end], ...]` for several segments) when it has
+/// no other settings, and as a table otherwise
+/// (`web = { range = [8000, 8999], exclude = [8080] }`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortRange {
+    /// First and last port of each segment, inclusive. Never empty.
+    pub segments: Vec<[u16; 2]>,
+    pub policy: AllocationPolicy,
+    /// Ports inside the range that are never handed out (e.g., ports
+    /// permanently owned by other tooling).
+    pub exclude: Vec<u16>,
+    /// Project globs the range is reserved for; empty means any project.
+    pub projects: Vec<String>,
+}
+
+impl PortRange {
+    /// Creates a range with the default policy and no exclusions.
+    pub fn new(start: u16, end: u16) -> Self {
+        Self {
+            segments: vec![[start, end]],
+            policy: AllocationPolicy::default(),
+            exclude: Vec::new(),
+            projects: Vec::new(),
+        }
+    }
+
+    /// Returns true if the range only has segments, so it can be written as
+    /// `[start, end]` or a list of them.
+    fn is_plain(&self) -> bool {
+        self.policy == AllocationPolicy::default()
+            && self.exclude.is_empty()
+            && self.projects.is_empty()
+    }
+
+    /// Returns true if automatic allocation for `project` may draw from the
+    /// range.
+    pub fn allows(&self, project: &str, ignore_case: bool) -> bool {
+        self.projects.is_empty()
+            || self
+                .projects
+                .iter()
+                .any(|glob| glob_match(glob, project, ignore_case))
+    }
+
+    /// Returns the lowest port of any segment.
+    pub fn start(&self) -> u16 {
+        self.segments.iter().map(|s| s[0]).min().unwrap_or_default()
+    }
+
+    /// Returns the highest port of any segment.
+    pub fn end(&self) -> u16 {
+        self.segments.iter().map(|s| s[1]).max().unwrap_or_default()
+    }
+
+    /// Returns true if a segment contains `port` (excluded ports included).
+    pub fn contains(&self, port: u16) -> bool {
+        self.segments
+            .iter()
+            .any(|[start, end]| (*start..=*end).contains(&port))
+    }
+
+    /// Returns every port of every segment, in segment order.
+    pub fn ports(&self) -> impl Iterator<Item = u16> + '_ {
+        self.segments.iter().flat_map(|[start, end]| *start..=*end)
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let segments: Vec<String> = self
+            .segments
+            .iter()
+            .map(|[start, end]| format!("{start}-{end}"))
+            .collect();
+        f.write_str(&segments.join(","))?;
+        if !self.exclude.is_empty() {
+            let exclude: Vec<String> = self.exclude.iter().map(u16::to_string).collect();
+            write!(f, " except {}", exclude.join(", "))?;
+        }
+        if !self.projects.is_empty() {
+            write!(f, " only for {}", self.projects.join(", "))?;
+        }
+        if self.policy != AllocationPolicy::default() {
+            write!(f, " ({})", self.policy)?;
+        }
+        Ok(())
+    }
+}
+
+/// How automatic allocation picks a free port from a range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum AllocationPolicy {
+    /// The lowest free port.
+    #[default]
+    LowestFree,
+    /// The first free port after the highest one allocated in the range,
+    /// wrapping around, so freed ports aren't handed out again right away.
+    Sequential,
+    /// A free port picked at random.
+    Random,
+}
+
+impl AllocationPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AllocationPolicy::LowestFree => "lowest-free",
+            AllocationPolicy::Sequential => "sequential",
+            AllocationPolicy::Random => "random",
+        }
+    }
+}
+
+impl fmt::Display for AllocationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AllocationPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "lowest-free" => Ok(AllocationPolicy::LowestFree),
+            "sequential" => Ok(AllocationPolicy::Sequential),
+            "random" => Ok(AllocationPolicy::Random),
+            other => Err(other.to_string()),
+        }
+    }
+}
+
+/// A range's segments: one `[start, end]` pair, or a list of them.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SegmentsRepr {
+    One([u16; 2]),
+    Many(Vec<[u16; 2]>),
+}
+
+impl SegmentsRepr {
+    fn new(segments: &[[u16; 2]]) -> Self {
+        match segments {
+            [segment] => SegmentsRepr::One(*segment),
+            segments => SegmentsRepr::Many(segments.to_vec()),
+        }
+    }
+
+    fn into_segments(self) -> Vec<[u16; 2]> {
+        match self {
+            SegmentsRepr::One(segment) => vec![segment],
+            SegmentsRepr::Many(segments) => segments,
+        }
+    }
+}
+
+/// Table form of a range, used when it has settings beyond its segments.
+#[derive(Serialize, Deserialize)]
+struct RangeTable {
+    range: SegmentsRepr,
+    #[serde(default, skip_serializing_if = "is_default_policy")]
+    policy: AllocationPolicy,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exclude: Vec<u16>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    projects: Vec<String>,
+}
+
+fn is_default_policy(policy: &AllocationPolicy) -> bool {
+    *policy == AllocationPolicy::default()
+}
+
+/// Either form of a range, as read from disk.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RangeRepr {
+    Segments(SegmentsRepr),
+    Table(RangeTable),
+}
+
+impl Serialize for PortRange {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.is_plain() {
+            SegmentsRepr::new(&self.segments).serialize(serializer)
+        } else {
+            RangeTable {
+                range: SegmentsRepr::new(&self.segments),
+                policy: self.policy,
+                exclude: self.exclude.clone(),
+                projects: self.projects.clone(),
+            }
+            .serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PortRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let range = match RangeRepr::deserialize(deserializer)? {
+            RangeRepr::Segments(segments) => PortRange {
+                segments: segments.into_segments(),
+                policy: AllocationPolicy::default(),
+                exclude: Vec::new(),
+                projects: Vec::new(),
+            },
+            RangeRepr::Table(table) => PortRange {
+                segments: table.range.into_segments(),
+                policy: table.policy,
+                exclude: table.exclude,
+                projects: table.projects,
+            },
+        };
+        if range.segments.is_empty() {
+            return Err(de::Error::custom("a range needs at least one segment"));
+        }
+        Ok(range)
+    }
+}
+
 impl Registry {
     /// Gets the port range for a given type, falling back to "default".
-    pub fn get_range(&self, port_type: &str) -> [u16; 2] {
-        self.defaults
-            .ranges
+    ///
+    /// Ranges from the system registry fill in types this registry lacks.
+    pub fn get_range(&self, port_type: &str) -> PortRange {
+        let ranges = self.ranges();
+        ranges
             .get(port_type)
-            .copied()
-            .or_else(|| self.defaults.ranges.get("default").copied())
-            .unwrap_or([9000, 9999])
+            .or_else(|| ranges.get("default"))
+            .cloned()
+            .unwrap_or(PortRange::new(9000, 9999))
     }
 
-    /// Returns all allocated ports across all projects.
-    pub fn all_allocated_ports(&self) -> Vec<Port> {
-        self.projects
+    /// Returns the range type an allocation named `name` draws from: the one
+    /// stored in `meta`, else that of the first type rule matching the name
+    /// (this registry's before the system registry's), else the name itself.
+    pub fn port_type<'a>(&'a self, name: &'a str, meta: &'a PortMeta) -> &'a str {
+        if let Some(port_type) = &meta.range_type {
+            return port_type;
+        }
+        let ignore_case = self.defaults.ignore_case;
+        self.type_rules()
+            .into_iter()
+            .find(|rule| rule.matches(name, ignore_case))
+            .map_or(name, |rule| rule.type_name.as_str())
+    }
+
+    /// Returns the type rules in effect, in the order they are tried: this
+    /// registry's, then the system registry's.
+    pub fn type_rules(&self) -> Vec<&TypeRule> {
+        self.defaults
+            .type_rules
+            .iter()
+            .chain(
+                self.system_registry()
+                    .into_iter()
+                    .flat_map(|system| &system.defaults.type_rules),
+            )
+            .collect()
+    }
+
+    /// Returns the quotas in effect: the stricter of this registry's and the
+    /// system registry's limits.
+    pub fn quotas(&self) -> Quotas {
+        match self.system_registry() {
+            Some(system) => self.defaults.quotas.stricter(&system.defaults.quotas),
+            None => self.defaults.quotas.clone(),
+        }
+    }
+
+    /// Returns the port ranges in effect: this registry's, plus any types
+    /// only the system registry defines.
+    pub fn ranges(&self) -> BTreeMap<&str, PortRange> {
+        let mut ranges: BTreeMap<&str, PortRange> = self
+            .system_registry()
+            .map(|system| {
+                system
+                    .defaults
+                    .ranges
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        ranges.extend(
+            self.defaults
+                .ranges
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.clone())),
+        );
+        ranges
+    }
+
+    /// Returns the system registry layered underneath this one, if any.
+    pub fn system_registry(&self) -> Option<&Registry> {
+        self.system.as_ref().map(|s| &s.registry)
+    }
+
+    /// Returns system registry allocations as (project, name, allocation),
+    /// skipping names this registry also defines.
+    pub fn system_allocations(&self) -> Vec<(&ProjectName, &PortName, &Allocation)> {
+        let Some(system) = self.system_registry() else {
+            return Vec::new();
+        };
+        system
+            .projects
+            .iter()
+            .flat_map(|(project_name, project)| {
+                project
+                    .ports
+                    .iter()
+                    .map(move |(name, allocation)| (project_name, name, allocation))
+            })
+            .filter(|(project_name, name, _)| {
+                !self
+                    .projects
+                    .get(*project_name)
+                    .is_some_and(|p| p.contains_name(name))
+            })
+            .collect()
+    }
+
+    /// Remembers `port` as the one `project.name` had when it was freed,
+    /// forgetting the longest-freed ports beyond [`MAX_HISTORY`].
+    pub fn remember_port(&mut self, project: &ProjectName, name: &PortName, port: Port) {
+        let freed_at = chrono::Local::now().fixed_offset();
+        self.history
+            .entry(project.clone())
+            .or_default()
+            .insert(name.clone(), PreviousPort { port, freed_at });
+
+        let mut remembered: Vec<(DateTime<FixedOffset>, ProjectName, PortName)> = self
+            .history
+            .iter()
+            .flat_map(|(project, ports)| {
+                ports
+                    .iter()
+                    .map(|(name, previous)| (previous.freed_at, project.clone(), name.clone()))
+            })
+            .collect();
+        if remembered.len() > MAX_HISTORY {
+            remembered.sort();
+            let excess = remembered.len() - MAX_HISTORY;
+            for (_, project, name) in &remembered[..excess] {
+                self.take_previous_port(project, name);
+            }
+        }
+    }
+
+    /// Forgets and returns the port `project.name` had when it was last freed.
+    pub fn take_previous_port(&mut self, project: &str, name: &str) -> Option<Port> {
+        let ports = self.history.get_mut(project)?;
+        let previous = ports.remove(name);
+        if ports.is_empty() {
+            self.history.remove(project);
+        }
+        previous.map(|previous| previous.port)
+    }
+
+    /// Forgets the ports of names freed before `cutoff`, returning how many.
+    pub fn forget_ports_freed_before(&mut self, cutoff: DateTime<FixedOffset>) -> usize {
+        let mut forgotten = 0;
+        for ports in self.history.values_mut() {
+            let before = ports.len();
+            ports.retain(|_, previous| previous.freed_at >= cutoff);
+            forgotten += before - ports.len();
+        }
+        self.history.retain(|_, ports| !ports.is_empty());
+        forgotten
+    }
+
+    /// Returns all ports allocated on `host` (`None` for this machine)
+    /// across all projects. This machine's include ports reserved by the
+    /// system registry.
+    pub fn all_allocated_ports(&self, host: Option<&str>) -> Vec<Port> {
+        let mut ports: Vec<Port> = self
+            .projects
             .values()
             .flat_map(|p| p.ports.values())
-            .copied()
-            .collect()
+            .filter(|a| a.meta.host.as_deref() == host)
+            .map(|a| a.port)
+            .collect();
+        if let Some(system) = self.system_registry().filter(|_| host.is_none()) {
+            ports.extend(system.all_allocated_ports(None));
+        }
+        ports
     }
 
-    /// Finds which project and name owns a given port.
+    /// Finds which project and name owns a port on this machine, checking
+    /// the system registry after this one.
     pub fn find_port_owner(&self, port: Port) -> Option<(&str, &str)> {
+        self.find_port_owner_on(None, port)
+    }
+
+    /// Finds which project and name owns a port on `host` (`None` for this
+    /// machine).
+    pub fn find_port_owner_on(&self, host: Option<&str>, port: Port) -> Option<(&str, &str)> {
         for (project_name, project) in &self.projects {
-            for (port_name, &p) in &project.ports {
-                if p == port {
+            for (port_name, allocation) in &project.ports {
+                if allocation.port == port && allocation.meta.host.as_deref() == host {
                     return Some((project_name, port_name));
                 }
             }
         }
-        None
+        self.system_registry()
+            .filter(|_| host.is_none())?
+            .find_port_owner(port)
     }
 }
 
@@ -92,13 +1034,25 @@ impl Registry {
 mod tests {
     use super::*;
 
+    fn name(s: &str) -> PortName {
+        PortName::new(s).unwrap()
+    }
+
+    fn project_name(s: &str) -> ProjectName {
+        ProjectName::new(s).unwrap()
+    }
+
+    fn alloc(n: u16) -> Allocation {
+        Allocation::new(Port::new(n).unwrap(), PortMeta::default())
+    }
+
     #[test]
     fn test_default_registry() {
         let registry = Registry::default();
         assert!(registry.projects.is_empty());
         assert!(!registry.defaults.ranges.is_empty());
-        assert_eq!(registry.get_range("web"), [8000, 8999]);
-        assert_eq!(registry.get_range("unknown"), [9000, 9999]);
+        assert_eq!(registry.get_range("web").segments, [[8000, 8999]]);
+        assert_eq!(registry.get_range("unknown").segments, [[9000, 9999]]);
     }
 
     #[test]
@@ -106,23 +1060,17 @@ mod tests {
         let mut registry = Registry::default();
 
         let mut project1 = Project::default();
-        project1
-            .ports
-            .insert("web".to_string(), Port::new(8080).unwrap());
-        project1
-            .ports
-            .insert("api".to_string(), Port::new(3000).unwrap());
+        project1.ports.insert(name("web"), alloc(8080));
+        project1.ports.insert(name("api"), alloc(3000));
 
         let mut project2 = Project::default();
-        project2
-            .ports
-            .insert("web".to_string(), Port::new(8081).unwrap());
+        project2.ports.insert(name("web"), alloc(8081));
 
-        registry.projects.insert("p1".to_string(), project1);
-        registry.projects.insert("p2".to_string(), project2);
+        registry.projects.insert(project_name("p1"), project1);
+        registry.projects.insert(project_name("p2"), project2);
 
         let mut ports: Vec<u16> = registry
-            .all_allocated_ports()
+            .all_allocated_ports(None)
             .into_iter()
             .map(Port::as_u16)
             .collect();
@@ -135,10 +1083,8 @@ mod tests {
         let mut registry = Registry::default();
 
         let mut project = Project::default();
-        project
-            .ports
-            .insert("web".to_string(), Port::new(8080).unwrap());
-        registry.projects.insert("webapp".to_string(), project);
+        project.ports.insert(name("web"), alloc(8080));
+        registry.projects.insert(project_name("webapp"), project);
 
         assert_eq!(
             registry.find_port_owner(Port::new(8080).unwrap()),
@@ -146,4 +1092,303 @@ mod tests {
         );
         assert_eq!(registry.find_port_owner(Port::new(9999).unwrap()), None);
     }
+
+    #[test]
+    fn test_ports_per_host() {
+        let mut registry = Registry::default();
+        let mut project = Project::default();
+        project.ports.insert(name("web"), alloc(8080));
+        let mut on_build = alloc(8080);
+        on_build.meta.host = Some("build".to_string());
+        project.ports.insert(name("ci"), on_build);
+        registry.projects.insert(project_name("webapp"), project);
+
+        let port = Port::new(8080).unwrap();
+        assert_eq!(registry.all_allocated_ports(None), [port]);
+        assert_eq!(registry.all_allocated_ports(Some("build")), [port]);
+        assert!(registry.all_allocated_ports(Some("db1")).is_empty());
+        assert_eq!(registry.find_port_owner(port), Some(("webapp", "web")));
+        assert_eq!(
+            registry.find_port_owner_on(Some("build"), port),
+            Some(("webapp", "ci"))
+        );
+        assert_eq!(parse_host("all"), Err("'all' is reserved".to_string()));
+        assert!(parse_host("db 1").is_err());
+        assert_eq!(parse_host("db1.internal"), Ok("db1.internal".to_string()));
+    }
+
+    #[test]
+    fn test_allocation_serde_formats() {
+        let mut registry = Registry::default();
+        let mut project = Project::default();
+        project.ports.insert(name("web"), alloc(8080));
+        project.ports.insert(
+            name("api"),
+            Allocation::new(
+                Port::new(3000).unwrap(),
+                PortMeta {
+                    group: Some("sprint-42".to_string()),
+                    ..Default::default()
+                },
+            ),
+        );
+        registry.projects.insert(project_name("webapp"), project);
+
+        let content = toml::to_string_pretty(&registry).unwrap();
+        assert!(content.contains("web = 8080"));
+        assert!(content.contains("group = \"sprint-42\""));
+
+        let parsed: Registry = toml::from_str(&content).unwrap();
+        let ports = &parsed.projects["webapp"].ports;
+        assert_eq!(ports["web"], alloc(8080));
+        assert_eq!(ports["api"].meta.group.as_deref(), Some("sprint-42"));
+    }
+
+    #[test]
+    fn test_port_meta_url() {
+        let port = Port::new(8443).unwrap();
+        assert_eq!(PortMeta::default().url(port), "http://localhost:8443");
+
+        let meta = PortMeta {
+            url_template: Some("https://localhost:{port}/admin".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(meta.url(port), "https://localhost:8443/admin");
+        assert_eq!(meta.url_on(port, "10.0.0.5"), "https://10.0.0.5:8443/admin");
+
+        let meta = PortMeta {
+            host: Some("devbox".to_string()),
+            url_template: Some("https://{host}:{port}/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(meta.url(port), "https://devbox:8443/");
+        let meta = PortMeta {
+            host: Some("devbox".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(meta.url(port), "http://devbox:8443");
+        assert_eq!(meta.url_on(port, "10.0.0.5"), "http://devbox:8443");
+    }
+
+    #[test]
+    fn test_alias_entries_roundtrip() {
+        let content = "[projects.webapp]\nweb = 8080\nfrontend = { alias = \"web\" }\n";
+        let registry: Registry = toml::from_str(content).unwrap();
+        let project = &registry.projects["webapp"];
+
+        assert_eq!(project.aliases["frontend"], "web");
+        assert!(!project.ports.contains_key("frontend"));
+        assert_eq!(project.resolve("frontend"), Some(&alloc(8080)));
+        assert_eq!(project.aliases_for("web"), vec!["frontend"]);
+
+        let serialized = toml::to_string_pretty(&registry).unwrap();
+        let reparsed: Registry = toml::from_str(&serialized).unwrap();
+        assert_eq!(reparsed.projects["webapp"].aliases["frontend"], "web");
+    }
+
+    #[test]
+    fn test_range_policy_roundtrip() {
+        let content = "[defaults.ranges]\nweb = [8000, 8999]\n\
+                       db = { range = [5400, 5499], policy = \"sequential\" }\n";
+        let registry: Registry = toml::from_str(content).unwrap();
+        assert_eq!(registry.get_range("web"), PortRange::new(8000, 8999));
+        assert_eq!(
+            registry.get_range("db").policy,
+            AllocationPolicy::Sequential
+        );
+
+        let serialized = toml::to_string_pretty(&registry).unwrap();
+        assert!(serialized.contains("web = [\n    8000,"));
+        assert!(serialized.contains("policy = \"sequential\""));
+        let reparsed: Registry = toml::from_str(&serialized).unwrap();
+        assert_eq!(reparsed.get_range("db"), registry.get_range("db"));
+    }
+
+    #[test]
+    fn test_range_segments_roundtrip() {
+        let content = "[defaults.ranges]\napi = [[3000, 3099], [3300, 3399]]\n\
+                       db = { range = [[5400, 5409], [5500, 5509]], policy = \"random\" }\n";
+        let registry: Registry = toml::from_str(content).unwrap();
+        let api = registry.get_range("api");
+        assert_eq!(api.segments, [[3000, 3099], [3300, 3399]]);
+        assert_eq!((api.start(), api.end()), (3000, 3399));
+        assert!(api.contains(3305) && !api.contains(3200));
+        assert_eq!(api.to_string(), "3000-3099,3300-3399");
+        assert_eq!(
+            registry.get_range("db").to_string(),
+            "5400-5409,5500-5509 (random)"
+        );
+
+        let serialized = toml::to_string_pretty(&registry).unwrap();
+        let reparsed: Registry = toml::from_str(&serialized).unwrap();
+        assert_eq!(reparsed.get_range("api"), api);
+        assert_eq!(reparsed.get_range("db"), registry.get_range("db"));
+
+        let result: Result<Registry, _> = toml::from_str("[defaults.ranges]\napi = []\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_range_projects() {
+        let content = "[defaults.ranges]\ninfra = { range = [9500, 9599], projects = [\"infra-*\", \"dns\"] }\n";
+        let registry: Registry = toml::from_str(content).unwrap();
+        let range = registry.get_range("infra");
+        assert!(range.allows("infra-proxy", false));
+        assert!(range.allows("dns", false));
+        assert!(!range.allows("webapp", false));
+        assert!(!range.allows("Infra-proxy", false));
+        assert!(range.allows("Infra-proxy", true));
+        assert!(PortRange::new(8000, 8999).allows("webapp", false));
+        assert_eq!(range.to_string(), "9500-9599 only for infra-*, dns");
+
+        let serialized = toml::to_string_pretty(&registry).unwrap();
+        let reparsed: Registry = toml::from_str(&serialized).unwrap();
+        assert_eq!(reparsed.get_range("infra"), range);
+    }
+
+    #[test]
+    fn test_range_exclusions_roundtrip() {
+        let content = "[defaults.ranges]\nweb = { range = [8000, 8999], exclude = [8080, 8443] }\n";
+        let registry: Registry = toml::from_str(content).unwrap();
+        let range = registry.get_range("web");
+        assert_eq!(range.exclude, vec![8080, 8443]);
+        assert_eq!(range.policy, AllocationPolicy::LowestFree);
+        assert_eq!(range.to_string(), "8000-8999 except 8080, 8443");
+
+        let serialized = toml::to_string_pretty(&registry).unwrap();
+        assert!(!serialized.contains("policy"));
+        let reparsed: Registry = toml::from_str(&serialized).unwrap();
+        assert_eq!(reparsed.get_range("web"), range);
+    }
+
+    #[test]
+    fn test_allocation_rejects_invalid_port() {
+        let result: Result<Registry, _> = toml::from_str("[projects.webapp]\nweb = 0\n");
+        assert!(result.is_err());
+
+        let result: Result<Registry, _> = toml::from_str("[projects.webapp]\nweb = 70000\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let mut registry = Registry::default();
+        let project = project_name("webapp");
+        for i in 0..=MAX_HISTORY {
+            registry.remember_port(&project, &name(&format!("web{i}")), alloc(8000).port);
+        }
+        assert_eq!(registry.history["webapp"].len(), MAX_HISTORY);
+    }
+
+    #[test]
+    fn test_registry_reads_legacy_names() {
+        let registry: Registry =
+            toml::from_str("[projects.\"My App\"]\n\"web.v2\" = 8080\n").unwrap();
+        assert!(registry.projects["My App"].contains_name("web.v2"));
+    }
+
+    #[test]
+    fn test_system_registry_layering() {
+        let system: Registry = toml::from_str(
+            "[defaults.ranges]\nweb = [7000, 7099]\ngpu = [7100, 7199]\n\n\
+             [projects.shared]\ndb = 5432\nweb = 7000\n",
+        )
+        .unwrap();
+
+        let mut registry = Registry::default();
+        let mut project = Project::default();
+        project.ports.insert(name("web"), alloc(8080));
+        registry.projects.insert(project_name("shared"), project);
+        registry.system = Some(Box::new(SystemRegistry {
+            path: PathBuf::from("/etc/port-manager/registry.toml"),
+            registry: system,
+        }));
+
+        // User ranges win; system ranges fill in missing types
+        assert_eq!(registry.get_range("web").segments, [[8000, 8999]]);
+        assert_eq!(registry.get_range("gpu").segments, [[7100, 7199]]);
+
+        // System reservations count as allocated even when shadowed
+        let mut ports: Vec<u16> = registry
+            .all_allocated_ports(None)
+            .into_iter()
+            .map(Port::as_u16)
+            .collect();
+        ports.sort();
+        assert_eq!(ports, vec![5432, 7000, 8080]);
+        assert_eq!(
+            registry.find_port_owner(Port::new(5432).unwrap()),
+            Some(("shared", "db"))
+        );
+
+        // Only unshadowed system entries are listed
+        let listed: Vec<_> = registry
+            .system_allocations()
+            .into_iter()
+            .map(|(p, n, a)| (p.as_str(), n.as_str(), a.port.as_u16()))
+            .collect();
+        assert_eq!(listed, vec![("shared", "db", 5432)]);
+
+        // The system layer is never saved
+        let saved = toml::to_string_pretty(&registry).unwrap();
+        assert!(!saved.contains("5432"));
+    }
+
+    #[test]
+    fn test_quotas_stricter() {
+        let own: Quotas = toml::from_str("per_project = 10\nranges = { web = 5 }").unwrap();
+        let system: Quotas =
+            toml::from_str("per_project = 4\nper_user = 8\nranges = { web = 20, db = 2 }").unwrap();
+        let quotas = own.stricter(&system);
+        assert_eq!(quotas.per_project, Some(4));
+        assert_eq!(quotas.per_user, Some(8));
+        assert_eq!(quotas.ranges["web"], 5);
+        assert_eq!(quotas.ranges["db"], 2);
+        assert!(Quotas::default().is_empty());
+    }
+
+    #[test]
+    fn test_hostname_problem() {
+        assert_eq!(hostname_problem("app.localhost"), None);
+        assert_eq!(hostname_problem("web-1.example.com"), None);
+        for bad in ["", "a..b", "a;b", "a{", "a b", "a\nb", "a_b", ".a"] {
+            assert!(hostname_problem(bad).is_some(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_port_type() {
+        let registry: Registry = toml::from_str(
+            r#"
+[[defaults.type_rules]]
+name = "frontend*"
+type = "web"
+
+[[defaults.type_rules]]
+regex = "-grpc$"
+type = "api"
+
+[[defaults.type_rules]]
+name = "*"
+type = "db"
+"#,
+        )
+        .unwrap();
+        let meta = PortMeta::default();
+        assert_eq!(registry.port_type("frontend-admin", &meta), "web");
+        assert_eq!(registry.port_type("users-grpc", &meta), "api");
+        // Rules are tried in order, so the catch-all only gets the rest
+        assert_eq!(registry.port_type("worker", &meta), "db");
+
+        let explicit = PortMeta {
+            range_type: Some("cache".to_string()),
+            ..PortMeta::default()
+        };
+        assert_eq!(registry.port_type("frontend", &explicit), "cache");
+        assert_eq!(Registry::default().port_type("frontend", &meta), "frontend");
+
+        let saved = toml::to_string_pretty(&registry).unwrap();
+        let reparsed: Registry = toml::from_str(&saved).unwrap();
+        assert_eq!(reparsed.defaults.type_rules, registry.defaults.type_rules);
+    }
 }
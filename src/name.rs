@@ -0,0 +1,365 @@
+//! Name newtypes for projects and ports.
+//!
+//! Project and port names end up in `key=value` query output, environment
+//! variable names, hostnames, and file paths, so they are restricted to a
+//! small, shell-safe character set at construction time. `.` is left out so
+//! that `project.name` is never ambiguous.
+//!
+//! Registries written by older versions may hold names outside that set
+//! (e.g., "My App"). Those still load, so nothing is lost on upgrade, and
+//! `pm config validate` reports them; only names given as new input are checked.
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject, StringValidation};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Maximum length of a project, port, or range name.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Error returned when attempting to create an invalid name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidNameError {
+    /// The rejected name.
+    pub name: String,
+    /// Why the name was rejected.
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (use letters, digits, '-' and '_')", self.reason)
+    }
+}
+
+impl std::error::Error for InvalidNameError {}
+
+/// Returns why `name` is not a valid project, port, or range name, if it isn't.
+///
+/// Names may contain ASCII letters, digits, `-`, and `_`, up to
+/// [`MAX_NAME_LEN`] characters, and may not start with `-` (so they can't be
+/// mistaken for flags). `.` separates project and port in `project.name`, so
+/// it isn't allowed, which also keeps names apart from the "." shorthand.
+pub fn name_problem(name: &str) -> Option<String> {
+    if name.is_empty() {
+        return Some("name is empty".to_string());
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Some(format!("name is longer than {MAX_NAME_LEN} characters"));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_')))
+    {
+        return Some(format!("name contains invalid character {c:?}"));
+    }
+    if name.starts_with('-') {
+        return Some("name cannot start with '-'".to_string());
+    }
+    None
+}
+
+/// Matches `name` against a shell-style glob where `*` matches any run of
+/// characters and `?` matches exactly one (e.g., "web*" matches "webapp").
+pub fn glob_match(pattern: &str, name: &str, ignore_case: bool) -> bool {
+    let eq = |p: u8, c: u8| p == b'?' || p == c || (ignore_case && p.eq_ignore_ascii_case(&c));
+    let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and the name position it is currently matched up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, n));
+            p += 1;
+        } else if p < pattern.len() && eq(pattern[p], name[n]) {
+            p += 1;
+            n += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // Let the last `*` absorb one more character and retry
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, n));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Returns true if `s` is a glob rather than a name. Names can't contain
+/// `*` or `?`, so a pattern is never mistaken for one.
+pub fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+fn check(name: String) -> Result<String, InvalidNameError> {
+    match name_problem(&name) {
+        Some(reason) => Err(InvalidNameError { name, reason }),
+        None => Ok(name),
+    }
+}
+
+macro_rules! name_type {
+    ($(#[$doc:meta])* $ty:ident, $schema_name:literal) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $ty(String);
+
+        impl $ty {
+            /// Creates a name, validating its characters and length.
+            ///
+            /// # Errors
+            ///
+            /// Returns `InvalidNameError` if the name is empty, too long, or
+            /// contains characters outside `[A-Za-z0-9_-]`.
+            pub fn new(name: impl Into<String>) -> Result<Self, InvalidNameError> {
+                check(name.into()).map($ty)
+            }
+
+            /// Returns the name as a string slice.
+            #[inline]
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl Deref for $ty {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl AsRef<str> for $ty {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Borrow<str> for $ty {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl PartialEq<str> for $ty {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $ty {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl From<$ty> for String {
+            fn from(name: $ty) -> Self {
+                name.0
+            }
+        }
+
+        impl FromStr for $ty {
+            type Err = InvalidNameError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $ty::new(s)
+            }
+        }
+
+        impl Serialize for $ty {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                self.0.serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                // Not checked: older versions allowed other names
+                String::deserialize(deserializer).map($ty)
+            }
+        }
+
+        impl JsonSchema for $ty {
+            fn schema_name() -> String {
+                $schema_name.to_string()
+            }
+
+            fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+                SchemaObject {
+                    instance_type: Some(InstanceType::String.into()),
+                    string: Some(Box::new(StringValidation {
+                        max_length: Some(MAX_NAME_LEN as u32),
+                        min_length: Some(1),
+                        pattern: Some("^[A-Za-z0-9_][A-Za-z0-9_-]*$".to_string()),
+                    })),
+                    ..Default::default()
+                }
+                .into()
+            }
+        }
+    };
+}
+
+name_type!(
+    /// A validated project name (e.g., "webapp").
+    ProjectName,
+    "ProjectName"
+);
+
+name_type!(
+    /// A validated port or alias name within a project (e.g., "web").
+    PortName,
+    "PortName"
+);
+
+impl PortName {
+    /// Returns the environment variable form of this name: uppercase, with
+    /// anything but ASCII letters and digits (`-` and `.`, or the spaces of a
+    /// legacy name) replaced by `_` (e.g., "web-api" -> "WEB_API").
+    ///
+    /// Names starting with a digit get a leading `_` so the result is a
+    /// valid shell identifier.
+    pub fn env_name(&self) -> String {
+        let name: String = self
+            .0
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            format!("_{name}")
+        } else {
+            name
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_names() {
+        for name in [
+            "web",
+            "web-app_2",
+            "_internal",
+            "8080",
+            &"x".repeat(MAX_NAME_LEN),
+        ] {
+            assert!(PortName::new(name).is_ok(), "{name}");
+            assert!(ProjectName::new(name).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        for name in [
+            "",
+            "a b",
+            "x=y",
+            "a/b",
+            "a\\b",
+            ".",
+            "..",
+            "web.v1",
+            "-web",
+            "wéb",
+            "tab\t",
+            &"x".repeat(MAX_NAME_LEN + 1),
+        ] {
+            assert!(PortName::new(name).is_err(), "{name:?}");
+            assert!(ProjectName::new(name).is_err(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn test_error_message() {
+        let err = PortName::new("x=y").unwrap_err();
+        assert_eq!(err.name, "x=y");
+        assert!(err
+            .to_string()
+            .starts_with("name contains invalid character '='"));
+    }
+
+    #[test]
+    fn test_from_str_and_display() {
+        let name: ProjectName = "webapp".parse().unwrap();
+        assert_eq!(name.to_string(), "webapp");
+        assert_eq!(name, "webapp");
+        assert!("a b".parse::<ProjectName>().is_err());
+    }
+
+    #[test]
+    fn test_serde_accepts_legacy_names() {
+        let name: PortName = serde_json::from_str("\"web\"").unwrap();
+        assert_eq!(serde_json::to_string(&name).unwrap(), "\"web\"");
+
+        let name: ProjectName = serde_json::from_str("\"My App\"").unwrap();
+        assert_eq!(name, "My App");
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("web*", "webapp", false));
+        assert!(glob_match("*app", "webapp", false));
+        assert!(glob_match("w?b*p", "webapp", false));
+        assert!(glob_match("*", "", false));
+        assert!(glob_match("webapp", "webapp", false));
+        assert!(!glob_match("web", "webapp", false));
+        assert!(!glob_match("api*", "webapp", false));
+        assert!(!glob_match("WEB*", "webapp", false));
+        assert!(glob_match("WEB*", "webapp", true));
+        assert!(is_glob("team-a-*"));
+        assert!(!is_glob("team-a"));
+    }
+
+    #[test]
+    fn test_env_name() {
+        assert_eq!(PortName::new("web").unwrap().env_name(), "WEB");
+        assert_eq!(
+            PortName::new("web-api_v2").unwrap().env_name(),
+            "WEB_API_V2"
+        );
+        assert_eq!(PortName::new("Admin_UI").unwrap().env_name(), "ADMIN_UI");
+        assert_eq!(PortName::new("8080").unwrap().env_name(), "_8080");
+
+        // Legacy names load unchecked
+        let legacy: PortName = serde_json::from_str("\"My App$(x)\"").unwrap();
+        assert_eq!(legacy.env_name(), "MY_APP__X_");
+    }
+
+    #[test]
+    fn test_borrow_lookup() {
+        let mut map = std::collections::BTreeMap::new();
+        map.insert(PortName::new("web").unwrap(), 8080);
+        assert_eq!(map.get("web"), Some(&8080));
+    }
+}
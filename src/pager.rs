@@ -0,0 +1,155 @@
+//! Paging table output, like git.
+//!
+//! When stdout is a terminal, commands that print tables send their output
+//! through `$PM_PAGER` or `$PAGER` (`less` by default). With `LESS=FRX`, less
+//! exits at once when the output fits on one screen, so short tables print
+//! as before. `--no-pager`, an empty pager, or `cat` turn paging off.
+
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use comfy_table::Table;
+use tracing::debug;
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+static STARTED: AtomicBool = AtomicBool::new(false);
+static PAGER: Mutex<Option<Pager>> = Mutex::new(None);
+
+/// A running pager that stdout is redirected into.
+struct Pager {
+    child: Child,
+    /// The terminal's width, since tables can't ask a pipe for it.
+    width: Option<u16>,
+    /// The original stdout, restored when the pager finishes.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    saved_stdout: i32,
+}
+
+/// Turns paging off for this run (`--no-pager`).
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Returns the pager to run: `PM_PAGER`, then `PAGER`, then `less`. An empty
+/// command or `cat` means no pager.
+fn pager_command(pm_pager: Option<String>, pager: Option<String>) -> Option<String> {
+    let command = pm_pager.or(pager).unwrap_or_else(|| "less".to_string());
+    let command = command.trim();
+    (!command.is_empty() && command != "cat").then(|| command.to_string())
+}
+
+/// Starts the pager and sends the rest of stdout through it, if stdout is a
+/// terminal and paging is on. Later calls do nothing.
+pub fn start() {
+    if DISABLED.load(Ordering::Relaxed) || STARTED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    if !io::stdout().is_terminal() || env::var("TERM").as_deref() == Ok("dumb") {
+        return;
+    }
+    let Some(command) = pager_command(env::var("PM_PAGER").ok(), env::var("PAGER").ok()) else {
+        return;
+    };
+    match spawn(&command) {
+        Ok(pager) => *PAGER.lock().unwrap_or_else(|e| e.into_inner()) = Some(pager),
+        Err(e) => debug!("not paging through '{command}': {e}"),
+    }
+}
+
+#[cfg(unix)]
+fn spawn(command: &str) -> io::Result<Pager> {
+    use std::os::unix::io::AsRawFd;
+    use std::process::{Command, Stdio};
+
+    let width = Table::new().width();
+    let mut pager = Command::new("sh");
+    pager.arg("-c").arg(command).stdin(Stdio::piped());
+    // Quit if the output fits one screen, keep colors, and don't clear the screen
+    if env::var_os("LESS").is_none() {
+        pager.env("LESS", "FRX");
+    }
+    if env::var_os("LV").is_none() {
+        pager.env("LV", "-c");
+    }
+    let mut child = pager.spawn()?;
+    let stdin = child.stdin.take().expect("pager stdin is piped");
+
+    io::stdout().flush()?;
+    // SAFETY: dup and dup2 only duplicate descriptors this process owns
+    let saved_stdout = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if saved_stdout < 0 || unsafe { libc::dup2(stdin.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        let error = io::Error::last_os_error();
+        if saved_stdout >= 0 {
+            unsafe { libc::close(saved_stdout) };
+        }
+        drop(stdin);
+        let _ = child.wait();
+        return Err(error);
+    }
+    // Quitting the pager early closes the pipe; end quietly like git instead
+    // of failing on the next write
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+    Ok(Pager {
+        child,
+        width,
+        saved_stdout,
+    })
+}
+
+#[cfg(not(unix))]
+fn spawn(_command: &str) -> io::Result<Pager> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "paging needs a Unix terminal",
+    ))
+}
+
+/// Gives a table the terminal's width and colors while output is paged.
+pub fn prepare_table(table: &mut Table) {
+    let pager = PAGER.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(pager) = pager.as_ref() {
+        table.enforce_styling();
+        if let Some(width) = pager.width {
+            table.set_width(width);
+        }
+    }
+}
+
+/// Ends paging: restores stdout and waits for the user to quit the pager.
+pub fn finish() {
+    let Some(mut pager) = PAGER.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    let _ = io::stdout().flush();
+    // Putting the original stdout back closes the pipe, so the pager sees
+    // the end of the output
+    #[cfg(unix)]
+    unsafe {
+        libc::dup2(pager.saved_stdout, libc::STDOUT_FILENO);
+        libc::close(pager.saved_stdout);
+    }
+    let _ = pager.child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pager_command() {
+        assert_eq!(pager_command(None, None).as_deref(), Some("less"));
+        assert_eq!(
+            pager_command(None, Some("more".to_string())).as_deref(),
+            Some("more")
+        );
+        assert_eq!(
+            pager_command(Some("bat -p".to_string()), Some("more".to_string())).as_deref(),
+            Some("bat -p")
+        );
+        assert_eq!(pager_command(Some(String::new()), None), None);
+        assert_eq!(pager_command(None, Some("cat".to_string())), None);
+    }
+}
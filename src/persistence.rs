@@ -1,34 +1,184 @@
 //! Persistence layer for the port manager.
 //!
 //! Handles loading and saving the TOML registry file with file locking
-//! for safe concurrent access.
+//! for safe concurrent access, and a write-ahead journal so a write cut
+//! short by a crash is finished or discarded by the next process.
 
-use std::fs::{self, File};
-use std::io::Write;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Instant;
 
+use chrono::Local;
 use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, enabled, warn, Level};
 
 use crate::error::{ConfigError, Result};
-use crate::model::Registry;
+use crate::gitsync::commit_change;
+use crate::model::{Registry, SystemRegistry};
+use crate::repair::parse_by_section;
+use crate::theme;
+use crate::usage::record_change;
+
+/// Default location of the machine-wide registry.
+const SYSTEM_REGISTRY_PATH: &str = "/etc/port-manager/registry.toml";
+
+/// Registry path from `pm --config`, set once at startup.
+static REGISTRY_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Makes `path` the registry for the rest of this process, ahead of
+/// `PM_CONFIG_DIR` and `PM_CONFIG_PATH`. Relative paths are resolved
+/// against the current directory.
+pub fn set_registry_path_override(path: &Path) -> Result<()> {
+    let path = std::env::current_dir()?.join(path);
+    // Only main calls this, once
+    let _ = REGISTRY_PATH_OVERRIDE.set(path);
+    Ok(())
+}
+
+/// Returns the registry path given with `pm --config`, if any.
+pub fn registry_path_override() -> Option<&'static Path> {
+    REGISTRY_PATH_OVERRIDE.get().map(PathBuf::as_path)
+}
+
+/// Profile from `pm --profile`, set once at startup.
+static PROFILE_OVERRIDE: OnceLock<String> = OnceLock::new();
+
+/// Name of the profile that uses the base registry rather than a
+/// `profiles/` subdirectory.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// File in the base config directory naming the profile `pm profile switch`
+/// selected.
+const ACTIVE_PROFILE_FILE: &str = "active-profile";
+
+/// Uses `name` as the profile for the rest of this process, ahead of the
+/// one selected with `pm profile switch`.
+pub fn set_profile_override(name: &str) {
+    // Only main calls this, once
+    let _ = PROFILE_OVERRIDE.set(name.to_string());
+}
+
+/// Returns true if `--config` or `--profile` picked the registry, rather
+/// than the environment and `pm profile switch`.
+pub fn registry_chosen_on_command_line() -> bool {
+    REGISTRY_PATH_OVERRIDE.get().is_some() || PROFILE_OVERRIDE.get().is_some()
+}
+
+/// Reads a path from an environment variable, treating an empty value as unset.
+fn env_path(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Returns the directory holding the registry and its auxiliary files
+/// (lock file, temporary files).
+///
+/// This is the directory containing the `pm --config` registry if given,
+/// then the active profile's directory, then [`base_config_dir`].
+pub fn config_dir() -> std::result::Result<PathBuf, ConfigError> {
+    if let Some(path) = registry_path_override() {
+        let parent = path.parent().ok_or(ConfigError::NoConfigDir)?;
+        return Ok(parent.to_path_buf());
+    }
+    match active_profile()? {
+        Some(profile) => profile_dir(&profile),
+        None => base_config_dir(),
+    }
+}
+
+/// Returns the directory holding the default profile's registry, which
+/// other profiles live under.
+///
+/// This is `PM_CONFIG_DIR`, then the directory containing `PM_CONFIG_PATH`,
+/// then `<config dir>/port-manager`.
+pub fn base_config_dir() -> std::result::Result<PathBuf, ConfigError> {
+    if let Some(dir) = env_path("PM_CONFIG_DIR") {
+        return Ok(dir);
+    }
+    if let Some(path) = env_path("PM_CONFIG_PATH") {
+        let parent = path.parent().ok_or(ConfigError::NoConfigDir)?;
+        return Ok(parent.to_path_buf());
+    }
+    let config_dir = dirs::config_dir().ok_or(ConfigError::NoConfigDir)?;
+    Ok(config_dir.join("port-manager"))
+}
 
 /// Returns the path to the registry file.
 ///
-/// Respects the `PM_CONFIG_PATH` environment variable if set,
-/// otherwise uses the system config directory.
+/// This is the `pm --config` path if given, otherwise the active profile's
+/// registry, otherwise the default profile's: `registry.toml` in
+/// `PM_CONFIG_DIR` if set, otherwise `PM_CONFIG_PATH` if set, otherwise
+/// `registry.toml` in the system config directory.
 pub fn registry_path() -> std::result::Result<PathBuf, ConfigError> {
-    if let Ok(path) = std::env::var("PM_CONFIG_PATH") {
-        return Ok(PathBuf::from(path));
+    if let Some(path) = registry_path_override() {
+        return Ok(path.to_path_buf());
     }
-    let config_dir = dirs::config_dir().ok_or(ConfigError::NoConfigDir)?;
-    Ok(config_dir.join("port-manager").join("registry.toml"))
+    let profile = active_profile()?;
+    profile_registry_path(profile.as_deref().unwrap_or(DEFAULT_PROFILE))
+}
+
+/// Returns the path to a profile's registry file.
+pub fn profile_registry_path(profile: &str) -> std::result::Result<PathBuf, ConfigError> {
+    if profile != DEFAULT_PROFILE {
+        return Ok(profile_dir(profile)?.join("registry.toml"));
+    }
+    if env_path("PM_CONFIG_DIR").is_none() {
+        if let Some(path) = env_path("PM_CONFIG_PATH") {
+            return Ok(path);
+        }
+    }
+    Ok(base_config_dir()?.join("registry.toml"))
+}
+
+/// Returns the directory holding a (non-default) profile's registry.
+pub fn profile_dir(profile: &str) -> std::result::Result<PathBuf, ConfigError> {
+    Ok(base_config_dir()?.join("profiles").join(profile))
+}
+
+/// Returns the profile in use, or `None` for the default profile.
+///
+/// `pm --profile` wins over the profile selected with `pm profile switch`.
+pub fn active_profile() -> std::result::Result<Option<String>, ConfigError> {
+    let profile = match PROFILE_OVERRIDE.get() {
+        Some(profile) => Some(profile.clone()),
+        None => switched_profile()?,
+    };
+    Ok(profile.filter(|p| p != DEFAULT_PROFILE))
+}
+
+/// Returns the profile selected with `pm profile switch`, if any.
+fn switched_profile() -> std::result::Result<Option<String>, ConfigError> {
+    let path = base_config_dir()?.join(ACTIVE_PROFILE_FILE);
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(Some(content.trim().to_string()).filter(|p| !p.is_empty())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(ConfigError::ReadFailed { path, source }),
+    }
+}
+
+/// Makes `profile` the one used when `pm --profile` isn't given.
+pub fn switch_profile(profile: &str) -> std::result::Result<(), ConfigError> {
+    let dir = base_config_dir()?;
+    let path = dir.join(ACTIVE_PROFILE_FILE);
+    let result = if profile == DEFAULT_PROFILE {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    } else {
+        fs::create_dir_all(&dir).and_then(|_| fs::write(&path, format!("{profile}\n")))
+    };
+    result.map_err(|source| ConfigError::WriteFailed { path, source })
 }
 
 /// Returns the path to the lock file used for concurrent access protection.
 fn lock_file_path() -> std::result::Result<PathBuf, ConfigError> {
-    let registry = registry_path()?;
-    let parent = registry.parent().ok_or(ConfigError::NoConfigDir)?;
-    Ok(parent.join(".registry.lock"))
+    Ok(config_dir()?.join(".registry.lock"))
 }
 
 /// Creates and opens the lock file, creating parent directories if needed.
@@ -43,44 +193,261 @@ fn open_lock_file() -> std::result::Result<File, ConfigError> {
         })?;
     }
 
-    File::create(&lock_path).map_err(|source| ConfigError::WriteFailed {
-        path: lock_path,
-        source,
-    })
+    // Don't truncate: on Windows that fails while another process holds the lock
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|source| ConfigError::WriteFailed {
+            path: lock_path,
+            source,
+        })
 }
 
-/// Loads the registry from disk, creating a default one if it doesn't exist.
+/// Returns where the machine-wide registry is read from, if anywhere.
 ///
-/// Acquires an exclusive lock since loading may need to create the default
-/// registry file. This ensures safe concurrent access.
-pub fn load_registry() -> Result<Registry> {
-    let path = registry_path()?;
+/// `PM_SYSTEM_REGISTRY` takes precedence (set it to an empty value to
+/// disable the system registry), then `[defaults] system_registry`, then
+/// `/etc/port-manager/registry.toml`.
+fn system_registry_path(registry: &Registry) -> Option<PathBuf> {
+    match std::env::var_os("PM_SYSTEM_REGISTRY") {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(
+            registry
+                .defaults
+                .system_registry
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(SYSTEM_REGISTRY_PATH)),
+        ),
+    }
+}
 
-    // Acquire exclusive lock (we may need to write if file doesn't exist)
+/// Loads the machine-wide registry, if there is one, underneath `registry`.
+///
+/// The system registry is shared by every user on the machine, so a broken
+/// one is reported and skipped rather than making pm unusable.
+fn attach_system_registry(registry: &mut Registry, own_path: &Path) {
+    let Some(path) = system_registry_path(registry) else {
+        return;
+    };
+    if path == own_path || !path.is_file() {
+        return;
+    }
+    let loaded = fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| toml::from_str::<Registry>(&content).map_err(|e| e.to_string()));
+    match loaded {
+        Ok(system) => {
+            debug!(path = %path.display(), "loaded system registry");
+            registry.system = Some(Box::new(SystemRegistry {
+                path,
+                registry: system,
+            }));
+        }
+        Err(e) => warn!("Ignoring system registry {}: {e}", path.display()),
+    }
+}
+
+/// Opens the lock file and blocks until we hold an exclusive lock on it,
+/// then finishes or discards any write a crashed process left in the journal.
+///
+/// The lock is released when the returned file is dropped.
+fn acquire_lock() -> Result<File> {
     let lock_file = open_lock_file()?;
     let lock_path = lock_file_path()?;
+    let start = Instant::now();
     lock_file
         .lock_exclusive()
         .map_err(|source| ConfigError::LockFailed {
-            path: lock_path,
+            path: lock_path.clone(),
             source,
         })?;
+    debug!(path = %lock_path.display(), waited = ?start.elapsed(), "acquired registry lock");
 
-    // Lock is held until lock_file is dropped at end of function
-    if !path.exists() {
-        let registry = Registry::default();
-        save_registry_inner(&registry)?;
-        return Ok(registry);
+    let journal = journal_path()?;
+    let registry = registry_path()?;
+    match recover_journal(&journal, &registry) {
+        Ok(Recovery::Clean) => {}
+        Ok(Recovery::RolledForward) => {
+            warn!(
+                "Finished an interrupted registry write from {}",
+                journal.display()
+            )
+        }
+        Ok(Recovery::Discarded) => {
+            warn!("Discarded an incomplete registry write; the registry was not changed")
+        }
+        Err(source) => {
+            return Err(ConfigError::WriteFailed {
+                path: journal,
+                source,
+            }
+            .into())
+        }
     }
+    Ok(lock_file)
+}
 
-    let content = fs::read_to_string(&path).map_err(|source| ConfigError::ReadFailed {
-        path: path.clone(),
-        source,
-    })?;
+/// Returns the path to the write-ahead journal.
+fn journal_path() -> std::result::Result<PathBuf, ConfigError> {
+    Ok(config_dir()?.join(".registry.journal"))
+}
+
+/// A registry write recorded before it is applied.
+///
+/// The journal is synced to disk before the registry is touched and removed
+/// once the new content is in place, so finding one means a writer died
+/// part-way. A journal that doesn't parse or whose checksum doesn't match was
+/// itself cut short, before the registry was touched, and is discarded.
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    version: u32,
+    /// Registry content before the write, if the file existed (kept for undo).
+    before: Option<String>,
+    /// Registry content the write will leave in place.
+    after: String,
+    /// FNV-1a hash of `after`, in hex.
+    checksum: String,
+}
 
-    let registry: Registry =
-        toml::from_str(&content).map_err(|source| ConfigError::ParseFailed { path, source })?;
+const JOURNAL_VERSION: u32 = 1;
+
+/// What `recover_journal` found.
+#[derive(Debug, PartialEq, Eq)]
+enum Recovery {
+    /// No journal: the last write completed.
+    Clean,
+    /// A complete journal was found and its content written to the registry.
+    RolledForward,
+    /// An incomplete journal was found and removed.
+    Discarded,
+}
+
+/// 64-bit FNV-1a, enough to tell a torn journal from a complete one.
+fn checksum(content: &str) -> String {
+    let hash = content.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
 
+/// Records an intended registry write in the journal and syncs it to disk.
+fn write_journal(journal: &Path, registry: &Path, after: &str) -> io::Result<()> {
+    let before = match fs::read_to_string(registry) {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let entry = Journal {
+        version: JOURNAL_VERSION,
+        before,
+        after: after.to_string(),
+        checksum: checksum(after),
+    };
+    let mut file = File::create(journal)?;
+    serde_json::to_writer(&mut file, &entry)?;
+    file.sync_all()?;
+    sync_dir(journal)
+}
+
+/// Rolls an interrupted write forward, or discards a torn journal.
+fn recover_journal(journal: &Path, registry: &Path) -> io::Result<Recovery> {
+    let content = match fs::read_to_string(journal) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Recovery::Clean),
+        // Invalid UTF-8 can only come from a torn write
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let entry = serde_json::from_str::<Journal>(&content)
+        .ok()
+        .filter(|j| j.version == JOURNAL_VERSION && j.checksum == checksum(&j.after));
+    let recovery = match entry {
+        Some(entry) => {
+            if fs::read_to_string(registry).ok().as_deref() != Some(entry.after.as_str()) {
+                write_atomic(registry, &entry.after)?;
+            }
+            Recovery::RolledForward
+        }
+        None => Recovery::Discarded,
+    };
+    fs::remove_file(journal)?;
+    Ok(recovery)
+}
+
+/// Syncs the directory containing `path`, so a newly created file survives a crash.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+/// Directories can't be opened for syncing on Windows; NTFS journals metadata itself.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Loads the registry from disk, creating a default one if it doesn't exist.
+///
+/// Acquires an exclusive lock since loading may need to create the default
+/// registry file. This ensures safe concurrent access.
+///
+/// Sections that don't parse are left out with a warning. Changes go
+/// through `with_registry_mut`, which refuses such a registry, so what was
+/// left out is never saved over.
+pub fn load_registry() -> Result<Registry> {
+    let path = registry_path()?;
+
+    // Acquire exclusive lock (we may need to write if file doesn't exist)
+    let _lock = acquire_lock()?;
+
+    // Lock is held until _lock is dropped at end of function
+    let mut registry = if !path.exists() {
+        let registry = Registry::default();
+        save_registry_inner(&registry)?;
+        registry
+    } else {
+        let content = fs::read_to_string(&path).map_err(|source| ConfigError::ReadFailed {
+            path: path.clone(),
+            source,
+        })?;
+        parse_tolerantly(&path, &content)?
+    };
+
+    attach_system_registry(&mut registry, &path);
+    // Tables printed from here on follow its [display] section
+    theme::configure(registry.display);
+    Ok(registry)
+}
+
+/// Parses registry content, leaving out sections that don't parse with a
+/// warning. Content that isn't TOML at all fails as a whole.
+fn parse_tolerantly(path: &Path, content: &str) -> Result<Registry> {
+    let error = match toml::from_str(content) {
+        Ok(registry) => return Ok(registry),
+        Err(e) => e,
+    };
+    let Ok((registry, skipped)) = parse_by_section(content) else {
+        return Err(ConfigError::RegistryCorrupt {
+            path: path.to_path_buf(),
+            source: Box::new(error),
+        }
+        .into());
+    };
+    for section in skipped {
+        warn!(
+            "Leaving out {} of {}: {} (run 'pm config repair' to fix the file)",
+            section.section,
+            path.display(),
+            section.reason
+        );
+    }
     Ok(registry)
 }
 
@@ -94,17 +461,10 @@ pub fn load_registry() -> Result<Registry> {
 #[allow(dead_code)]
 pub fn save_registry(registry: &Registry) -> Result<()> {
     // Acquire exclusive lock for writing
-    let lock_file = open_lock_file()?;
-    let lock_path = lock_file_path()?;
-    lock_file
-        .lock_exclusive()
-        .map_err(|source| ConfigError::LockFailed {
-            path: lock_path,
-            source,
-        })?;
+    let _lock = acquire_lock()?;
 
-    // Lock is held until lock_file is dropped at end of function
-    // Lock is automatically released when lock_file is dropped
+    // Lock is held until _lock is dropped at end of function
+    // Lock is automatically released when _lock is dropped
     save_registry_inner(registry)
 }
 
@@ -122,14 +482,7 @@ where
     let path = registry_path()?;
 
     // Acquire exclusive lock for the entire read-modify-write cycle
-    let lock_file = open_lock_file()?;
-    let lock_path = lock_file_path()?;
-    lock_file
-        .lock_exclusive()
-        .map_err(|source| ConfigError::LockFailed {
-            path: lock_path,
-            source,
-        })?;
+    let _lock = acquire_lock()?;
 
     // Load or create default registry
     let mut registry = if !path.exists() {
@@ -141,55 +494,337 @@ where
             path: path.clone(),
             source,
         })?;
-        toml::from_str(&content).map_err(|source| ConfigError::ParseFailed { path, source })?
+        toml::from_str(&content).map_err(|source| ConfigError::RegistryCorrupt {
+            path: path.clone(),
+            source: Box::new(source),
+        })?
     };
+    attach_system_registry(&mut registry, &path);
+
+    let before = enabled!(Level::DEBUG).then(|| toml::to_string_pretty(&registry));
+    let snapshot = registry.clone();
 
     // Call the closure to modify the registry
     let result = f(&mut registry)?;
 
+    if let Some(Ok(before)) = before {
+        if let Ok(after) = toml::to_string_pretty(&registry) {
+            log_registry_diff(&before, &after);
+        }
+    }
+
     // Save the modified registry
     save_registry_inner(&registry)?;
+    commit_change(&path, Some(&snapshot), &registry);
+    record_change(&snapshot, &registry);
 
-    // Lock is automatically released when lock_file is dropped
+    // Lock is automatically released when _lock is dropped
     Ok(result)
 }
 
-/// Inner implementation of save_registry without locking.
-fn save_registry_inner(registry: &Registry) -> Result<()> {
+/// Reads the raw registry file, creating the default registry if it doesn't exist.
+pub fn read_registry_content() -> Result<String> {
+    // Loading creates the default file on first use
+    load_registry()?;
+
     let path = registry_path()?;
+    let content = fs::read_to_string(&path).map_err(|source| ConfigError::ReadFailed {
+        path: path.clone(),
+        source,
+    })?;
+    Ok(content)
+}
 
-    // Ensure the parent directory exists
-    let parent = path.parent().ok_or(ConfigError::NoConfigDir)?;
-    fs::create_dir_all(parent).map_err(|source| ConfigError::WriteFailed {
-        path: parent.to_path_buf(),
+/// Atomically replaces the registry file with `content`, verbatim.
+///
+/// Fails with `ModifiedDuringEdit` unless the file still contains `expected`,
+/// so edits made from a stale copy never overwrite concurrent changes.
+pub fn replace_registry_content(expected: &str, content: &str) -> Result<()> {
+    let path = registry_path()?;
+
+    let _lock = acquire_lock()?;
+
+    let current = fs::read_to_string(&path).map_err(|source| ConfigError::ReadFailed {
+        path: path.clone(),
         source,
     })?;
+    if current != expected {
+        return Err(ConfigError::ModifiedDuringEdit(path).into());
+    }
 
-    let content = toml::to_string_pretty(registry).map_err(ConfigError::SerializeFailed)?;
+    write_registry_file(content)?;
+    if let Ok(after) = toml::from_str::<Registry>(content) {
+        let before = toml::from_str::<Registry>(expected).ok();
+        commit_change(&path, before.as_ref(), &after);
+        if let Some(before) = &before {
+            record_change(before, &after);
+        }
+    }
+    Ok(())
+}
 
-    // Create temp file in the same directory (required for atomic rename)
-    let temp_path = parent.join(".registry.toml.tmp");
+/// Replaces a registry that doesn't parse with `content`, after copying the
+/// broken file beside it as `<file>.broken-<time>`. Returns the copy's path.
+///
+/// Fails with `ModifiedDuringEdit` unless the file still contains `expected`.
+pub fn replace_broken_registry(expected: &str, content: &str) -> Result<PathBuf> {
+    let path = registry_path()?;
+
+    let _lock = acquire_lock()?;
 
-    // Write to temp file
-    let mut file = File::create(&temp_path).map_err(|source| ConfigError::WriteFailed {
-        path: temp_path.clone(),
+    let current = fs::read_to_string(&path).map_err(|source| ConfigError::ReadFailed {
+        path: path.clone(),
         source,
     })?;
+    if current != expected {
+        return Err(ConfigError::ModifiedDuringEdit(path).into());
+    }
 
-    file.write_all(content.as_bytes())
-        .map_err(|source| ConfigError::WriteFailed {
-            path: temp_path.clone(),
+    let mut backup = path.clone().into_os_string();
+    backup.push(Local::now().format(".broken-%Y%m%d-%H%M%S").to_string());
+    let backup = PathBuf::from(backup);
+    fs::write(&backup, &current).map_err(|source| ConfigError::WriteFailed {
+        path: backup.clone(),
+        source,
+    })?;
+
+    write_registry_file(content)?;
+    if let Ok(after) = toml::from_str::<Registry>(content) {
+        commit_change(&path, None, &after);
+    }
+    Ok(backup)
+}
+
+/// Runs `f` on the registry path while holding the registry lock, for
+/// changes made to the file by other programs (`pm sync` running git).
+pub fn with_registry_locked<T>(f: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+    let path = registry_path()?;
+    let _lock = acquire_lock()?;
+    f(&path)
+}
+
+/// Logs the lines a registry change removed and added, at debug level.
+fn log_registry_diff(before: &str, after: &str) {
+    if before == after {
+        debug!("registry unchanged");
+        return;
+    }
+    let old: HashSet<&str> = before.lines().collect();
+    let new: HashSet<&str> = after.lines().collect();
+    for line in before.lines().filter(|l| !new.contains(l)) {
+        debug!("registry - {line}");
+    }
+    for line in after.lines().filter(|l| !old.contains(l)) {
+        debug!("registry + {line}");
+    }
+}
+
+/// Inner implementation of save_registry without locking.
+fn save_registry_inner(registry: &Registry) -> Result<()> {
+    let content = toml::to_string_pretty(registry).map_err(ConfigError::SerializeFailed)?;
+    write_registry_file(&content)
+}
+
+/// Writes `content` to the registry path through the journal: the write is
+/// recorded, applied via a temp file and atomic rename, then the journal is
+/// removed.
+fn write_registry_file(content: &str) -> Result<()> {
+    let path = registry_path()?;
+    let journal = journal_path()?;
+
+    // Ensure the parent directories exist
+    for dir in [path.parent(), journal.parent()].into_iter().flatten() {
+        fs::create_dir_all(dir).map_err(|source| ConfigError::WriteFailed {
+            path: dir.to_path_buf(),
             source,
         })?;
+    }
 
-    // Sync to disk to ensure data is persisted
-    file.sync_all().map_err(|source| ConfigError::WriteFailed {
-        path: temp_path.clone(),
+    write_journal(&journal, &path, content).map_err(|source| ConfigError::WriteFailed {
+        path: journal.clone(),
         source,
     })?;
 
-    // Atomically rename temp file to target
-    fs::rename(&temp_path, &path).map_err(|source| ConfigError::WriteFailed { path, source })?;
+    write_atomic(&path, content).map_err(|source| ConfigError::WriteFailed {
+        path: path.clone(),
+        source,
+    })?;
+
+    fs::remove_file(&journal).map_err(|source| ConfigError::WriteFailed {
+        path: journal,
+        source,
+    })?;
 
     Ok(())
 }
+
+/// Writes `content` to `path` via a synced temp file and atomic rename.
+fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    // Create temp file in the same directory (required for atomic rename)
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let temp_path = parent.join(".registry.toml.tmp");
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(content.as_bytes())?;
+
+    // Sync to disk to ensure data is persisted
+    file.sync_all()?;
+
+    // Windows can't rename a file that is still open
+    drop(file);
+
+    replace_file(&temp_path, path)
+}
+
+/// Atomically replaces `path` with `temp`, which must be in the same directory.
+#[cfg(not(windows))]
+fn replace_file(temp: &Path, path: &Path) -> io::Result<()> {
+    fs::rename(temp, path)
+}
+
+/// Atomically replaces `path` with `temp`, which must be in the same directory.
+///
+/// Uses `ReplaceFileW` when the target exists, which keeps the target's
+/// identity and ACLs. Readers, virus scanners, and the search indexer can
+/// hold the target open briefly, so sharing violations are retried.
+#[cfg(windows)]
+fn replace_file(temp: &Path, path: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{ReplaceFileW, REPLACEFILE_IGNORE_MERGE_ERRORS};
+
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_UNABLE_TO_REMOVE_REPLACED: i32 = 1175;
+    const ERROR_UNABLE_TO_MOVE_REPLACEMENT: i32 = 1176;
+    const ATTEMPTS: u32 = 10;
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    let (temp_w, path_w) = (wide(temp), wide(path));
+    let mut attempt = 0;
+    loop {
+        let result = if path.exists() {
+            // SAFETY: both paths are NUL-terminated UTF-16 strings that outlive the call
+            let ok = unsafe {
+                ReplaceFileW(
+                    path_w.as_ptr(),
+                    temp_w.as_ptr(),
+                    std::ptr::null(),
+                    REPLACEFILE_IGNORE_MERGE_ERRORS,
+                    std::ptr::null(),
+                    std::ptr::null(),
+                )
+            };
+            if ok != 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        } else {
+            fs::rename(temp, path)
+        };
+
+        attempt += 1;
+        match result {
+            Err(e)
+                if attempt < ATTEMPTS
+                    && matches!(
+                        e.raw_os_error(),
+                        Some(
+                            ERROR_ACCESS_DENIED
+                                | ERROR_SHARING_VIOLATION
+                                | ERROR_UNABLE_TO_REMOVE_REPLACED
+                                | ERROR_UNABLE_TO_MOVE_REPLACEMENT
+                        )
+                    ) =>
+            {
+                debug!(attempt, "registry replace failed, retrying: {e}");
+                std::thread::sleep(std::time::Duration::from_millis(10 * u64::from(attempt)));
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_file_overwrites_existing() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("registry.toml");
+        let temp = dir.path().join(".registry.toml.tmp");
+
+        fs::write(&temp, "first").unwrap();
+        replace_file(&temp, &target).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "first");
+
+        // Replacing while a reader has the target open must still work
+        let reader = File::open(&target).unwrap();
+        fs::write(&temp, "second").unwrap();
+        replace_file(&temp, &target).unwrap();
+        drop(reader);
+
+        assert_eq!(fs::read_to_string(&target).unwrap(), "second");
+        assert!(!temp.exists());
+    }
+
+    #[test]
+    fn test_journal_rolls_forward() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = dir.path().join("registry.toml");
+        let journal = dir.path().join(".registry.journal");
+        fs::write(&registry, "old").unwrap();
+
+        // Crash after journaling, before the rename
+        write_journal(&journal, &registry, "new").unwrap();
+        assert_eq!(
+            recover_journal(&journal, &registry).unwrap(),
+            Recovery::RolledForward
+        );
+        assert_eq!(fs::read_to_string(&registry).unwrap(), "new");
+        assert!(!journal.exists());
+
+        // Crash after the rename, before the journal was removed
+        write_journal(&journal, &registry, "new").unwrap();
+        assert_eq!(
+            recover_journal(&journal, &registry).unwrap(),
+            Recovery::RolledForward
+        );
+        assert_eq!(fs::read_to_string(&registry).unwrap(), "new");
+
+        assert_eq!(
+            recover_journal(&journal, &registry).unwrap(),
+            Recovery::Clean
+        );
+    }
+
+    #[test]
+    fn test_torn_journal_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = dir.path().join("registry.toml");
+        let journal = dir.path().join(".registry.journal");
+        fs::write(&registry, "old").unwrap();
+
+        write_journal(&journal, &registry, "new").unwrap();
+        let content = fs::read_to_string(&journal).unwrap();
+        let entry: Journal = serde_json::from_str(&content).unwrap();
+        assert_eq!(entry.before.as_deref(), Some("old"));
+
+        for torn in [
+            content[..content.len() / 2].to_string(),
+            content.replace("\"after\":\"new\"", "\"after\":\"nex\""),
+        ] {
+            fs::write(&journal, torn).unwrap();
+            assert_eq!(
+                recover_journal(&journal, &registry).unwrap(),
+                Recovery::Discarded
+            );
+            assert_eq!(fs::read_to_string(&registry).unwrap(), "old");
+            assert!(!journal.exists());
+        }
+    }
+}
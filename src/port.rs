@@ -6,6 +6,9 @@
 use std::fmt;
 use std::str::FromStr;
 
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, NumberValidation, Schema, SchemaObject};
+use schemars::JsonSchema;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// A validated TCP/UDP port number (1-65535).
@@ -129,6 +132,26 @@ impl<'de> Deserialize<'de> for Port {
     }
 }
 
+impl JsonSchema for Port {
+    fn schema_name() -> String {
+        "Port".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::Integer.into()),
+            format: Some("uint16".to_string()),
+            number: Some(Box::new(NumberValidation {
+                minimum: Some(1.0),
+                maximum: Some(65535.0),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -204,4 +227,12 @@ mod tests {
         let result: Result<Port, _> = serde_json::from_str("0");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_json_schema_bounds() {
+        let schema = serde_json::to_value(schemars::schema_for!(Port)).unwrap();
+        assert_eq!(schema["type"], "integer");
+        assert_eq!(schema["minimum"], 1.0);
+        assert_eq!(schema["maximum"], 65535.0);
+    }
 }
@@ -0,0 +1,70 @@
+//! Linux-specific socket state, read from `/proc/net`.
+
+use std::fs;
+
+use crate::error::{PortDetectionError, Result};
+use crate::port::Port;
+
+/// TCP states in `/proc/net/tcp`, from include/net/tcp_states.h.
+const TCP_TIME_WAIT: &str = "06";
+const TCP_CLOSE_WAIT: &str = "08";
+
+/// Returns local ports with connections in TIME_WAIT or CLOSE_WAIT, with
+/// the state's name.
+pub fn get_closing_ports() -> Result<Vec<(Port, &'static str)>> {
+    let mut ports = Vec::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        match fs::read_to_string(table) {
+            Ok(content) => ports.extend(parse_proc_net_tcp(&content)),
+            // Without IPv6 there is no tcp6
+            Err(_) if table.ends_with('6') => {}
+            Err(e) => {
+                return Err(
+                    PortDetectionError::ProcessEnumFailed(format!("reading {table}: {e}")).into(),
+                )
+            }
+        }
+    }
+    Ok(ports)
+}
+
+/// Parses `/proc/net/tcp` (or `tcp6`), e.g.
+/// `0: 0100007F:1F90 0100007F:C4A2 06 00000000:00000000 03:00001770 00000000 0 0 0 ...`.
+fn parse_proc_net_tcp(content: &str) -> Vec<(Port, &'static str)> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let state = match *fields.get(3)? {
+                TCP_TIME_WAIT => "TIME_WAIT",
+                TCP_CLOSE_WAIT => "CLOSE_WAIT",
+                _ => return None,
+            };
+            let (_, port) = fields.get(1)?.rsplit_once(':')?;
+            let port = Port::new(u16::from_str_radix(port, 16).ok()?).ok()?;
+            Some((port, state))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_net_tcp() {
+        let content = "  \
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 27316 1
+   1: 0100007F:1F90 0100007F:C4A2 06 00000000:00000000 03:00001770 00000000     0        0 0 3
+   2: 0100007F:0BB8 0100007F:D00C 08 00000000:00000000 00:00000000 00000000  1000        0 27411 1
+   3: 0100007F:0BB8 0100007F:D00E 01 00000000:00000000 00:00000000 00000000  1000        0 27412 1
+";
+        let ports: Vec<(u16, &str)> = parse_proc_net_tcp(content)
+            .into_iter()
+            .map(|(port, state)| (port.as_u16(), state))
+            .collect();
+        assert_eq!(ports, [(8080, "TIME_WAIT"), (3000, "CLOSE_WAIT")]);
+    }
+}
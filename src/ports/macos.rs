@@ -8,9 +8,10 @@ use std::path::PathBuf;
 use std::ptr;
 
 use libc::{c_int, c_void, size_t};
+use libproc::libproc::bsd_info::BSDInfo;
 use libproc::libproc::file_info::{pidfdinfo, ListFDs, ProcFDType};
 use libproc::libproc::net_info::SocketFDInfo;
-use libproc::libproc::proc_pid::{listpidinfo, name};
+use libproc::libproc::proc_pid::{listpidinfo, name, pidinfo};
 use libproc::processes::{pids_by_type, ProcFilter};
 
 use crate::error::{PortDetectionError, Result};
@@ -23,8 +24,14 @@ const PF_INET: c_int = 2;
 const IPPROTO_TCP: c_int = 6;
 const TCPCTL_PCBLIST: c_int = 11;
 
-// TCP states
+// TCP states, from netinet/tcp_fsm.h
 const TCPS_LISTEN: c_int = 1;
+const TCPS_CLOSE_WAIT: c_int = 5;
+const TCPS_TIME_WAIT: c_int = 10;
+
+// sysctl MIB for a process's arguments, from sys/sysctl.h
+const CTL_KERN: c_int = 1;
+const KERN_PROCARGS2: c_int = 49;
 
 // proc_pidinfo constants from sys/proc_info.h
 const PROC_PIDVNODEPATHINFO: c_int = 9;
@@ -106,40 +113,139 @@ pub fn get_process_cwd(pid: i32) -> Option<PathBuf> {
     Some(PathBuf::from(path_str))
 }
 
-/// Gets all listening TCP ports on the system.
+/// Returns whether a process exists (signal 0 checks without sending anything).
+pub fn process_alive(pid: i32) -> bool {
+    let ret = unsafe { libc::kill(pid, 0) };
+    // EPERM: the process exists but belongs to another user
+    ret == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Gets the command line for a process, arguments joined by spaces.
+pub fn get_process_command(pid: i32) -> Option<String> {
+    let mib = [CTL_KERN, KERN_PROCARGS2, pid];
+    let mut size: size_t = 0;
+
+    // First call gets the buffer size
+    let ret = unsafe {
+        sysctl(
+            mib.as_ptr(),
+            mib.len() as u32,
+            ptr::null_mut(),
+            &mut size,
+            ptr::null(),
+            0,
+        )
+    };
+    if ret != 0 || size == 0 {
+        return None;
+    }
+
+    let mut buffer = vec![0u8; size];
+    let ret = unsafe {
+        sysctl(
+            mib.as_ptr(),
+            mib.len() as u32,
+            buffer.as_mut_ptr() as *mut c_void,
+            &mut size,
+            ptr::null(),
+            0,
+        )
+    };
+    if ret != 0 {
+        return None;
+    }
+    buffer.truncate(size);
+
+    parse_procargs(&buffer)
+}
+
+/// Parses a KERN_PROCARGS2 buffer: `argc`, the executable path, NUL padding,
+/// then `argc` NUL-terminated arguments (followed by the environment).
+fn parse_procargs(buffer: &[u8]) -> Option<String> {
+    let argc = i32::from_ne_bytes(buffer.get(..4)?.try_into().ok()?);
+    let rest = &buffer[4..];
+    let exec_end = rest.iter().position(|&b| b == 0)?;
+    let args_start = exec_end + rest[exec_end..].iter().position(|&b| b != 0)?;
+
+    let args: Vec<_> = rest[args_start..]
+        .split(|&b| b == 0)
+        .take(usize::try_from(argc).ok()?)
+        .map(String::from_utf8_lossy)
+        .collect();
+    if args.is_empty() {
+        None
+    } else {
+        Some(args.join(" "))
+    }
+}
+
+/// Gets all listening TCP ports on the system, one entry per port and
+/// listening process.
 pub fn get_listening_ports() -> Result<Vec<ListeningPort>> {
     // Use sysctl to get all listening ports (reliable, no permission issues)
     let listening_ports_raw = get_listening_ports_sysctl()?;
 
     // Try to get PID info via libproc for each port
-    let port_to_pid = build_port_to_pid_map(&listening_ports_raw);
+    let mut port_to_listeners = build_port_to_listeners_map(&listening_ports_raw);
 
-    // Combine port list with PID info, converting raw u16 to Port
+    // Ports whose owner can't be seen (another user's, without root) are
+    // still listed, without a process
     let mut result: Vec<ListeningPort> = listening_ports_raw
         .into_iter()
-        .filter_map(|port_num| {
-            // Port::new only fails for port 0, which we filter out in get_listening_ports_sysctl
-            let port = Port::new(port_num).ok()?;
-            let (pid, proc_name, proc_cwd) = port_to_pid
-                .get(&port_num)
-                .cloned()
-                .unwrap_or((None, None, None));
-            Some(ListeningPort {
-                port,
-                pid,
-                process_name: proc_name,
-                process_cwd: proc_cwd,
+        .flat_map(|port_num| {
+            port_to_listeners.remove(&port_num).unwrap_or_else(|| {
+                // Port::new only fails for port 0, which we filter out in get_listening_ports_sysctl
+                Port::new(port_num)
+                    .map(|port| vec![unknown_listener(port)])
+                    .unwrap_or_default()
             })
         })
         .collect();
 
-    result.sort_by_key(|p| p.port);
-    result.dedup_by_key(|p| p.port);
+    result.sort_by_key(|p| (p.port, p.pid));
+    result.dedup_by_key(|p| (p.port, p.pid));
     Ok(result)
 }
 
+fn unknown_listener(port: Port) -> ListeningPort {
+    ListeningPort {
+        port,
+        pid: None,
+        process_name: None,
+        process_cwd: None,
+        process_command: None,
+        uid: None,
+        user: None,
+        host: None,
+    }
+}
+
 /// Gets listening ports using sysctl (TCPCTL_PCBLIST).
 fn get_listening_ports_sysctl() -> Result<Vec<u16>> {
+    let ports = tcp_ports_sysctl(&[TCPS_LISTEN])?;
+    Ok(ports.into_iter().map(|(port, _)| port).collect())
+}
+
+/// Returns local ports with connections in TIME_WAIT or CLOSE_WAIT, with
+/// the state's name.
+pub fn get_closing_ports() -> Result<Vec<(Port, &'static str)>> {
+    let ports = tcp_ports_sysctl(&[TCPS_TIME_WAIT, TCPS_CLOSE_WAIT])?;
+    Ok(ports
+        .into_iter()
+        .filter_map(|(port, state)| {
+            let name = if state == TCPS_TIME_WAIT {
+                "TIME_WAIT"
+            } else {
+                "CLOSE_WAIT"
+            };
+            Some((Port::new(port).ok()?, name))
+        })
+        .collect())
+}
+
+/// Returns the distinct local ports of TCP sockets in one of `states`, with
+/// their state, using sysctl (TCPCTL_PCBLIST).
+fn tcp_ports_sysctl(states: &[c_int]) -> Result<Vec<(u16, c_int)>> {
     let mib: [c_int; 4] = [CTL_NET, PF_INET, IPPROTO_TCP, TCPCTL_PCBLIST];
 
     // First call to get buffer size
@@ -176,7 +282,7 @@ fn get_listening_ports_sysctl() -> Result<Vec<u16>> {
     }
 
     // Parse the buffer
-    let mut listening_ports: HashSet<u16> = HashSet::new();
+    let mut found: HashSet<(u16, c_int)> = HashSet::new();
 
     // Offsets determined from macOS headers (verified with offsetof):
     // sizeof(xtcpcb) = 524
@@ -224,7 +330,7 @@ fn get_listening_ports_sysctl() -> Result<Vec<u16>> {
             buffer[offset + T_STATE_OFFSET + 3],
         ]);
 
-        if state == TCPS_LISTEN {
+        if states.contains(&state) {
             // Read local port at offset 22 (network byte order = big-endian)
             let lport = u16::from_be_bytes([
                 buffer[offset + INP_LPORT_OFFSET],
@@ -232,23 +338,22 @@ fn get_listening_ports_sysctl() -> Result<Vec<u16>> {
             ]);
 
             if lport > 0 {
-                listening_ports.insert(lport);
+                found.insert((lport, state));
             }
         }
 
         offset += entry_len;
     }
 
-    Ok(listening_ports.into_iter().collect())
+    Ok(found.into_iter().collect())
 }
 
-/// Builds a map from port number to (PID, process name, CWD) using libproc.
+/// Builds a map from port number to the processes listening on it, using
+/// libproc. Several processes can share a port (SO_REUSEPORT, pre-forked
+/// workers); each one is listed once.
 /// Iterates all processes and their file descriptors to find socket owners.
-#[allow(clippy::type_complexity)]
-fn build_port_to_pid_map(
-    ports: &[u16],
-) -> HashMap<u16, (Option<i32>, Option<String>, Option<PathBuf>)> {
-    let mut map = HashMap::new();
+fn build_port_to_listeners_map(ports: &[u16]) -> HashMap<u16, Vec<ListeningPort>> {
+    let mut map: HashMap<u16, Vec<ListeningPort>> = HashMap::new();
 
     if ports.is_empty() {
         return map;
@@ -296,17 +401,28 @@ fn build_port_to_pid_map(
             let tcp_info = unsafe { socket.psi.soi_proto.pri_tcp };
             let local_port = u16::from_be(tcp_info.tcpsi_ini.insi_lport as u16);
 
-            // Check if this is a port we're looking for
-            if local_port > 0 && port_set.contains(&local_port) && !map.contains_key(&local_port) {
-                let proc_name = name(pid_i32).ok();
-                let proc_cwd = get_process_cwd(pid_i32);
-                map.insert(local_port, (Some(pid_i32), proc_name, proc_cwd));
-
-                // Early exit if we've found all ports
-                if map.len() == port_set.len() {
-                    return map;
-                }
+            // Check if this is a port we're looking for; a process listening
+            // on several addresses (IPv4 and IPv6) is still one listener
+            if local_port == 0 || !port_set.contains(&local_port) {
+                continue;
             }
+            let Ok(port) = Port::new(local_port) else {
+                continue;
+            };
+            let listeners = map.entry(local_port).or_default();
+            if listeners.iter().any(|lp| lp.pid == Some(pid_i32)) {
+                continue;
+            }
+            listeners.push(ListeningPort {
+                port,
+                pid: Some(pid_i32),
+                process_name: name(pid_i32).ok(),
+                process_cwd: get_process_cwd(pid_i32),
+                process_command: get_process_command(pid_i32),
+                uid: pidinfo::<BSDInfo>(pid_i32, 0).ok().map(|info| info.pbi_uid),
+                user: None,
+                host: None,
+            });
         }
     }
 
@@ -317,6 +433,14 @@ fn build_port_to_pid_map(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_procargs() {
+        let mut buffer = 2i32.to_ne_bytes().to_vec();
+        buffer.extend_from_slice(b"/usr/local/bin/node\0\0\0node\0vite\0PATH=/usr/bin\0");
+        assert_eq!(parse_procargs(&buffer), Some("node vite".to_string()));
+        assert_eq!(parse_procargs(&[1, 0]), None);
+    }
+
     #[test]
     fn test_get_listening_ports_sysctl() {
         // This should work without special permissions
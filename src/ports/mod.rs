@@ -3,17 +3,28 @@
 //! Provides platform-specific implementations for detecting listening ports
 //! and mapping them to processes.
 
+#[cfg(target_os = "linux")]
+mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+#[cfg(target_os = "linux")]
+mod wsl;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Instant;
 
 use serde::Serialize;
+use tracing::{debug, warn};
 
-use crate::error::Result;
+use crate::error::{Error, PortDetectionError, Result};
+use crate::persistence::load_registry;
 use crate::port::Port;
 
-/// Information about a listening port.
+/// A process listening on a port.
+///
+/// Several processes can listen on one port (SO_REUSEPORT, pre-forked
+/// workers like nginx or a node cluster); each gets its own entry.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct ListeningPort {
     /// The port number.
@@ -24,20 +35,295 @@ pub struct ListeningPort {
     pub process_name: Option<String>,
     /// The process's current working directory (if detectable).
     pub process_cwd: Option<PathBuf>,
+    /// The process's command line, arguments joined by spaces (if detectable).
+    pub process_command: Option<String>,
+    /// The user ID the process runs as (if detectable).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    /// The name of the user the process runs as (if detectable).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// The machine the port is on, for listeners reported by a `pm agent`;
+    /// `None` for this one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+}
+
+impl ListeningPort {
+    /// Returns true if the listener is known to belong to the current user.
+    pub fn is_mine(&self) -> bool {
+        self.uid.is_some() && self.uid == current_uid()
+    }
+}
+
+/// Fills in `user` from `uid`, looking each UID up once.
+fn resolve_users(mut ports: Vec<ListeningPort>) -> Vec<ListeningPort> {
+    let mut names: HashMap<u32, Option<String>> = HashMap::new();
+    for lp in &mut ports {
+        if let (Some(uid), None) = (lp.uid, &lp.user) {
+            lp.user = names.entry(uid).or_insert_with(|| user_name(uid)).clone();
+        }
+    }
+    ports
+}
+
+/// Returns the login name for a UID, if the user database has one.
+#[cfg(unix)]
+pub fn user_name(uid: u32) -> Option<String> {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    // SAFETY: passwd is plain data, and getpwuid_r only writes into it and
+    // the buffer whose length it is given
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let ret = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if ret != 0 || result.is_null() || passwd.pw_name.is_null() {
+        return None;
+    }
+    // SAFETY: on success pw_name points to a NUL-terminated string in buffer
+    let name = unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Returns the login name for a UID, if the user database has one.
+#[cfg(not(unix))]
+pub fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+/// Returns the current user's ID, or `None` where there is no such thing.
+#[cfg(unix)]
+pub fn current_uid() -> Option<u32> {
+    // SAFETY: getuid has no preconditions and cannot fail
+    Some(unsafe { libc::getuid() })
+}
+
+/// Returns the current user's ID, or `None` where there is no such thing.
+#[cfg(not(unix))]
+pub fn current_uid() -> Option<u32> {
+    None
+}
+
+/// Returns the current user's login name: $USER (or %USERNAME%), else the
+/// user database's name for the current UID.
+pub fn current_user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|name| !name.is_empty())
+        .or_else(|| current_uid().and_then(user_name))
 }
 
 /// Returns all TCP ports currently listening on the system.
 ///
 /// On macOS, uses native syscalls (sysctl + libproc) to enumerate ports.
-/// Returns ports sorted by port number.
+/// In WSL with `PM_WSL_HOST=1`, includes the Windows host's listeners.
+/// Returns one entry per port and process, sorted by port number, then PID.
 pub fn get_listening_ports() -> Result<Vec<ListeningPort>> {
+    let start = Instant::now();
+
     #[cfg(target_os = "macos")]
-    {
-        macos::get_listening_ports()
-    }
+    let result = macos::get_listening_ports();
 
     #[cfg(not(target_os = "macos"))]
-    {
-        Err(crate::error::PortDetectionError::PlatformNotSupported.into())
+    let result: Result<Vec<ListeningPort>> =
+        Err(crate::error::PortDetectionError::PlatformNotSupported.into());
+
+    // Inside WSL2, ports bound on Windows collide with ports here too
+    #[cfg(target_os = "linux")]
+    let result = wsl::merge_host_listeners(result);
+
+    let result = result.map(resolve_users);
+
+    match &result {
+        Ok(ports) => {
+            debug!(count = ports.len(), elapsed = ?start.elapsed(), "detected listening ports")
+        }
+        Err(e) => debug!(elapsed = ?start.elapsed(), "port detection failed: {e}"),
+    }
+    result
+}
+
+/// Detects listening ports for an allocation's conflict checks.
+///
+/// If detection fails, pm warns and goes on without the checks (degraded
+/// mode), unless `require` (`--require-detection`) or `strict_detection`
+/// makes it an error. Platforms without detection are expected to lack it
+/// and aren't warned about.
+pub fn listening_ports_for_checks(require: bool) -> Result<Vec<ListeningPort>> {
+    match get_listening_ports() {
+        Ok(ports) => Ok(ports),
+        Err(e) if require || load_registry()?.defaults.strict_detection => Err(e),
+        Err(Error::PortDetection(PortDetectionError::PlatformNotSupported)) => Ok(Vec::new()),
+        Err(e) => {
+            warn!("Not checking for processes already listening: {e} (see `pm doctor`)");
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// Returns local TCP ports with connections in TIME_WAIT or CLOSE_WAIT,
+/// with the state's name. Binding such a port without SO_REUSEADDR fails
+/// until they are gone. A port is listed once per state.
+pub fn get_closing_ports() -> Result<Vec<(Port, &'static str)>> {
+    #[cfg(target_os = "macos")]
+    let result = macos::get_closing_ports();
+
+    #[cfg(target_os = "linux")]
+    let result = linux::get_closing_ports();
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    let result: Result<Vec<(Port, &'static str)>> =
+        Err(crate::error::PortDetectionError::PlatformNotSupported.into());
+
+    result.map(|mut ports| {
+        ports.sort();
+        ports.dedup();
+        ports
+    })
+}
+
+/// Groups listeners by port and host, for showing each port once. Listeners
+/// on one port must be next to each other, as in [`get_listening_ports`].
+pub fn group_by_port(listening: &[ListeningPort]) -> Vec<&[ListeningPort]> {
+    let mut groups = Vec::new();
+    let mut rest = listening;
+    while let Some(first) = rest.first() {
+        let len = rest
+            .iter()
+            .take_while(|lp| lp.port == first.port && lp.host == first.host)
+            .count();
+        let (group, tail) = rest.split_at(len);
+        groups.push(group);
+        rest = tail;
+    }
+    groups
+}
+
+/// Returns whether a process with this PID is running, or `None` where
+/// that can't be checked.
+#[cfg(target_os = "linux")]
+pub fn process_alive(pid: i32) -> Option<bool> {
+    Some(std::path::Path::new(&format!("/proc/{pid}")).exists())
+}
+
+/// Returns whether a process with this PID is running, or `None` where
+/// that can't be checked.
+#[cfg(target_os = "macos")]
+pub fn process_alive(pid: i32) -> Option<bool> {
+    Some(macos::process_alive(pid))
+}
+
+/// Returns whether a process with this PID is running, or `None` where
+/// that can't be checked.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn process_alive(_pid: i32) -> Option<bool> {
+    None
+}
+
+/// Returns the range the OS picks local ports for outgoing connections
+/// from, inclusive. Ports in it can be taken briefly by any client.
+#[cfg(target_os = "linux")]
+pub fn ephemeral_range() -> [u16; 2] {
+    std::fs::read_to_string("/proc/sys/net/ipv4/ip_local_port_range")
+        .ok()
+        .and_then(|content| parse_port_range(&content))
+        .unwrap_or([32768, 60999])
+}
+
+/// Returns the range the OS picks local ports for outgoing connections
+/// from, inclusive. Ports in it can be taken briefly by any client.
+#[cfg(not(target_os = "linux"))]
+pub fn ephemeral_range() -> [u16; 2] {
+    // IANA's dynamic range, the default on macOS and Windows
+    [49152, 65535]
+}
+
+/// Parses `ip_local_port_range` ("32768\t60999").
+#[cfg(target_os = "linux")]
+fn parse_port_range(content: &str) -> Option<[u16; 2]> {
+    let mut bounds = content.split_whitespace().map(str::parse);
+    match (bounds.next()?, bounds.next()?) {
+        (Ok(start), Ok(end)) if start <= end => Some([start, end]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_mine() {
+        let mut listener = ListeningPort {
+            port: Port::new(8080).unwrap(),
+            pid: Some(42),
+            process_name: None,
+            process_cwd: None,
+            process_command: None,
+            uid: None,
+            user: None,
+            host: None,
+        };
+        // An unknown owner is never assumed to be us
+        assert!(!listener.is_mine());
+
+        listener.uid = current_uid();
+        assert_eq!(listener.is_mine(), cfg!(unix));
+        listener.uid = current_uid().map(|uid| uid.wrapping_add(1));
+        assert!(!listener.is_mine());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_users() {
+        let listener = |uid| ListeningPort {
+            port: Port::new(8080).unwrap(),
+            pid: None,
+            process_name: None,
+            process_cwd: None,
+            process_command: None,
+            uid,
+            user: None,
+            host: None,
+        };
+        let ports = resolve_users(vec![listener(Some(0)), listener(None)]);
+        assert_eq!(ports[0].user.as_deref(), Some("root"));
+        assert_eq!(ports[1].user, None);
+    }
+
+    #[test]
+    fn test_group_by_port() {
+        let listener = |port, pid| ListeningPort {
+            port: Port::new(port).unwrap(),
+            pid: Some(pid),
+            process_name: Some("nginx".to_string()),
+            process_cwd: None,
+            process_command: None,
+            uid: None,
+            user: None,
+            host: None,
+        };
+        let listening = [listener(80, 10), listener(80, 11), listener(443, 10)];
+        let groups = group_by_port(&listening);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), 2);
+        assert_eq!(groups[1][0].port.as_u16(), 443);
+        assert!(group_by_port(&[]).is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_port_range() {
+        assert_eq!(parse_port_range("32768\t60999\n"), Some([32768, 60999]));
+        assert_eq!(parse_port_range("60999 32768"), None);
+        assert_eq!(parse_port_range(""), None);
     }
 }
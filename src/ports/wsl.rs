@@ -0,0 +1,175 @@
+//! Windows host listeners, seen from inside WSL2.
+//!
+//! WSL2 forwards `localhost` between Windows and the Linux VM, so a port
+//! bound on the Windows side collides with the same port inside WSL. With
+//! `PM_WSL_HOST=1`, pm asks Windows for its listeners through interop
+//! (`netstat.exe` and `tasklist.exe`) and counts them as listening here.
+
+use std::collections::HashMap;
+use std::env;
+use std::process::Command;
+
+use tracing::{debug, warn};
+
+use crate::error::{Error, PortDetectionError, Result};
+use crate::port::Port;
+use crate::ports::ListeningPort;
+use crate::subprocess::{self, Bounded};
+
+/// Set to 1 to include Windows host listeners when running in WSL.
+const WSL_HOST: &str = "PM_WSL_HOST";
+
+/// Returns true when running inside WSL.
+fn is_wsl() -> bool {
+    env::var_os("WSL_DISTRO_NAME").is_some()
+        || std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .is_ok_and(|release| release.to_ascii_lowercase().contains("microsoft"))
+}
+
+fn enabled() -> bool {
+    let wanted = env::var(WSL_HOST).is_ok_and(|value| matches!(value.trim(), "1" | "true"));
+    if wanted && !is_wsl() {
+        debug!("{WSL_HOST} is set, but this is not WSL");
+        return false;
+    }
+    wanted
+}
+
+/// Adds the Windows host's listeners to `detected` when enabled. Linux has
+/// no detection of its own yet, so an unsupported platform is not an error
+/// then. A failed host query is a warning, leaving `detected` as it was.
+pub fn merge_host_listeners(detected: Result<Vec<ListeningPort>>) -> Result<Vec<ListeningPort>> {
+    if !enabled() {
+        return detected;
+    }
+    let host = match host_listeners() {
+        Ok(host) => host,
+        Err(e) => {
+            warn!("Not checking Windows host listeners: {e}");
+            return detected;
+        }
+    };
+    let mut ports = match detected {
+        Ok(ports) => ports,
+        Err(Error::PortDetection(PortDetectionError::PlatformNotSupported)) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    ports.extend(host);
+    ports.sort_by_key(|lp| lp.port);
+    Ok(ports)
+}
+
+/// Runs a Windows program through interop and returns its output, or what
+/// it printed before it ran out of time.
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let command = format!("{program} {}", args.join(" "));
+    debug!("running {command}");
+    let Bounded { output, timed_out } = subprocess::output(Command::new(program).args(args))
+        .map_err(|e| Error::CommandFailed {
+            command: command.clone(),
+            reason: e.to_string(),
+        })?;
+    if !timed_out && !output.status.success() {
+        return Err(Error::CommandFailed {
+            command,
+            reason: output.status.to_string(),
+        });
+    }
+    // The console code page is rarely UTF-8; names are ASCII in practice
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Returns the Windows host's TCP listeners, one per port and process.
+fn host_listeners() -> Result<Vec<ListeningPort>> {
+    let listeners = parse_netstat(&run("netstat.exe", &["-ano"])?);
+    // Process names are a nicety; the ports matter
+    let names = run("tasklist.exe", &["/FO", "CSV", "/NH"])
+        .map(|output| parse_tasklist(&output))
+        .unwrap_or_default();
+
+    let mut ports: Vec<ListeningPort> = Vec::new();
+    for (port, pid) in listeners {
+        let process_name = Some(match names.get(&pid) {
+            Some(name) => format!("{name} (Windows)"),
+            None => format!("Windows PID {pid}"),
+        });
+        // Windows PIDs mean nothing inside WSL, so they're only named
+        let listener = ListeningPort {
+            port,
+            pid: None,
+            process_name,
+            process_cwd: None,
+            process_command: None,
+            uid: None,
+            user: None,
+            host: None,
+        };
+        if !ports.contains(&listener) {
+            ports.push(listener);
+        }
+    }
+    Ok(ports)
+}
+
+/// Parses `netstat -ano` output into (port, PID) pairs for listening
+/// sockets. The state column is translated on localized Windows, so a
+/// listener is told apart by its foreign port of 0 instead.
+fn parse_netstat(output: &str) -> Vec<(Port, u32)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [proto, local, foreign, .., pid] = fields.as_slice() else {
+                return None;
+            };
+            if !proto.eq_ignore_ascii_case("tcp") || !foreign.ends_with(":0") {
+                return None;
+            }
+            let (_, port) = local.rsplit_once(':')?;
+            Some((Port::new(port.parse().ok()?).ok()?, pid.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Parses `tasklist /FO CSV /NH` output into a map from PID to image name.
+fn parse_tasklist(output: &str) -> HashMap<u32, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split("\",\"");
+            let name = fields.next()?.trim_start_matches('"');
+            let pid = fields.next()?.parse().ok()?;
+            Some((pid, name.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_netstat() {
+        let output = "\r\nActive Connections\r\n\r\n  \
+            Proto  Local Address          Foreign Address        State           PID\r\n  \
+            TCP    0.0.0.0:135            0.0.0.0:0              LISTENING       1044\r\n  \
+            TCP    127.0.0.1:5173         0.0.0.0:0              ABHÖREN         8120\r\n  \
+            TCP    [::]:135               [::]:0                 LISTENING       1044\r\n  \
+            TCP    192.168.1.5:50432      140.82.112.4:443       ESTABLISHED     8120\r\n  \
+            UDP    0.0.0.0:5353           *:*                                    2260\r\n";
+        let ports: Vec<(u16, u32)> = parse_netstat(output)
+            .into_iter()
+            .map(|(port, pid)| (port.as_u16(), pid))
+            .collect();
+        assert_eq!(ports, [(135, 1044), (5173, 8120), (135, 1044)]);
+    }
+
+    #[test]
+    fn test_parse_tasklist() {
+        let output = "\"System Idle Process\",\"0\",\"Services\",\"0\",\"8 K\"\r\n\
+                      \"node.exe\",\"8120\",\"Console\",\"1\",\"45,000 K\"\r\n";
+        let names = parse_tasklist(output);
+        assert_eq!(names[&8120], "node.exe");
+        assert_eq!(names[&0], "System Idle Process");
+    }
+}
@@ -0,0 +1,177 @@
+//! Curated port range bundles for `pm config --preset`.
+//!
+//! The built-in ranges (web, api, db, cache, default) are generic. Presets
+//! add named ranges around the well-known ports of an ecosystem, so
+//! `pm allocate myapp kafka` gets a kafka-like port instead of one from
+//! 9000-9999. A well-known port inside a built-in range can't have a range
+//! of its own there, so it moves up by 10000 (kafka gets 19092-19111), the
+//! usual way of running a second instance.
+
+use clap::ValueEnum;
+
+use crate::model::{PortRange, Registry};
+
+/// A bundle of named port ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// vite, storybook, node-debug
+    Node,
+    /// flask, jupyter, streamlit
+    Python,
+    /// jvm-debug, jmx, tomcat
+    Jvm,
+    /// mysql, mongo, elasticsearch, elasticsearch-transport, clickhouse
+    Databases,
+    /// rabbitmq, kafka, nats, mqtt
+    Messaging,
+    /// grpc, envoy-admin
+    Grpc,
+}
+
+impl Preset {
+    /// Returns the preset's ranges as (type, [start, end]).
+    pub fn ranges(self) -> &'static [(&'static str, [u16; 2])] {
+        match self {
+            Preset::Node => &[
+                ("vite", [5173, 5199]),
+                ("storybook", [6006, 6029]),
+                ("node-debug", [19229, 19249]),
+            ],
+            Preset::Python => &[
+                ("flask", [5000, 5099]),
+                ("jupyter", [18888, 18899]),
+                ("streamlit", [18501, 18519]),
+            ],
+            Preset::Jvm => &[
+                ("jvm-debug", [15005, 15024]),
+                ("jmx", [19010, 19029]),
+                ("tomcat", [18080, 18099]),
+            ],
+            Preset::Databases => &[
+                ("mysql", [13306, 13325]),
+                ("mongo", [27017, 27036]),
+                ("elasticsearch", [19200, 19219]),
+                ("elasticsearch-transport", [19300, 19319]),
+                ("clickhouse", [18123, 18142]),
+            ],
+            Preset::Messaging => &[
+                ("rabbitmq", [5672, 5691]),
+                ("kafka", [19092, 19111]),
+                ("nats", [4222, 4241]),
+                ("mqtt", [1883, 1902]),
+            ],
+            Preset::Grpc => &[("grpc", [50051, 50099]), ("envoy-admin", [19901, 19920])],
+        }
+    }
+}
+
+/// What applying a preset did to one range type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresetChange {
+    /// The type was new and got the preset's range.
+    Added(&'static str, [u16; 2]),
+    /// The type already had a different range, which was kept.
+    Kept(&'static str, PortRange),
+    /// The preset's range overlaps the named type's, so it wasn't added.
+    Overlaps(&'static str, [u16; 2], String),
+}
+
+/// Adds the presets' ranges for types the registry doesn't define yet.
+///
+/// Existing ranges, including ones set by an earlier preset, are never
+/// changed, and a preset range overlapping one of them is skipped, since
+/// ranges may not overlap. Types that already match the preset are left out
+/// of the result.
+pub fn apply_presets(registry: &mut Registry, presets: &[Preset]) -> Vec<PresetChange> {
+    let mut changes = Vec::new();
+    for preset in presets {
+        for &(type_name, range) in preset.ranges() {
+            let [start, end] = range;
+            let overlapping = registry.defaults.ranges.iter().find(|(_, existing)| {
+                existing
+                    .segments
+                    .iter()
+                    .any(|&[low, high]| start <= high && low <= end)
+            });
+            match registry.defaults.ranges.get(type_name) {
+                Some(existing) if existing.segments == [range] => {}
+                Some(existing) => changes.push(PresetChange::Kept(type_name, existing.clone())),
+                None if overlapping.is_some() => {
+                    let (other, _) = overlapping.expect("checked above");
+                    changes.push(PresetChange::Overlaps(type_name, range, other.clone()));
+                }
+                None => {
+                    registry
+                        .defaults
+                        .ranges
+                        .insert(type_name.to_string(), PortRange::new(range[0], range[1]));
+                    changes.push(PresetChange::Added(type_name, range));
+                }
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_presets_validate() {
+        let mut registry = Registry::default();
+        let changes = apply_presets(&mut registry, Preset::value_variants());
+        assert!(changes
+            .iter()
+            .all(|change| matches!(change, PresetChange::Added(..))));
+        assert_eq!(crate::validate::validate_registry(&registry), vec![]);
+        for preset in Preset::value_variants() {
+            for (type_name, _) in preset.ranges() {
+                assert!(
+                    crate::name::PortName::new(*type_name).is_ok(),
+                    "{type_name}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_presets_skips_overlapping_ranges() {
+        let mut registry = Registry::default();
+        registry
+            .defaults
+            .ranges
+            .insert("admin".to_string(), PortRange::new(19090, 19095));
+
+        let changes = apply_presets(&mut registry, &[Preset::Messaging]);
+        assert!(changes.contains(&PresetChange::Overlaps(
+            "kafka",
+            [19092, 19111],
+            "admin".to_string()
+        )));
+        assert!(!registry.defaults.ranges.contains_key("kafka"));
+        assert_eq!(crate::validate::validate_registry(&registry), vec![]);
+    }
+
+    #[test]
+    fn test_apply_presets_is_non_destructive() {
+        let mut registry = Registry::default();
+        registry
+            .defaults
+            .ranges
+            .insert("kafka".to_string(), PortRange::new(19092, 19099));
+
+        let changes = apply_presets(&mut registry, &[Preset::Messaging]);
+        assert!(changes.contains(&PresetChange::Added("rabbitmq", [5672, 5691])));
+        assert!(changes.contains(&PresetChange::Kept("kafka", PortRange::new(19092, 19099))));
+        assert_eq!(registry.defaults.ranges["kafka"].segments, [[19092, 19099]]);
+        assert_eq!(registry.defaults.ranges["web"].segments, [[8000, 8999]]);
+
+        // Applying it again changes nothing
+        let changes = apply_presets(&mut registry, &[Preset::Messaging]);
+        assert_eq!(
+            changes,
+            vec![PresetChange::Kept("kafka", PortRange::new(19092, 19099))]
+        );
+    }
+}
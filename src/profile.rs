@@ -0,0 +1,81 @@
+//! Profiles: separate named registries in one config directory.
+//!
+//! The `default` profile is the registry pm has always used. Every other
+//! profile keeps its registry, lock, and journal in `profiles/<name>/` under
+//! that registry's directory, so profiles never contend with each other.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::{ConfigError, Result};
+use crate::model::Registry;
+use crate::name::{name_problem, InvalidNameError};
+use crate::persistence::{base_config_dir, profile_dir, profile_registry_path, DEFAULT_PROFILE};
+
+/// Checks that `name` can be used as a profile directory.
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    match name_problem(name) {
+        Some(reason) => Err(InvalidNameError {
+            name: name.to_string(),
+            reason,
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Returns true if the profile exists (`default` always does).
+pub fn profile_exists(name: &str) -> Result<bool> {
+    if name == DEFAULT_PROFILE {
+        return Ok(true);
+    }
+    validate_profile_name(name)?;
+    Ok(profile_registry_path(name)?.is_file())
+}
+
+/// Fails unless the profile exists.
+pub fn ensure_profile_exists(name: &str) -> Result<()> {
+    if profile_exists(name)? {
+        Ok(())
+    } else {
+        Err(ConfigError::ProfileNotFound(name.to_string()).into())
+    }
+}
+
+/// Returns every profile name, `default` first and the rest sorted.
+pub fn list_profiles() -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    let dir = base_config_dir()?.join("profiles");
+    if dir.is_dir() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if name_problem(name).is_none() && path.join("registry.toml").is_file() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    names.insert(0, DEFAULT_PROFILE.to_string());
+    Ok(names)
+}
+
+/// Creates a profile with an empty registry, returning its path.
+pub fn create_profile(name: &str) -> Result<PathBuf> {
+    if profile_exists(name)? {
+        return Err(ConfigError::ProfileExists(name.to_string()).into());
+    }
+    let dir = profile_dir(name)?;
+    let path = profile_registry_path(name)?;
+    let content =
+        toml::to_string_pretty(&Registry::default()).map_err(ConfigError::SerializeFailed)?;
+    fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&path, content))
+        .map_err(|source| ConfigError::WriteFailed {
+            path: path.clone(),
+            source,
+        })?;
+    Ok(path)
+}
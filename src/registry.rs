@@ -1,37 +1,134 @@
 //! Port allocation and management logic.
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
-use crate::error::{RegistryError, Result};
-use crate::model::Registry;
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+use tracing::{debug, trace, warn};
+
+use crate::error::{Error, RegistryError, Result};
+use crate::model::{
+    hostname_problem, Allocation, AllocationPolicy, PortMeta, PortRange, Project, Quotas, Registry,
+};
+use crate::name::{glob_match, PortName, ProjectName};
 use crate::port::Port;
-use crate::ports::ListeningPort;
+use crate::ports::{current_user, ListeningPort};
+
+/// Finds the stored name matching `name`: an exact match, or, when
+/// `ignore_case` is set, the one entry equal to it ignoring ASCII case.
+///
+/// Fails if several entries differ from `name` only by case.
+pub fn find_name<'a, K: AsRef<str> + 'a>(
+    keys: impl IntoIterator<Item = &'a K>,
+    name: &str,
+    ignore_case: bool,
+) -> Result<Option<&'a K>> {
+    let mut matches = Vec::new();
+    for key in keys {
+        if key.as_ref() == name {
+            return Ok(Some(key));
+        }
+        if ignore_case && key.as_ref().eq_ignore_ascii_case(name) {
+            matches.push(key);
+        }
+    }
+
+    match matches.as_slice() {
+        [] => Ok(None),
+        [key] => Ok(Some(*key)),
+        _ => Err(RegistryError::AmbiguousName {
+            name: name.to_string(),
+            matches: matches
+                .iter()
+                .map(|k| format!("'{}'", k.as_ref()))
+                .collect::<Vec<_>>()
+                .join(" and "),
+        }
+        .into()),
+    }
+}
+
+/// Resolves `name` to the stored port or alias name in `project`, honoring
+/// the registry's `ignore_case` setting. Unknown names are returned as given.
+fn port_key(registry: &Registry, project: &Project, name: &str) -> Result<String> {
+    let keys = project.ports.keys().chain(project.aliases.keys());
+    Ok(find_name(keys, name, registry.defaults.ignore_case)?
+        .map_or(name, PortName::as_str)
+        .to_string())
+}
 
 /// Allocates a port to a project with a given name.
 ///
 /// If `port` is `None`, automatically suggests a port based on the port type.
+/// The given metadata (e.g., group label) is stored with the allocation.
 pub fn allocate_port(
     registry: &mut Registry,
-    project: &str,
-    name: &str,
+    project: &ProjectName,
+    name: &PortName,
     port: Option<Port>,
+    mut meta: PortMeta,
     active_ports: &[ListeningPort],
 ) -> Result<Port> {
-    // Check if port name already exists in project
-    if let Some(proj) = registry.projects.get(project) {
-        if proj.ports.contains_key(name) {
+    if let Some(pattern) = &meta.expect_process {
+        Regex::new(pattern).map_err(|e| RegistryError::InvalidProcessPattern {
+            pattern: pattern.clone(),
+            reason: e.to_string(),
+        })?;
+    }
+    if let Some(reason) = meta.hostname.as_deref().and_then(hostname_problem) {
+        return Err(RegistryError::InvalidHostname {
+            owner: format!("{project}.{name}"),
+            reason,
+        }
+        .into());
+    }
+
+    // Allocate into an existing project that differs only by case
+    let project = find_name(
+        registry.projects.keys(),
+        project,
+        registry.defaults.ignore_case,
+    )?
+    .unwrap_or(project)
+    .clone();
+
+    // This machine's listeners say nothing about another host's ports
+    let host = meta.host.clone();
+    let port_type = registry.port_type(name, &meta).to_string();
+    let active_ports = if host.is_some() { &[] } else { active_ports };
+
+    // Check if port name (or an alias) already exists in project
+    if let Some(proj) = registry.projects.get(&project) {
+        let existing = port_key(registry, proj, name)?;
+        if proj.contains_name(&existing) {
             return Err(RegistryError::PortNameExists {
                 project: project.to_string(),
-                name: name.to_string(),
+                name: existing,
             }
             .into());
         }
     }
 
+    let quotas = registry.quotas();
+    if quotas.per_user.is_some() && meta.owner.is_none() {
+        meta.owner = current_user();
+    }
+    check_quotas(
+        registry,
+        &quotas,
+        &project,
+        &port_type,
+        meta.owner.as_deref(),
+    )?;
+
+    // An allocated name has no use for the port it had before
+    let previous = registry.take_previous_port(&project, name);
     let allocated_port = match port {
         Some(p) => {
             // Verify port is not already allocated
-            if let Some((owner_project, owner_name)) = registry.find_port_owner(p) {
+            if let Some((owner_project, owner_name)) =
+                registry.find_port_owner_on(host.as_deref(), p)
+            {
                 return Err(RegistryError::PortAlreadyAllocated {
                     port: p,
                     project: owner_project.to_string(),
@@ -51,175 +148,1481 @@ pub fn allocate_port(
                 }
                 .into());
             }
+            warn_if_reserved(registry, &project, p);
             p
         }
-        None => {
-            // Auto-suggest based on port type (name)
-            suggest_port(registry, name, 1, active_ports)?
-                .first()
-                .copied()
-                .ok_or_else(|| {
-                    let range = registry.get_range(name);
-                    RegistryError::NoAvailablePorts {
-                        start: range[0],
-                        end: range[1],
-                    }
-                })?
-        }
+        None => match previous.filter(|p| {
+            reusable(
+                registry,
+                host.as_deref(),
+                &project,
+                &port_type,
+                *p,
+                active_ports,
+            )
+        }) {
+            Some(p) => {
+                debug!(%p, "reusing the port {project}.{name} had before");
+                p
+            }
+            // Auto-suggest based on port type (the name, unless given)
+            None => suggestions_on(
+                registry,
+                host.as_deref(),
+                &port_type,
+                Some(&project),
+                1,
+                active_ports,
+            )?
+            .suggestions()
+            .next()
+            .ok_or_else(|| RegistryError::NoAvailablePorts {
+                range: registry.get_range(&port_type).to_string(),
+            })?,
+        },
     };
 
     // Get or create the project
-    let proj = registry.projects.entry(project.to_string()).or_default();
+    let proj = registry.projects.entry(project).or_default();
 
-    proj.ports.insert(name.to_string(), allocated_port);
+    proj.ports
+        .insert(name.clone(), Allocation::new(allocated_port, meta));
 
     Ok(allocated_port)
 }
 
+/// Fails if one more `port_type` port for `project`, made by `owner`, would
+/// go over a quota.
+fn check_quotas(
+    registry: &Registry,
+    quotas: &Quotas,
+    project: &str,
+    port_type: &str,
+    owner: Option<&str>,
+) -> Result<()> {
+    let allocations: Vec<(&ProjectName, &PortName, &Allocation)> = registry
+        .projects
+        .iter()
+        .flat_map(|(p, proj)| proj.ports.iter().map(move |(n, a)| (p, n, a)))
+        .collect();
+    let count = |held: &dyn Fn(&ProjectName, &PortName, &Allocation) -> bool| {
+        allocations.iter().filter(|(p, n, a)| held(p, n, a)).count()
+    };
+
+    let mut checks = Vec::new();
+    if let Some(limit) = quotas.per_project {
+        let held = count(&|p, _, _| p.as_str() == project);
+        checks.push((format!("project '{project}'"), limit, held, "per_project"));
+    }
+    if let (Some(limit), Some(owner)) = (quotas.per_user, owner) {
+        let held = count(&|_, _, a| a.meta.owner.as_deref() == Some(owner));
+        checks.push((format!("user '{owner}'"), limit, held, "per_user"));
+    }
+    if let Some(&limit) = quotas.ranges.get(port_type) {
+        let held = count(&|_, n, a| registry.port_type(n, &a.meta) == port_type);
+        checks.push((format!("range '{port_type}'"), limit, held, "ranges"));
+    }
+
+    match checks.into_iter().find(|(_, limit, held, _)| held >= limit) {
+        Some((holder, limit, _, quota)) => Err(RegistryError::QuotaExceeded {
+            holder,
+            limit,
+            quota: quota.to_string(),
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Replaces the allocation for `project.name` with a new port in one step.
+///
+/// The new port is `port`, or a suggestion that avoids the current one.
+/// Metadata not given in `meta` is carried over from the old allocation.
+/// Locked allocations and aliases are refused. If there is no existing
+/// allocation this is a plain allocation. Returns the old and new ports.
+pub fn reallocate_port(
+    registry: &mut Registry,
+    project: &ProjectName,
+    name: &PortName,
+    port: Option<Port>,
+    meta: PortMeta,
+    active_ports: &[ListeningPort],
+) -> Result<(Option<Port>, Port)> {
+    let existing = find_name(
+        registry.projects.keys(),
+        project,
+        registry.defaults.ignore_case,
+    )?
+    .and_then(|key| registry.projects.get_key_value(key.as_str()));
+    let Some((project_key, proj)) = existing else {
+        return allocate_port(registry, project, name, port, meta, active_ports).map(|p| (None, p));
+    };
+    let port_name = port_key(registry, proj, name)?;
+    if let Some(target) = proj.aliases.get(port_name.as_str()) {
+        return Err(RegistryError::NameIsAlias {
+            project: project_key.to_string(),
+            alias: port_name,
+            target: target.to_string(),
+        }
+        .into());
+    }
+    let Some((port_name, old)) = proj.ports.get_key_value(port_name.as_str()) else {
+        return allocate_port(registry, project, name, port, meta, active_ports).map(|p| (None, p));
+    };
+    if old.meta.locked {
+        return Err(RegistryError::PortLocked {
+            project: project_key.to_string(),
+            name: port_name.to_string(),
+        }
+        .into());
+    }
+
+    let (project_key, port_name, old) = (project_key.clone(), port_name.clone(), old.clone());
+    let meta = PortMeta {
+        group: meta.group.or(old.meta.group),
+        range_type: meta.range_type.or(old.meta.range_type),
+        locked: false,
+        host: meta.host.or(old.meta.host),
+        hostname: meta.hostname.or(old.meta.hostname),
+        command: meta.command.or(old.meta.command),
+        expect_process: meta.expect_process.or(old.meta.expect_process),
+        note: meta.note.or(old.meta.note),
+        url_template: meta.url_template.or(old.meta.url_template),
+        free_at: meta.free_at.or(old.meta.free_at),
+        pid: meta.pid.or(old.meta.pid),
+        last_active: meta.last_active.or(old.meta.last_active),
+        owner: meta.owner.or(old.meta.owner),
+    };
+
+    // Pick the new port while the old one is still allocated, so a
+    // suggestion never hands back the port being replaced
+    let new_port = match port {
+        Some(p) => p,
+        None => suggestions_on(
+            registry,
+            meta.host.as_deref(),
+            registry.port_type(&port_name, &meta),
+            Some(&project_key),
+            1,
+            if meta.host.is_some() {
+                &[]
+            } else {
+                active_ports
+            },
+        )?
+        .suggestions()
+        .next()
+        .ok_or_else(|| RegistryError::NoAvailablePorts {
+            range: registry
+                .get_range(registry.port_type(&port_name, &meta))
+                .to_string(),
+        })?,
+    };
+
+    // Stage the change on a copy so a failed allocation leaves the registry untouched
+    let mut staged = registry.clone();
+    if let Some(proj) = staged.projects.get_mut(&project_key) {
+        proj.ports.remove(&port_name);
+    }
+    let active_ports: Vec<ListeningPort> = if new_port == old.port {
+        // Rebinding to the same port only updates metadata; the service may be running
+        Vec::new()
+    } else {
+        active_ports.to_vec()
+    };
+    allocate_port(
+        &mut staged,
+        &project_key,
+        &port_name,
+        Some(new_port),
+        meta,
+        &active_ports,
+    )?;
+    *registry = staged;
+
+    Ok((Some(old.port), new_port))
+}
+
+/// How [`allocate_named`] treats a name that already has a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnExisting {
+    /// Fail with `PortNameExists`.
+    Fail,
+    /// Keep the existing port (`--if-absent`).
+    Keep,
+    /// Move the allocation to the new port (`--replace`).
+    Replace,
+}
+
+/// Outcome of [`allocate_named`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Allocated {
+    New(Port),
+    Existing(Port),
+    Replaced { old: Port, new: Port },
+}
+
+impl Allocated {
+    /// The port `project.name` ends up with.
+    pub fn port(self) -> Port {
+        match self {
+            Allocated::New(port) | Allocated::Existing(port) => port,
+            Allocated::Replaced { new, .. } => new,
+        }
+    }
+}
+
+/// Allocates `project.name`, resolving an existing allocation per `on_existing`.
+pub fn allocate_named(
+    registry: &mut Registry,
+    project: &ProjectName,
+    name: &PortName,
+    port: Option<Port>,
+    meta: PortMeta,
+    on_existing: OnExisting,
+    active_ports: &[ListeningPort],
+) -> Result<Allocated> {
+    match on_existing {
+        OnExisting::Fail => {}
+        OnExisting::Keep => {
+            // An explicit port that differs from the existing one still fails below
+            let existing = existing_port(registry, project, name);
+            if let Some(existing) = existing.filter(|e| port.is_none() || port == Some(*e)) {
+                return Ok(Allocated::Existing(existing));
+            }
+        }
+        OnExisting::Replace => {
+            return Ok(
+                match reallocate_port(registry, project, name, port, meta, active_ports)? {
+                    (Some(old), new) => Allocated::Replaced { old, new },
+                    (None, new) => Allocated::New(new),
+                },
+            );
+        }
+    }
+    allocate_port(registry, project, name, port, meta, active_ports).map(Allocated::New)
+}
+
+/// Returns the port already allocated to `project.name` (or the alias's target), if any.
+pub fn existing_port(registry: &Registry, project: &str, name: &str) -> Option<Port> {
+    query_ports(registry, project, Some(name))
+        .ok()
+        .and_then(|ports| ports.first().map(|(_, port)| *port))
+}
+
 /// Frees a port from a project.
 ///
 /// If `name` is `None`, frees all ports from the project.
+/// Locked allocations are refused unless `force` is set, and allocations
+/// listening in `active_ports` are always refused (pass an empty slice to
+/// skip that check); nothing is freed if any targeted allocation is refused.
 /// Returns the freed ports as (name, port) pairs.
 pub fn free_port(
     registry: &mut Registry,
     project: &str,
     name: Option<&str>,
+    force: bool,
+    active_ports: &[ListeningPort],
 ) -> Result<Vec<(String, Port)>> {
-    let proj = registry
+    let name = match (name, registry.projects.get(project)) {
+        (Some(n), Some(proj)) => Some(port_key(registry, proj, n)?),
+        _ => name.map(str::to_string),
+    };
+    let name = name.as_deref();
+
+    let (project_name, proj) = registry
         .projects
-        .get_mut(project)
+        .iter_mut()
+        .find(|(p, _)| p.as_str() == project)
         .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+    let project_name = project_name.clone();
 
-    let freed = match name {
-        Some(n) => {
-            let port = proj
-                .ports
-                .remove(n)
-                .ok_or_else(|| RegistryError::PortNameNotFound {
-                    project: project.to_string(),
-                    name: n.to_string(),
-                })?;
-            vec![(n.to_string(), port)]
+    if let Some(n) = name {
+        if let Some(target) = proj.aliases.get(n) {
+            return Err(RegistryError::NameIsAlias {
+                project: project.to_string(),
+                alias: n.to_string(),
+                target: target.to_string(),
+            }
+            .into());
         }
-        None => {
-            let all_ports: Vec<_> = std::mem::take(&mut proj.ports).into_iter().collect();
-            all_ports
+        if !proj.ports.contains_key(n) {
+            return Err(RegistryError::PortNameNotFound {
+                project: project.to_string(),
+                name: n.to_string(),
+            }
+            .into());
         }
+    }
+
+    if !force {
+        let locked = proj
+            .ports
+            .iter()
+            .find(|(n, a)| a.meta.locked && (name.is_none() || name == Some(n.as_str())));
+        if let Some((locked_name, _)) = locked {
+            return Err(RegistryError::PortLocked {
+                project: project.to_string(),
+                name: locked_name.to_string(),
+            }
+            .into());
+        }
+    }
+
+    let targets = proj
+        .ports
+        .iter()
+        .filter(|(n, _)| name.is_none() || name == Some(n.as_str()));
+    for (port_name, allocation) in targets {
+        check_idle(project, port_name, allocation.port, active_ports)?;
+    }
+
+    let freed: Vec<(PortName, Port)> = match name {
+        Some(n) => proj
+            .ports
+            .remove_entry(n)
+            .map(|(n, a)| vec![(n, a.port)])
+            .unwrap_or_default(),
+        None => std::mem::take(&mut proj.ports)
+            .into_iter()
+            .map(|(n, a)| (n, a.port))
+            .collect(),
     };
+    proj.prune_aliases();
 
     // Remove project if empty
     if proj.ports.is_empty() {
         registry.projects.remove(project);
     }
 
+    for (port_name, port) in &freed {
+        registry.remember_port(&project_name, port_name, *port);
+    }
+    Ok(freed
+        .into_iter()
+        .map(|(n, port)| (n.into(), port))
+        .collect())
+}
+
+/// Archives `project`: frees its ports like [`free_port`] and keeps the
+/// project, aliases and metadata included, under `archived`, replacing an
+/// earlier archive of the same name. Process bindings and scheduled frees are
+/// dropped, since they were about the project's old run. Returns the freed
+/// ports as (name, port) pairs.
+pub fn archive_project(
+    registry: &mut Registry,
+    project: &str,
+    force: bool,
+) -> Result<Vec<(String, Port)>> {
+    let (name, mut archived) = registry
+        .projects
+        .get_key_value(project)
+        .map(|(name, proj)| (name.clone(), proj.clone()))
+        .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+    let freed = free_port(registry, project, None, force, &[])?;
+    for allocation in archived.ports.values_mut() {
+        allocation.meta.pid = None;
+        allocation.meta.free_at = None;
+    }
+    registry.archived.insert(name, archived);
     Ok(freed)
 }
 
+/// Restores an archived project, allocating each name its old port if it is
+/// still free and a suggested one otherwise. Returns (name, old port, new
+/// port) for each allocation.
+pub fn unarchive_project(
+    registry: &mut Registry,
+    project: &str,
+    active_ports: &[ListeningPort],
+) -> Result<Vec<(PortName, Port, Port)>> {
+    if !registry.archived.contains_key(project) {
+        return Err(RegistryError::ProjectNotArchived(project.to_string()).into());
+    }
+    if registry.projects.contains_key(project) {
+        return Err(RegistryError::ProjectExists(project.to_string()).into());
+    }
+    let Some((name, archived)) = registry.archived.remove_entry(project) else {
+        return Ok(Vec::new());
+    };
+
+    let mut restored = Vec::new();
+    for (port_name, allocation) in archived.ports {
+        let old = allocation.port;
+        let new = match allocate_port(
+            registry,
+            &name,
+            &port_name,
+            Some(old),
+            allocation.meta.clone(),
+            active_ports,
+        ) {
+            Ok(port) => port,
+            Err(Error::Registry(
+                RegistryError::PortAlreadyAllocated { .. } | RegistryError::PortInUse { .. },
+            )) => allocate_port(
+                registry,
+                &name,
+                &port_name,
+                None,
+                allocation.meta,
+                active_ports,
+            )?,
+            Err(e) => return Err(e),
+        };
+        restored.push((port_name, old, new));
+    }
+    if let Some(proj) = registry.projects.get_mut(&name) {
+        proj.aliases = archived.aliases;
+    }
+    Ok(restored)
+}
+
+/// Frees every allocation tagged with `group`, across all projects.
+///
+/// Locked allocations in the group abort the whole operation unless `force`
+/// is set, as do allocations listening in `active_ports`. Projects left
+/// without ports are removed. Returns the freed ports as (project, name,
+/// port) triples, sorted by project and name.
+pub fn free_group(
+    registry: &mut Registry,
+    group: &str,
+    force: bool,
+    active_ports: &[ListeningPort],
+) -> Result<Vec<(String, String, Port)>> {
+    for (project_name, project) in &registry.projects {
+        for (port_name, allocation) in &project.ports {
+            if allocation.meta.group.as_deref() != Some(group) {
+                continue;
+            }
+            if allocation.meta.locked && !force {
+                return Err(RegistryError::PortLocked {
+                    project: project_name.to_string(),
+                    name: port_name.to_string(),
+                }
+                .into());
+            }
+            check_idle(project_name, port_name, allocation.port, active_ports)?;
+        }
+    }
+
+    let mut freed = Vec::new();
+
+    for (project_name, project) in registry.projects.iter_mut() {
+        project.ports.retain(|port_name, allocation| {
+            if allocation.meta.group.as_deref() == Some(group) {
+                freed.push((project_name.clone(), port_name.clone(), allocation.port));
+                false
+            } else {
+                true
+            }
+        });
+        project.prune_aliases();
+    }
+
+    if freed.is_empty() {
+        return Err(RegistryError::GroupNotFound(group.to_string()).into());
+    }
+
+    registry
+        .projects
+        .retain(|_, project| !project.ports.is_empty());
+
+    for (project_name, port_name, port) in &freed {
+        registry.remember_port(project_name, port_name, *port);
+    }
+    Ok(freed
+        .into_iter()
+        .map(|(project, name, port)| (project.into(), name.into(), port))
+        .collect())
+}
+
+/// Returns allocations whose port falls outside every configured range, as
+/// (project, name, port), e.g., after `pm config --set` moved a range.
+pub fn find_orphans(registry: &Registry) -> Vec<(ProjectName, PortName, Port)> {
+    let ranges = registry.ranges();
+    registry
+        .projects
+        .iter()
+        .flat_map(|(project, proj)| {
+            proj.ports
+                .iter()
+                .map(move |(name, allocation)| (project, name, allocation.port))
+        })
+        .filter(|(_, _, port)| !ranges.values().any(|range| range.contains(port.as_u16())))
+        .map(|(project, name, port)| (project.clone(), name.clone(), port))
+        .collect()
+}
+
+/// Schedules one port, or all ports of a project, to be freed by `pm gc`
+/// once `at` has passed.
+///
+/// Targets are checked as by [`free_port`], so aliases and locked
+/// allocations are refused. Returns the scheduled ports as (name, port) pairs.
+pub fn schedule_free(
+    registry: &mut Registry,
+    project: &str,
+    name: Option<&str>,
+    at: DateTime<FixedOffset>,
+) -> Result<Vec<(String, Port)>> {
+    let targets = free_port(&mut registry.clone(), project, name, false, &[])?;
+    if let Some(proj) = registry.projects.get_mut(project) {
+        for (port_name, _) in &targets {
+            if let Some(allocation) = proj.ports.get_mut(port_name.as_str()) {
+                allocation.meta.free_at = Some(at);
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// Fails if `port` is listening in `active_ports`.
+fn check_idle(project: &str, name: &str, port: Port, active_ports: &[ListeningPort]) -> Result<()> {
+    match active_ports.iter().find(|lp| lp.port == port) {
+        Some(active) => Err(RegistryError::PortActive {
+            project: project.to_string(),
+            name: name.to_string(),
+            port,
+            process_name: active
+                .process_name
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()),
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Sets or clears the locked flag on one port or all ports of a project.
+///
+/// Returns the affected ports as (name, port) pairs.
+pub fn set_locked(
+    registry: &mut Registry,
+    project: &str,
+    name: Option<&str>,
+    locked: bool,
+) -> Result<Vec<(String, Port)>> {
+    let proj = registry
+        .projects
+        .get_mut(project)
+        .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+
+    let mut changed = Vec::new();
+    match name {
+        Some(n) => {
+            let target = proj.aliases.get(n).map(PortName::as_str).unwrap_or(n);
+            let allocation =
+                proj.ports
+                    .get_mut(target)
+                    .ok_or_else(|| RegistryError::PortNameNotFound {
+                        project: project.to_string(),
+                        name: n.to_string(),
+                    })?;
+            allocation.meta.locked = locked;
+            changed.push((target.to_string(), allocation.port));
+        }
+        None => {
+            for (port_name, allocation) in proj.ports.iter_mut() {
+                allocation.meta.locked = locked;
+                changed.push((port_name.to_string(), allocation.port));
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Adds an alias so `name` in `project` can also be queried as `alias`.
+///
+/// If `name` is itself an alias, the new alias points at its target.
+/// Returns the resolved port name and port.
+pub fn add_alias(
+    registry: &mut Registry,
+    project: &str,
+    name: &str,
+    alias: &PortName,
+) -> Result<(String, Port)> {
+    let proj = registry
+        .projects
+        .get_mut(project)
+        .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+
+    if proj.contains_name(alias.as_str()) {
+        return Err(RegistryError::PortNameExists {
+            project: project.to_string(),
+            name: alias.to_string(),
+        }
+        .into());
+    }
+
+    let target = proj.aliases.get(name).map(PortName::as_str).unwrap_or(name);
+    let (target, allocation) =
+        proj.ports
+            .get_key_value(target)
+            .ok_or_else(|| RegistryError::PortNameNotFound {
+                project: project.to_string(),
+                name: name.to_string(),
+            })?;
+    let (target, port) = (target.clone(), allocation.port);
+
+    proj.aliases.insert(alias.clone(), target.clone());
+
+    Ok((target.into(), port))
+}
+
+/// Removes an alias from a project, returning the name it pointed at.
+pub fn remove_alias(registry: &mut Registry, project: &str, alias: &str) -> Result<String> {
+    let proj = registry
+        .projects
+        .get_mut(project)
+        .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+
+    proj.aliases.remove(alias).map(String::from).ok_or_else(|| {
+        RegistryError::AliasNotFound {
+            project: project.to_string(),
+            alias: alias.to_string(),
+        }
+        .into()
+    })
+}
+
+/// Renames `project.old` to `new` in one change, keeping its port and
+/// metadata. Aliases, the project's port history, and other projects'
+/// dependencies on the port follow it to the new name. A port that took its
+/// range from its name keeps that range as an explicit type. Locked
+/// allocations are refused unless `force` is set. Returns the stored old
+/// name and the port.
+pub fn rename_port(
+    registry: &mut Registry,
+    project: &str,
+    old: &str,
+    new: &PortName,
+    force: bool,
+) -> Result<(String, Port)> {
+    let project_key = find_name(
+        registry.projects.keys(),
+        project,
+        registry.defaults.ignore_case,
+    )?
+    .cloned()
+    .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+    let proj = &registry.projects[&project_key];
+    let old = port_key(registry, proj, old)?;
+    if let Some(target) = proj.aliases.get(old.as_str()) {
+        return Err(RegistryError::NameIsAlias {
+            project: project.to_string(),
+            alias: old,
+            target: target.to_string(),
+        }
+        .into());
+    }
+    let Some((old, allocation)) = proj.ports.get_key_value(old.as_str()) else {
+        return Err(RegistryError::PortNameNotFound {
+            project: project.to_string(),
+            name: old,
+        }
+        .into());
+    };
+    if allocation.meta.locked && !force {
+        return Err(RegistryError::PortLocked {
+            project: project.to_string(),
+            name: old.to_string(),
+        }
+        .into());
+    }
+    if proj.contains_name(new.as_str()) {
+        return Err(RegistryError::PortNameExists {
+            project: project.to_string(),
+            name: new.to_string(),
+        }
+        .into());
+    }
+    let old = old.clone();
+    let mut allocation = allocation.clone();
+    let old_type = range_type(registry, registry.port_type(&old, &allocation.meta)).to_string();
+    if range_type(registry, registry.port_type(new, &allocation.meta)) != old_type {
+        allocation.meta.range_type = Some(old_type);
+    }
+    let port = allocation.port;
+
+    let proj = registry
+        .projects
+        .get_mut(&project_key)
+        .expect("looked up above");
+    proj.ports.remove(&old);
+    proj.ports.insert(new.clone(), allocation);
+    for target in proj.aliases.values_mut() {
+        if *target == old {
+            *target = new.clone();
+        }
+    }
+    if let Some(history) = registry.history.get_mut(&project_key) {
+        if let Some(previous) = history.remove(&old) {
+            history.insert(new.clone(), previous);
+        }
+    }
+    for names in registry
+        .dependencies
+        .values_mut()
+        .filter_map(|on| on.get_mut(&project_key))
+    {
+        for name in names.iter_mut().filter(|name| **name == old) {
+            *name = new.clone();
+        }
+    }
+
+    Ok((old.into(), port))
+}
+
+/// Declares that `project` uses `on`'s port `name`, or every port of `on`
+/// without one. Both must exist. Returns false if the dependency was
+/// already declared.
+pub fn add_dependency(
+    registry: &mut Registry,
+    project: &str,
+    on: &str,
+    name: Option<&str>,
+) -> Result<bool> {
+    let project = registry
+        .projects
+        .get_key_value(project)
+        .map(|(key, _)| key.clone())
+        .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+    query_ports(registry, on, name)?;
+    let on = ProjectName::new(on)?;
+
+    let names = registry.dependencies.entry(project).or_default();
+    let declared = names.contains_key(&on);
+    let names = names.entry(on).or_default();
+    match name {
+        // An empty list already means every port
+        _ if declared && names.is_empty() => Ok(false),
+        None => {
+            names.clear();
+            Ok(true)
+        }
+        Some(name) if names.iter().any(|n| n.as_str() == name) => Ok(false),
+        Some(name) => {
+            names.push(PortName::new(name)?);
+            Ok(true)
+        }
+    }
+}
+
+/// Removes a dependency declared with [`add_dependency`]: on `on`'s port
+/// `name`, or on `on` as a whole without one.
+pub fn remove_dependency(
+    registry: &mut Registry,
+    project: &str,
+    on: &str,
+    name: Option<&str>,
+) -> Result<()> {
+    let not_found = || RegistryError::DependencyNotFound {
+        project: project.to_string(),
+        dependency: name.map_or_else(|| on.to_string(), |name| format!("{on}.{name}")),
+    };
+    let deps = registry
+        .dependencies
+        .get_mut(project)
+        .ok_or_else(not_found)?;
+    let names = deps.get_mut(on).ok_or_else(not_found)?;
+    match name {
+        None => {
+            deps.remove(on);
+        }
+        Some(name) => {
+            let i = names
+                .iter()
+                .position(|n| n.as_str() == name)
+                .ok_or_else(not_found)?;
+            names.remove(i);
+            // An empty list would mean every port
+            if names.is_empty() {
+                deps.remove(on);
+            }
+        }
+    }
+    if deps.is_empty() {
+        registry.dependencies.remove(project);
+    }
+    Ok(())
+}
+
+/// Adds the dependencies `from` declares to `into`'s. Dependencies only
+/// ever add up, so this never conflicts.
+pub fn merge_dependencies(into: &mut Registry, from: &Registry) {
+    for (project, deps) in &from.dependencies {
+        let ours = into.dependencies.entry(project.clone()).or_default();
+        for (on, names) in deps {
+            match ours.get_mut(on) {
+                None => {
+                    ours.insert(on.clone(), names.clone());
+                }
+                // An empty list means every port, which covers any other
+                Some(ours) if ours.is_empty() => {}
+                Some(ours) if names.is_empty() => ours.clear(),
+                Some(ours) => {
+                    for name in names {
+                        if !ours.contains(name) {
+                            ours.push(name.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Resolves `project`'s dependencies to (project, name, port) triples, in
+/// the order they are declared. A dependency that no longer resolves, such
+/// as a freed port, is skipped with a warning.
+pub fn dependency_ports(registry: &Registry, project: &str) -> Vec<(String, String, Port)> {
+    let mut ports = Vec::new();
+    for (on, names) in registry.dependencies.get(project).into_iter().flatten() {
+        let names: Vec<Option<&str>> = if names.is_empty() {
+            vec![None]
+        } else {
+            names.iter().map(|n| Some(n.as_str())).collect()
+        };
+        for name in names {
+            match query_ports(registry, on, name) {
+                Ok(found) => ports.extend(
+                    found
+                        .into_iter()
+                        .map(|(name, port)| (on.to_string(), name, port)),
+                ),
+                Err(e) => warn!("Skipping a dependency of {project}: {e}"),
+            }
+        }
+    }
+    ports
+}
+
 /// Suggests available ports in the given type's range.
 ///
 /// Returns up to `count` ports that are:
-/// - Within the range for the given port type
+/// - Within the range for the given port type (see [`allocation_range`]
+///   for ranges reserved for other projects than `project`)
+/// - Not excluded by the range
 /// - Not already allocated in the registry
 /// - Not currently in use on the system
 pub fn suggest_port(
     registry: &Registry,
     port_type: &str,
+    project: Option<&ProjectName>,
     count: usize,
     active_ports: &[ListeningPort],
 ) -> Result<Vec<Port>> {
-    let range = registry.get_range(port_type);
+    let report = explain_suggestions(registry, port_type, project, count, active_ports)?;
+    Ok(report.suggestions().collect())
+}
+
+/// What suggestion made of a port it looked at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Suggested,
+    /// Skipped: allocated to `owner` ("project.name").
+    Allocated {
+        owner: String,
+    },
+    /// Skipped: listening on the system.
+    Active {
+        pid: Option<i32>,
+        process: Option<String>,
+    },
+    /// Skipped: excluded by the range.
+    Excluded,
+}
+
+/// A port suggestion looked at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub port: Port,
+    pub verdict: Verdict,
+}
+
+/// Every port a suggestion looked at, in order, for `pm suggest --explain`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestionReport {
+    /// The type whose range was used: the one asked for, or "default".
+    pub range_type: String,
+    pub range: PortRange,
+    pub candidates: Vec<Candidate>,
+}
+
+impl SuggestionReport {
+    /// Returns the suggested ports.
+    pub fn suggestions(&self) -> impl Iterator<Item = Port> + '_ {
+        self.candidates
+            .iter()
+            .filter(|c| c.verdict == Verdict::Suggested)
+            .map(|c| c.port)
+    }
+}
+
+/// Works out suggestions like [`suggest_port`], keeping the ports skipped on
+/// the way and why.
+pub fn explain_suggestions(
+    registry: &Registry,
+    port_type: &str,
+    project: Option<&ProjectName>,
+    count: usize,
+    active_ports: &[ListeningPort],
+) -> Result<SuggestionReport> {
+    suggestions_on(registry, None, port_type, project, count, active_ports)
+}
+
+/// Works out suggestions for ports on `host` (`None` for this machine).
+/// `active_ports` are the host's listeners, if known.
+fn suggestions_on(
+    registry: &Registry,
+    host: Option<&str>,
+    port_type: &str,
+    project: Option<&ProjectName>,
+    count: usize,
+    active_ports: &[ListeningPort],
+) -> Result<SuggestionReport> {
+    let (range_type, range) = allocation_range(registry, port_type, project)?;
+    debug!(
+        port_type,
+        host,
+        range = %range,
+        "suggesting {count} port(s)"
+    );
 
     // Collect all ports to exclude
-    let allocated: HashSet<Port> = registry.all_allocated_ports().into_iter().collect();
-    let active: HashSet<Port> = active_ports.iter().map(|p| p.port).collect();
+    let allocated: HashSet<Port> = registry.all_allocated_ports(host).into_iter().collect();
 
-    let mut suggestions = Vec::new();
-    for port_num in range[0]..=range[1] {
+    let mut candidates = Vec::new();
+    let mut suggested = 0;
+    for port_num in candidate_order(&range, &allocated) {
         // Port::new can only fail for port 0, which is never in a valid range
         let port = Port::new(port_num).expect("port ranges contain valid ports");
-        if !allocated.contains(&port) && !active.contains(&port) {
-            suggestions.push(port);
-            if suggestions.len() >= count {
-                break;
+        let verdict = if range.exclude.contains(&port_num) {
+            trace!(%port, "skipped: excluded");
+            Verdict::Excluded
+        } else if allocated.contains(&port) {
+            trace!(%port, "skipped: already allocated");
+            let owner = registry
+                .find_port_owner_on(host, port)
+                .map(|(project, name)| format!("{project}.{name}"))
+                .unwrap_or_default();
+            Verdict::Allocated { owner }
+        } else if let Some(active) = active_ports.iter().find(|p| p.port == port) {
+            trace!(%port, "skipped: in use");
+            Verdict::Active {
+                pid: active.pid,
+                process: active.process_name.clone(),
             }
+        } else {
+            debug!(%port, "suggested");
+            suggested += 1;
+            Verdict::Suggested
+        };
+        candidates.push(Candidate { port, verdict });
+        if suggested >= count {
+            break;
         }
     }
 
-    if suggestions.is_empty() {
+    if suggested == 0 {
         return Err(RegistryError::NoAvailablePorts {
-            start: range[0],
-            end: range[1],
+            range: range.to_string(),
         }
         .into());
     }
 
-    Ok(suggestions)
+    Ok(SuggestionReport {
+        range_type,
+        range,
+        candidates,
+    })
+}
+
+/// Returns the range automatic allocation for `project` draws from, and the
+/// type it belongs to.
+///
+/// That is the type's range, unless it is reserved for other projects; then
+/// it is the default range, as if the type had none. Without a project, the
+/// type's range is used as is.
+fn allocation_range(
+    registry: &Registry,
+    port_type: &str,
+    project: Option<&ProjectName>,
+) -> Result<(String, PortRange)> {
+    let range = registry.get_range(port_type);
+    let range_type = if registry.ranges().contains_key(port_type) {
+        port_type
+    } else {
+        "default"
+    };
+    let Some(project) = project else {
+        return Ok((range_type.to_string(), range));
+    };
+    let ignore_case = registry.defaults.ignore_case;
+    if range.allows(project.as_str(), ignore_case) {
+        return Ok((range_type.to_string(), range));
+    }
+    let fallback = registry.get_range("default");
+    if fallback.allows(project.as_str(), ignore_case) {
+        debug!(port_type, %project, "range reserved for other projects; using the default range");
+        return Ok(("default".to_string(), fallback));
+    }
+    Err(RegistryError::RangeReserved {
+        port_type: port_type.to_string(),
+        project: project.to_string(),
+        projects: range.projects.join(", "),
+    }
+    .into())
+}
+
+/// Returns true if automatic allocation may hand `port` to an allocation of
+/// `port_type` in `project` again: it is in the range the type draws from and
+/// not excluded there, and neither allocated on `host` nor listening.
+fn reusable(
+    registry: &Registry,
+    host: Option<&str>,
+    project: &ProjectName,
+    port_type: &str,
+    port: Port,
+    active_ports: &[ListeningPort],
+) -> bool {
+    let Ok((_, range)) = allocation_range(registry, port_type, Some(project)) else {
+        return false;
+    };
+    range.contains(port.as_u16())
+        && !range.exclude.contains(&port.as_u16())
+        && registry.find_port_owner_on(host, port).is_none()
+        && !active_ports.iter().any(|lp| lp.port == port)
+}
+
+/// Warns when an explicit port lies in a range reserved for other projects.
+fn warn_if_reserved(registry: &Registry, project: &ProjectName, port: Port) {
+    for (type_name, range) in registry.ranges() {
+        if range.contains(port.as_u16())
+            && !range.allows(project.as_str(), registry.defaults.ignore_case)
+        {
+            warn!(
+                "Port {port} is in the '{type_name}' range, reserved for {}",
+                range.projects.join(", ")
+            );
+        }
+    }
+}
+
+/// Returns the ports of `range` in the order its policy tries them.
+fn candidate_order(range: &PortRange, allocated: &HashSet<Port>) -> Vec<u16> {
+    let mut ports: Vec<u16> = range.ports().collect();
+    match range.policy {
+        AllocationPolicy::LowestFree => {}
+        AllocationPolicy::Sequential => {
+            // Continue after the highest port handed out so far
+            let last = allocated
+                .iter()
+                .map(|p| p.as_u16())
+                .filter(|p| range.contains(*p))
+                .max();
+            if let Some(i) = last.and_then(|last| ports.iter().position(|p| *p == last)) {
+                ports.rotate_left(i + 1);
+            }
+        }
+        AllocationPolicy::Random => fastrand::shuffle(&mut ports),
+    }
+    ports
 }
 
 /// Parses and sets a port range from a string specification.
 ///
-/// The format is "type=start-end" (e.g., "web=8000-8999").
-/// Returns the parsed type name, start, and end ports on success.
+/// The format is "type=start-end" (e.g., "web=8000-8999"), with more
+/// comma-separated segments for a range in several pieces
+/// ("api=3000-3099,3300-3399"), optionally followed by an allocation policy
+/// ("web=8000-8999:random"). Without one, the type keeps its current policy.
+/// Returns the parsed type name and the lowest and highest port on success.
 pub fn set_port_range(registry: &mut Registry, range_spec: &str) -> Result<(String, u16, u16)> {
-    // Parse "type=start-end"
+    // Parse "type=start-end[,start-end...][:policy]"
     let parts: Vec<&str> = range_spec.splitn(2, '=').collect();
     if parts.len() != 2 {
         return Err(RegistryError::InvalidRangeFormat.into());
     }
 
     let type_name = parts[0];
-    let range_parts: Vec<&str> = parts[1].splitn(2, '-').collect();
-    if range_parts.len() != 2 {
-        return Err(RegistryError::InvalidRangeFormat.into());
+    let (bounds, policy) = match parts[1].split_once(':') {
+        Some((bounds, policy)) => {
+            let policy = policy
+                .parse::<AllocationPolicy>()
+                .map_err(RegistryError::UnknownPolicy)?;
+            (bounds, Some(policy))
+        }
+        None => (parts[1], None),
+    };
+    let segments = bounds
+        .split(',')
+        .map(parse_segment)
+        .collect::<Result<Vec<_>>>()?;
+    for (i, a) in segments.iter().enumerate() {
+        for b in &segments[i + 1..] {
+            if a[0] <= b[1] && b[0] <= a[1] {
+                return Err(RegistryError::OverlappingSegments {
+                    first: format!("{}-{}", a[0], a[1]),
+                    second: format!("{}-{}", b[0], b[1]),
+                }
+                .into());
+            }
+        }
     }
 
-    let start: u16 = range_parts[0]
-        .parse()
-        .map_err(|_| RegistryError::InvalidPortNumber(range_parts[0].to_string()))?;
+    // Keep the current policy unless a new one was given, the projects the
+    // range is reserved for, and any exclusions that still fall inside it
+    let current = registry.defaults.ranges.get(type_name);
+    let policy = policy
+        .or_else(|| current.map(|r| r.policy))
+        .unwrap_or_default();
+    let mut range = PortRange {
+        segments,
+        policy,
+        exclude: Vec::new(),
+        projects: Vec::new(),
+    };
+    if let Some(current) = current {
+        range.projects = current.projects.clone();
+        range.exclude = current
+            .exclude
+            .iter()
+            .copied()
+            .filter(|port| range.contains(*port))
+            .collect();
+    }
+    let (start, end) = (range.start(), range.end());
+    registry
+        .defaults
+        .ranges
+        .insert(type_name.to_string(), range);
 
-    let end: u16 = range_parts[1]
-        .parse()
-        .map_err(|_| RegistryError::InvalidPortNumber(range_parts[1].to_string()))?;
+    Ok((type_name.to_string(), start, end))
+}
+
+/// An allocation moved into a type's new range by [`migrate_range`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeMigration {
+    pub project: ProjectName,
+    pub name: PortName,
+    pub from: Port,
+    /// The new port, or `None` for a locked allocation left where it was.
+    pub to: Option<Port>,
+}
 
+/// Returns the type whose range `port_type` allocates from: its own, or "default".
+fn range_type<'a>(registry: &Registry, port_type: &'a str) -> &'a str {
+    if registry.ranges().contains_key(port_type) {
+        port_type
+    } else {
+        "default"
+    }
+}
+
+/// Returns allocations that drew their port from `type_name`'s `old` range
+/// and now fall outside its current one, as (project, name, port).
+pub fn range_strays(
+    registry: &Registry,
+    type_name: &str,
+    old: &PortRange,
+) -> Vec<(ProjectName, PortName, Port)> {
+    let current = registry.get_range(type_name);
+    let mut strays = Vec::new();
+    for (project, proj) in &registry.projects {
+        for (name, allocation) in &proj.ports {
+            let port = allocation.port.as_u16();
+            if range_type(registry, registry.port_type(name, &allocation.meta)) == type_name
+                && old.contains(port)
+                && !current.contains(port)
+            {
+                strays.push((project.clone(), name.clone(), allocation.port));
+            }
+        }
+    }
+    strays
+}
+
+/// Moves the allocations [`range_strays`] finds to free ports in the type's
+/// current range, after `pm config --set` shrank or moved it. Locked
+/// allocations are left where they are.
+pub fn migrate_range(
+    registry: &mut Registry,
+    type_name: &str,
+    old: &PortRange,
+    active_ports: &[ListeningPort],
+) -> Result<Vec<RangeMigration>> {
+    let mut migrations = Vec::new();
+    for (project, name, from) in range_strays(registry, type_name, old) {
+        let to = if registry.projects[&project].ports[&name].meta.locked {
+            None
+        } else {
+            let (_, to) = reallocate_port(
+                registry,
+                &project,
+                &name,
+                None,
+                PortMeta::default(),
+                active_ports,
+            )?;
+            Some(to)
+        };
+        migrations.push(RangeMigration {
+            project,
+            name,
+            from,
+            to,
+        });
+    }
+    Ok(migrations)
+}
+
+/// Parses one "start-end" segment of a range specification.
+fn parse_segment(segment: &str) -> Result<[u16; 2]> {
+    let Some((start, end)) = segment.split_once('-') else {
+        return Err(RegistryError::InvalidRangeFormat.into());
+    };
+    let start: u16 = start
+        .parse()
+        .map_err(|_| RegistryError::InvalidPortNumber(start.to_string()))?;
+    let end: u16 = end
+        .parse()
+        .map_err(|_| RegistryError::InvalidPortNumber(end.to_string()))?;
     if start >= end {
         return Err(RegistryError::InvalidPortRange { start, end }.into());
     }
+    Ok([start, end])
+}
 
-    registry
-        .defaults
-        .ranges
-        .insert(type_name.to_string(), [start, end]);
+/// Returns a project's ports as environment variables (`WEB_PORT` -> 8080),
+/// sorted by variable name. Aliases are excluded.
+///
+/// Fails if two port names map to the same variable (e.g., "web-api" and
+/// "web_api").
+pub fn env_vars(registry: &Registry, project: &str) -> Result<Vec<(String, Port)>> {
+    let proj = registry
+        .projects
+        .get(project)
+        .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+
+    let mut vars: BTreeMap<String, Vec<(&PortName, Port)>> = BTreeMap::new();
+    for (name, allocation) in &proj.ports {
+        vars.entry(format!("{}_PORT", name.env_name()))
+            .or_default()
+            .push((name, allocation.port));
+    }
+
+    vars.into_iter()
+        .map(|(var, entries)| match entries.as_slice() {
+            [(_, port)] => Ok((var, *port)),
+            _ => Err(RegistryError::EnvVarCollision {
+                project: project.to_string(),
+                names: entries
+                    .iter()
+                    .map(|(name, _)| format!("'{name}'"))
+                    .collect::<Vec<_>>()
+                    .join(" and "),
+                var,
+            }
+            .into()),
+        })
+        .collect()
+}
+
+/// Returns a `<NAME>_URL` variable per port of a project, reaching services
+/// on this machine at `local_host`. Fails on the collisions [`env_vars`] does.
+pub fn env_urls(
+    registry: &Registry,
+    project: &str,
+    local_host: &str,
+) -> Result<Vec<(String, String)>> {
+    env_vars(registry, project)?;
+    let urls: BTreeMap<String, String> = registry.projects[project]
+        .ports
+        .iter()
+        .map(|(name, allocation)| {
+            (
+                format!("{}_URL", name.env_name()),
+                allocation.meta.url_on(allocation.port, local_host),
+            )
+        })
+        .collect();
+    Ok(urls.into_iter().collect())
+}
+
+/// Queries ports for a project.
+///
+/// If `name` is `None`, returns all ports for the project (aliases excluded).
+/// A single `name` may be an alias. Returns (name, port) pairs.
+///
+/// Projects and names this registry doesn't have are looked up in the
+/// system registry.
+pub fn query_ports(
+    registry: &Registry,
+    project: &str,
+    name: Option<&str>,
+) -> Result<Vec<(String, Port)>> {
+    let result = query_own_ports(registry, project, name);
+    if let (Err(crate::error::Error::Registry(e)), Some(system)) =
+        (&result, registry.system_registry())
+    {
+        if matches!(
+            e,
+            RegistryError::ProjectNotFound(_) | RegistryError::PortNameNotFound { .. }
+        ) {
+            if let Ok(ports) = query_own_ports(system, project, name) {
+                return Ok(ports);
+            }
+        }
+    }
+    result
+}
+
+/// Queries ports for every project matching `pattern`, a glob such as
+/// "team-a-*", including system registry projects. With `name`, projects
+/// without that port are skipped. Returns (project, name, port) triples
+/// sorted by project and name.
+pub fn query_matching_ports(
+    registry: &Registry,
+    pattern: &str,
+    name: Option<&str>,
+) -> Result<Vec<(String, String, Port)>> {
+    let ignore_case = registry.defaults.ignore_case;
+    let projects: BTreeSet<&ProjectName> = registry
+        .projects
+        .keys()
+        .chain(
+            registry
+                .system_registry()
+                .into_iter()
+                .flat_map(|system| system.projects.keys()),
+        )
+        .filter(|project| glob_match(pattern, project.as_str(), ignore_case))
+        .collect();
+    if projects.is_empty() {
+        return Err(RegistryError::ProjectNotFound(pattern.to_string()).into());
+    }
+
+    let mut ports = Vec::new();
+    for project in projects {
+        let found = match query_ports(registry, project, name) {
+            Err(Error::Registry(RegistryError::PortNameNotFound { .. })) => continue,
+            result => result?,
+        };
+        ports.extend(
+            found
+                .into_iter()
+                .map(|(name, port)| (project.to_string(), name, port)),
+        );
+    }
+    Ok(ports)
+}
+
+fn query_own_ports(
+    registry: &Registry,
+    project: &str,
+    name: Option<&str>,
+) -> Result<Vec<(String, Port)>> {
+    let proj = registry
+        .projects
+        .get(project)
+        .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+
+    match name {
+        Some(n) => {
+            let n = port_key(registry, proj, n)?;
+            let allocation = get_allocation(registry, project, &n)?;
+            Ok(vec![(n, allocation.port)])
+        }
+        None => Ok(proj
+            .ports
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.port))
+            .collect()),
+    }
+}
+
+/// Returns the allocation for `project.name`, following aliases.
+pub fn get_allocation<'a>(
+    registry: &'a Registry,
+    project: &str,
+    name: &str,
+) -> Result<&'a Allocation> {
+    let proj = registry
+        .projects
+        .get(project)
+        .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+    let name = port_key(registry, proj, name)?;
+    proj.resolve(&name).ok_or_else(|| {
+        RegistryError::PortNameNotFound {
+            project: project.to_string(),
+            name,
+        }
+        .into()
+    })
+}
 
-    Ok((type_name.to_string(), start, end))
+/// Returns true if `project.name`, here or in the system registry, has the
+/// group label `group`. Every port is in group `None`.
+pub fn in_group(registry: &Registry, project: &str, name: &str, group: Option<&str>) -> bool {
+    let Some(group) = group else {
+        return true;
+    };
+    get_allocation(registry, project, name)
+        .ok()
+        .or_else(|| get_allocation(registry.system_registry()?, project, name).ok())
+        .is_some_and(|a| a.meta.group.as_deref() == Some(group))
 }
 
-/// Queries ports for a project.
-///
-/// If `name` is `None`, returns all ports for the project.
-/// Returns (name, port) pairs.
-pub fn query_ports(
+/// Renders the URL of each of `project`'s ports, or just `name`'s, as
+/// (name, port, url) triples. Ports are looked up as by [`query_ports`],
+/// and services on this machine are reached at `local_host`.
+pub fn service_urls(
     registry: &Registry,
     project: &str,
     name: Option<&str>,
-) -> Result<Vec<(String, Port)>> {
-    let proj = registry
-        .projects
-        .get(project)
-        .ok_or_else(|| RegistryError::ProjectNotFound(project.to_string()))?;
+    local_host: &str,
+) -> Result<Vec<(String, Port, String)>> {
+    Ok(query_ports(registry, project, name)?
+        .into_iter()
+        .map(|(name, port)| {
+            let url = get_allocation(registry, project, &name)
+                .ok()
+                .or_else(|| {
+                    let system = registry.system_registry()?;
+                    get_allocation(system, project, &name).ok()
+                })
+                .map_or_else(
+                    || PortMeta::default().url_on(port, local_host),
+                    |a| a.meta.url_on(port, local_host),
+                );
+            (name, port, url)
+        })
+        .collect())
+}
 
-    match name {
-        Some(n) => {
-            let port = proj
+/// Returns every allocation in the registry, including system registry
+/// reservations, as (project, name, port) triples sorted by project and name.
+/// Aliases are excluded.
+pub fn query_all_ports(registry: &Registry) -> Vec<(String, String, Port)> {
+    let mut ports: Vec<_> = registry
+        .projects
+        .iter()
+        .flat_map(|(project_name, project)| {
+            project
                 .ports
-                .get(n)
-                .ok_or_else(|| RegistryError::PortNameNotFound {
-                    project: project.to_string(),
-                    name: n.to_string(),
-                })?;
-            Ok(vec![(n.to_string(), *port)])
-        }
-        None => Ok(proj.ports.iter().map(|(k, v)| (k.clone(), *v)).collect()),
-    }
+                .iter()
+                .map(move |(name, a)| (project_name.to_string(), name.to_string(), a.port))
+        })
+        .chain(
+            registry
+                .system_allocations()
+                .into_iter()
+                .map(|(project, name, a)| (project.to_string(), name.to_string(), a.port)),
+        )
+        .collect();
+    ports.sort();
+    ports
 }
 
 #[cfg(test)]
@@ -234,15 +1637,30 @@ mod tests {
         Port::new(n).unwrap()
     }
 
+    fn project(s: &str) -> ProjectName {
+        ProjectName::new(s).unwrap()
+    }
+
+    fn name(s: &str) -> PortName {
+        PortName::new(s).unwrap()
+    }
+
     #[test]
     fn test_allocate_explicit_port() {
         let mut registry = empty_registry();
         let active = vec![];
 
-        let allocated =
-            allocate_port(&mut registry, "webapp", "web", Some(port(8080)), &active).unwrap();
+        let allocated = allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
         assert_eq!(allocated, port(8080));
-        assert_eq!(registry.projects["webapp"].ports["web"], port(8080));
+        assert_eq!(registry.projects["webapp"].ports["web"].port, port(8080));
     }
 
     #[test]
@@ -250,7 +1668,15 @@ mod tests {
         let mut registry = empty_registry();
         let active = vec![];
 
-        let allocated = allocate_port(&mut registry, "webapp", "web", None, &active).unwrap();
+        let allocated = allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            None,
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
         assert_eq!(allocated, port(8000)); // First port in web range
     }
 
@@ -263,16 +1689,32 @@ mod tests {
                 pid: Some(123),
                 process_name: Some("python".to_string()),
                 process_cwd: None,
+                process_command: None,
+                uid: None,
+                user: None,
+                host: None,
             },
             ListeningPort {
                 port: port(8001),
                 pid: Some(124),
                 process_name: Some("node".to_string()),
                 process_cwd: None,
+                process_command: None,
+                uid: None,
+                user: None,
+                host: None,
             },
         ];
 
-        let allocated = allocate_port(&mut registry, "webapp", "web", None, &active).unwrap();
+        let allocated = allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            None,
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
         assert_eq!(allocated, port(8002)); // Skips 8000 and 8001
     }
 
@@ -281,8 +1723,23 @@ mod tests {
         let mut registry = empty_registry();
         let active = vec![];
 
-        allocate_port(&mut registry, "webapp", "web", Some(port(8080)), &active).unwrap();
-        let result = allocate_port(&mut registry, "backend", "api", Some(port(8080)), &active);
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        let result = allocate_port(
+            &mut registry,
+            &project("backend"),
+            &name("api"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        );
 
         assert!(matches!(
             result,
@@ -298,9 +1755,20 @@ mod tests {
             pid: Some(999),
             process_name: Some("python".to_string()),
             process_cwd: None,
+            process_command: None,
+            uid: None,
+            user: None,
+            host: None,
         }];
 
-        let result = allocate_port(&mut registry, "webapp", "web", Some(port(8080)), &active);
+        let result = allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        );
 
         assert!(matches!(
             result,
@@ -317,10 +1785,26 @@ mod tests {
         let mut registry = empty_registry();
         let active = vec![];
 
-        allocate_port(&mut registry, "webapp", "web", Some(port(8080)), &active).unwrap();
-        allocate_port(&mut registry, "webapp", "api", Some(port(3000)), &active).unwrap();
-
-        let freed = free_port(&mut registry, "webapp", Some("web")).unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+
+        let freed = free_port(&mut registry, "webapp", Some("web"), false, &[]).unwrap();
         assert_eq!(freed, vec![("web".to_string(), port(8080))]);
         assert!(!registry.projects["webapp"].ports.contains_key("web"));
         assert!(registry.projects["webapp"].ports.contains_key("api"));
@@ -331,50 +1815,1306 @@ mod tests {
         let mut registry = empty_registry();
         let active = vec![];
 
-        allocate_port(&mut registry, "webapp", "web", Some(port(8080)), &active).unwrap();
-        allocate_port(&mut registry, "webapp", "api", Some(port(3000)), &active).unwrap();
-
-        let freed = free_port(&mut registry, "webapp", None).unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+
+        let freed = free_port(&mut registry, "webapp", None, false, &[]).unwrap();
         assert_eq!(freed.len(), 2);
         assert!(!registry.projects.contains_key("webapp"));
     }
 
+    fn grouped(group: &str) -> PortMeta {
+        PortMeta {
+            group: Some(group.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_free_group_across_projects() {
+        let mut registry = empty_registry();
+        let active = vec![];
+
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            grouped("sprint-42"),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("backend"),
+            &name("api"),
+            Some(port(3001)),
+            grouped("sprint-42"),
+            &active,
+        )
+        .unwrap();
+
+        let freed = free_group(&mut registry, "sprint-42", false, &[]).unwrap();
+        assert_eq!(
+            freed,
+            vec![
+                ("backend".to_string(), "api".to_string(), port(3001)),
+                ("webapp".to_string(), "web".to_string(), port(8080)),
+            ]
+        );
+        assert!(!registry.projects.contains_key("backend"));
+        assert!(registry.projects["webapp"].ports.contains_key("api"));
+        assert!(!registry.projects["webapp"].ports.contains_key("web"));
+    }
+
+    #[test]
+    fn test_allocate_prefers_previous_port() {
+        let mut registry = empty_registry();
+        let allocate = |registry: &mut Registry, p: &str, n: &str, port: Option<u16>| {
+            allocate_port(
+                registry,
+                &project(p),
+                &name(n),
+                port.map(self::port),
+                PortMeta::default(),
+                &[],
+            )
+            .unwrap()
+        };
+
+        allocate(&mut registry, "webapp", "web", Some(8005));
+        free_port(&mut registry, "webapp", Some("web"), false, &[]).unwrap();
+        assert_eq!(registry.history["webapp"]["web"].port, port(8005));
+        assert_eq!(allocate(&mut registry, "webapp", "web", None), port(8005));
+        assert!(registry.history.is_empty());
+
+        // Taken in the meantime: suggest as usual
+        free_port(&mut registry, "webapp", Some("web"), false, &[]).unwrap();
+        allocate(&mut registry, "other", "web", Some(8005));
+        assert_eq!(allocate(&mut registry, "webapp", "web", None), port(8000));
+
+        // Outside the range the name draws from
+        allocate(&mut registry, "webapp", "api", Some(9500));
+        free_port(&mut registry, "webapp", Some("api"), false, &[]).unwrap();
+        assert_eq!(allocate(&mut registry, "webapp", "api", None), port(3000));
+    }
+
+    #[test]
+    fn test_archive_and_unarchive() {
+        let mut registry = empty_registry();
+        for (n, p) in [("web", 8080), ("api", 3000)] {
+            allocate_port(
+                &mut registry,
+                &project("webapp"),
+                &name(n),
+                Some(port(p)),
+                PortMeta::default(),
+                &[],
+            )
+            .unwrap();
+        }
+        add_alias(&mut registry, "webapp", "api", &name("backend")).unwrap();
+
+        let freed = archive_project(&mut registry, "webapp", false).unwrap();
+        assert_eq!(
+            freed,
+            vec![
+                ("api".to_string(), port(3000)),
+                ("web".to_string(), port(8080))
+            ]
+        );
+        assert!(!registry.projects.contains_key("webapp"));
+        assert!(registry.archived.contains_key("webapp"));
+
+        // Someone else took 3000 in the meantime
+        allocate_port(
+            &mut registry,
+            &project("other"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        let restored = unarchive_project(&mut registry, "webapp", &[]).unwrap();
+        assert_eq!(
+            restored,
+            vec![
+                (name("api"), port(3000), port(3001)),
+                (name("web"), port(8080), port(8080)),
+            ]
+        );
+        assert!(registry.archived.is_empty());
+        assert_eq!(
+            registry.projects["webapp"].aliases.get("backend"),
+            Some(&name("api"))
+        );
+
+        let err = unarchive_project(&mut registry, "webapp", &[]).unwrap_err();
+        assert_eq!(err.code(), "E_PROJECT_NOT_ARCHIVED");
+        archive_project(&mut registry, "other", false).unwrap();
+        allocate_port(
+            &mut registry,
+            &project("other"),
+            &name("db"),
+            None,
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        let err = unarchive_project(&mut registry, "other", &[]).unwrap_err();
+        assert_eq!(err.code(), "E_PROJECT_EXISTS");
+    }
+
+    #[test]
+    fn test_free_unknown_group() {
+        let mut registry = empty_registry();
+
+        let result = free_group(&mut registry, "nope", false, &[]);
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Registry(RegistryError::GroupNotFound(
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_find_orphans_after_range_change() {
+        let mut registry = empty_registry();
+        for (p, n, number) in [("webapp", "web", 8080), ("webapp", "api", 3000)] {
+            allocate_port(
+                &mut registry,
+                &project(p),
+                &name(n),
+                Some(port(number)),
+                PortMeta::default(),
+                &[],
+            )
+            .unwrap();
+        }
+        assert!(find_orphans(&registry).is_empty());
+
+        set_port_range(&mut registry, "api=4000-4099").unwrap();
+        assert_eq!(
+            find_orphans(&registry),
+            vec![(project("webapp"), name("api"), port(3000))]
+        );
+    }
+
+    #[test]
+    fn test_migrate_range_moves_strays() {
+        let mut registry = empty_registry();
+        for (p, number) in [("webapp", 8000), ("shop", 8001), ("admin", 8090)] {
+            allocate_port(
+                &mut registry,
+                &project(p),
+                &name("web"),
+                Some(port(number)),
+                PortMeta::default(),
+                &[],
+            )
+            .unwrap();
+        }
+        set_locked(&mut registry, "shop", Some("web"), true).unwrap();
+
+        let old = registry.get_range("web");
+        set_port_range(&mut registry, "web=8050-8099").unwrap();
+        let migrations = migrate_range(&mut registry, "web", &old, &[]).unwrap();
+        assert_eq!(
+            migrations,
+            vec![
+                RangeMigration {
+                    project: project("shop"),
+                    name: name("web"),
+                    from: port(8001),
+                    to: None,
+                },
+                RangeMigration {
+                    project: project("webapp"),
+                    name: name("web"),
+                    from: port(8000),
+                    to: Some(port(8050)),
+                },
+            ]
+        );
+        assert_eq!(
+            registry.projects["admin"].ports["web"].port,
+            port(8090),
+            "ports already inside the new range stay"
+        );
+    }
+
+    #[test]
+    fn test_locked_ports_refuse_free() {
+        let mut registry = empty_registry();
+        let active = vec![];
+
+        allocate_port(
+            &mut registry,
+            &project("infra"),
+            &name("registry"),
+            Some(port(5000)),
+            grouped("stack"),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("infra"),
+            &name("auth"),
+            Some(port(5001)),
+            grouped("stack"),
+            &active,
+        )
+        .unwrap();
+        set_locked(&mut registry, "infra", Some("registry"), true).unwrap();
+
+        for result in [
+            free_port(&mut registry, "infra", Some("registry"), false, &[]).map(|_| ()),
+            free_port(&mut registry, "infra", None, false, &[]).map(|_| ()),
+            free_group(&mut registry, "stack", false, &[]).map(|_| ()),
+        ] {
+            assert!(matches!(
+                result,
+                Err(crate::error::Error::Registry(
+                    RegistryError::PortLocked { .. }
+                ))
+            ));
+        }
+        // Refused operations leave everything in place
+        assert_eq!(registry.projects["infra"].ports.len(), 2);
+
+        // Unlocked siblings can still be freed individually
+        free_port(&mut registry, "infra", Some("auth"), false, &[]).unwrap();
+
+        // Force overrides the lock
+        let freed = free_port(&mut registry, "infra", Some("registry"), true, &[]).unwrap();
+        assert_eq!(freed, vec![("registry".to_string(), port(5000))]);
+    }
+
+    #[test]
+    fn test_if_idle_refuses_listening_ports() {
+        let mut registry = empty_registry();
+
+        for (n, p) in [("web", 8080), ("api", 3000)] {
+            allocate_port(
+                &mut registry,
+                &project("webapp"),
+                &name(n),
+                Some(port(p)),
+                grouped("stack"),
+                &[],
+            )
+            .unwrap();
+        }
+        let active = vec![ListeningPort {
+            port: port(8080),
+            pid: Some(42),
+            process_name: Some("node".to_string()),
+            process_cwd: None,
+            process_command: None,
+            uid: None,
+            user: None,
+            host: None,
+        }];
+
+        for result in [
+            free_port(&mut registry, "webapp", Some("web"), false, &active).map(|_| ()),
+            free_port(&mut registry, "webapp", None, true, &active).map(|_| ()),
+            free_group(&mut registry, "stack", false, &active).map(|_| ()),
+        ] {
+            assert!(matches!(
+                result,
+                Err(crate::error::Error::Registry(
+                    RegistryError::PortActive { .. }
+                ))
+            ));
+        }
+        assert_eq!(registry.projects["webapp"].ports.len(), 2);
+
+        // Idle ports are freed as usual
+        let freed = free_port(&mut registry, "webapp", Some("api"), false, &active).unwrap();
+        assert_eq!(freed, vec![("api".to_string(), port(3000))]);
+    }
+
+    #[test]
+    fn test_reallocate_port() {
+        let mut registry = empty_registry();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8000)),
+            grouped("stack"),
+            &[],
+        )
+        .unwrap();
+
+        // A suggestion never hands back the port being replaced
+        let (old, new) = reallocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            None,
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!((old, new), (Some(port(8000)), port(8001)));
+        let allocation = &registry.projects["webapp"].ports["web"];
+        assert_eq!(allocation.port, port(8001));
+        assert_eq!(allocation.meta.group.as_deref(), Some("stack"));
+
+        // Explicit port taken elsewhere: the old allocation is kept
+        allocate_port(
+            &mut registry,
+            &project("other"),
+            &name("web"),
+            Some(port(9000)),
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        let result = reallocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(9000)),
+            PortMeta::default(),
+            &[],
+        );
+        assert!(result.is_err());
+        assert_eq!(registry.projects["webapp"].ports["web"].port, port(8001));
+
+        // Nothing to replace is a plain allocation
+        let (old, new) = reallocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!((old, new), (None, port(3000)));
+    }
+
+    #[test]
+    fn test_allocate_with_type() {
+        let mut registry = empty_registry();
+        let web = PortMeta {
+            range_type: Some("web".to_string()),
+            ..PortMeta::default()
+        };
+        let allocated = allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("admin"),
+            None,
+            web,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(allocated, port(8000));
+
+        // Reallocation stays in the type's range
+        let (_, new) = reallocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("admin"),
+            None,
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(new, port(8001));
+        let meta = &registry.projects["webapp"].ports["admin"].meta;
+        assert_eq!(meta.range_type.as_deref(), Some("web"));
+
+        // So does moving the range
+        let old = registry.get_range("web");
+        set_port_range(&mut registry, "web=7000-7099").unwrap();
+        let migrations = migrate_range(&mut registry, "web", &old, &[]).unwrap();
+        assert_eq!(migrations.len(), 1);
+        assert_eq!(migrations[0].to, Some(port(7000)));
+    }
+
+    #[test]
+    fn test_quotas() {
+        let mut registry = empty_registry();
+        registry.defaults.quotas.per_project = Some(2);
+        registry.defaults.quotas.per_user = Some(3);
+        registry.defaults.quotas.ranges.insert("db".to_string(), 1);
+        let by = |owner: &str| PortMeta {
+            owner: Some(owner.to_string()),
+            ..PortMeta::default()
+        };
+        let allocate = |registry: &mut Registry, p: &str, n: &str, owner: &str| {
+            allocate_port(registry, &project(p), &name(n), None, by(owner), &[])
+        };
+
+        allocate(&mut registry, "webapp", "web", "alice").unwrap();
+        allocate(&mut registry, "webapp", "db", "alice").unwrap();
+        let err = allocate(&mut registry, "webapp", "api", "alice").unwrap_err();
+        assert_eq!(err.code(), "E_QUOTA_EXCEEDED");
+        assert!(err.to_string().contains("project 'webapp'"));
+
+        // Ranges count across projects
+        let err = allocate(&mut registry, "shop", "db", "bob").unwrap_err();
+        assert!(err.to_string().contains("range 'db'"));
+
+        allocate(&mut registry, "shop", "web", "alice").unwrap();
+        let err = allocate(&mut registry, "shop", "api", "alice").unwrap_err();
+        assert!(err.to_string().contains("user 'alice'"));
+        allocate(&mut registry, "shop", "api", "bob").unwrap();
+
+        // Moving an allocation doesn't add one
+        reallocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            None,
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            registry.projects["webapp"].ports["web"]
+                .meta
+                .owner
+                .as_deref(),
+            Some("alice")
+        );
+    }
+
+    #[test]
+    fn test_reallocate_refuses_locked_and_aliases() {
+        let mut registry = empty_registry();
+        allocate_port(
+            &mut registry,
+            &project("infra"),
+            &name("registry"),
+            Some(port(5000)),
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        add_alias(&mut registry, "infra", "registry", &name("docker")).unwrap();
+
+        let result = reallocate_port(
+            &mut registry,
+            &project("infra"),
+            &name("docker"),
+            None,
+            PortMeta::default(),
+            &[],
+        );
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Registry(
+                RegistryError::NameIsAlias { .. }
+            ))
+        ));
+
+        set_locked(&mut registry, "infra", Some("registry"), true).unwrap();
+        let result = reallocate_port(
+            &mut registry,
+            &project("infra"),
+            &name("registry"),
+            None,
+            PortMeta::default(),
+            &[],
+        );
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Registry(
+                RegistryError::PortLocked { .. }
+            ))
+        ));
+        assert_eq!(
+            registry.projects["infra"].ports["registry"].port,
+            port(5000)
+        );
+    }
+
+    #[test]
+    fn test_unlock_allows_free() {
+        let mut registry = empty_registry();
+        let active = vec![];
+
+        allocate_port(
+            &mut registry,
+            &project("infra"),
+            &name("registry"),
+            Some(port(5000)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        set_locked(&mut registry, "infra", None, true).unwrap();
+        assert!(registry.projects["infra"].ports["registry"].meta.locked);
+
+        set_locked(&mut registry, "infra", None, false).unwrap();
+        assert!(free_port(&mut registry, "infra", None, false, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_schedule_free() {
+        let mut registry = empty_registry();
+        let active = vec![];
+        for (n, p) in [("web", 8080), ("api", 3000)] {
+            allocate_port(
+                &mut registry,
+                &project("demo"),
+                &name(n),
+                Some(port(p)),
+                PortMeta::default(),
+                &active,
+            )
+            .unwrap();
+        }
+        let at = DateTime::parse_from_rfc3339("2026-06-01T18:00:00+00:00").unwrap();
+
+        let scheduled = schedule_free(&mut registry, "demo", Some("web"), at).unwrap();
+        assert_eq!(scheduled, vec![("web".to_string(), port(8080))]);
+        let ports = &registry.projects["demo"].ports;
+        assert_eq!(ports["web"].meta.free_at, Some(at));
+        assert_eq!(ports["api"].meta.free_at, None);
+
+        set_locked(&mut registry, "demo", Some("api"), true).unwrap();
+        assert!(matches!(
+            schedule_free(&mut registry, "demo", None, at),
+            Err(crate::error::Error::Registry(
+                RegistryError::PortLocked { .. }
+            ))
+        ));
+        assert_eq!(registry.projects["demo"].ports["api"].meta.free_at, None);
+    }
+
+    #[test]
+    fn test_alias_query_and_free() {
+        let mut registry = empty_registry();
+        let active = vec![];
+
+        allocate_port(
+            &mut registry,
+            &project("myapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("myapp"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+
+        let (target, p) = add_alias(&mut registry, "myapp", "web", &name("frontend")).unwrap();
+        assert_eq!((target.as_str(), p), ("web", port(8080)));
+
+        // Alias of an alias points at the real port name
+        let (target, _) = add_alias(&mut registry, "myapp", "frontend", &name("ui")).unwrap();
+        assert_eq!(target, "web");
+
+        let ports = query_ports(&registry, "myapp", Some("frontend")).unwrap();
+        assert_eq!(ports, vec![("frontend".to_string(), port(8080))]);
+
+        // Full query lists real names only
+        assert_eq!(query_ports(&registry, "myapp", None).unwrap().len(), 2);
+
+        // Freeing by alias is refused
+        let result = free_port(&mut registry, "myapp", Some("frontend"), false, &[]);
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Registry(
+                RegistryError::NameIsAlias { .. }
+            ))
+        ));
+
+        // Freeing the target drops its aliases
+        free_port(&mut registry, "myapp", Some("web"), false, &[]).unwrap();
+        assert!(registry.projects["myapp"].aliases.is_empty());
+    }
+
+    #[test]
+    fn test_rename_port() {
+        let mut registry = Registry::default();
+        let project = ProjectName::new("webapp").unwrap();
+        let web = PortName::new("web").unwrap();
+        let meta = PortMeta {
+            note: Some("storefront".to_string()),
+            ..PortMeta::default()
+        };
+        allocate_port(&mut registry, &project, &web, Some(port(8000)), meta, &[]).unwrap();
+        add_alias(
+            &mut registry,
+            "webapp",
+            "web",
+            &PortName::new("frontend").unwrap(),
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &ProjectName::new("shop").unwrap(),
+            &PortName::new("api").unwrap(),
+            None,
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        add_dependency(&mut registry, "shop", "webapp", Some("web")).unwrap();
+
+        let site = PortName::new("site").unwrap();
+        let (old, renamed) = rename_port(&mut registry, "webapp", "web", &site, false).unwrap();
+        assert_eq!((old.as_str(), renamed.as_u16()), ("web", 8000));
+
+        let proj = &registry.projects["webapp"];
+        assert!(!proj.ports.contains_key("web"));
+        let allocation = &proj.ports["site"];
+        assert_eq!(allocation.port.as_u16(), 8000);
+        assert_eq!(allocation.meta.note.as_deref(), Some("storefront"));
+        // "site" has no range of its own, so the port keeps drawing from web's
+        assert_eq!(allocation.meta.range_type.as_deref(), Some("web"));
+        assert_eq!(proj.aliases["frontend"].as_str(), "site");
+        assert_eq!(registry.dependencies["shop"]["webapp"], vec![site.clone()]);
+
+        let err = rename_port(&mut registry, "webapp", "frontend", &web, false).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Registry(RegistryError::NameIsAlias { .. })
+        ));
+        let err = rename_port(&mut registry, "webapp", "site", &site, false).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Registry(RegistryError::PortNameExists { .. })
+        ));
+
+        set_locked(&mut registry, "webapp", Some("site"), true).unwrap();
+        let err = rename_port(&mut registry, "webapp", "site", &web, false).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Registry(RegistryError::PortLocked { .. })
+        ));
+        rename_port(&mut registry, "webapp", "site", &web, true).unwrap();
+        assert!(registry.projects["webapp"].ports["web"].meta.locked);
+
+        registry.defaults.ignore_case = true;
+        rename_port(&mut registry, "WebApp", "web", &site, true).unwrap();
+        assert!(registry.projects["webapp"].ports.contains_key("site"));
+    }
+
+    #[test]
+    fn test_alias_conflicts() {
+        let mut registry = empty_registry();
+        let active = vec![];
+
+        allocate_port(
+            &mut registry,
+            &project("myapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("myapp"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        add_alias(&mut registry, "myapp", "web", &name("frontend")).unwrap();
+
+        // Alias name already used by a port
+        assert!(add_alias(&mut registry, "myapp", "web", &name("api")).is_err());
+        // Allocation name already used by an alias
+        let result = allocate_port(
+            &mut registry,
+            &project("myapp"),
+            &name("frontend"),
+            None,
+            PortMeta::default(),
+            &active,
+        );
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Registry(
+                RegistryError::PortNameExists { .. }
+            ))
+        ));
+        // Unknown target
+        assert!(add_alias(&mut registry, "myapp", "db", &name("database")).is_err());
+
+        assert_eq!(
+            remove_alias(&mut registry, "myapp", "frontend").unwrap(),
+            "web"
+        );
+        assert!(matches!(
+            remove_alias(&mut registry, "myapp", "frontend"),
+            Err(crate::error::Error::Registry(
+                RegistryError::AliasNotFound { .. }
+            ))
+        ));
+    }
+
     #[test]
     fn test_query_all_ports() {
         let mut registry = empty_registry();
         let active = vec![];
 
-        allocate_port(&mut registry, "webapp", "web", Some(port(8080)), &active).unwrap();
-        allocate_port(&mut registry, "webapp", "api", Some(port(3000)), &active).unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
 
         let ports = query_ports(&registry, "webapp", None).unwrap();
         assert_eq!(ports.len(), 2);
     }
 
+    #[test]
+    fn test_query_all_projects() {
+        let mut registry = empty_registry();
+        let active = vec![];
+
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("backend"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+
+        assert_eq!(
+            query_all_ports(&registry),
+            vec![
+                ("backend".to_string(), "api".to_string(), port(3000)),
+                ("webapp".to_string(), "web".to_string(), port(8080)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_matching_ports() {
+        let mut registry = empty_registry();
+        for (p, n, number) in [
+            ("team-a-api", "api", 3000),
+            ("team-a-web", "web", 8000),
+            ("team-a-web", "api", 3001),
+            ("team-b-web", "web", 8001),
+        ] {
+            allocate_port(
+                &mut registry,
+                &project(p),
+                &name(n),
+                Some(port(number)),
+                PortMeta::default(),
+                &[],
+            )
+            .unwrap();
+        }
+
+        let ports = query_matching_ports(&registry, "team-a-*", None).unwrap();
+        assert_eq!(
+            ports,
+            vec![
+                ("team-a-api".to_string(), "api".to_string(), port(3000)),
+                ("team-a-web".to_string(), "api".to_string(), port(3001)),
+                ("team-a-web".to_string(), "web".to_string(), port(8000)),
+            ]
+        );
+
+        // Projects without the name are skipped
+        let ports = query_matching_ports(&registry, "*-web", Some("web")).unwrap();
+        assert_eq!(ports.len(), 2);
+
+        let err = query_matching_ports(&registry, "team-c-*", None).unwrap_err();
+        assert_eq!(err.code(), "E_PROJECT_NOT_FOUND");
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let mut registry = empty_registry();
+        for (p, n, number) in [
+            ("myapp", "web", 8000),
+            ("authsvc", "api", 3000),
+            ("authsvc", "admin", 3001),
+            ("billing", "api", 3002),
+        ] {
+            allocate_port(
+                &mut registry,
+                &project(p),
+                &name(n),
+                Some(port(number)),
+                PortMeta::default(),
+                &[],
+            )
+            .unwrap();
+        }
+
+        assert!(add_dependency(&mut registry, "myapp", "authsvc", Some("api")).unwrap());
+        assert!(!add_dependency(&mut registry, "myapp", "authsvc", Some("api")).unwrap());
+        assert!(add_dependency(&mut registry, "myapp", "billing", None).unwrap());
+        let err = add_dependency(&mut registry, "myapp", "authsvc", Some("nope")).unwrap_err();
+        assert_eq!(err.code(), "E_PORT_NAME_NOT_FOUND");
+        assert_eq!(
+            dependency_ports(&registry, "myapp"),
+            vec![
+                ("authsvc".to_string(), "api".to_string(), port(3000)),
+                ("billing".to_string(), "api".to_string(), port(3002)),
+            ]
+        );
+
+        // A freed port is skipped
+        free_port(&mut registry, "billing", None, false, &[]).unwrap();
+        assert_eq!(dependency_ports(&registry, "myapp").len(), 1);
+
+        // Removing the last port name drops the dependency rather than
+        // widening it to every port
+        remove_dependency(&mut registry, "myapp", "authsvc", Some("api")).unwrap();
+        remove_dependency(&mut registry, "myapp", "billing", None).unwrap();
+        assert!(registry.dependencies.is_empty());
+        let err = remove_dependency(&mut registry, "myapp", "authsvc", None).unwrap_err();
+        assert_eq!(err.code(), "E_DEPENDENCY_NOT_FOUND");
+    }
+
+    #[test]
+    fn test_merge_dependencies() {
+        let deps = |content: &str| -> Registry { toml::from_str(content).unwrap() };
+        let mut ours = deps("[dependencies.myapp]\nauthsvc = [\"api\"]\nbilling = []\n");
+        let theirs = deps("[dependencies.myapp]\nauthsvc = [\"admin\"]\nbilling = [\"api\"]\n");
+        merge_dependencies(&mut ours, &theirs);
+        let merged = &ours.dependencies["myapp"];
+        assert_eq!(merged["authsvc"], vec![name("api"), name("admin")]);
+        assert!(merged["billing"].is_empty());
+    }
+
     #[test]
     fn test_query_single_port() {
         let mut registry = empty_registry();
         let active = vec![];
 
-        allocate_port(&mut registry, "webapp", "web", Some(port(8080)), &active).unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
 
         let ports = query_ports(&registry, "webapp", Some("web")).unwrap();
         assert_eq!(ports, vec![("web".to_string(), port(8080))]);
     }
 
+    #[test]
+    fn test_ignore_case_matching() {
+        let mut registry = empty_registry();
+        let active = vec![];
+
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8080)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+
+        // Off by default: different case means a different entry
+        assert!(query_ports(&registry, "webapp", Some("WEB")).is_err());
+
+        registry.defaults.ignore_case = true;
+        let ports = query_ports(&registry, "webapp", Some("WEB")).unwrap();
+        assert_eq!(ports, vec![("web".to_string(), port(8080))]);
+
+        let result = allocate_port(
+            &mut registry,
+            &project("WebApp"),
+            &name("Web"),
+            None,
+            PortMeta::default(),
+            &active,
+        );
+        assert!(result.is_err());
+
+        allocate_port(
+            &mut registry,
+            &project("WebApp"),
+            &name("api"),
+            None,
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        assert_eq!(registry.projects.len(), 1);
+
+        let freed = free_port(&mut registry, "webapp", Some("API"), false, &[]).unwrap();
+        assert_eq!(freed[0].0, "api");
+    }
+
+    #[test]
+    fn test_find_name_ambiguous() {
+        let keys = [name("webapp"), name("WebApp")];
+
+        assert_eq!(find_name(&keys, "WebApp", true).unwrap(), Some(&keys[1]));
+        assert_eq!(find_name(&keys, "WEBAPP", false).unwrap(), None);
+        assert!(find_name(&keys, "WEBAPP", true).is_err());
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let mut registry = empty_registry();
+        for (n, p) in [("web", 8080), ("admin-ui", 8081)] {
+            allocate_port(
+                &mut registry,
+                &project("webapp"),
+                &name(n),
+                Some(port(p)),
+                PortMeta::default(),
+                &[],
+            )
+            .unwrap();
+        }
+
+        let vars = env_vars(&registry, "webapp").unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("ADMIN_UI_PORT".to_string(), port(8081)),
+                ("WEB_PORT".to_string(), port(8080)),
+            ]
+        );
+        let urls = env_urls(&registry, "webapp", "10.0.0.5").unwrap();
+        assert_eq!(
+            urls[1],
+            ("WEB_URL".to_string(), "http://10.0.0.5:8080".to_string())
+        );
+
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("admin_ui"),
+            Some(port(8082)),
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        let err = env_vars(&registry, "webapp").unwrap_err().to_string();
+        assert!(err.contains("'admin-ui' and 'admin_ui'"));
+        assert!(err.contains("$ADMIN_UI_PORT"));
+        assert!(env_urls(&registry, "webapp", "10.0.0.5").is_err());
+    }
+
     #[test]
     fn test_suggest_ports() {
         let mut registry = empty_registry();
         let active = vec![];
 
         // Allocate first few ports
-        allocate_port(&mut registry, "p1", "web", Some(port(8000)), &active).unwrap();
-        allocate_port(&mut registry, "p2", "web", Some(port(8001)), &active).unwrap();
-
-        let suggestions = suggest_port(&registry, "web", 3, &active).unwrap();
+        allocate_port(
+            &mut registry,
+            &project("p1"),
+            &name("web"),
+            Some(port(8000)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+        allocate_port(
+            &mut registry,
+            &project("p2"),
+            &name("web"),
+            Some(port(8001)),
+            PortMeta::default(),
+            &active,
+        )
+        .unwrap();
+
+        let suggestions = suggest_port(&registry, "web", None, 3, &active).unwrap();
         assert_eq!(suggestions, vec![port(8002), port(8003), port(8004)]);
     }
 
+    #[test]
+    fn test_suggest_ports_policies() {
+        let mut registry = empty_registry();
+        for (project_name, p) in [("p1", 8000), ("p2", 8005)] {
+            allocate_port(
+                &mut registry,
+                &project(project_name),
+                &name("web"),
+                Some(port(p)),
+                PortMeta::default(),
+                &[],
+            )
+            .unwrap();
+        }
+        free_port(&mut registry, "p2", Some("web"), false, &[]).unwrap();
+        allocate_port(
+            &mut registry,
+            &project("p3"),
+            &name("web"),
+            Some(port(8004)),
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+
+        // lowest-free fills the gap at the start of the range
+        let suggestions = suggest_port(&registry, "web", None, 2, &[]).unwrap();
+        assert_eq!(suggestions, vec![port(8001), port(8002)]);
+
+        // sequential continues after the highest allocated port
+        set_port_range(&mut registry, "web=8000-8005:sequential").unwrap();
+        let suggestions = suggest_port(&registry, "web", None, 3, &[]).unwrap();
+        assert_eq!(suggestions, vec![port(8005), port(8001), port(8002)]);
+
+        // random stays inside the range and skips allocated ports
+        set_port_range(&mut registry, "web=8000-8005:random").unwrap();
+        let suggestions = suggest_port(&registry, "web", None, 4, &[]).unwrap();
+        assert_eq!(suggestions.len(), 4);
+        assert!(suggestions
+            .iter()
+            .all(|p| (8001..=8005).contains(&p.as_u16()) && p.as_u16() != 8004));
+
+        // Changing only the bounds keeps the policy
+        set_port_range(&mut registry, "web=8000-8099").unwrap();
+        assert_eq!(registry.get_range("web").policy, AllocationPolicy::Random);
+
+        let result = set_port_range(&mut registry, "web=8000-8099:fastest");
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Registry(RegistryError::UnknownPolicy(
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_suggest_ports_across_segments() {
+        let mut registry = empty_registry();
+        set_port_range(&mut registry, "api=3000-3001,3300-3301").unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("api"),
+            Some(port(3000)),
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+
+        let suggestions = suggest_port(&registry, "api", None, 5, &[]).unwrap();
+        assert_eq!(suggestions, vec![port(3001), port(3300), port(3301)]);
+
+        // sequential moves on to the next segment and wraps around
+        set_port_range(&mut registry, "api=3000-3001,3300-3301:sequential").unwrap();
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("admin"),
+            Some(port(3300)),
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        let suggestions = suggest_port(&registry, "api", None, 2, &[]).unwrap();
+        assert_eq!(suggestions, vec![port(3301), port(3001)]);
+
+        let result = set_port_range(&mut registry, "api=3000-3099,3050-3199");
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Registry(
+                RegistryError::OverlappingSegments { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_reserved_range_only_serves_its_projects() {
+        let mut registry = empty_registry();
+        let mut infra = PortRange::new(9500, 9599);
+        infra.projects = vec!["infra-*".to_string()];
+        registry.defaults.ranges.insert("infra".to_string(), infra);
+        registry
+            .defaults
+            .ranges
+            .insert("default".to_string(), PortRange::new(9000, 9099));
+
+        let owner = project("infra-dns");
+        let suggestions = suggest_port(&registry, "infra", Some(&owner), 1, &[]).unwrap();
+        assert_eq!(suggestions, vec![port(9500)]);
+
+        // Other projects fall back to the default range
+        let other = project("webapp");
+        let suggestions = suggest_port(&registry, "infra", Some(&other), 1, &[]).unwrap();
+        assert_eq!(suggestions, vec![port(9000)]);
+        let port = allocate_port(
+            &mut registry,
+            &other,
+            &name("infra"),
+            None,
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(port.as_u16(), 9000);
+
+        // ...unless that is reserved too
+        registry
+            .defaults
+            .ranges
+            .get_mut("default")
+            .unwrap()
+            .projects = vec!["infra-*".to_string()];
+        let result = suggest_port(&registry, "infra", Some(&other), 1, &[]);
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Registry(
+                RegistryError::RangeReserved { .. }
+            ))
+        ));
+
+        // Changing the bounds keeps the reservation
+        set_port_range(&mut registry, "infra=9500-9549").unwrap();
+        assert_eq!(registry.get_range("infra").projects, ["infra-*"]);
+    }
+
+    #[test]
+    fn test_explain_suggestions() {
+        let mut registry = empty_registry();
+        let mut range = PortRange::new(8000, 8999);
+        range.exclude = vec![8002];
+        registry.defaults.ranges.insert("web".to_string(), range);
+        allocate_port(
+            &mut registry,
+            &project("webapp"),
+            &name("web"),
+            Some(port(8000)),
+            PortMeta::default(),
+            &[],
+        )
+        .unwrap();
+        let active = vec![ListeningPort {
+            port: port(8001),
+            pid: Some(123),
+            process_name: Some("node".to_string()),
+            process_cwd: None,
+            process_command: None,
+            uid: None,
+            user: None,
+            host: None,
+        }];
+
+        let report = explain_suggestions(&registry, "web", None, 1, &active).unwrap();
+        assert_eq!(report.range_type, "web");
+        let verdicts: Vec<(u16, Verdict)> = report
+            .candidates
+            .iter()
+            .map(|c| (c.port.as_u16(), c.verdict.clone()))
+            .collect();
+        assert_eq!(
+            verdicts,
+            vec![
+                (
+                    8000,
+                    Verdict::Allocated {
+                        owner: "webapp.web".to_string()
+                    }
+                ),
+                (
+                    8001,
+                    Verdict::Active {
+                        pid: Some(123),
+                        process: Some("node".to_string())
+                    }
+                ),
+                (8002, Verdict::Excluded),
+                (8003, Verdict::Suggested),
+            ]
+        );
+        assert_eq!(report.suggestions().collect::<Vec<_>>(), vec![port(8003)]);
+
+        let report = explain_suggestions(&registry, "unknown", None, 1, &[]).unwrap();
+        assert_eq!(report.range_type, "default");
+    }
+
+    #[test]
+    fn test_suggest_ports_skips_exclusions() {
+        let mut registry = empty_registry();
+        let mut range = PortRange::new(8000, 8004);
+        range.exclude = vec![8000, 8002];
+        registry.defaults.ranges.insert("web".to_string(), range);
+
+        let suggestions = suggest_port(&registry, "web", None, 5, &[]).unwrap();
+        assert_eq!(suggestions, vec![port(8001), port(8003), port(8004)]);
+
+        // New bounds keep the exclusions still inside them
+        set_port_range(&mut registry, "web=8001-8099").unwrap();
+        assert_eq!(registry.get_range("web").exclude, vec![8002]);
+    }
+
     #[test]
     fn test_set_port_range() {
         let mut registry = empty_registry();
@@ -383,7 +3123,7 @@ mod tests {
         assert_eq!(type_name, "custom");
         assert_eq!(start, 5000);
         assert_eq!(end, 5999);
-        assert_eq!(registry.get_range("custom"), [5000, 5999]);
+        assert_eq!(registry.get_range("custom").segments, [[5000, 5999]]);
     }
 
     #[test]
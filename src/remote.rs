@@ -0,0 +1,372 @@
+//! Listening ports on another machine, over SSH (`pm status --remote`).
+//!
+//! The remote side runs `pm status --json` when pm is installed and can
+//! detect ports there, and falls back to `ss` or `lsof` otherwise, so a bare
+//! Raspberry Pi or dev box works too. Whatever ran, the listeners come back
+//! as [`ListeningPort`]s and are shown like local ones.
+//!
+//! The same parsers, plus one for `netstat`, read listings saved on machines
+//! pm can't detect ports on, for `pm import netstat`.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::port::Port;
+use crate::ports::ListeningPort;
+
+/// Runs on the remote host. The first line of output names the tool used.
+const REMOTE_SCRIPT: &str = "sh -c '\
+if out=$(pm status --json=v1 --full 2>/dev/null); then echo pm; printf \"%s\\n\" \"$out\"; \
+elif command -v ss >/dev/null 2>&1; then echo ss; ss -Htlnp; \
+else echo lsof; lsof -nP -iTCP -sTCP:LISTEN; fi'";
+
+/// A listener in `pm status --json` output.
+#[derive(Deserialize)]
+struct StatusEntry {
+    port: Port,
+    pid: Option<i32>,
+    #[serde(default)]
+    pids: Vec<i32>,
+    process: Option<String>,
+    cwd: Option<PathBuf>,
+    uid: Option<u32>,
+    user: Option<String>,
+}
+
+/// Returns the listening ports on `host` (`[user@]host`, or an alias from
+/// the SSH config), sorted by port.
+pub fn remote_listening_ports(host: &str) -> Result<Vec<ListeningPort>> {
+    let command = format!("ssh {host}");
+    let failed = |reason: String| Error::CommandFailed {
+        command: command.clone(),
+        reason,
+    };
+    // Anything starting with '-' would be read as an ssh option
+    if host.is_empty() || host.starts_with('-') {
+        return Err(failed(format!("'{host}' is not a host name")));
+    }
+
+    debug!("running detection on {host}");
+    let output = Command::new("ssh")
+        .arg(host)
+        .arg(REMOTE_SCRIPT)
+        .output()
+        .map_err(|e| failed(e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(failed(match stderr.trim() {
+            "" => output.status.to_string(),
+            stderr => stderr.to_string(),
+        }));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (tool, listing) = stdout.split_once('\n').unwrap_or((&stdout, ""));
+    let mut ports = match tool.trim() {
+        "pm" => parse_pm_status(listing)
+            .ok_or_else(|| failed("unreadable `pm status --json` output".to_string()))?,
+        "ss" => parse_ss(listing),
+        "lsof" => parse_lsof(listing),
+        _ => return Err(failed("unexpected output".to_string())),
+    };
+    ports.sort_by_key(|lp| (lp.port, lp.pid));
+    ports.dedup_by_key(|lp| (lp.port, lp.pid));
+    Ok(ports)
+}
+
+fn listener(port: Port, pid: Option<i32>, process_name: Option<String>) -> ListeningPort {
+    ListeningPort {
+        port,
+        pid,
+        process_name,
+        process_cwd: None,
+        process_command: None,
+        uid: None,
+        user: None,
+        host: None,
+    }
+}
+
+/// Parses `pm status --json=v1 --full` output.
+pub fn parse_pm_status(output: &str) -> Option<Vec<ListeningPort>> {
+    let mut value: Value = serde_json::from_str(output).ok()?;
+    status_listeners(value.get_mut("data")?.take()).ok()
+}
+
+/// Converts the entries of `pm status --json --full` output back into
+/// listeners, one for each process on a port.
+pub fn status_listeners(entries: Value) -> serde_json::Result<Vec<ListeningPort>> {
+    let entries: Vec<StatusEntry> = serde_json::from_value(entries)?;
+    let mut ports = Vec::new();
+    for entry in entries {
+        let pids = if entry.pids.is_empty() {
+            vec![entry.pid]
+        } else {
+            entry.pids.into_iter().map(Some).collect()
+        };
+        for pid in pids {
+            ports.push(ListeningPort {
+                process_cwd: entry.cwd.clone(),
+                uid: entry.uid,
+                user: entry.user.clone(),
+                ..listener(entry.port, pid, entry.process.clone())
+            });
+        }
+    }
+    Ok(ports)
+}
+
+/// Returns the port of a local address (`0.0.0.0:22`, `[::]:22`, `*:22`).
+fn address_port(address: &str) -> Option<Port> {
+    let (_, port) = address.rsplit_once(':')?;
+    Port::new(port.parse().ok()?).ok()
+}
+
+/// Parses `ss -Htlnp` output, e.g.
+/// `LISTEN 0 511 0.0.0.0:80 0.0.0.0:* users:(("nginx",pid=812,fd=6),("nginx",pid=813,fd=6))`.
+/// Processes are only listed for sockets the remote user may inspect.
+fn parse_ss(output: &str) -> Vec<ListeningPort> {
+    let mut ports = Vec::new();
+    for line in output.lines() {
+        let mut fields: Vec<&str> = line.split_whitespace().collect();
+        // `ss -a` and `-u` add a protocol column; only TCP listeners count
+        if fields.first() == Some(&"tcp") {
+            fields.remove(0);
+        }
+        if fields.first() != Some(&"LISTEN") {
+            continue;
+        }
+        let Some(port) = fields.get(3).and_then(|address| address_port(address)) else {
+            continue;
+        };
+        let users = line.split_once("users:(").map_or("", |(_, users)| users);
+        let before = ports.len();
+        // Each process is ("name",pid=N,fd=N)
+        for process in users.split("(\"").skip(1) {
+            let Some((name, rest)) = process.split_once('"') else {
+                continue;
+            };
+            let pid = rest
+                .split_once("pid=")
+                .and_then(|(_, pid)| pid.split(|c: char| !c.is_ascii_digit()).next())
+                .and_then(|pid| pid.parse().ok());
+            ports.push(listener(port, pid, Some(name.to_string())));
+        }
+        if ports.len() == before {
+            ports.push(listener(port, None, None));
+        }
+    }
+    ports
+}
+
+/// Parses `lsof -nP -iTCP -sTCP:LISTEN` output, e.g.
+/// `node 4242 pi 21u IPv4 0x1 0t0 TCP *:3000 (LISTEN)`.
+fn parse_lsof(output: &str) -> Vec<ListeningPort> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [command, pid, user, .., address, _state] = fields.as_slice() else {
+                return None;
+            };
+            Some(ListeningPort {
+                user: Some(user.to_string()),
+                ..listener(
+                    address_port(address)?,
+                    pid.parse().ok(),
+                    Some(command.to_string()),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Parses `netstat -an` output from Linux (`tcp 0 0 0.0.0.0:22 0.0.0.0:* LISTEN`,
+/// followed by `812/sshd` with -p), macOS and BSD (`tcp4 0 0 *.8080 *.* LISTEN`),
+/// or Windows (`TCP 0.0.0.0:135 0.0.0.0:0 LISTENING`, followed by the PID with -o).
+fn parse_netstat(output: &str) -> Vec<ListeningPort> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if !fields.first()?.to_ascii_lowercase().starts_with("tcp") {
+                return None;
+            }
+            let state = fields
+                .iter()
+                .position(|field| matches!(*field, "LISTEN" | "LISTENING"))?;
+            // Unix netstat has receive and send queue columns before the addresses
+            let local = match fields.get(1)?.parse::<u64>() {
+                Ok(_) => fields.get(3)?,
+                Err(_) => fields.get(1)?,
+            };
+            let (pid, process) = match fields.get(state + 1).map(|owner| owner.split_once('/')) {
+                Some(Some((pid, process))) => (pid.parse().ok(), Some(process.to_string())),
+                Some(None) => (fields[state + 1].parse().ok(), None),
+                None => (None, None),
+            };
+            Some(listener(netstat_port(local)?, pid, process))
+        })
+        .collect()
+}
+
+/// Returns the port of a `netstat` local address: after the last ':', or
+/// the last '.' on macOS and BSD (`*.8080`, `127.0.0.1.5432`).
+fn netstat_port(address: &str) -> Option<Port> {
+    let (_, port) = address.rsplit_once([':', '.'])?;
+    Port::new(port.parse().ok()?).ok()
+}
+
+/// Parses a saved `ss -tlnp`, `netstat -an`, or `lsof -nP -iTCP -sTCP:LISTEN`
+/// listing, telling the formats apart by their lines. Returns one listener
+/// per port and process, sorted by port; text in none of the formats has none.
+pub fn parse_listing(text: &str) -> Vec<ListeningPort> {
+    let is_ss = |line: &str| {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("LISTEN") => true,
+            Some("tcp") => fields.next() == Some("LISTEN"),
+            _ => false,
+        }
+    };
+    let first = text.lines().find(|line| !line.trim().is_empty());
+    let mut ports = if first.is_some_and(|line| line.starts_with("COMMAND")) {
+        parse_lsof(text)
+    } else if text.lines().any(is_ss) {
+        parse_ss(text)
+    } else {
+        parse_netstat(text)
+    };
+    ports.sort_by_key(|lp| (lp.port, lp.pid));
+    ports.dedup_by_key(|lp| (lp.port, lp.pid));
+    ports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ports_and_pids(ports: &[ListeningPort]) -> Vec<(u16, Option<i32>)> {
+        ports.iter().map(|lp| (lp.port.as_u16(), lp.pid)).collect()
+    }
+
+    #[test]
+    fn test_parse_pm_status() {
+        let output = r#"{"apiVersion": "v1", "data": [
+            {"port": 3000, "project": "webapp", "name": "api", "pid": 4242,
+             "process": "node", "cwd": "/home/pi/webapp", "user": "pi"}
+        ]}"#;
+        let ports = parse_pm_status(output).unwrap();
+        assert_eq!(ports_and_pids(&ports), [(3000, Some(4242))]);
+        assert_eq!(ports[0].process_cwd, Some(PathBuf::from("/home/pi/webapp")));
+        assert_eq!(ports[0].user.as_deref(), Some("pi"));
+        assert!(parse_pm_status("Error: Platform not supported").is_none());
+
+        let output = r#"{"data": [
+            {"port": 80, "pid": 812, "pids": [812, 813], "process": "nginx"}
+        ]}"#;
+        let ports = parse_pm_status(output).unwrap();
+        assert_eq!(ports_and_pids(&ports), [(80, Some(812)), (80, Some(813))]);
+    }
+
+    #[test]
+    fn test_parse_ss() {
+        let output = "\
+LISTEN 0 511 0.0.0.0:80 0.0.0.0:* users:((\"nginx\",pid=812,fd=6),(\"nginx\",pid=813,fd=6))
+LISTEN 0 128 [::]:22 [::]:*
+LISTEN 0 4096 127.0.0.53%lo:53 0.0.0.0:* users:((\"systemd-resolve\",pid=401,fd=14))
+";
+        let ports = parse_ss(output);
+        assert_eq!(
+            ports_and_pids(&ports),
+            [
+                (80, Some(812)),
+                (80, Some(813)),
+                (22, None),
+                (53, Some(401))
+            ]
+        );
+        assert_eq!(ports[3].process_name.as_deref(), Some("systemd-resolve"));
+    }
+
+    #[test]
+    fn test_parse_lsof() {
+        let output = "\
+COMMAND  PID USER   FD   TYPE DEVICE SIZE/OFF NODE NAME
+node    4242   pi   21u  IPv6  12345      0t0  TCP *:3000 (LISTEN)
+postgres 512 postgres 5u IPv4  12346      0t0  TCP 127.0.0.1:5432 (LISTEN)
+";
+        let ports = parse_lsof(output);
+        assert_eq!(
+            ports_and_pids(&ports),
+            [(3000, Some(4242)), (5432, Some(512))]
+        );
+        assert_eq!(ports[1].user.as_deref(), Some("postgres"));
+    }
+
+    #[test]
+    fn test_parse_netstat() {
+        let linux = "\
+Active Internet connections (only servers)
+Proto Recv-Q Send-Q Local Address           Foreign Address         State       PID/Program name
+tcp        0      0 0.0.0.0:22              0.0.0.0:*               LISTEN      812/sshd
+tcp6       0      0 :::8080                 :::*                    LISTEN      -
+tcp        0      0 10.0.0.5:41234          140.82.112.3:443        ESTABLISHED 901/git
+udp        0      0 0.0.0.0:68              0.0.0.0:*                           401/dhclient
+";
+        let ports = parse_netstat(linux);
+        assert_eq!(ports_and_pids(&ports), [(22, Some(812)), (8080, None)]);
+        assert_eq!(ports[0].process_name.as_deref(), Some("sshd"));
+
+        let macos = "\
+Proto Recv-Q Send-Q  Local Address          Foreign Address        (state)
+tcp4       0      0  127.0.0.1.5432         *.*                    LISTEN
+tcp46      0      0  *.3000                 *.*                    LISTEN
+";
+        assert_eq!(
+            ports_and_pids(&parse_netstat(macos)),
+            [(5432, None), (3000, None)]
+        );
+
+        let windows = "\
+  Proto  Local Address          Foreign Address        State           PID
+  TCP    0.0.0.0:135            0.0.0.0:0              LISTENING       912
+  TCP    [::]:445               [::]:0                 LISTENING       4
+";
+        assert_eq!(
+            ports_and_pids(&parse_netstat(windows)),
+            [(135, Some(912)), (445, Some(4))]
+        );
+    }
+
+    #[test]
+    fn test_parse_listing() {
+        let ss = "\
+State  Recv-Q Send-Q Local Address:Port Peer Address:Port Process
+LISTEN 0      511          0.0.0.0:80        0.0.0.0:*     users:((\"nginx\",pid=812,fd=6))
+";
+        assert_eq!(ports_and_pids(&parse_listing(ss)), [(80, Some(812))]);
+
+        let ss_all = "\
+Netid State  Recv-Q Send-Q Local Address:Port Peer Address:Port
+udp   UNCONN 0      0            0.0.0.0:68        0.0.0.0:*
+tcp   LISTEN 0      128          0.0.0.0:22        0.0.0.0:*
+";
+        assert_eq!(ports_and_pids(&parse_listing(ss_all)), [(22, None)]);
+
+        let lsof = "\
+COMMAND  PID USER   FD   TYPE DEVICE SIZE/OFF NODE NAME
+node    4242   pi   21u  IPv6  12345      0t0  TCP *:3000 (LISTEN)
+node    4242   pi   22u  IPv4  12346      0t0  TCP *:3000 (LISTEN)
+";
+        assert_eq!(ports_and_pids(&parse_listing(lsof)), [(3000, Some(4242))]);
+
+        assert!(parse_listing("nothing to see here\n").is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+//! Recovering a registry that no longer parses (`pm config repair`).
+//!
+//! One stray character makes the whole registry unreadable, and with it
+//! every command. Repair keeps as much of the file as still parses: each
+//! `[section]` is tried whole, and a section that fails is retried line by
+//! line, so a bad entry costs only its own lines. What is kept is the
+//! original text, comments and all; what is dropped is reported.
+//!
+//! Loading is tolerant in the same way without changing the file: a project
+//! (or `[defaults]`, ...) that doesn't parse is left out with a warning, so
+//! one bad hand edit doesn't hide everything else from `pm list`.
+
+use regex::Regex;
+use toml::{Table, Value};
+
+use crate::model::Registry;
+
+/// Lines dropped from the registry because they didn't parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dropped {
+    /// 1-based line numbers of the first and last dropped line.
+    pub lines: (usize, usize),
+    /// The section the lines were in, as its header ("" before any header,
+    /// or when the section was dropped whole).
+    pub section: String,
+    /// The first dropped line, trimmed.
+    pub text: String,
+    pub reason: String,
+}
+
+/// What repair keeps and drops.
+#[derive(Debug)]
+pub struct Salvage {
+    /// The registry file rewritten without the dropped lines.
+    pub content: String,
+    pub registry: Registry,
+    pub dropped: Vec<Dropped>,
+}
+
+/// A registry section left out because it didn't parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedSection {
+    /// Key path of the section (e.g., "projects.webapp" or "defaults").
+    pub section: String,
+    pub reason: String,
+}
+
+/// Tables whose entries are parsed one at a time; other top-level keys
+/// are parsed whole.
+const PER_ENTRY: [&str; 5] = ["projects", "archived", "history", "dependencies", "links"];
+
+/// Parses a registry one section at a time, leaving out sections that don't
+/// parse instead of failing. Fails only if `content` isn't TOML at all.
+pub fn parse_by_section(
+    content: &str,
+) -> std::result::Result<(Registry, Vec<SkippedSection>), toml::de::Error> {
+    let table: Table = toml::from_str(content)?;
+    let mut kept = Table::new();
+    let mut skipped = Vec::new();
+    let parses = |key: &str, value: Value| {
+        let mut alone = Table::new();
+        alone.insert(key.to_string(), value);
+        Value::Table(alone)
+            .try_into::<Registry>()
+            .err()
+            .map(|e| e.message().lines().next().unwrap_or_default().to_string())
+    };
+
+    for (key, value) in table {
+        match value {
+            Value::Table(entries) if PER_ENTRY.contains(&key.as_str()) => {
+                let mut good = Table::new();
+                for (name, entry) in entries {
+                    let mut one = Table::new();
+                    one.insert(name.clone(), entry.clone());
+                    match parses(&key, Value::Table(one)) {
+                        None => {
+                            good.insert(name, entry);
+                        }
+                        Some(reason) => skipped.push(SkippedSection {
+                            section: format!("{key}.{name}"),
+                            reason,
+                        }),
+                    }
+                }
+                kept.insert(key, Value::Table(good));
+            }
+            value => match parses(&key, value.clone()) {
+                None => {
+                    kept.insert(key, value);
+                }
+                Some(reason) => skipped.push(SkippedSection {
+                    section: key,
+                    reason,
+                }),
+            },
+        }
+    }
+
+    let registry = Value::Table(kept).try_into()?;
+    Ok((registry, skipped))
+}
+
+/// A `[section]` header and the lines up to the next one.
+struct Section<'a> {
+    /// Line number of `lines[0]`, 1-based.
+    first_line: usize,
+    lines: Vec<&'a str>,
+}
+
+/// Splits `content` at table headers, including ones too broken to parse.
+/// Only unindented lines count, since pm indents the elements of arrays it
+/// writes and those can look alike.
+fn sections(content: &str) -> Vec<Section<'_>> {
+    let header = Regex::new(r"^\[[^,=]*$").expect("valid regex");
+    let mut sections = vec![Section {
+        first_line: 1,
+        lines: Vec::new(),
+    }];
+    for (i, line) in content.lines().enumerate() {
+        if header.is_match(line) {
+            sections.push(Section {
+                first_line: i + 1,
+                lines: Vec::new(),
+            });
+        }
+        sections
+            .last_mut()
+            .expect("starts with one")
+            .lines
+            .push(line);
+    }
+    sections
+}
+
+/// Returns why `content` isn't a registry, or `None` if it is.
+fn problem(content: &str) -> Option<String> {
+    toml::from_str::<Registry>(content)
+        .err()
+        .map(|e| e.message().lines().next().unwrap_or_default().to_string())
+}
+
+fn join(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+/// Keeps the parts of `content` that parse as a registry.
+pub fn salvage(content: &str) -> Salvage {
+    // A new key, as opposed to a line continuing a multi-line value
+    let key = Regex::new(
+        r#"^\s*([A-Za-z0-9_-]+|"[^"]*"|'[^']*')(\s*\.\s*([A-Za-z0-9_-]+|"[^"]*"|'[^']*'))*\s*="#,
+    )
+    .expect("valid regex");
+    let mut kept = String::new();
+    let mut dropped = Vec::new();
+
+    for section in sections(content) {
+        let Some(&first) = section.lines.first() else {
+            continue;
+        };
+        let whole = join(&section.lines);
+        let Some(reason) = problem(&(kept.clone() + &whole)) else {
+            kept.push_str(&whole);
+            continue;
+        };
+        let header = if section.first_line == 1 && !first.starts_with('[') {
+            String::new()
+        } else {
+            first.trim().to_string()
+        };
+        let last_line = section.first_line
+            + section
+                .lines
+                .iter()
+                .rposition(|line| !line.trim().is_empty())
+                .unwrap_or(0);
+
+        // Without its header, a section's lines would land in the one before
+        let (mut base, body, body_line) = if header.is_empty() {
+            (kept.clone(), &section.lines[..], section.first_line)
+        } else {
+            let with_header = kept.clone() + &join(&section.lines[..1]);
+            if problem(&with_header).is_some() {
+                dropped.push(Dropped {
+                    lines: (section.first_line, last_line),
+                    section: String::new(),
+                    text: first.trim().to_string(),
+                    reason,
+                });
+                continue;
+            }
+            (with_header, &section.lines[1..], section.first_line + 1)
+        };
+
+        // Lines are kept a statement at a time: a line that starts a new key
+        // ends the statement before it, which is dropped if it never parsed
+        let mut pending: Vec<&str> = Vec::new();
+        let mut pending_line = body_line;
+        let mut drop_pending = |pending: &mut Vec<&str>, at: usize, base: &str| {
+            let filled: Vec<usize> = (0..pending.len())
+                .filter(|&i| !pending[i].trim().is_empty())
+                .collect();
+            if let (Some(&first), Some(&last)) = (filled.first(), filled.last()) {
+                dropped.push(Dropped {
+                    lines: (at + first, at + last),
+                    section: header.clone(),
+                    text: pending[first].trim().to_string(),
+                    reason: problem(&(base.to_string() + &join(pending))).unwrap_or_default(),
+                });
+            }
+            pending.clear();
+        };
+        for (i, &line) in body.iter().enumerate() {
+            if !pending.is_empty() && key.is_match(line) {
+                drop_pending(&mut pending, pending_line, &base);
+            }
+            if pending.is_empty() {
+                pending_line = body_line + i;
+            }
+            pending.push(line);
+            let candidate = base.clone() + &join(&pending);
+            if problem(&candidate).is_none() {
+                base = candidate;
+                pending.clear();
+            }
+        }
+        drop_pending(&mut pending, pending_line, &base);
+        kept = base;
+    }
+
+    let registry = toml::from_str(&kept).unwrap_or_default();
+    Salvage {
+        content: kept,
+        registry,
+        dropped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_salvage_drops_broken_lines() {
+        let content = "\
+[defaults.ranges]
+web = [
+    8000,
+    8999,
+]
+
+[projects.webapp]
+web = 8000
+api = 30x0
+db = 5432
+
+[projects.shop.web]
+port = 8100
+";
+        let salvage = salvage(content);
+        assert_eq!(salvage.dropped.len(), 1);
+        assert_eq!(salvage.dropped[0].lines, (9, 9));
+        assert_eq!(salvage.dropped[0].section, "[projects.webapp]");
+        assert_eq!(salvage.dropped[0].text, "api = 30x0");
+        let webapp = &salvage.registry.projects["webapp"];
+        assert_eq!(webapp.ports.len(), 2);
+        assert_eq!(
+            salvage.registry.projects["shop"].ports["web"].port.as_u16(),
+            8100
+        );
+        assert!(salvage.content.contains("    8999,\n"));
+    }
+
+    #[test]
+    fn test_parse_by_section() {
+        let content = "\
+[defaults]
+stale_after_days = \"soon\"
+
+[projects.webapp]
+web = 8000
+
+[projects.shop]
+web = 0
+
+[links]
+\"/code/webapp\" = \"webapp\"
+";
+        let (registry, skipped) = parse_by_section(content).unwrap();
+        assert_eq!(registry.projects.len(), 1);
+        assert!(registry.projects["webapp"].contains_name("web"));
+        assert_eq!(registry.links.len(), 1);
+        assert!(registry.defaults.ranges.contains_key("web"));
+        let sections: Vec<&str> = skipped.iter().map(|s| s.section.as_str()).collect();
+        assert_eq!(sections, ["defaults", "projects.shop"]);
+
+        assert!(parse_by_section("[projects.webapp\n").is_err());
+    }
+
+    #[test]
+    fn test_salvage_drops_section_with_broken_header() {
+        let content = "\
+[projects.webapp]
+web = 8000
+
+[projects.shop
+web = 8100
+stray
+
+";
+        let salvage = salvage(content);
+        assert_eq!(salvage.dropped.len(), 1);
+        assert_eq!(salvage.dropped[0].lines, (4, 6));
+        assert_eq!(salvage.registry.projects.len(), 1);
+        assert!(salvage.registry.projects["webapp"].contains_name("web"));
+
+        let clean = "[projects.webapp]\nweb = 8000\n";
+        let salvage = super::salvage(clean);
+        assert!(salvage.dropped.is_empty());
+        assert_eq!(salvage.content, clean);
+    }
+}
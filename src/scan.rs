@@ -0,0 +1,412 @@
+//! Heuristic discovery of hardcoded dev-server ports.
+//!
+//! Scans common project files (package.json scripts, Vite config,
+//! docker-compose files, Makefiles) for port numbers so an existing
+//! project can be onboarded with `pm import scan`. The parsing is
+//! deliberately line-based and forgiving: a missed port is better than
+//! refusing to scan a file we do not fully understand.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::name::MAX_NAME_LEN;
+use crate::port::Port;
+
+/// Vite config file names, in the order Vite resolves them.
+const VITE_CONFIGS: &[&str] = &[
+    "vite.config.js",
+    "vite.config.mjs",
+    "vite.config.ts",
+    "vite.config.mts",
+];
+
+/// docker-compose file names.
+const COMPOSE_FILES: &[&str] = &[
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+/// A port found in a project file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanFinding {
+    /// Suggested allocation name (script, service, or variable name).
+    pub name: String,
+    pub port: Port,
+    /// File the port was found in, relative to the scanned directory.
+    pub file: PathBuf,
+    /// The trimmed source line, for showing the user what matched.
+    pub context: String,
+}
+
+/// Scans `dir` for hardcoded ports, returning findings de-duplicated by port.
+pub fn scan_directory(dir: &Path) -> Result<Vec<ScanFinding>> {
+    let mut findings = Vec::new();
+
+    let package_json = dir.join("package.json");
+    if package_json.is_file() {
+        findings.extend(scan_package_json(&fs::read_to_string(&package_json)?));
+    }
+
+    for file in VITE_CONFIGS {
+        let path = dir.join(file);
+        if path.is_file() {
+            findings.extend(scan_vite_config(&fs::read_to_string(&path)?, file));
+        }
+    }
+
+    for file in COMPOSE_FILES {
+        let path = dir.join(file);
+        if path.is_file() {
+            findings.extend(scan_compose(&fs::read_to_string(&path)?, file));
+        }
+    }
+
+    let makefile = dir.join("Makefile");
+    if makefile.is_file() {
+        findings.extend(scan_makefile(&fs::read_to_string(&makefile)?));
+    }
+
+    // The same port often appears in several files; keep the first sighting
+    let mut seen = HashSet::new();
+    findings.retain(|f| seen.insert(f.port));
+
+    // Different ports may share a suggested name; number the repeats
+    let mut names = HashSet::new();
+    for finding in &mut findings {
+        let base = port_name_for(&finding.name);
+        finding.name = base.clone();
+        let mut n = 2;
+        while !names.insert(finding.name.clone()) {
+            finding.name = format!("{base}-{n}");
+            n += 1;
+        }
+    }
+
+    Ok(findings)
+}
+
+/// Turns a script, service, or variable name into a valid port name,
+/// replacing unsupported characters (e.g., `dev:web`, `@scope/app`) with `-`.
+fn port_name_for(raw: &str) -> String {
+    let name: String = raw
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let mut name = name.trim_matches('-').to_string();
+    // Leave room for a "-N" suffix on repeated names
+    name.truncate(MAX_NAME_LEN - 4);
+    if name.is_empty() {
+        "default".to_string()
+    } else {
+        name
+    }
+}
+
+/// Extracts ports from `scripts` entries in package.json.
+fn scan_package_json(content: &str) -> Vec<ScanFinding> {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(content) else {
+        return Vec::new();
+    };
+    let Some(scripts) = json.get("scripts").and_then(|s| s.as_object()) else {
+        return Vec::new();
+    };
+
+    scripts
+        .iter()
+        .filter_map(|(name, script)| {
+            let script = script.as_str()?;
+            let port = command_port(script)?;
+            Some(ScanFinding {
+                name: name.clone(),
+                port,
+                file: PathBuf::from("package.json"),
+                context: format!("\"{name}\": \"{script}\""),
+            })
+        })
+        .collect()
+}
+
+/// Extracts `port: N` from a Vite config's `server` or `preview` block.
+fn scan_vite_config(content: &str, file: &str) -> Vec<ScanFinding> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let value = line.trim().strip_prefix("port:")?;
+            let port = parse_port(value.trim().trim_end_matches(','))?;
+            Some(ScanFinding {
+                name: "web".to_string(),
+                port,
+                file: PathBuf::from(file),
+                context: line.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Extracts published host ports from docker-compose `ports:` lists,
+/// naming each after its service.
+fn scan_compose(content: &str, file: &str) -> Vec<ScanFinding> {
+    let mut findings = Vec::new();
+    let mut in_services = false;
+    let mut service: Option<String> = None;
+    let mut service_indent = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+
+        if indent == 0 {
+            in_services = trimmed == "services:";
+            service = None;
+            continue;
+        }
+        if !in_services {
+            continue;
+        }
+
+        // Service keys are the first (and shallowest) keys under `services:`
+        if trimmed.ends_with(':')
+            && !trimmed.starts_with('-')
+            && (service_indent.is_none() || service_indent == Some(indent))
+        {
+            service_indent = Some(indent);
+            service = Some(trimmed.trim_end_matches(':').to_string());
+            continue;
+        }
+
+        let Some(service) = &service else {
+            continue;
+        };
+        let Some(mapping) = trimmed.strip_prefix('-') else {
+            continue;
+        };
+        if let Some(port) = compose_host_port(mapping) {
+            findings.push(ScanFinding {
+                name: service.clone(),
+                port,
+                file: PathBuf::from(file),
+                context: trimmed.to_string(),
+            });
+        }
+    }
+
+    findings
+}
+
+/// Parses the host side of a compose port mapping such as `"8080:80"` or
+/// `127.0.0.1:5432:5432`. Container-only ports (no colon) are ignored.
+fn compose_host_port(mapping: &str) -> Option<Port> {
+    let mapping = mapping.trim().trim_matches(|c| c == '"' || c == '\'');
+    let parts: Vec<&str> = mapping.split(':').collect();
+    match parts.as_slice() {
+        [host, _container] | [_, host, _container] => parse_port(host),
+        _ => None,
+    }
+}
+
+/// Extracts `PORT = N` variables and `--port N` flags from a Makefile.
+fn scan_makefile(content: &str) -> Vec<ScanFinding> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let trimmed = line.trim();
+            if trimmed.starts_with('#') {
+                return None;
+            }
+            let (name, port) = match make_variable_port(trimmed) {
+                Some(found) => found,
+                None => ("default".to_string(), command_port(trimmed)?),
+            };
+            Some(ScanFinding {
+                name,
+                port,
+                file: PathBuf::from("Makefile"),
+                context: trimmed.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Matches `WEB_PORT ?= 8080` style assignments, returning a name derived
+/// from the variable (`web`, or `default` for a bare `PORT`).
+fn make_variable_port(line: &str) -> Option<(String, Port)> {
+    let (var, value) = line.split_once('=')?;
+    let var = var.trim().trim_end_matches(['?', ':', '+']).trim();
+    if !var.ends_with("PORT") || var.contains(char::is_whitespace) {
+        return None;
+    }
+    let port = parse_port(value.trim())?;
+    let name = var
+        .trim_end_matches("PORT")
+        .trim_end_matches('_')
+        .to_lowercase();
+    let name = if name.is_empty() {
+        "default".to_string()
+    } else {
+        name
+    };
+    Some((name, port))
+}
+
+/// Finds a port in a shell command: `--port N`, `--port=N`, `-p N`, or a
+/// `PORT=N` environment assignment.
+fn command_port(command: &str) -> Option<Port> {
+    let mut tokens = command.split_whitespace();
+    while let Some(token) = tokens.next() {
+        if let Some(value) = token
+            .strip_prefix("--port=")
+            .or_else(|| token.strip_prefix("PORT="))
+        {
+            if let Some(port) = parse_port(value) {
+                return Some(port);
+            }
+        } else if token == "--port" || token == "-p" {
+            if let Some(port) = tokens.next().and_then(parse_port) {
+                return Some(port);
+            }
+        }
+    }
+    None
+}
+
+/// Parses a port number, ignoring surrounding quotes and trailing punctuation.
+fn parse_port(value: &str) -> Option<Port> {
+    let value = value.trim_matches(|c: char| c == '"' || c == '\'' || c == ';' || c == ',');
+    value.parse::<u16>().ok().and_then(|p| Port::new(p).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(n: u16) -> Port {
+        Port::new(n).unwrap()
+    }
+
+    #[test]
+    fn test_command_port() {
+        assert_eq!(command_port("vite --port 5174"), Some(port(5174)));
+        assert_eq!(command_port("next dev -p 3001"), Some(port(3001)));
+        assert_eq!(command_port("PORT=4000 node server.js"), Some(port(4000)));
+        assert_eq!(command_port("astro dev --port=4321"), Some(port(4321)));
+        assert_eq!(command_port("vite build"), None);
+    }
+
+    #[test]
+    fn test_scan_package_json() {
+        let content = r#"{
+            "scripts": {
+                "dev": "next dev -p 3001",
+                "storybook": "storybook dev --port 6006",
+                "build": "next build"
+            }
+        }"#;
+
+        let findings = scan_package_json(content);
+        let pairs: Vec<_> = findings.iter().map(|f| (f.name.as_str(), f.port)).collect();
+        assert_eq!(pairs, vec![("dev", port(3001)), ("storybook", port(6006))]);
+    }
+
+    #[test]
+    fn test_scan_vite_config() {
+        let content = "export default defineConfig({\n  server: {\n    port: 5180,\n  },\n})\n";
+        let findings = scan_vite_config(content, "vite.config.ts");
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].port, port(5180));
+    }
+
+    #[test]
+    fn test_scan_compose() {
+        let content = r#"
+services:
+  db:
+    image: postgres
+    ports:
+      - "5433:5432"
+  cache:
+    image: redis
+    ports:
+      - 127.0.0.1:6380:6379
+      - "9000"
+volumes:
+  data:
+"#;
+        let findings = scan_compose(content, "compose.yaml");
+        let pairs: Vec<_> = findings.iter().map(|f| (f.name.as_str(), f.port)).collect();
+        assert_eq!(pairs, vec![("db", port(5433)), ("cache", port(6380))]);
+    }
+
+    #[test]
+    fn test_scan_makefile() {
+        let content =
+            "API_PORT ?= 8081\nPORT := 3000\n\nrun:\n\tpython -m http.server --port 8090\n";
+        let findings = scan_makefile(content);
+        let pairs: Vec<_> = findings.iter().map(|f| (f.name.as_str(), f.port)).collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("api", port(8081)),
+                ("default", port(3000)),
+                ("default", port(8090))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_port_name_for() {
+        assert_eq!(port_name_for("dev:web"), "dev-web");
+        assert_eq!(port_name_for("@scope/app"), "scope-app");
+        assert_eq!(port_name_for("api_v2.1"), "api_v2-1");
+        assert_eq!(port_name_for("::"), "default");
+        assert!(port_name_for(&"x".repeat(100)).len() < MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn test_scan_directory_dedupes_ports() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"scripts": {"dev": "vite --port 5173"}}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("vite.config.ts"),
+            "server: {\n  port: 5173,\n}\n",
+        )
+        .unwrap();
+
+        let findings = scan_directory(dir.path()).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].name, "dev");
+    }
+
+    #[test]
+    fn test_scan_directory_numbers_repeated_names() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Makefile"),
+            "PORT = 3000
+serve:
+	python -m http.server -p 8090
+",
+        )
+        .unwrap();
+
+        let findings = scan_directory(dir.path()).unwrap();
+        let names: Vec<_> = findings.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["default", "default-2"]);
+    }
+}
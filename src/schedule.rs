@@ -0,0 +1,139 @@
+//! Parsing for scheduled frees (`pm free --at` / `--after`).
+
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+};
+
+/// Parses `--at`: "HH:MM" (the next occurrence, local time),
+/// "YYYY-MM-DD HH:MM" (local time), or an RFC 3339 timestamp.
+pub fn parse_at(s: &str) -> Result<DateTime<FixedOffset>, String> {
+    parse_at_from(s, Local::now())
+}
+
+fn parse_at_from(s: &str, now: DateTime<Local>) -> Result<DateTime<FixedOffset>, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(s) {
+        return Ok(at);
+    }
+    let naive = if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M") {
+        let today = now.date_naive().and_time(time);
+        if today > now.naive_local() {
+            today
+        } else {
+            today + Duration::days(1)
+        }
+    } else {
+        ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })
+            .ok_or_else(|| {
+                format!("'{s}' is not a time; expected HH:MM, YYYY-MM-DD HH:MM, or RFC 3339")
+            })?
+    };
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|at| at.fixed_offset())
+        .ok_or_else(|| format!("'{s}' does not exist in the local time zone"))
+}
+
+/// Parses `--after`: a duration such as "90m", "2h", "1h30m", or "3d",
+/// returning that long from now.
+pub fn parse_after(s: &str) -> Result<DateTime<FixedOffset>, String> {
+    after_from(s, Local::now().fixed_offset())
+}
+
+fn after_from(s: &str, now: DateTime<FixedOffset>) -> Result<DateTime<FixedOffset>, String> {
+    now.checked_add_signed(parse_duration(s)?)
+        .ok_or_else(|| too_long(s))
+}
+
+fn too_long(s: &str) -> String {
+    format!("'{s}' is too long a duration")
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("'{s}' is not a duration; expected e.g. 30m, 2h, 1h30m, or 3d");
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n: i64 = digits.parse().map_err(|_| invalid())?;
+        digits.clear();
+        let part = match c {
+            's' => Duration::try_seconds(n),
+            'm' => Duration::try_minutes(n),
+            'h' => Duration::try_hours(n),
+            'd' => Duration::try_days(n),
+            _ => return Err(invalid()),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(|| too_long(s))?;
+    }
+    if !digits.is_empty() || total.is_zero() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90m"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_duration("3d"), Ok(Duration::days(3)));
+        assert!(parse_duration("2").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("2w").is_err());
+        assert!(parse_duration("0h").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_overflow() {
+        let now = Local::now().fixed_offset();
+        assert!(parse_duration("99999999999999d").is_err());
+        assert!(parse_duration("99999999999999999999d").is_err());
+        assert!(after_from("999999999d", now)
+            .unwrap_err()
+            .contains("too long"));
+        assert!(after_from("3d", now).is_ok());
+    }
+
+    #[test]
+    fn test_parse_at() {
+        let now = Local.with_ymd_and_hms(2026, 3, 10, 12, 0, 0).unwrap();
+        let local = |y, m, d, h, min| {
+            Local
+                .with_ymd_and_hms(y, m, d, h, min, 0)
+                .unwrap()
+                .fixed_offset()
+        };
+
+        // A time of day is the next occurrence
+        assert_eq!(parse_at_from("18:00", now), Ok(local(2026, 3, 10, 18, 0)));
+        assert_eq!(parse_at_from("09:30", now), Ok(local(2026, 3, 11, 9, 30)));
+
+        assert_eq!(
+            parse_at_from("2026-04-01 08:15", now),
+            Ok(local(2026, 4, 1, 8, 15))
+        );
+        assert_eq!(
+            parse_at_from("2026-04-01T10:00:00Z", now)
+                .unwrap()
+                .timestamp(),
+            1_775_037_600
+        );
+        assert!(parse_at_from("6pm", now).is_err());
+    }
+}
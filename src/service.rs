@@ -0,0 +1,232 @@
+//! Service definitions for running `pm gc` periodically.
+//!
+//! Renders a launchd agent on macOS and a systemd user service plus timer on
+//! Linux. Both run the current `pm` binary with the registry environment
+//! (`PM_CONFIG_DIR`, `PM_CONFIG_PATH`, `pm --config`, or `pm --profile`) it
+//! was installed with, so the service cleans up the same registry the user
+//! works with.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{ConfigError, Error, Result};
+use crate::persistence::{registry_chosen_on_command_line, registry_path};
+
+/// launchd label, also the plist's file name.
+pub const LAUNCHD_LABEL: &str = "com.github.gorgeguy.port-manager.gc";
+
+/// systemd unit names; the timer starts the service.
+pub const SYSTEMD_SERVICE: &str = "pm-gc.service";
+pub const SYSTEMD_TIMER: &str = "pm-gc.timer";
+
+/// Environment variables carried into the service.
+const CARRIED_ENV: [&str; 3] = ["PM_CONFIG_DIR", "PM_CONFIG_PATH", "PM_SYSTEM_REGISTRY"];
+
+/// The service manager on this platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Manager {
+    Launchd,
+    Systemd,
+}
+
+impl Manager {
+    /// Returns this platform's service manager.
+    pub fn current() -> Result<Self> {
+        if cfg!(target_os = "macos") {
+            Ok(Manager::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Ok(Manager::Systemd)
+        } else {
+            Err(Error::Unsupported(
+                "pm install-service needs launchd (macOS) or systemd (Linux)",
+            ))
+        }
+    }
+}
+
+/// A file that makes up the service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// Returns the files to install for `manager`, running `exe gc` every
+/// `interval_minutes`.
+pub fn service_files(
+    manager: Manager,
+    exe: &Path,
+    interval_minutes: u32,
+) -> Result<Vec<ServiceFile>> {
+    let env = carried_env()?;
+    Ok(match manager {
+        Manager::Launchd => vec![ServiceFile {
+            path: launchd_dir()?.join(format!("{LAUNCHD_LABEL}.plist")),
+            content: render_launchd_plist(exe, interval_minutes, &env),
+        }],
+        Manager::Systemd => {
+            let dir = systemd_dir()?;
+            vec![
+                ServiceFile {
+                    path: dir.join(SYSTEMD_SERVICE),
+                    content: render_systemd_service(exe, &env),
+                },
+                ServiceFile {
+                    path: dir.join(SYSTEMD_TIMER),
+                    content: render_systemd_timer(interval_minutes),
+                },
+            ]
+        }
+    })
+}
+
+/// Returns the paths `service_files` installs to, for uninstalling.
+pub fn service_paths(manager: Manager) -> Result<Vec<PathBuf>> {
+    Ok(match manager {
+        Manager::Launchd => vec![launchd_dir()?.join(format!("{LAUNCHD_LABEL}.plist"))],
+        Manager::Systemd => {
+            let dir = systemd_dir()?;
+            vec![dir.join(SYSTEMD_SERVICE), dir.join(SYSTEMD_TIMER)]
+        }
+    })
+}
+
+fn launchd_dir() -> Result<PathBuf> {
+    dirs::home_dir()
+        .map(|home| home.join("Library").join("LaunchAgents"))
+        .ok_or_else(|| ConfigError::NoConfigDir.into())
+}
+
+fn systemd_dir() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|config| config.join("systemd").join("user"))
+        .ok_or_else(|| ConfigError::NoConfigDir.into())
+}
+
+fn carried_env() -> Result<Vec<(&'static str, String)>> {
+    let mut env: Vec<(&'static str, String)> = CARRIED_ENV
+        .iter()
+        .filter_map(|var| Some((*var, std::env::var(var).ok()?)))
+        .collect();
+    // --config and --profile win over both variables, so pass the registry
+    // they chose on in their place
+    if registry_chosen_on_command_line() {
+        env.retain(|(var, _)| !matches!(*var, "PM_CONFIG_DIR" | "PM_CONFIG_PATH"));
+        env.insert(
+            0,
+            ("PM_CONFIG_PATH", registry_path()?.display().to_string()),
+        );
+    }
+    Ok(env)
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Quotes a value for a systemd unit file (`%` starts a specifier there).
+fn systemd_quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    format!("\"{escaped}\"")
+}
+
+fn render_launchd_plist(exe: &Path, interval_minutes: u32, env: &[(&str, String)]) -> String {
+    let mut plist = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n",
+        "<dict>\n",
+    ));
+    plist.push_str(&format!(
+        "    <key>Label</key>\n    <string>{LAUNCHD_LABEL}</string>\n"
+    ));
+    plist.push_str(&format!(
+        "    <key>ProgramArguments</key>\n    <array>\n        <string>{}</string>\n        <string>gc</string>\n    </array>\n",
+        xml_escape(&exe.display().to_string())
+    ));
+    plist.push_str(&format!(
+        "    <key>StartInterval</key>\n    <integer>{}</integer>\n",
+        u64::from(interval_minutes) * 60
+    ));
+    plist.push_str("    <key>RunAtLoad</key>\n    <true/>\n");
+    if !env.is_empty() {
+        plist.push_str("    <key>EnvironmentVariables</key>\n    <dict>\n");
+        for (var, value) in env {
+            plist.push_str(&format!(
+                "        <key>{var}</key>\n        <string>{}</string>\n",
+                xml_escape(value)
+            ));
+        }
+        plist.push_str("    </dict>\n");
+    }
+    plist.push_str("</dict>\n</plist>\n");
+    plist
+}
+
+fn render_systemd_service(exe: &Path, env: &[(&str, String)]) -> String {
+    let mut unit = String::from(
+        "[Unit]\nDescription=Free expired port-manager allocations (pm gc)\n\n[Service]\nType=oneshot\n",
+    );
+    unit.push_str(&format!(
+        "ExecStart={} gc\n",
+        systemd_quote(&exe.display().to_string())
+    ));
+    for (var, value) in env {
+        unit.push_str(&format!(
+            "Environment={}\n",
+            systemd_quote(&format!("{var}={value}"))
+        ));
+    }
+    unit
+}
+
+fn render_systemd_timer(interval_minutes: u32) -> String {
+    format!(
+        "[Unit]\nDescription=Run pm gc every {interval_minutes} minutes\n\n\
+         [Timer]\nOnBootSec=5min\nOnUnitActiveSec={interval_minutes}min\n\n\
+         [Install]\nWantedBy=timers.target\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_launchd_plist() {
+        let plist = render_launchd_plist(
+            Path::new("/opt/pm & co/pm"),
+            15,
+            &[("PM_CONFIG_DIR", "/tmp/pm".to_string())],
+        );
+        assert!(plist.contains("<string>com.github.gorgeguy.port-manager.gc</string>"));
+        assert!(plist.contains("<string>/opt/pm &amp; co/pm</string>\n        <string>gc</string>"));
+        assert!(plist.contains("<key>StartInterval</key>\n    <integer>900</integer>"));
+        assert!(plist.contains("<key>PM_CONFIG_DIR</key>\n        <string>/tmp/pm</string>"));
+        assert!(plist.ends_with("</plist>\n"));
+
+        let plist = render_launchd_plist(Path::new("/usr/local/bin/pm"), 15, &[]);
+        assert!(!plist.contains("EnvironmentVariables"));
+    }
+
+    #[test]
+    fn test_render_systemd_units() {
+        let service = render_systemd_service(
+            Path::new("/home/me/.cargo/bin/pm"),
+            &[("PM_CONFIG_PATH", "/home/me/my \"pm\".toml".to_string())],
+        );
+        assert!(service.contains("Type=oneshot\n"));
+        assert!(service.contains("ExecStart=\"/home/me/.cargo/bin/pm\" gc\n"));
+        assert!(service.contains("Environment=\"PM_CONFIG_PATH=/home/me/my \\\"pm\\\".toml\"\n"));
+
+        let timer = render_systemd_timer(30);
+        assert!(timer.contains("OnUnitActiveSec=30min\n"));
+        assert!(timer.contains("WantedBy=timers.target\n"));
+    }
+}
@@ -0,0 +1,112 @@
+//! Point-in-time captures of ports for bug reports (`pm snapshot`).
+//!
+//! A snapshot is one JSON document holding the registry, the listeners
+//! detected at the time, the allocations they conflicted with, and when it
+//! was taken. `pm status --snapshot` reads one back and shows it as if it
+//! were live, so a capture from another machine can be looked at offline.
+
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::display::{
+    build_allocated_port_list, build_status_port_list, AllocatedPortInfo, ListFilter, PortStatus,
+    StatusPortInfo,
+};
+use crate::error::{ConfigError, Result};
+use crate::model::Registry;
+use crate::ports::ListeningPort;
+use crate::remote::status_listeners;
+
+/// Everything `pm snapshot` captures.
+#[derive(Debug, Serialize)]
+pub struct Snapshot<'a> {
+    pub captured_at: DateTime<FixedOffset>,
+    pub registry: &'a Registry,
+    /// Listeners as `pm status --json --full` shows them.
+    pub listeners: Vec<StatusPortInfo>,
+    /// Allocations whose port was held by another process.
+    pub conflicts: Vec<AllocatedPortInfo>,
+}
+
+/// The parts of a snapshot read back; conflicts are worked out again.
+#[derive(Deserialize)]
+struct SnapshotFile {
+    registry: Registry,
+    listeners: Value,
+}
+
+/// Captures `registry` and `listening` as of now.
+pub fn capture<'a>(registry: &'a Registry, listening: &[ListeningPort]) -> Snapshot<'a> {
+    let conflicts = build_allocated_port_list(registry, listening, &ListFilter::default())
+        .into_iter()
+        .filter(|info| info.status == PortStatus::Conflict)
+        .collect();
+    Snapshot {
+        captured_at: Local::now().fixed_offset(),
+        registry,
+        listeners: build_status_port_list(listening, registry, true),
+        conflicts,
+    }
+}
+
+/// Reads the registry and listeners from a snapshot file ("-" for stdin),
+/// with or without the `apiVersion` envelope.
+pub fn read_snapshot(path: &Path) -> Result<(Registry, Vec<ListeningPort>)> {
+    let value = read_json(path)?;
+    parse_snapshot(path, value)
+}
+
+/// Reads a snapshot as [`read_snapshot`] does, or a registry exported with
+/// `pm export registry`, which comes without listeners.
+pub fn read_snapshot_or_registry(path: &Path) -> Result<(Registry, Vec<ListeningPort>)> {
+    let value = read_json(path)?;
+    if value.get("registry").is_some() {
+        return parse_snapshot(path, value);
+    }
+    let registry =
+        serde_json::from_value(value).map_err(|source| ConfigError::JsonParseFailed {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    Ok((registry, Vec::new()))
+}
+
+/// Reads JSON from `path` ("-" for stdin), unwrapping an `apiVersion`
+/// envelope if there is one.
+fn read_json(path: &Path) -> Result<Value> {
+    let content = if path == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        std::fs::read_to_string(path).map_err(|source| ConfigError::ReadFailed {
+            path: path.to_path_buf(),
+            source,
+        })?
+    };
+    let mut value: Value =
+        serde_json::from_str(&content).map_err(|source| ConfigError::JsonParseFailed {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    if let Some(data) = value
+        .as_object_mut()
+        .filter(|object| object.contains_key("apiVersion"))
+        .and_then(|object| object.remove("data"))
+    {
+        value = data;
+    }
+    Ok(value)
+}
+
+fn parse_snapshot(path: &Path, value: Value) -> Result<(Registry, Vec<ListeningPort>)> {
+    let parse_failed = |source| ConfigError::JsonParseFailed {
+        path: path.to_path_buf(),
+        source,
+    };
+    let snapshot: SnapshotFile = serde_json::from_value(value).map_err(parse_failed)?;
+    let listening = status_listeners(snapshot.listeners).map_err(parse_failed)?;
+    Ok((snapshot.registry, listening))
+}
@@ -0,0 +1,183 @@
+//! Running helper programs (`netstat.exe`, `lsof`, ...) with a time limit.
+//!
+//! Some helpers hang: lsof on an unresponsive network filesystem, or
+//! Windows interop when the host is busy. Rather than block the whole
+//! command, a helper that runs longer than `PM_COMMAND_TIMEOUT` seconds is
+//! killed, and whatever it printed by then is used with a warning that the
+//! results may be incomplete.
+
+use std::env;
+use std::io::{self, Read};
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+/// Seconds a helper program may run before it is killed.
+pub const COMMAND_TIMEOUT: &str = "PM_COMMAND_TIMEOUT";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to keep reading after the program exits or is killed. Pipes
+/// can outlive it when it left children behind that inherited them.
+const DRAIN: Duration = Duration::from_millis(100);
+
+/// What a helper program printed, and whether it was cut short.
+#[derive(Debug)]
+pub struct Bounded {
+    pub output: Output,
+    /// True if the program was killed for running too long; `output` then
+    /// holds what it printed before that.
+    pub timed_out: bool,
+}
+
+/// Returns the time limit from `PM_COMMAND_TIMEOUT`, or the default.
+fn timeout() -> Duration {
+    match env::var(COMMAND_TIMEOUT) {
+        Ok(value) => match value.trim().parse::<f64>() {
+            Ok(secs) if secs > 0.0 && secs.is_finite() => Duration::from_secs_f64(secs),
+            _ => {
+                warn!("Ignoring {COMMAND_TIMEOUT}={value}: expected a number of seconds");
+                DEFAULT_TIMEOUT
+            }
+        },
+        Err(_) => DEFAULT_TIMEOUT,
+    }
+}
+
+/// Runs `command` like [`Command::output`], killing it after
+/// `PM_COMMAND_TIMEOUT` seconds and warning that its output may be
+/// incomplete.
+pub fn output(command: &mut Command) -> io::Result<Bounded> {
+    let limit = timeout();
+    let bounded = output_within(command, limit)?;
+    if bounded.timed_out {
+        warn!(
+            "{} didn't finish within {}s and was stopped; results may be incomplete \
+             (set {COMMAND_TIMEOUT} to allow longer)",
+            command.get_program().to_string_lossy(),
+            limit.as_secs_f64()
+        );
+    }
+    Ok(bounded)
+}
+
+/// Runs `command`, killing it if it is still running after `limit`.
+fn output_within(command: &mut Command, limit: Duration) -> io::Result<Bounded> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Read both pipes as the program writes, so a full pipe can't stall it
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let deadline = Instant::now() + limit;
+    let (status, timed_out) = loop {
+        if let Some(status) = child.try_wait()? {
+            break (status, false);
+        }
+        if Instant::now() >= deadline {
+            debug!("killing {:?} after {limit:?}", command.get_program());
+            // It may have exited since try_wait; wait() reaps it either way
+            let _ = child.kill();
+            break (child.wait()?, true);
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let until = deadline.max(Instant::now() + DRAIN);
+    Ok(Bounded {
+        output: Output {
+            status,
+            stdout: stdout.collect(until),
+            stderr: stderr.collect(until),
+        },
+        timed_out,
+    })
+}
+
+/// A pipe being read on another thread.
+struct Reader {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    handle: JoinHandle<()>,
+}
+
+impl Reader {
+    /// Returns what was read by the time the pipe closes or `until` passes,
+    /// whichever comes first. A reader still blocked then is left behind.
+    fn collect(self, until: Instant) -> Vec<u8> {
+        while !self.handle.is_finished() && Instant::now() < until {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut buffer = self.buffer.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::take(&mut *buffer)
+    }
+}
+
+/// Reads `pipe` to the end on another thread.
+fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> Reader {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let shared = Arc::clone(&buffer);
+    let handle = thread::spawn(move || {
+        let Some(mut pipe) = pipe else { return };
+        let mut chunk = [0; 8192];
+        loop {
+            match pipe.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => shared
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+    });
+    Reader { buffer, handle }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_within_finishes() {
+        let bounded =
+            output_within(Command::new("echo").arg("hello"), Duration::from_secs(5)).unwrap();
+        assert!(!bounded.timed_out);
+        assert!(bounded.output.status.success());
+        assert_eq!(bounded.output.stdout, b"hello\n");
+    }
+
+    #[test]
+    fn test_output_within_kills_and_keeps_partial_output() {
+        let started = Instant::now();
+        let bounded = output_within(
+            Command::new("sh").args(["-c", "echo partial; exec sleep 30"]),
+            Duration::from_millis(300),
+        )
+        .unwrap();
+        assert!(bounded.timed_out);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(bounded.output.stdout, b"partial\n");
+    }
+
+    #[test]
+    fn test_output_within_does_not_wait_for_inherited_pipes() {
+        // Without exec, the shell's sleep keeps stdout open after the kill
+        let started = Instant::now();
+        let bounded = output_within(
+            Command::new("sh").args(["-c", "echo partial; sleep 30"]),
+            Duration::from_millis(300),
+        )
+        .unwrap();
+        assert!(bounded.timed_out);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(bounded.output.stdout, b"partial\n");
+    }
+}
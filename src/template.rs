@@ -0,0 +1,28 @@
+//! Starter port sets for `pm init --template`.
+//!
+//! A template names the ports a kind of project usually needs. Each name
+//! matches a built-in range type, so the ports land in their usual ranges.
+
+use clap::ValueEnum;
+
+/// A set of port names allocated together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Template {
+    /// web, api, db, cache
+    Fullstack,
+    /// api, db
+    Backend,
+    /// web
+    Frontend,
+}
+
+impl Template {
+    /// Returns the port names to allocate.
+    pub fn ports(self) -> &'static [&'static str] {
+        match self {
+            Template::Fullstack => &["web", "api", "db", "cache"],
+            Template::Backend => &["api", "db"],
+            Template::Frontend => &["web"],
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Table styling from the registry's `[display]` section.
+//!
+//! Loading the registry picks up its settings, so every table printed
+//! afterwards uses them; until then tables are drawn with the defaults.
+
+use std::sync::Mutex;
+
+use comfy_table::{Cell, Color};
+
+use crate::model::{DisplayConfig, Theme};
+
+static CONFIG: Mutex<Option<DisplayConfig>> = Mutex::new(None);
+
+/// Uses `config` for the tables printed from now on.
+pub fn configure(config: DisplayConfig) {
+    *CONFIG.lock().unwrap_or_else(|e| e.into_inner()) = Some(config);
+}
+
+/// Returns the display settings in effect.
+pub fn current() -> DisplayConfig {
+    CONFIG
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .unwrap_or_default()
+}
+
+/// What a colored cell means, which each theme shows in its own color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    /// Active, suggested, added
+    Good,
+    /// Conflicts, removals, unexpected processes
+    Bad,
+    /// Changed entries
+    Changed,
+    /// Idle, skipped, or otherwise unimportant
+    Muted,
+}
+
+/// Returns the color for `tone` in `theme`, if it has one.
+fn color(theme: Theme, tone: Tone) -> Option<Color> {
+    match (theme, tone) {
+        (Theme::None, _) => None,
+        (_, Tone::Muted) => Some(Color::DarkGrey),
+        (Theme::Default, Tone::Good) => Some(Color::Green),
+        (Theme::Default, Tone::Bad) => Some(Color::Red),
+        (Theme::Default, Tone::Changed) => Some(Color::Yellow),
+        (Theme::Colorblind, Tone::Good) => Some(Color::Blue),
+        (Theme::Colorblind, Tone::Bad) => Some(Color::Yellow),
+        (Theme::Colorblind, Tone::Changed) => Some(Color::Magenta),
+    }
+}
+
+/// Colors `cell` for `tone` in the current theme.
+pub fn paint(cell: Cell, tone: Tone) -> Cell {
+    match color(current().theme, tone) {
+        Some(color) => cell.fg(color),
+        None => cell,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color() {
+        assert_eq!(color(Theme::Default, Tone::Bad), Some(Color::Red));
+        assert_eq!(color(Theme::Colorblind, Tone::Good), Some(Color::Blue));
+        assert_eq!(color(Theme::None, Tone::Good), None);
+        assert_eq!(color(Theme::None, Tone::Muted), None);
+    }
+}
@@ -0,0 +1,276 @@
+//! Moving whole registries between machines as JSON.
+//!
+//! `pm export registry` prints the registry as JSON and `pm import registry`
+//! reads it back, either replacing the registry or merging into it. A merge
+//! never overwrites anything: an entry that disagrees with the registry is a
+//! conflict, and any conflict leaves the registry untouched.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::model::Registry;
+use crate::registry::merge_dependencies;
+
+/// An imported entry that disagrees with the registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportConflict {
+    /// Dotted key path of the entry (e.g., "projects.webapp.web").
+    pub location: String,
+    pub message: String,
+}
+
+impl ImportConflict {
+    fn new(location: String, message: String) -> Self {
+        Self { location, message }
+    }
+}
+
+impl fmt::Display for ImportConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// What a merge added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    /// Ports and aliases added.
+    pub added: usize,
+    /// Ports and aliases already present with the same value.
+    pub unchanged: usize,
+}
+
+/// Parses an exported registry, with or without the `apiVersion` envelope.
+pub fn parse_registry_json(content: &str) -> serde_json::Result<Registry> {
+    let mut value: Value = serde_json::from_str(content)?;
+    if let Some(data) = value
+        .as_object_mut()
+        .filter(|object| object.contains_key("apiVersion"))
+        .and_then(|object| object.remove("data"))
+    {
+        value = data;
+    }
+    serde_json::from_value(value)
+}
+
+/// Returns imported ports that the system registry already reserves.
+pub fn system_conflicts(registry: &Registry, incoming: &Registry) -> Vec<ImportConflict> {
+    let Some(system) = registry.system_registry() else {
+        return Vec::new();
+    };
+    let mut conflicts = Vec::new();
+    for (project_name, project) in &incoming.projects {
+        for (name, allocation) in &project.ports {
+            // The system registry only reserves this machine's ports
+            if allocation.meta.host.is_some() {
+                continue;
+            }
+            if let Some((owner, owner_name)) = system.find_port_owner(allocation.port) {
+                conflicts.push(ImportConflict::new(
+                    format!("projects.{project_name}.{name}"),
+                    format!(
+                        "port {} is reserved for {owner}.{owner_name} by the system registry",
+                        allocation.port
+                    ),
+                ));
+            }
+        }
+    }
+    conflicts
+}
+
+/// Adds everything in `incoming` that `registry` doesn't have yet.
+///
+/// Entries already present with the same value are skipped. Ports, aliases,
+/// ranges, or links that differ, and ports owned by another entry, are
+/// conflicts; `registry` is only changed when there are none. Settings other
+/// than ranges are kept from `registry`.
+pub fn merge_registry(
+    registry: &mut Registry,
+    incoming: Registry,
+) -> std::result::Result<MergeSummary, Vec<ImportConflict>> {
+    let mut conflicts = system_conflicts(registry, &incoming);
+    let mut summary = MergeSummary::default();
+    let mut merged = registry.clone();
+
+    for (project_name, project) in &incoming.projects {
+        let existing = registry.projects.get(project_name);
+        for (name, allocation) in &project.ports {
+            let location = format!("projects.{project_name}.{name}");
+            match existing.and_then(|p| p.ports.get(name)) {
+                Some(current) if current.port == allocation.port => {
+                    summary.unchanged += 1;
+                    continue;
+                }
+                Some(current) => {
+                    conflicts.push(ImportConflict::new(
+                        location,
+                        format!(
+                            "is {} here but {} in the import",
+                            current.port, allocation.port
+                        ),
+                    ));
+                    continue;
+                }
+                None => {}
+            }
+            if let Some(target) = existing.and_then(|p| p.aliases.get(name)) {
+                conflicts.push(ImportConflict::new(
+                    location,
+                    format!("is an alias for '{target}' here"),
+                ));
+                continue;
+            }
+            if let Some((owner, owner_name)) =
+                registry.find_port_owner_on(allocation.meta.host.as_deref(), allocation.port)
+            {
+                conflicts.push(ImportConflict::new(
+                    location,
+                    format!(
+                        "port {} is already allocated to {owner}.{owner_name}",
+                        allocation.port
+                    ),
+                ));
+                continue;
+            }
+            merged
+                .projects
+                .entry(project_name.clone())
+                .or_default()
+                .ports
+                .insert(name.clone(), allocation.clone());
+            summary.added += 1;
+        }
+
+        for (alias, target) in &project.aliases {
+            let location = format!("projects.{project_name}.{alias}");
+            match existing.and_then(|p| p.aliases.get(alias)) {
+                Some(current) if current == target => summary.unchanged += 1,
+                Some(current) => conflicts.push(ImportConflict::new(
+                    location,
+                    format!("aliases '{current}' here but '{target}' in the import"),
+                )),
+                None if existing.is_some_and(|p| p.ports.contains_key(alias)) => {
+                    conflicts.push(ImportConflict::new(
+                        location,
+                        "is a port here but an alias in the import".to_string(),
+                    ))
+                }
+                None => {
+                    merged
+                        .projects
+                        .entry(project_name.clone())
+                        .or_default()
+                        .aliases
+                        .insert(alias.clone(), target.clone());
+                    summary.added += 1;
+                }
+            }
+        }
+    }
+
+    for (type_name, range) in &incoming.defaults.ranges {
+        match registry.defaults.ranges.get(type_name) {
+            Some(current) if current == range => {}
+            Some(current) => conflicts.push(ImportConflict::new(
+                format!("defaults.ranges.{type_name}"),
+                format!("is {current} here but {range} in the import"),
+            )),
+            None => {
+                merged
+                    .defaults
+                    .ranges
+                    .insert(type_name.clone(), range.clone());
+            }
+        }
+    }
+
+    for (path, project) in &incoming.links {
+        match registry.links.get(path) {
+            Some(current) if current == project => {}
+            Some(current) => conflicts.push(ImportConflict::new(
+                format!("links.{path}"),
+                format!("links to '{current}' here but '{project}' in the import"),
+            )),
+            None => {
+                merged.links.insert(path.clone(), project.clone());
+            }
+        }
+    }
+    merge_dependencies(&mut merged, &incoming);
+
+    if conflicts.is_empty() {
+        *registry = merged;
+        Ok(summary)
+    } else {
+        Err(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Allocation, PortMeta, Project};
+    use crate::name::{PortName, ProjectName};
+    use crate::port::Port;
+
+    fn registry_with(ports: &[(&str, &str, u16)]) -> Registry {
+        let mut registry = Registry::default();
+        for (project, name, port) in ports {
+            registry
+                .projects
+                .entry(ProjectName::new(*project).unwrap())
+                .or_insert_with(Project::default)
+                .ports
+                .insert(
+                    PortName::new(*name).unwrap(),
+                    Allocation::new(Port::new(*port).unwrap(), PortMeta::default()),
+                );
+        }
+        registry
+    }
+
+    #[test]
+    fn test_parse_registry_json_accepts_envelope() {
+        let bare = r#"{"projects": {"webapp": {"web": 8080}}}"#;
+        let wrapped = r#"{"apiVersion": "v1", "data": {"projects": {"webapp": {"web": 8080}}}}"#;
+        for content in [bare, wrapped] {
+            let registry = parse_registry_json(content).unwrap();
+            assert_eq!(registry.projects["webapp"].ports["web"].port.as_u16(), 8080);
+            assert_eq!(registry.defaults.ranges["web"].segments, [[8000, 8999]]);
+        }
+        assert!(parse_registry_json(r#"{"projects": {"webapp": {"web": 0}}}"#).is_err());
+    }
+
+    #[test]
+    fn test_merge_registry() {
+        let mut registry = registry_with(&[("webapp", "web", 8080)]);
+        let incoming = registry_with(&[("webapp", "web", 8080), ("api", "http", 3000)]);
+
+        let summary = merge_registry(&mut registry, incoming).unwrap();
+        assert_eq!(
+            summary,
+            MergeSummary {
+                added: 1,
+                unchanged: 1
+            }
+        );
+        assert_eq!(registry.projects["api"].ports["http"].port.as_u16(), 3000);
+    }
+
+    #[test]
+    fn test_merge_registry_conflicts_leave_registry_unchanged() {
+        let mut registry = registry_with(&[("webapp", "web", 8080), ("api", "http", 3000)]);
+        let incoming = registry_with(&[
+            ("webapp", "web", 8081),
+            ("other", "http", 3000),
+            ("new", "web", 4000),
+        ]);
+
+        let conflicts = merge_registry(&mut registry, incoming).unwrap_err();
+        let locations: Vec<&str> = conflicts.iter().map(|c| c.location.as_str()).collect();
+        assert_eq!(locations, ["projects.other.http", "projects.webapp.web"]);
+        assert!(!registry.projects.contains_key("new"));
+    }
+}
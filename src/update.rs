@@ -0,0 +1,193 @@
+//! Self-update from GitHub releases.
+//!
+//! Only built with the `self-update` feature, for standalone binary installs.
+//! Installs managed by Homebrew, cargo, or a system package manager are left
+//! to those tools.
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Result, UpdateError};
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/gorgeguy/port-manager/releases/latest";
+
+/// A GitHub release, as returned by the releases API.
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<ReleaseAsset>,
+}
+
+/// A downloadable file attached to a release.
+#[derive(Debug, Deserialize)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    /// Returns the version without a leading `v`.
+    pub fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    fn asset(&self, name: &str) -> Result<&ReleaseAsset> {
+        self.assets.iter().find(|a| a.name == name).ok_or_else(|| {
+            UpdateError::NoAsset {
+                version: self.tag_name.clone(),
+                asset: name.to_string(),
+            }
+            .into()
+        })
+    }
+}
+
+/// Returns the release asset name for this platform (e.g., `pm-aarch64-macos`).
+pub fn asset_name() -> String {
+    format!("pm-{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// Returns the upgrade command if `exe` is managed by a package manager.
+pub fn managed_by(exe: &Path) -> Option<&'static str> {
+    let path = exe.to_string_lossy();
+    if path.contains("/Cellar/") || path.contains("/homebrew/") || path.contains("/linuxbrew/") {
+        Some("brew upgrade port-manager")
+    } else if path.contains("/.cargo/bin/") {
+        Some("cargo install port-manager")
+    } else if path.starts_with("/usr/bin/") || path.starts_with("/nix/store/") {
+        Some("your system package manager")
+    } else {
+        None
+    }
+}
+
+/// Returns true if dotted version `latest` is newer than `current`.
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    fn parse(v: &str) -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    }
+    parse(latest) > parse(current)
+}
+
+/// Returns true if `checksum_file` (in `sha256sum` format) matches `bytes`.
+pub fn verify_checksum(bytes: &[u8], checksum_file: &str) -> bool {
+    let Some(expected) = checksum_file.split_whitespace().next() else {
+        return false;
+    };
+    let actual: String = Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    expected.eq_ignore_ascii_case(&actual)
+}
+
+fn get(url: &str) -> Result<ureq::Response> {
+    ureq::get(url)
+        .set("User-Agent", concat!("pm/", env!("CARGO_PKG_VERSION")))
+        .call()
+        .map_err(|e| UpdateError::Http(e.to_string()).into())
+}
+
+/// Fetches the latest published release.
+pub fn fetch_latest_release() -> Result<Release> {
+    get(LATEST_RELEASE_URL)?
+        .into_json()
+        .map_err(|e| UpdateError::Http(e.to_string()).into())
+}
+
+/// Downloads this platform's binary from `release` and verifies its checksum.
+pub fn download_verified(release: &Release) -> Result<Vec<u8>> {
+    let name = asset_name();
+    let binary = release.asset(&name)?;
+    let checksum = release.asset(&format!("{name}.sha256"))?;
+
+    let mut bytes = Vec::new();
+    get(&binary.browser_download_url)?
+        .into_reader()
+        .read_to_end(&mut bytes)?;
+    let checksum_file = get(&checksum.browser_download_url)?
+        .into_string()
+        .map_err(|e| UpdateError::Http(e.to_string()))?;
+
+    if !verify_checksum(&bytes, &checksum_file) {
+        return Err(UpdateError::ChecksumMismatch { asset: name }.into());
+    }
+    Ok(bytes)
+}
+
+/// Atomically replaces the executable at `exe` with `bytes`.
+///
+/// The new binary is written next to the old one and renamed over it, so an
+/// interrupted update leaves the existing binary intact.
+pub fn replace_executable(exe: &Path, bytes: &[u8]) -> Result<()> {
+    let staged = exe.with_extension("update");
+    fs::write(&staged, bytes)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    if let Err(e) = fs::rename(&staged, exe) {
+        let _ = fs::remove_file(&staged);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("v1.2.0", "1.1.0"));
+        assert!(is_newer("1.10.0", "1.9.3"));
+        assert!(!is_newer("v1.1.0", "1.1.0"));
+        assert!(!is_newer("1.0.9", "1.1.0"));
+    }
+
+    #[test]
+    fn test_managed_by() {
+        assert_eq!(
+            managed_by(Path::new("/opt/homebrew/Cellar/port-manager/1.1.0/bin/pm")),
+            Some("brew upgrade port-manager")
+        );
+        assert_eq!(
+            managed_by(Path::new("/Users/me/.cargo/bin/pm")),
+            Some("cargo install port-manager")
+        );
+        assert_eq!(managed_by(Path::new("/Users/me/bin/pm")), None);
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        // sha256("hello")
+        let sum =
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  pm-aarch64-macos\n";
+        assert!(verify_checksum(b"hello", sum));
+        assert!(!verify_checksum(b"tampered", sum));
+        assert!(!verify_checksum(b"hello", ""));
+    }
+
+    #[test]
+    fn test_replace_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("pm");
+        fs::write(&exe, b"old").unwrap();
+
+        replace_executable(&exe, b"new").unwrap();
+
+        assert_eq!(fs::read(&exe).unwrap(), b"new");
+        assert!(!exe.with_extension("update").exists());
+    }
+}
@@ -0,0 +1,312 @@
+//! Local allocation statistics (`pm usage`).
+//!
+//! Every registry change appends the allocations it made, freed, and moved
+//! to `.usage` beside the registry, one `seconds +|-|~ project name port`
+//! line each; nothing leaves the machine. `pm usage` reads the log back to
+//! show how much each project churns, how long its allocations last, and
+//! how many were held at once, which helps size ranges and pick a
+//! `stale_after_days`.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+use schemars::JsonSchema;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::error::Result;
+use crate::model::Registry;
+use crate::persistence::config_dir;
+use crate::port::Port;
+use crate::watch::registry_change;
+
+fn log_file() -> Result<PathBuf> {
+    Ok(config_dir()?.join(".usage"))
+}
+
+/// What happened to an allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Allocated,
+    Freed,
+    Moved,
+}
+
+/// One line of the usage log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Event {
+    at: u64,
+    action: Action,
+    project: String,
+    name: String,
+    port: Port,
+}
+
+/// Returns the log lines for a change from `before` to `after`, made at `at`.
+fn change_lines(before: &Registry, after: &Registry, at: u64) -> String {
+    let change = registry_change(before, after);
+    let mut lines = String::new();
+    for (sign, entry) in change
+        .added
+        .iter()
+        .map(|entry| ('+', entry))
+        .chain(change.removed.iter().map(|entry| ('-', entry)))
+    {
+        lines.push_str(&format!(
+            "{at} {sign} {} {} {}\n",
+            entry.project, entry.name, entry.port
+        ));
+    }
+    for entry in &change.changed {
+        lines.push_str(&format!(
+            "{at} ~ {} {} {}\n",
+            entry.project, entry.name, entry.new
+        ));
+    }
+    lines
+}
+
+fn append(lines: &str) -> io::Result<()> {
+    let path = log_file().map_err(io::Error::other)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(lines.as_bytes())
+}
+
+/// Records the change from `before` to `after` in the usage log.
+///
+/// The registry is already saved, so a failed write only loses statistics.
+pub fn record_change(before: &Registry, after: &Registry) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let lines = change_lines(before, after, now);
+    if lines.is_empty() {
+        return;
+    }
+    if let Err(e) = append(&lines) {
+        debug!("not recording usage: {e}");
+    }
+}
+
+/// Parses the usage log, skipping lines it can't read.
+fn parse_events(content: &str) -> Vec<Event> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [at, action, project, name, port] = fields[..] else {
+                return None;
+            };
+            let action = match action {
+                "+" => Action::Allocated,
+                "-" => Action::Freed,
+                "~" => Action::Moved,
+                _ => return None,
+            };
+            Some(Event {
+                at: at.parse().ok()?,
+                action,
+                project: project.to_string(),
+                name: name.to_string(),
+                port: port.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// One project's usage.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct ProjectUsage {
+    pub project: String,
+    pub allocated: usize,
+    pub freed: usize,
+    /// Allocations moved to another port.
+    pub moved: usize,
+    /// Average seconds between allocating and freeing a port, over the
+    /// allocations freed since the log began.
+    pub average_lifetime_secs: Option<u64>,
+    /// Most ports the project held at once.
+    pub peak: usize,
+}
+
+/// What `pm usage` reports.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct UsageReport {
+    /// When the log begins (RFC 3339); absent until something is recorded.
+    pub since: Option<String>,
+    pub allocated: usize,
+    pub freed: usize,
+    pub moved: usize,
+    pub average_lifetime_secs: Option<u64>,
+    /// Most ports allocated at once across all projects.
+    pub peak: usize,
+    /// When the peak was first reached (RFC 3339).
+    pub peak_at: Option<String>,
+    pub projects: Vec<ProjectUsage>,
+}
+
+fn rfc3339(at: u64) -> Option<String> {
+    let at = DateTime::from_timestamp(at as i64, 0)?;
+    Some(at.with_timezone(&Local).fixed_offset().to_rfc3339())
+}
+
+fn average(lifetimes: &[u64]) -> Option<u64> {
+    (!lifetimes.is_empty()).then(|| lifetimes.iter().sum::<u64>() / lifetimes.len() as u64)
+}
+
+/// Running totals while replaying the log.
+#[derive(Default)]
+struct Tally {
+    usage: ProjectUsage,
+    held: usize,
+    lifetimes: Vec<u64>,
+}
+
+/// Replays `events` into a report. Allocations made before the log began
+/// count toward peaks, worked out backwards from what `registry` holds now.
+fn build_report(registry: &Registry, events: &[Event]) -> UsageReport {
+    let mut tallies: BTreeMap<&str, Tally> = BTreeMap::new();
+    for event in events {
+        let tally = tallies.entry(&event.project).or_default();
+        match event.action {
+            Action::Allocated => tally.usage.allocated += 1,
+            Action::Freed => tally.usage.freed += 1,
+            Action::Moved => tally.usage.moved += 1,
+        }
+    }
+    let mut held_total = 0;
+    for (project, tally) in &mut tallies {
+        let now = registry.projects.get(*project).map_or(0, |p| p.ports.len());
+        tally.held = (now + tally.usage.freed).saturating_sub(tally.usage.allocated);
+        tally.usage.peak = tally.held;
+        held_total += tally.held;
+    }
+
+    let mut report = UsageReport {
+        since: events.first().and_then(|event| rfc3339(event.at)),
+        peak: held_total,
+        ..UsageReport::default()
+    };
+    let mut allocated_at: BTreeMap<(&str, &str), u64> = BTreeMap::new();
+    let mut lifetimes = Vec::new();
+    for event in events {
+        let tally = tallies
+            .get_mut(event.project.as_str())
+            .expect("tallied above");
+        let key = (event.project.as_str(), event.name.as_str());
+        match event.action {
+            Action::Allocated => {
+                allocated_at.insert(key, event.at);
+                tally.held += 1;
+                held_total += 1;
+            }
+            Action::Freed => {
+                if let Some(at) = allocated_at.remove(&key) {
+                    tally.lifetimes.push(event.at.saturating_sub(at));
+                    lifetimes.push(event.at.saturating_sub(at));
+                }
+                tally.held = tally.held.saturating_sub(1);
+                held_total = held_total.saturating_sub(1);
+            }
+            Action::Moved => {}
+        }
+        tally.usage.peak = tally.usage.peak.max(tally.held);
+        if held_total > report.peak {
+            report.peak = held_total;
+            report.peak_at = rfc3339(event.at);
+        }
+    }
+
+    for (project, tally) in tallies {
+        report.allocated += tally.usage.allocated;
+        report.freed += tally.usage.freed;
+        report.moved += tally.usage.moved;
+        report.projects.push(ProjectUsage {
+            project: project.to_string(),
+            average_lifetime_secs: average(&tally.lifetimes),
+            ..tally.usage
+        });
+    }
+    report.average_lifetime_secs = average(&lifetimes);
+    report
+}
+
+/// Reads the usage log and reports on it.
+pub fn usage_report(registry: &Registry) -> Result<UsageReport> {
+    let content = match fs::read_to_string(log_file()?) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(build_report(registry, &parse_events(&content)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Allocation, PortMeta, Project};
+    use crate::name::{PortName, ProjectName};
+
+    fn registry_with(ports: &[(&str, &str, u16)]) -> Registry {
+        let mut registry = Registry::default();
+        for &(project, name, port) in ports {
+            registry
+                .projects
+                .entry(ProjectName::new(project).unwrap())
+                .or_insert_with(Project::default)
+                .ports
+                .insert(
+                    PortName::new(name).unwrap(),
+                    Allocation::new(Port::new(port).unwrap(), PortMeta::default()),
+                );
+        }
+        registry
+    }
+
+    #[test]
+    fn test_change_lines() {
+        let before = registry_with(&[("webapp", "web", 8000), ("webapp", "api", 3000)]);
+        let after = registry_with(&[("webapp", "web", 8001), ("shop", "db", 5400)]);
+        assert_eq!(
+            change_lines(&before, &after, 100),
+            "100 + shop db 5400\n100 - webapp api 3000\n100 ~ webapp web 8001\n"
+        );
+        let events = parse_events(&change_lines(&before, &after, 100));
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].action, Action::Freed);
+        assert!(parse_events("garbage\n100 ? a b 1\n").is_empty());
+    }
+
+    #[test]
+    fn test_build_report() {
+        // webapp.web predates the log; webapp.api came and went twice
+        let registry = registry_with(&[("webapp", "web", 8000)]);
+        let events = parse_events(
+            "\
+100 + webapp api 3000
+160 - webapp api 3000
+200 + webapp api 3000
+300 ~ webapp web 8001
+400 - webapp api 3000
+",
+        );
+        let report = build_report(&registry, &events);
+        assert_eq!(report.allocated, 2);
+        assert_eq!(report.freed, 2);
+        assert_eq!(report.moved, 1);
+        assert_eq!(report.average_lifetime_secs, Some(130));
+        assert_eq!(report.peak, 2);
+        assert_eq!(report.peak_at, rfc3339(100));
+        assert_eq!(report.projects.len(), 1);
+        assert_eq!(report.projects[0].peak, 2);
+
+        assert_eq!(build_report(&registry, &[]), UsageReport::default());
+    }
+}
@@ -0,0 +1,628 @@
+//! Registry validation.
+//!
+//! Checks registry content for problems the typed parser either rejects with
+//! a single opaque error or cannot catch at all, such as one port assigned
+//! twice. Content is first checked as plain TOML values so every problem is
+//! reported at once, with the key path it was found at.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Serialize;
+use toml::{Table, Value};
+
+use crate::model::{hostname_problem, AllocationPolicy, Registry};
+use crate::name::name_problem;
+use crate::port::Port;
+
+/// A problem found in a registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Diagnostic {
+    /// Stable identifier for the kind of problem (e.g., "duplicate-port").
+    pub code: &'static str,
+    pub message: String,
+    /// Dotted key path of the offending entry (e.g., "projects.webapp.web").
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// 1-based line number, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+impl Diagnostic {
+    fn new(code: &'static str, message: String) -> Self {
+        Self {
+            code,
+            message,
+            location: None,
+            line: None,
+        }
+    }
+
+    fn at(mut self, location: String) -> Self {
+        self.location = Some(location);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.location, self.line) {
+            (_, Some(line)) => write!(f, "line {line}: ")?,
+            (Some(location), None) => write!(f, "{location}: ")?,
+            (None, None) => {}
+        }
+        write!(f, "{} [{}]", self.message, self.code)
+    }
+}
+
+/// Parses and validates registry TOML, returning the registry if it is valid.
+pub fn validate_content(content: &str) -> std::result::Result<Registry, Vec<Diagnostic>> {
+    let table: Table = toml::from_str(content).map_err(|e| vec![parse_diagnostic(&e, content)])?;
+
+    let diagnostics = validate_table(&table);
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    let registry: Registry = toml::from_str(content).map_err(|e| {
+        let mut diagnostic = parse_diagnostic(&e, content);
+        diagnostic.code = "schema-error";
+        vec![diagnostic]
+    })?;
+
+    let diagnostics = validate_registry(&registry);
+    if diagnostics.is_empty() {
+        Ok(registry)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Validates a registry read from somewhere other than TOML (such as
+/// `pm import registry`), with the same checks as [`validate_content`].
+pub fn validate_parsed(registry: &Registry) -> Vec<Diagnostic> {
+    let diagnostics = match Table::try_from(registry) {
+        Ok(table) => validate_table(&table),
+        Err(e) => vec![Diagnostic::new("schema-error", e.to_string())],
+    };
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+    validate_registry(registry)
+}
+
+/// Converts a TOML error into a diagnostic with its line number.
+fn parse_diagnostic(error: &toml::de::Error, content: &str) -> Diagnostic {
+    let mut diagnostic = Diagnostic::new("parse-error", error.message().trim_end().to_string());
+    diagnostic.line = error.span().map(|span| {
+        content[..span.start.min(content.len())]
+            .lines()
+            .count()
+            .max(1)
+    });
+    diagnostic
+}
+
+/// Checks names, port numbers, and range bounds on the untyped TOML tree.
+fn validate_table(table: &Table) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    if let Some(Value::Table(ranges)) = table
+        .get("defaults")
+        .and_then(|d| d.as_table())
+        .and_then(|d| d.get("ranges"))
+    {
+        for (name, value) in ranges {
+            let location = format!("defaults.ranges.{name}");
+            if let Some(problem) = name_problem(name) {
+                diagnostics.push(Diagnostic::new("invalid-name", problem).at(location.clone()));
+            }
+            check_range(value, location, &mut diagnostics);
+        }
+    }
+
+    if let Some(Value::Table(projects)) = table.get("projects") {
+        for (project, entries) in projects {
+            if let Some(problem) = name_problem(project) {
+                diagnostics.push(
+                    Diagnostic::new("invalid-name", problem).at(format!("projects.{project}")),
+                );
+            }
+            let Value::Table(entries) = entries else {
+                continue;
+            };
+            for (name, entry) in entries {
+                let location = format!("projects.{project}.{name}");
+                if let Some(problem) = name_problem(name) {
+                    diagnostics.push(Diagnostic::new("invalid-name", problem).at(location.clone()));
+                }
+                let port = match entry {
+                    Value::Table(t) => t.get("port"),
+                    other => Some(other),
+                };
+                if let Some(Value::Integer(n)) = port {
+                    check_port(*n, location, &mut diagnostics);
+                }
+            }
+        }
+    }
+
+    diagnostics
+}
+
+fn check_port(n: i64, location: String, diagnostics: &mut Vec<Diagnostic>) {
+    if !(1..=i64::from(u16::MAX)).contains(&n) {
+        diagnostics.push(
+            Diagnostic::new("invalid-port", format!("port {n} is outside 1-65535")).at(location),
+        );
+    }
+}
+
+fn check_range(value: &Value, location: String, diagnostics: &mut Vec<Diagnostic>) {
+    // Table form: { range = [start, end], policy = "...", exclude = [...],
+    // projects = [...] }
+    let mut exclude = None;
+    let value = match value {
+        Value::Table(table) => {
+            exclude = table.get("exclude");
+            if let Some(projects) = table.get("projects") {
+                let globs = projects
+                    .as_array()
+                    .is_some_and(|items| items.iter().all(Value::is_str));
+                if !globs {
+                    diagnostics.push(
+                        Diagnostic::new(
+                            "invalid-projects",
+                            "projects must be a list of project names or globs \
+                             (e.g., [\"team-a-*\"])"
+                                .to_string(),
+                        )
+                        .at(format!("{location}.projects")),
+                    );
+                }
+            }
+            if let Some(policy) = table.get("policy") {
+                let known = policy
+                    .as_str()
+                    .is_some_and(|p| p.parse::<AllocationPolicy>().is_ok());
+                if !known {
+                    diagnostics.push(
+                        Diagnostic::new(
+                            "invalid-policy",
+                            format!(
+                                "policy must be lowest-free, sequential, or random (found {policy})"
+                            ),
+                        )
+                        .at(location.clone()),
+                    );
+                }
+            }
+            table.get("range").unwrap_or(value)
+        }
+        other => other,
+    };
+    let Some(segments) = range_segments(value) else {
+        diagnostics.push(
+            Diagnostic::new(
+                "invalid-range",
+                "range must be [start, end] (e.g., [8000, 8999]), a list of them, or \
+                 { range = [start, end], ... }"
+                    .to_string(),
+            )
+            .at(location),
+        );
+        return;
+    };
+
+    let before = diagnostics.len();
+    for &(start, end) in &segments {
+        let before = diagnostics.len();
+        check_port(start, location.clone(), diagnostics);
+        check_port(end, location.clone(), diagnostics);
+        if diagnostics.len() == before && start >= end {
+            diagnostics.push(
+                Diagnostic::new(
+                    "inverted-range",
+                    format!("range start ({start}) must be less than end ({end})"),
+                )
+                .at(location.clone()),
+            );
+        }
+    }
+    if diagnostics.len() > before {
+        return;
+    }
+    for (i, (start_a, end_a)) in segments.iter().enumerate() {
+        for (start_b, end_b) in &segments[i + 1..] {
+            if start_a <= end_b && start_b <= end_a {
+                diagnostics.push(
+                    Diagnostic::new(
+                        "overlapping-segments",
+                        format!("segments {start_a}-{end_a} and {start_b}-{end_b} overlap"),
+                    )
+                    .at(location.clone()),
+                );
+            }
+        }
+    }
+    if let Some(exclude) = exclude {
+        check_exclude(
+            exclude,
+            &segments,
+            format!("{location}.exclude"),
+            diagnostics,
+        );
+    }
+}
+
+/// Reads a range's segments: `[start, end]` or a non-empty list of them.
+fn range_segments(value: &Value) -> Option<Vec<(i64, i64)>> {
+    let segment = |value: &Value| match value.as_array()?.as_slice() {
+        [Value::Integer(start), Value::Integer(end)] => Some((*start, *end)),
+        _ => None,
+    };
+    match value.as_array()?.as_slice() {
+        [] => None,
+        [Value::Integer(_), Value::Integer(_)] => segment(value).map(|s| vec![s]),
+        items => items.iter().map(segment).collect(),
+    }
+}
+
+fn check_exclude(
+    value: &Value,
+    segments: &[(i64, i64)],
+    location: String,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let ports = value.as_array().and_then(|items| {
+        items
+            .iter()
+            .map(Value::as_integer)
+            .collect::<Option<Vec<i64>>>()
+    });
+    let Some(ports) = ports else {
+        diagnostics.push(
+            Diagnostic::new(
+                "invalid-exclude",
+                "exclude must be a list of ports (e.g., [8080, 8443])".to_string(),
+            )
+            .at(location),
+        );
+        return;
+    };
+    let range: Vec<String> = segments
+        .iter()
+        .map(|(start, end)| format!("{start}-{end}"))
+        .collect();
+    for port in ports {
+        let before = diagnostics.len();
+        check_port(port, location.clone(), diagnostics);
+        let inside = segments
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&port));
+        if diagnostics.len() == before && !inside {
+            diagnostics.push(
+                Diagnostic::new(
+                    "exclude-outside-range",
+                    format!(
+                        "excluded port {port} is outside the range {}",
+                        range.join(",")
+                    ),
+                )
+                .at(location.clone()),
+            );
+        }
+    }
+}
+
+/// Checks a parsed registry for duplicate ports and overlapping ranges.
+pub fn validate_registry(registry: &Registry) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    // Ports only collide on the same host
+    let mut owners: HashMap<(Option<&str>, Port), String> = HashMap::new();
+    for (project_name, project) in &registry.projects {
+        for (name, allocation) in &project.ports {
+            let owner = format!("{project_name}.{name}");
+            let key = (allocation.meta.host.as_deref(), allocation.port);
+            if let Some(problem) = allocation
+                .meta
+                .hostname
+                .as_deref()
+                .and_then(hostname_problem)
+            {
+                diagnostics.push(
+                    Diagnostic::new("invalid-hostname", problem).at(format!("projects.{owner}")),
+                );
+            }
+            if let Some(first) = owners.get(&key) {
+                diagnostics.push(
+                    Diagnostic::new(
+                        "duplicate-port",
+                        format!(
+                            "port {} is assigned to both {first} and {owner}",
+                            allocation.port
+                        ),
+                    )
+                    .at(format!("projects.{owner}")),
+                );
+            } else {
+                owners.insert(key, owner);
+            }
+        }
+    }
+
+    if registry.defaults.ignore_case {
+        let projects: Vec<&str> = registry.projects.keys().map(|p| p.as_str()).collect();
+        check_case_conflicts(&projects, "projects", &mut diagnostics);
+        for (project_name, project) in &registry.projects {
+            let names: Vec<&str> = project
+                .ports
+                .keys()
+                .chain(project.aliases.keys())
+                .map(|n| n.as_str())
+                .collect();
+            check_case_conflicts(
+                &names,
+                &format!("projects.{project_name}"),
+                &mut diagnostics,
+            );
+        }
+    }
+
+    let segments: Vec<_> = registry
+        .defaults
+        .ranges
+        .iter()
+        .flat_map(|(name, range)| range.segments.iter().map(move |segment| (name, segment)))
+        .collect();
+    for (i, (name_a, [start_a, end_a])) in segments.iter().enumerate() {
+        for (name_b, [start_b, end_b]) in &segments[i + 1..] {
+            if name_a != name_b && start_a <= end_b && start_b <= end_a {
+                diagnostics.push(
+                    Diagnostic::new(
+                        "overlapping-ranges",
+                        format!(
+                            "range '{name_a}' ({start_a}-{end_a}) overlaps '{name_b}' ({start_b}-{end_b})"
+                        ),
+                    )
+                    .at(format!("defaults.ranges.{name_b}")),
+                );
+            }
+        }
+    }
+
+    for (i, rule) in registry.defaults.type_rules.iter().enumerate() {
+        let location = format!("defaults.type_rules.{}", i + 1);
+        let problem = match (&rule.name, &rule.regex) {
+            (Some(_), Some(_)) | (None, None) => {
+                Some("a type rule needs either `name` or `regex`, not both".to_string())
+            }
+            (None, Some(pattern)) => Regex::new(pattern)
+                .err()
+                .map(|e| format!("invalid regex '{pattern}': {e}")),
+            (Some(_), None) => None,
+        };
+        if let Some(problem) = problem {
+            diagnostics.push(Diagnostic::new("invalid-type-rule", problem).at(location.clone()));
+        }
+        if let Some(problem) = name_problem(&rule.type_name) {
+            diagnostics.push(Diagnostic::new("invalid-name", problem).at(location));
+        }
+    }
+
+    diagnostics
+}
+
+/// Reports names that differ only by case, which are ambiguous when the
+/// registry matches names ignoring case.
+fn check_case_conflicts(names: &[&str], parent: &str, diagnostics: &mut Vec<Diagnostic>) {
+    for (i, a) in names.iter().enumerate() {
+        for b in &names[i + 1..] {
+            if a.eq_ignore_ascii_case(b) {
+                diagnostics.push(
+                    Diagnostic::new(
+                        "case-conflict",
+                        format!("'{a}' and '{b}' differ only by case"),
+                    )
+                    .at(format!("{parent}.{b}")),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(content: &str) -> Vec<&'static str> {
+        validate_content(content)
+            .unwrap_err()
+            .iter()
+            .map(|d| d.code)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_registry() {
+        let content = r#"
+[defaults.ranges]
+web = [8000, 8999]
+api = [3000, 3999]
+
+[projects.webapp]
+web = 8080
+api = { port = 3000, group = "g" }
+frontend = { alias = "web" }
+"#;
+        assert!(validate_content(content).is_ok());
+    }
+
+    #[test]
+    fn test_parse_error_has_line() {
+        let diagnostics = validate_content("[projects.webapp]\nweb = = 8080\n").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "parse-error");
+        assert_eq!(diagnostics[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_invalid_ports_reported_together() {
+        let content = "[projects.webapp]\nweb = 70000\napi = { port = 0 }\n";
+        let diagnostics = validate_content(content).unwrap_err();
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|d| d.code == "invalid-port"));
+        assert_eq!(
+            diagnostics[0].location.as_deref(),
+            Some("projects.webapp.api")
+        );
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let content = "[projects.\"web app\"]\n\"x=y\" = 8080\n";
+        assert_eq!(codes(content), vec!["invalid-name", "invalid-name"]);
+
+        // The same names in a registry that never was TOML
+        let registry: Registry =
+            serde_json::from_str(r#"{"projects": {"web app": {"x=y": 8080}}}"#).unwrap();
+        let codes: Vec<_> = validate_parsed(&registry).iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["invalid-name", "invalid-name"]);
+    }
+
+    #[test]
+    fn test_inverted_range() {
+        assert_eq!(
+            codes("[defaults.ranges]\nweb = [8999, 8000]\n"),
+            vec!["inverted-range"]
+        );
+        assert_eq!(
+            codes("[defaults.ranges]\nweb = [8000]\n"),
+            vec!["invalid-range"]
+        );
+    }
+
+    #[test]
+    fn test_range_segments() {
+        let content = "[defaults.ranges]\napi = [[3000, 3099], [3300, 3399]]\nweb = [3100, 3299]\n";
+        assert!(validate_content(content).is_ok());
+
+        assert_eq!(
+            codes("[defaults.ranges]\napi = [[3000, 3099], [3050, 3199]]\n"),
+            vec!["overlapping-segments"]
+        );
+        assert_eq!(
+            codes("[defaults.ranges]\napi = [[3000, 3099], [3399, 3300]]\n"),
+            vec!["inverted-range"]
+        );
+        assert_eq!(
+            codes("[defaults.ranges]\napi = [[3000, 3099], 3300]\n"),
+            vec!["invalid-range"]
+        );
+
+        let content = "[defaults.ranges]\napi = [[3000, 3099], [3300, 3399]]\nweb = [3350, 3360]\n";
+        assert_eq!(codes(content), vec!["overlapping-ranges"]);
+    }
+
+    #[test]
+    fn test_range_projects() {
+        let content =
+            "[defaults.ranges]\ninfra = { range = [9500, 9599], projects = [\"infra-*\"] }\n";
+        assert!(validate_content(content).is_ok());
+        assert_eq!(
+            codes("[defaults.ranges]\ninfra = { range = [9500, 9599], projects = \"infra-*\" }\n"),
+            vec!["invalid-projects"]
+        );
+    }
+
+    #[test]
+    fn test_range_exclusions() {
+        let content = "[defaults.ranges]\nweb = { range = [8000, 8999], exclude = [8080, 8443] }\n";
+        assert!(validate_content(content).is_ok());
+        assert_eq!(
+            codes("[defaults.ranges]\nweb = { range = [8000, 8999], exclude = [9000, 0] }\n"),
+            vec!["exclude-outside-range", "invalid-port"]
+        );
+        assert_eq!(
+            codes("[defaults.ranges]\nweb = { range = [8000, 8999], exclude = 8080 }\n"),
+            vec!["invalid-exclude"]
+        );
+    }
+
+    #[test]
+    fn test_schema_error() {
+        let content = "[projects.webapp]\nweb = { port = 8080, alias = \"api\" }\n";
+        assert_eq!(codes(content), vec!["schema-error"]);
+    }
+
+    #[test]
+    fn test_duplicate_port() {
+        let content = "[projects.a]\nweb = 8080\n\n[projects.b]\nweb = 8080\n";
+        let diagnostics = validate_content(content).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "duplicate-port");
+        assert!(diagnostics[0].message.contains("a.web and b.web"));
+    }
+
+    #[test]
+    fn test_overlapping_ranges() {
+        let content = "[defaults.ranges]\nweb = [8000, 8999]\nalt = [8500, 9100]\n";
+        assert_eq!(codes(content), vec!["overlapping-ranges"]);
+    }
+
+    #[test]
+    fn test_type_rules() {
+        let content = r#"
+[[defaults.type_rules]]
+name = "frontend*"
+type = "web"
+
+[[defaults.type_rules]]
+regex = "^worker-[0-9]+$"
+type = "api"
+"#;
+        assert!(validate_content(content).is_ok());
+
+        let content = r#"
+[[defaults.type_rules]]
+regex = "(unclosed"
+type = "web"
+
+[[defaults.type_rules]]
+type = "web"
+"#;
+        assert_eq!(
+            codes(content),
+            vec!["invalid-type-rule", "invalid-type-rule"]
+        );
+    }
+
+    #[test]
+    fn test_case_conflicts_need_ignore_case() {
+        let content =
+            "[projects.webapp]\nweb = 8080\n\n[projects.WebApp]\nWeb = 8081\nweb = 8082\n";
+        assert!(validate_content(content).is_ok());
+
+        let content = format!("[defaults]\nignore_case = true\n\n{content}");
+        assert_eq!(codes(&content), vec!["case-conflict", "case-conflict"]);
+    }
+
+    #[test]
+    fn test_default_ranges_do_not_overlap() {
+        assert!(validate_registry(&Registry::default()).is_empty());
+    }
+
+    #[test]
+    fn test_name_problem() {
+        assert_eq!(name_problem("web-app_2"), None);
+        assert!(name_problem("web.v1").is_some());
+        assert!(name_problem("").is_some());
+        assert!(name_problem("a b").is_some());
+        assert!(name_problem("a/b").is_some());
+        assert!(name_problem(&"x".repeat(crate::name::MAX_NAME_LEN + 1)).is_some());
+    }
+}
@@ -0,0 +1,176 @@
+//! Registry change notifications.
+//!
+//! Watches the directory containing the registry (every save replaces the
+//! file by rename, so a watch on the file itself would be lost after the
+//! first write) and reports which allocations each change added, removed,
+//! or moved.
+
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+use schemars::JsonSchema;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::display::ProjectQueryResult;
+use crate::error::Result;
+use crate::model::Registry;
+use crate::persistence::{load_registry, registry_path};
+use crate::port::Port;
+use crate::registry::query_all_ports;
+
+/// How long to wait for a burst of filesystem events (temp file, rename,
+/// journal removal) to settle before reloading.
+const SETTLE: Duration = Duration::from_millis(50);
+
+/// Allocations added, removed, or moved to another port by a registry change.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct RegistryChange {
+    pub added: Vec<ProjectQueryResult>,
+    pub removed: Vec<ProjectQueryResult>,
+    pub changed: Vec<PortChange>,
+}
+
+/// An allocation whose port changed.
+#[derive(Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct PortChange {
+    pub project: String,
+    pub name: String,
+    pub old: Port,
+    pub new: Port,
+}
+
+impl RegistryChange {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+type Allocations = BTreeMap<(String, String), Port>;
+
+fn allocations(registry: &Registry) -> Allocations {
+    query_all_ports(registry)
+        .into_iter()
+        .map(|(project, name, port)| ((project, name), port))
+        .collect()
+}
+
+/// Returns the allocations added, removed, or moved between two registries.
+pub fn registry_change(before: &Registry, after: &Registry) -> RegistryChange {
+    diff(&allocations(before), &allocations(after))
+}
+
+/// Compares two allocation snapshots.
+fn diff(before: &Allocations, after: &Allocations) -> RegistryChange {
+    let mut change = RegistryChange::default();
+    for ((project, name), &port) in after {
+        match before.get(&(project.clone(), name.clone())) {
+            None => change.added.push(ProjectQueryResult {
+                project: project.clone(),
+                name: name.clone(),
+                port,
+            }),
+            Some(&old) if old != port => change.changed.push(PortChange {
+                project: project.clone(),
+                name: name.clone(),
+                old,
+                new: port,
+            }),
+            Some(_) => {}
+        }
+    }
+    for ((project, name), &port) in before {
+        if !after.contains_key(&(project.clone(), name.clone())) {
+            change.removed.push(ProjectQueryResult {
+                project: project.clone(),
+                name: name.clone(),
+                port,
+            });
+        }
+    }
+    change
+}
+
+/// Blocks, calling `on_change` each time a registry write changes allocations.
+///
+/// Writes that leave allocations as they were (e.g., a range edit) and
+/// registries that fail to load mid-edit are skipped.
+pub fn watch_registry(mut on_change: impl FnMut(&RegistryChange)) -> Result<()> {
+    let path = registry_path()?;
+    let file_name = path.file_name().map(|n| n.to_os_string());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => std::env::current_dir()?,
+    };
+
+    let mut current = allocations(&load_registry()?);
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    debug!(dir = %dir.display(), "watching registry");
+
+    for event in &rx {
+        let event = event?;
+        if !event
+            .paths
+            .iter()
+            .any(|p| p.file_name() == file_name.as_deref())
+        {
+            continue;
+        }
+        while rx.recv_timeout(SETTLE).is_ok() {}
+
+        let registry = match load_registry() {
+            Ok(registry) => registry,
+            Err(e) => {
+                warn!("Skipping registry change: {e}");
+                continue;
+            }
+        };
+        let next = allocations(&registry);
+        let change = diff(&current, &next);
+        current = next;
+        if !change.is_empty() {
+            on_change(&change);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(entries: &[(&str, &str, u16)]) -> Allocations {
+        entries
+            .iter()
+            .map(|(p, n, port)| ((p.to_string(), n.to_string()), Port::new(*port).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = snapshot(&[("webapp", "web", 8080), ("webapp", "api", 3000)]);
+        let after = snapshot(&[("webapp", "web", 8081), ("backend", "api", 3001)]);
+
+        let change = diff(&before, &after);
+        assert_eq!(change.added.len(), 1);
+        assert_eq!(change.added[0].project, "backend");
+        assert_eq!(change.removed.len(), 1);
+        assert_eq!(change.removed[0].name, "api");
+        assert_eq!(
+            change.changed,
+            vec![PortChange {
+                project: "webapp".to_string(),
+                name: "web".to_string(),
+                old: Port::new(8080).unwrap(),
+                new: Port::new(8081).unwrap(),
+            }]
+        );
+
+        assert!(diff(&after, &after).is_empty());
+    }
+}
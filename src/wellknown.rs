@@ -0,0 +1,104 @@
+//! Well-known port numbers, for naming listeners nobody allocated.
+//!
+//! A curated subset of the IANA registry plus common development defaults.
+//! `pm status` uses it to label unassigned listeners whose process name says
+//! little (e.g., `com.docker.backend` publishing 5432 for postgres).
+
+use crate::port::Port;
+
+/// (port, service), sorted by port.
+const WELL_KNOWN: &[(u16, &str)] = &[
+    (21, "ftp"),
+    (22, "ssh"),
+    (25, "smtp"),
+    (53, "dns"),
+    (80, "http"),
+    (88, "kerberos"),
+    (110, "pop3"),
+    (111, "rpcbind"),
+    (143, "imap"),
+    (389, "ldap"),
+    (443, "https"),
+    (445, "smb"),
+    (465, "smtps"),
+    (548, "afp"),
+    (587, "submission"),
+    (631, "ipp"),
+    (636, "ldaps"),
+    (993, "imaps"),
+    (995, "pop3s"),
+    (1080, "socks"),
+    (1433, "mssql"),
+    (1521, "oracle"),
+    (1883, "mqtt"),
+    (2181, "zookeeper"),
+    (2375, "docker"),
+    (2376, "docker-tls"),
+    (2379, "etcd"),
+    (2380, "etcd-peer"),
+    (3000, "dev-server"),
+    (3306, "mysql"),
+    (3389, "rdp"),
+    (4222, "nats"),
+    (4317, "otlp-grpc"),
+    (4318, "otlp-http"),
+    (4369, "epmd"),
+    (4566, "localstack"),
+    (5000, "flask"),
+    (5005, "jvm-debug"),
+    (5173, "vite"),
+    (5353, "mdns"),
+    (5432, "postgres"),
+    (5601, "kibana"),
+    (5672, "rabbitmq"),
+    (5900, "vnc"),
+    (5984, "couchdb"),
+    (6006, "storybook"),
+    (6379, "redis"),
+    (6443, "kubernetes"),
+    (7000, "airplay"),
+    (7687, "neo4j"),
+    (8025, "mailhog"),
+    (8080, "http-alt"),
+    (8086, "influxdb"),
+    (8123, "clickhouse"),
+    (8200, "vault"),
+    (8443, "https-alt"),
+    (8500, "consul"),
+    (8888, "jupyter"),
+    (9000, "minio"),
+    (9042, "cassandra"),
+    (9090, "prometheus"),
+    (9092, "kafka"),
+    (9200, "elasticsearch"),
+    (9229, "node-debug"),
+    (9300, "elasticsearch-transport"),
+    (9411, "zipkin"),
+    (9418, "git"),
+    (11211, "memcached"),
+    (15672, "rabbitmq-admin"),
+    (16686, "jaeger"),
+    (27017, "mongo"),
+    (50051, "grpc"),
+];
+
+/// Returns the service conventionally listening on `port`, if any.
+pub fn service_name(port: Port) -> Option<&'static str> {
+    WELL_KNOWN
+        .binary_search_by_key(&port.as_u16(), |(p, _)| *p)
+        .ok()
+        .map(|i| WELL_KNOWN[i].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_name() {
+        assert!(WELL_KNOWN.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(service_name(Port::new(5432).unwrap()), Some("postgres"));
+        assert_eq!(service_name(Port::new(6379).unwrap()), Some("redis"));
+        assert_eq!(service_name(Port::new(5433).unwrap()), None);
+    }
+}
@@ -0,0 +1,274 @@
+//! Directory-to-project resolution.
+//!
+//! Maps a working directory to a registry project using linked directories
+//! (from the registry or a repository's `.pm.toml`), falling back to the name
+//! of the enclosing git repository or directory.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ConfigError, RegistryError, Result};
+use crate::model::{Registry, RepoConfig};
+use crate::registry::find_name;
+
+/// Name of the repository-local settings file at the git root.
+pub const REPO_CONFIG_FILE: &str = ".pm.toml";
+
+/// Project argument that refers to the current directory's linked project.
+pub const CURRENT_PROJECT: &str = ".";
+
+/// Finds the root of the git repository containing `dir`, if any.
+///
+/// Looks for a `.git` entry (directory, or file for worktrees) in `dir`
+/// and each of its ancestors.
+pub fn find_git_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Loads the `.pm.toml` at a repository root, if present.
+pub fn load_repo_config(root: &Path) -> Result<Option<RepoConfig>> {
+    let path = root.join(REPO_CONFIG_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&path).map_err(|source| ConfigError::ReadFailed {
+        path: path.clone(),
+        source,
+    })?;
+    let config =
+        toml::from_str(&content).map_err(|source| ConfigError::ParseFailed { path, source })?;
+    Ok(Some(config))
+}
+
+/// Writes the `.pm.toml` at a repository root.
+pub fn save_repo_config(root: &Path, config: &RepoConfig) -> Result<()> {
+    let path = root.join(REPO_CONFIG_FILE);
+    let content = toml::to_string_pretty(config).map_err(ConfigError::SerializeFailed)?;
+    fs::write(&path, content).map_err(|source| ConfigError::WriteFailed { path, source })?;
+    Ok(())
+}
+
+/// Converts a `.pm.toml` link key into an absolute directory under `root`.
+fn repo_link_dir(root: &Path, subpath: &str) -> PathBuf {
+    // components() drops "." segments, so "." maps to the root itself
+    root.join(subpath).components().collect()
+}
+
+/// Converts a directory under `root` into a `.pm.toml` link key.
+pub fn repo_link_key(root: &Path, dir: &Path) -> Option<String> {
+    let relative = dir.strip_prefix(root).ok()?;
+    if relative.as_os_str().is_empty() {
+        Some(CURRENT_PROJECT.to_string())
+    } else {
+        Some(relative.to_string_lossy().into_owned())
+    }
+}
+
+/// Infers the project for a directory.
+///
+/// Resolution order:
+/// 1. The nearest linked directory at or above `dir`, from either the
+///    registry or the repository's `.pm.toml` (registry wins on ties)
+/// 2. The git repository root's name, if a project with that name exists
+/// 3. The directory's own name, if a project with that name exists
+pub fn infer_project(registry: &Registry, dir: &Path) -> Result<Option<String>> {
+    let git_root = find_git_root(dir);
+
+    let mut links: HashMap<PathBuf, &str> = HashMap::new();
+    let repo_config = match &git_root {
+        Some(root) => load_repo_config(root)?,
+        None => None,
+    };
+    if let (Some(root), Some(config)) = (&git_root, &repo_config) {
+        for (subpath, project) in &config.links {
+            links.insert(repo_link_dir(root, subpath), project);
+        }
+    }
+    for (path, project) in &registry.links {
+        links.insert(PathBuf::from(path), project);
+    }
+
+    if let Some(project) = dir.ancestors().find_map(|ancestor| links.get(ancestor)) {
+        return Ok(Some(project.to_string()));
+    }
+
+    let candidates = [git_root, Some(dir.to_path_buf())];
+    Ok(candidates
+        .into_iter()
+        .flatten()
+        .filter_map(|path| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .find(|name| registry.projects.contains_key(name.as_str())))
+}
+
+/// Resolves a project argument, inferring it from `cwd` when omitted or ".".
+///
+/// Explicit names resolve to the stored project name, ignoring case when the
+/// registry's `ignore_case` setting is on.
+pub fn resolve_project_arg(registry: &Registry, arg: Option<&str>, cwd: &Path) -> Result<String> {
+    match arg {
+        Some(project) if project != CURRENT_PROJECT => Ok(find_name(
+            registry.projects.keys(),
+            project,
+            registry.defaults.ignore_case,
+        )?
+        .map_or(project, |p| p.as_str())
+        .to_string()),
+        _ => infer_project(registry, cwd)?
+            .ok_or_else(|| RegistryError::NoLinkedProject(cwd.to_path_buf()).into()),
+    }
+}
+
+/// Links `dir` to `project`, returning the previously linked project, if any.
+pub fn link_directory(registry: &mut Registry, dir: &Path, project: &str) -> Option<String> {
+    registry
+        .links
+        .insert(dir.to_string_lossy().into_owned(), project.to_string())
+}
+
+/// Removes the link for `dir`, returning the project it pointed at.
+pub fn unlink_directory(registry: &mut Registry, dir: &Path) -> Result<String> {
+    registry
+        .links
+        .remove(dir.to_string_lossy().as_ref())
+        .ok_or_else(|| RegistryError::LinkNotFound(dir.to_path_buf()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Project;
+    use crate::name::ProjectName;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_git_root() {
+        let temp = TempDir::new().unwrap();
+        let repo = temp.path().join("repo");
+        let nested = repo.join("src").join("deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir(repo.join(".git")).unwrap();
+
+        assert_eq!(find_git_root(&nested), Some(repo.clone()));
+        assert_eq!(find_git_root(&repo), Some(repo));
+    }
+
+    #[test]
+    fn test_infer_project_from_link() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().join("checkout");
+        let nested = dir.join("sub");
+        fs::create_dir_all(&nested).unwrap();
+
+        let mut registry = Registry::default();
+        registry
+            .links
+            .insert(dir.to_string_lossy().into_owned(), "webapp".to_string());
+
+        assert_eq!(
+            infer_project(&registry, &nested).unwrap(),
+            Some("webapp".to_string())
+        );
+    }
+
+    #[test]
+    fn test_infer_project_from_git_root_name() {
+        let temp = TempDir::new().unwrap();
+        let repo = temp.path().join("webapp");
+        let nested = repo.join("src");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir(repo.join(".git")).unwrap();
+
+        let mut registry = Registry::default();
+        assert_eq!(infer_project(&registry, &nested).unwrap(), None);
+
+        registry
+            .projects
+            .insert(ProjectName::new("webapp").unwrap(), Project::default());
+        assert_eq!(
+            infer_project(&registry, &nested).unwrap(),
+            Some("webapp".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve_project_arg() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().to_path_buf();
+        let mut registry = Registry::default();
+
+        assert_eq!(
+            resolve_project_arg(&registry, Some("webapp"), &dir).unwrap(),
+            "webapp"
+        );
+        assert!(matches!(
+            resolve_project_arg(&registry, Some("."), &dir),
+            Err(crate::error::Error::Registry(
+                RegistryError::NoLinkedProject(_)
+            ))
+        ));
+
+        link_directory(&mut registry, &dir, "backend");
+        assert_eq!(
+            resolve_project_arg(&registry, None, &dir).unwrap(),
+            "backend"
+        );
+        assert_eq!(
+            resolve_project_arg(&registry, Some("."), &dir).unwrap(),
+            "backend"
+        );
+
+        assert_eq!(unlink_directory(&mut registry, &dir).unwrap(), "backend");
+        assert!(unlink_directory(&mut registry, &dir).is_err());
+    }
+
+    #[test]
+    fn test_infer_project_monorepo_subpaths() {
+        let temp = TempDir::new().unwrap();
+        let repo = temp.path().join("mono");
+        let api = repo.join("packages").join("api");
+        let web = repo.join("packages").join("web");
+        fs::create_dir_all(api.join("src")).unwrap();
+        fs::create_dir_all(&web).unwrap();
+        fs::create_dir(repo.join(".git")).unwrap();
+
+        let mut config = RepoConfig::default();
+        config.links.insert(".".to_string(), "mono".to_string());
+        config
+            .links
+            .insert("packages/api".to_string(), "api".to_string());
+        save_repo_config(&repo, &config).unwrap();
+
+        let mut registry = Registry::default();
+        assert_eq!(
+            infer_project(&registry, &api.join("src")).unwrap(),
+            Some("api".to_string())
+        );
+        assert_eq!(
+            infer_project(&registry, &web).unwrap(),
+            Some("mono".to_string())
+        );
+
+        // A registry link for the same directory overrides the repo file
+        link_directory(&mut registry, &api, "api-local");
+        assert_eq!(
+            infer_project(&registry, &api).unwrap(),
+            Some("api-local".to_string())
+        );
+    }
+
+    #[test]
+    fn test_repo_link_key() {
+        let root = Path::new("/repo");
+        assert_eq!(repo_link_key(root, root), Some(".".to_string()));
+        assert_eq!(
+            repo_link_key(root, &root.join("packages/api")),
+            Some("packages/api".to_string())
+        );
+        assert_eq!(repo_link_key(root, Path::new("/elsewhere")), None);
+    }
+}
@@ -13,7 +13,9 @@ use tempfile::TempDir;
 /// Creates a new command with a temporary config path.
 fn pm_cmd(config_path: &str) -> assert_cmd::Command {
     let mut cmd = Command::cargo_bin("pm").unwrap();
-    cmd.env("PM_CONFIG_PATH", config_path);
+    cmd.env("PM_CONFIG_PATH", config_path)
+        .env_remove("PM_CONFIG_DIR")
+        .env("PM_SYSTEM_REGISTRY", "");
     assert_cmd::Command::from_std(cmd)
 }
 
@@ -28,6 +30,60 @@ fn setup_temp_config() -> (TempDir, String) {
 // Allocation Flow Tests
 // ============================================================================
 
+#[test]
+fn test_init() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let dir = temp_dir.path().join("shop");
+    std::fs::create_dir(&dir).unwrap();
+
+    pm_cmd(&config_path)
+        .args([
+            "init",
+            "--ranges",
+            "node",
+            "--link",
+            ".",
+            "--template",
+            "backend",
+        ])
+        .current_dir(&dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("Created registry"))
+        .stdout(predicate::str::contains("Added vite range 5173-5199"))
+        .stdout(predicate::str::contains("Allocated shop.api = 3000"))
+        .stdout(predicate::str::contains("Allocated shop.db = 5400"));
+
+    // Rerunning changes nothing
+    pm_cmd(&config_path)
+        .args([
+            "init",
+            "--ranges",
+            "node",
+            "--link",
+            ".",
+            "--template",
+            "backend",
+        ])
+        .current_dir(&dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("Using registry"))
+        .stdout(predicate::str::contains(
+            "All preset ranges are already set",
+        ))
+        .stdout(predicate::str::contains(
+            "shop.api = 3000 (already allocated)",
+        ));
+
+    pm_cmd(&config_path)
+        .args(["query"])
+        .current_dir(&dir)
+        .assert()
+        .success()
+        .stdout("api=3000 db=5400\n");
+}
+
 #[test]
 fn test_allocate_auto_port() {
     let (_temp_dir, config_path) = setup_temp_config();
@@ -50,6 +106,34 @@ fn test_allocate_explicit_port() {
         .stdout(predicate::str::contains("Allocated webapp.web = 8080"));
 }
 
+#[test]
+fn test_quiet_prints_only_ports() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "ci-42", "web", "-q"])
+        .assert()
+        .success()
+        .stdout("8000\n")
+        .stderr("Allocated ci-42.web = 8000\n");
+    pm_cmd(&config_path)
+        .args(["allocate", "ci-42", "web", "--if-absent", "--quiet"])
+        .assert()
+        .success()
+        .stdout("8000\n");
+    pm_cmd(&config_path)
+        .args(["config", "--set", "web=8100-8199", "-q"])
+        .assert()
+        .success()
+        .stdout("8100-8199\n");
+    pm_cmd(&config_path)
+        .args(["free", "ci-42", "-q"])
+        .assert()
+        .success()
+        .stdout("8000\n")
+        .stderr("Freed ci-42.web (was 8000)\n");
+}
+
 #[test]
 fn test_allocate_then_query() {
     let (_temp_dir, config_path) = setup_temp_config();
@@ -116,6 +200,103 @@ fn test_allocate_then_free() {
         .failure();
 }
 
+#[test]
+fn test_archive_unarchive() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["archive", "webapp"])
+        .assert()
+        .success()
+        .stdout("Archived webapp (web=8000)\n");
+    pm_cmd(&config_path)
+        .args(["archive"])
+        .assert()
+        .success()
+        .stdout("webapp: web=8000\n");
+    pm_cmd(&config_path)
+        .args(["query", "webapp"])
+        .assert()
+        .failure();
+
+    // The old port went to someone else
+    pm_cmd(&config_path)
+        .args(["allocate", "other", "web", "8000"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["unarchive", "webapp"])
+        .assert()
+        .success()
+        .stdout("Restored webapp.web = 8001 (8000 is taken)\n");
+    pm_cmd(&config_path)
+        .args(["unarchive", "webapp"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not archived"));
+}
+
+#[test]
+fn test_allocate_reuses_previous_port() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8042"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["free", "webapp", "web"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout("Allocated webapp.web = 8042\n");
+
+    // --forget frees without remembering
+    pm_cmd(&config_path)
+        .args(["free", "webapp", "web", "--forget"])
+        .assert()
+        .success();
+    assert!(!fs::read_to_string(&config_path)
+        .unwrap()
+        .contains("[history"));
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout("Allocated webapp.web = 8000\n");
+
+    // pm gc forgets ports freed long ago
+    pm_cmd(&config_path)
+        .args(["free", "webapp", "web"])
+        .assert()
+        .success();
+    let content = fs::read_to_string(&config_path).unwrap();
+    let (head, rest) = content.split_once("freed_at = \"").unwrap();
+    let (_, tail) = rest.split_once('"').unwrap();
+    fs::write(
+        &config_path,
+        format!("{head}freed_at = \"2020-01-01T00:00:00+00:00\"{tail}"),
+    )
+    .unwrap();
+    pm_cmd(&config_path)
+        .args(["gc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Forgot the previous ports of 1 name(s) freed over 90 days ago",
+        ));
+    assert!(!fs::read_to_string(&config_path)
+        .unwrap()
+        .contains("[history"));
+}
+
 #[test]
 fn test_free_all_ports() {
     let (_temp_dir, config_path) = setup_temp_config();
@@ -192,513 +373,3005 @@ fn test_config_set_range() {
         .stdout(predicate::str::contains("7"));
 }
 
-// ============================================================================
-// List Command Tests
-// ============================================================================
-
 #[test]
-fn test_list_empty() {
+fn test_config_set_range_migrate() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    pm_cmd(&config_path).args(["list"]).assert().success();
-}
-
-#[test]
-fn test_list_with_allocations() {
-    let (_temp_dir, config_path) = setup_temp_config();
+    for (project, port) in [("webapp", "8500"), ("shop", "8001")] {
+        pm_cmd(&config_path)
+            .args(["allocate", project, "web", port])
+            .assert()
+            .success();
+    }
 
-    // Allocate some ports
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "8080"])
+        .args(["config", "--set", "web=8000-8099", "--migrate"])
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains("Moved webapp.web: 8500 -> 8000"))
+        .stdout(predicate::str::contains("shop.web").not());
 
     pm_cmd(&config_path)
-        .args(["allocate", "backend", "api", "3000"])
+        .args(["config", "--set", "web=8200-8299"])
         .assert()
-        .success();
-
-    // List should show both
+        .success()
+        .stderr(predicate::str::contains(
+            "shop.web (8001) is outside the new web range",
+        ));
     pm_cmd(&config_path)
-        .args(["list"])
+        .args(["query", "shop", "web"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("webapp"))
-        .stdout(predicate::str::contains("backend"))
-        .stdout(predicate::str::contains("8080"))
-        .stdout(predicate::str::contains("3000"));
-}
-
-// ============================================================================
-// Status Command Tests
-// ============================================================================
-
-#[test]
-fn test_status_runs() {
-    let (_temp_dir, config_path) = setup_temp_config();
-
-    // Status command should run successfully (output depends on system state)
-    pm_cmd(&config_path).args(["status"]).assert().success();
+        .stdout("8001\n");
 }
 
-// ============================================================================
-// Suggest Command Tests
-// ============================================================================
-
 #[test]
-fn test_suggest_default() {
+fn test_config_set_range_policy() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Suggest outputs just the port number (for scripting)
     pm_cmd(&config_path)
-        .args(["suggest"])
+        .args(["allocate", "shop", "db", "5410"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["config", "--set", "db=5400-5499:sequential"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("9")); // default range 9000-9999
-}
-
-#[test]
-fn test_suggest_multiple() {
-    let (_temp_dir, config_path) = setup_temp_config();
+        .stdout(predicate::str::contains(
+            "Set db range to 5400-5499 (sequential)",
+        ));
+    assert!(fs::read_to_string(&config_path).unwrap().contains(
+        "[defaults.ranges.db]\nrange = [\n    5400,\n    5499,\n]\npolicy = \"sequential\"\n"
+    ));
+
+    // Sequential continues after the highest allocated port
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "db"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.db = 5411"));
+
+    let output = pm_cmd(&config_path)
+        .args(["config", "--json=v0"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let db = json["ranges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["name"] == "db")
+        .unwrap();
+    assert_eq!(db["policy"], "sequential");
 
     pm_cmd(&config_path)
-        .args(["suggest", "3"])
+        .args(["config", "--set", "db=5400-5499:fastest"])
         .assert()
-        .success();
+        .failure()
+        .stderr(predicate::str::contains(
+            "Unknown allocation policy 'fastest'",
+        ));
 }
 
 #[test]
-fn test_suggest_by_type() {
+fn test_config_range_segments() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Suggest web ports (8000-8999 range)
     pm_cmd(&config_path)
-        .args(["suggest", "--type", "web"])
+        .args(["config", "--set", "api=3000-3001,3300-3399"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("8"));
-}
-
-// ============================================================================
-// Error Case Tests
-// ============================================================================
-
-#[test]
-fn test_allocate_duplicate_port() {
-    let (_temp_dir, config_path) = setup_temp_config();
+        .stdout(predicate::str::contains(
+            "Set api range to 3000-3001,3300-3399",
+        ));
+    pm_cmd(&config_path)
+        .arg("config")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3000-3001,3300-3399"));
 
-    // Allocate a port
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "8080"])
+        .args(["allocate", "webapp", "api"])
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.api = 3000"));
+    pm_cmd(&config_path)
+        .args(["suggest", "-t", "api", "2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3001").and(predicate::str::contains("3300")));
+
+    let output = pm_cmd(&config_path)
+        .args(["config", "--json=v0"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let api = json["ranges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["name"] == "api")
+        .unwrap();
+    assert_eq!(api["start"], 3000);
+    assert_eq!(api["end"], 3399);
+    assert_eq!(
+        api["segments"],
+        serde_json::json!([[3000, 3001], [3300, 3399]])
+    );
 
-    // Try to allocate the same port to another project
     pm_cmd(&config_path)
-        .args(["allocate", "other", "web", "8080"])
+        .args(["config", "--set", "api=3000-3099,3050-3199"])
         .assert()
         .failure()
-        .stderr(predicate::str::contains("already allocated"));
+        .stderr(predicate::str::contains(
+            "segments 3000-3099 and 3050-3199 overlap",
+        ));
 }
 
 #[test]
-fn test_allocate_same_name_twice() {
+fn test_config_range_projects() {
     let (_temp_dir, config_path) = setup_temp_config();
+    fs::write(
+        &config_path,
+        "[defaults.ranges]\ndefault = [9000, 9099]\n\
+         infra = { range = [9500, 9599], projects = [\"infra-*\"] }\n",
+    )
+    .unwrap();
 
-    // Allocate a port
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "8080"])
+        .args(["allocate", "infra-dns", "infra"])
         .assert()
-        .success();
-
-    // Try to allocate the same name again
+        .success()
+        .stdout(predicate::str::contains("Allocated infra-dns.infra = 9500"));
+    // Other projects never draw from the reserved range
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "8081"])
+        .args(["allocate", "webapp", "infra"])
         .assert()
-        .failure()
-        .stderr(predicate::str::contains("already exists"));
-}
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.infra = 9000"));
+    pm_cmd(&config_path)
+        .args(["suggest", "-t", "infra", "--project", "webapp"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("9001"));
 
-#[test]
-fn test_free_nonexistent_project() {
-    let (_temp_dir, config_path) = setup_temp_config();
+    // ...and explicit ports inside it warn
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api", "9510"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Port 9510 is in the 'infra' range, reserved for infra-*",
+        ));
 
     pm_cmd(&config_path)
-        .args(["free", "nonexistent", "web"])
+        .arg("config")
         .assert()
-        .failure()
-        .stderr(predicate::str::contains("not found"));
+        .success()
+        .stdout(predicate::str::contains("9500-9599 only for infra-*"));
 }
 
 #[test]
-fn test_query_nonexistent_project() {
+fn test_config_range_exclusions() {
     let (_temp_dir, config_path) = setup_temp_config();
+    fs::write(
+        &config_path,
+        "[defaults.ranges]\nweb = { range = [8000, 8999], exclude = [8000, 8001] }\n",
+    )
+    .unwrap();
 
     pm_cmd(&config_path)
-        .args(["query", "nonexistent"])
+        .args(["allocate", "webapp", "web"])
         .assert()
-        .failure()
-        .stderr(predicate::str::contains("not found"));
-}
-
-#[test]
-fn test_invalid_port_number() {
-    let (_temp_dir, config_path) = setup_temp_config();
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.web = 8002"));
+    pm_cmd(&config_path)
+        .args(["suggest", "-t", "web"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("8003"));
 
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "0"])
+        .arg("config")
         .assert()
-        .failure();
+        .success()
+        .stdout(predicate::str::contains("8000-8999 except 8000, 8001"));
+
+    let output = pm_cmd(&config_path)
+        .args(["config", "--json=v0"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let web = json["ranges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["name"] == "web")
+        .unwrap();
+    assert_eq!(web["exclude"], serde_json::json!([8000, 8001]));
 }
 
 #[test]
-fn test_invalid_port_too_large() {
+fn test_config_ignore_case() {
     let (_temp_dir, config_path) = setup_temp_config();
 
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "99999"])
+        .args(["allocate", "webapp", "web", "8080"])
         .assert()
-        .failure();
-}
-
-#[test]
-fn test_config_invalid_range_format() {
-    let (_temp_dir, config_path) = setup_temp_config();
+        .success();
 
     pm_cmd(&config_path)
-        .args(["config", "--set", "invalid"])
+        .args(["query", "WebApp", "WEB"])
         .assert()
-        .failure()
-        .stderr(predicate::str::contains("format"));
-}
+        .failure();
 
-// ============================================================================
-// Alias Tests
-// ============================================================================
+    pm_cmd(&config_path)
+        .args(["config", "--ignore-case", "true"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("matching is on"));
 
-#[test]
-fn test_allocate_alias() {
-    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["query", "WebApp", "WEB"])
+        .assert()
+        .success()
+        .stdout("8080\n");
 
     pm_cmd(&config_path)
-        .args(["a", "webapp", "web", "8080"])
+        .args(["allocate", "WebApp", "api", "3000"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("Allocated webapp.web = 8080"));
+        .stdout(predicate::str::contains("Allocated webapp.api = 3000"));
 }
 
 #[test]
-fn test_free_alias() {
+fn test_config_preset() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Allocate first
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "8080"])
+        .args(["config", "--set", "kafka=19092-19099"])
         .assert()
         .success();
 
-    // Free using alias
     pm_cmd(&config_path)
-        .args(["f", "webapp", "web"])
+        .args(["config", "--preset", "messaging,grpc"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("Freed"));
-}
+        .stdout(predicate::str::contains("Added rabbitmq range 5672-5691"))
+        .stdout(predicate::str::contains("Added grpc range 50051-50099"))
+        .stdout(predicate::str::contains(
+            "Kept existing kafka range 19092-19099",
+        ));
+
+    let output = pm_cmd(&config_path)
+        .args(["suggest", "--type", "rabbitmq"])
+        .output()
+        .unwrap();
+    let port: u16 = String::from_utf8(output.stdout)
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    assert!((5672..=5691).contains(&port));
+
+    pm_cmd(&config_path)
+        .args(["config", "--preset", "grpc"])
+        .assert()
+        .success()
+        .stdout("All preset ranges are already set\n");
+
+    pm_cmd(&config_path)
+        .args(["config", "--preset", "cobol"])
+        .assert()
+        .failure();
+}
+
+// ============================================================================
+// List Command Tests
+// ============================================================================
 
 #[test]
-fn test_list_alias() {
+fn test_list_empty() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    pm_cmd(&config_path).args(["ls"]).assert().success();
+    pm_cmd(&config_path).args(["list"]).assert().success();
 }
 
 #[test]
-fn test_query_alias() {
+fn test_list_with_allocations() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Allocate first
+    // Allocate some ports
     pm_cmd(&config_path)
         .args(["allocate", "webapp", "web", "8080"])
         .assert()
         .success();
 
     pm_cmd(&config_path)
-        .args(["q", "webapp"])
+        .args(["allocate", "backend", "api", "3000"])
+        .assert()
+        .success();
+
+    // List should show both
+    pm_cmd(&config_path)
+        .args(["list"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("8080"));
+        .stdout(predicate::str::contains("webapp"))
+        .stdout(predicate::str::contains("backend"))
+        .stdout(predicate::str::contains("8080"))
+        .stdout(predicate::str::contains("3000"));
 }
 
-// ============================================================================
-// Persistence Tests
-// ============================================================================
-
 #[test]
-fn test_config_persists() {
+fn test_list_not_paged_when_piped() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Allocate a port
     pm_cmd(&config_path)
         .args(["allocate", "webapp", "web", "8080"])
         .assert()
         .success();
 
-    // Check the config file was created and contains the allocation
-    let content = fs::read_to_string(&config_path).unwrap();
-    assert!(content.contains("webapp"));
-    assert!(content.contains("8080"));
+    // Output isn't a terminal here, so the pager never runs
+    for args in [&["list"][..], &["--no-pager", "list"][..]] {
+        pm_cmd(&config_path)
+            .env("PM_PAGER", "false")
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("8080"));
+    }
 }
 
 #[test]
-fn test_multiple_projects() {
+fn test_list_short_output() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Allocate ports to multiple projects
+    for (project, port) in [("webapp", "8080"), ("backend", "3000")] {
+        pm_cmd(&config_path)
+            .args(["allocate", project, "web", port])
+            .assert()
+            .success();
+    }
+
     pm_cmd(&config_path)
-        .args(["allocate", "frontend", "web", "8080"])
+        .args(["list", "-o", "short"])
         .assert()
-        .success();
+        .success()
+        .stdout(
+            predicate::str::is_match(
+                r"^backend\.web 3000 (IDLE|ACTIVE|CONFLICT) \S+\nwebapp\.web 8080 ",
+            )
+            .unwrap(),
+        );
 
     pm_cmd(&config_path)
-        .args(["allocate", "backend", "api", "3000"])
+        .args(["list", "-o", "short", "--json"])
         .assert()
-        .success();
+        .failure();
+}
 
+#[test]
+fn test_list_display_settings() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    std::fs::write(
+        &config_path,
+        "[display]\ntheme = \"none\"\nborders = \"ascii\"\nstatus_glyphs = true\n\n\
+         [projects.webapp]\nweb = 8080\n",
+    )
+    .unwrap();
+    let output = pm_cmd(&config_path).args(["list"]).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("+--------"), "{stdout}");
+    assert!(stdout.contains("| - IDLE |"), "{stdout}");
+    assert!(stdout.is_ascii(), "{stdout}");
+
+    // Saving keeps the section
     pm_cmd(&config_path)
-        .args(["allocate", "database", "db", "5432"])
+        .args(["allocate", "webapp", "api"])
         .assert()
         .success();
+    let content = std::fs::read_to_string(&config_path).unwrap();
+    assert!(content.contains("[display]\ntheme = \"none\""), "{content}");
+}
 
-    // Query each project
+#[test]
+fn test_list_hosts() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // The same port is free again on each shared server
+    for (project, name, host) in [
+        ("webapp", "web", None),
+        ("webapp", "ci", Some("build-1")),
+        ("backend", "api", Some("build-2")),
+    ] {
+        let mut cmd = pm_cmd(&config_path);
+        cmd.args(["allocate", project, name, "8080"]);
+        if let Some(host) = host {
+            cmd.args(["--host", host]);
+        }
+        cmd.assert().success();
+    }
     pm_cmd(&config_path)
-        .args(["query", "frontend", "web"])
+        .args(["allocate", "backend", "web", "8080", "--host", "build-1"])
         .assert()
-        .success()
-        .stdout(predicate::str::contains("8080"));
+        .failure()
+        .stderr(predicate::str::contains("already allocated to webapp.ci"));
 
     pm_cmd(&config_path)
-        .args(["query", "backend", "api"])
+        .args(["list", "-o", "short"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("3000"));
-
+        .stdout(predicate::str::is_match(r"^webapp\.web 8080 \S+ \S+\n$").unwrap());
     pm_cmd(&config_path)
-        .args(["query", "database", "db"])
+        .args(["list", "--host", "build-1", "-o", "short"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("5432"));
-}
+        .stdout("webapp.ci build-1:8080 REMOTE -\n");
+    pm_cmd(&config_path)
+        .args(["list", "--host", "all", "-o", "short"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "backend.api build-2:8080 REMOTE -\n",
+        ))
+        .stdout(predicate::str::contains(
+            "webapp.ci build-1:8080 REMOTE -\n",
+        ))
+        .stdout(predicate::str::contains("webapp.web 8080 "));
 
-// ============================================================================
-// JSON Output Tests
-// ============================================================================
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "docs", "--host", "all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'all' is reserved"));
+}
 
 #[test]
-fn test_list_json() {
+fn test_list_project_filter() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Allocate a port first
+    for (project, port) in [
+        ("webapp", "8080"),
+        ("webadmin", "8081"),
+        ("backend", "3000"),
+    ] {
+        pm_cmd(&config_path)
+            .args(["allocate", project, "web", port])
+            .assert()
+            .success();
+    }
+
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "8080"])
+        .args(["list", "--project", "web*"])
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains("webapp"))
+        .stdout(predicate::str::contains("webadmin"))
+        .stdout(predicate::str::contains("backend").not());
 
-    // List with JSON output
     pm_cmd(&config_path)
-        .args(["list", "--json"])
+        .args([
+            "list",
+            "--project",
+            "webapp",
+            "--project",
+            "back*",
+            "--json",
+        ])
         .assert()
         .success()
-        .stdout(predicate::str::contains("\"project\""))
-        .stdout(predicate::str::contains("\"port\""))
-        .stdout(predicate::str::contains("8080"))
-        .stdout(predicate::str::contains("webapp"));
+        .stdout(predicate::str::contains("\"backend\""))
+        .stdout(predicate::str::contains("\"webapp\""))
+        .stdout(predicate::str::contains("webadmin").not());
 }
 
 #[test]
-fn test_list_json_empty() {
+fn test_list_and_query_group_filter() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // List with JSON output when empty
+    for (project, name, port) in [
+        ("webapp", "web", "8080"),
+        ("webapp", "api", "3000"),
+        ("backend", "api", "3001"),
+    ] {
+        let mut cmd = pm_cmd(&config_path);
+        cmd.args(["allocate", project, name, port]);
+        if name == "api" {
+            cmd.args(["--group", "sprint-42"]);
+        }
+        cmd.assert().success();
+    }
+
     pm_cmd(&config_path)
-        .args(["list", "--json"])
+        .args(["list", "--tag", "sprint-42", "--project", "web*"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("[]"));
+        .stdout(predicate::str::contains("3000"))
+        .stdout(predicate::str::contains("3001").not())
+        .stdout(predicate::str::contains("8080").not());
+
+    pm_cmd(&config_path)
+        .args(["query", "webapp", "--group", "sprint-42"])
+        .assert()
+        .success()
+        .stdout("api=3000\n");
+
+    pm_cmd(&config_path)
+        .args(["query", "--all", "--tag", "sprint-42"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("webapp.api=3000"))
+        .stdout(predicate::str::contains("backend.api=3001"))
+        .stdout(predicate::str::contains("8080").not());
 }
 
 #[test]
-fn test_query_json() {
+fn test_query_all() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Allocate a port first
     pm_cmd(&config_path)
         .args(["allocate", "webapp", "web", "8080"])
         .assert()
         .success();
 
-    // Query with JSON output
     pm_cmd(&config_path)
-        .args(["query", "webapp", "--json"])
+        .args(["allocate", "backend", "api", "3000"])
         .assert()
-        .success()
-        .stdout(predicate::str::contains("\"name\""))
-        .stdout(predicate::str::contains("\"port\""))
-        .stdout(predicate::str::contains("8080"));
-}
+        .success();
 
-#[test]
-fn test_status_json() {
-    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["query", "--all"])
+        .assert()
+        .success()
+        .stdout("backend.api=3000\nwebapp.web=8080\n");
 
-    // Status with JSON output
     pm_cmd(&config_path)
-        .args(["status", "--json"])
+        .args(["query", "--all", "--json"])
         .assert()
         .success()
-        .stdout(predicate::str::starts_with("["));
+        .stdout(predicate::str::contains("\"project\": \"backend\""))
+        .stdout(predicate::str::contains("\"port\": 8080"));
 }
 
 #[test]
-fn test_status_full() {
+fn test_query_with_deps() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Status with --full flag shows DIRECTORY column
+    for (project, name, port) in [
+        ("myapp", "web", "8000"),
+        ("authsvc", "api", "3000"),
+        ("authsvc", "admin", "3001"),
+    ] {
+        pm_cmd(&config_path)
+            .args(["allocate", project, name, port])
+            .assert()
+            .success();
+    }
+
     pm_cmd(&config_path)
-        .args(["status", "--full"])
+        .args(["depend", "myapp", "authsvc", "api"])
         .assert()
-        .success();
-}
+        .success()
+        .stdout("myapp depends on authsvc.api\n");
+    pm_cmd(&config_path)
+        .args(["depend", "myapp", "billing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not found"));
 
-#[test]
-fn test_status_full_json() {
-    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["query", "myapp", "--with-deps"])
+        .assert()
+        .success()
+        .stdout("web=8000 authsvc.api=3000\n");
+    let output = pm_cmd(&config_path)
+        .args(["query", "myapp", "--with-deps", "--json=v1"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["data"][1]["project"], "authsvc");
+    assert_eq!(json["data"][1]["port"], 3000);
 
-    // Status with --full --json includes cwd field
     pm_cmd(&config_path)
-        .args(["status", "--full", "--json"])
+        .args(["undepend", "myapp", "authsvc", "api"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["query", "myapp", "--with-deps"])
         .assert()
         .success()
-        .stdout(predicate::str::starts_with("["));
+        .stdout("web=8000\n");
+    pm_cmd(&config_path)
+        .args(["undepend", "myapp", "authsvc"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("doesn't depend on authsvc"));
 }
 
 #[test]
-fn test_suggest_json() {
+fn test_query_glob() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Suggest with JSON output
+    for (project, name, port) in [
+        ("team-a-api", "api", "3000"),
+        ("team-a-web", "web", "8000"),
+        ("team-b-web", "web", "8001"),
+    ] {
+        pm_cmd(&config_path)
+            .args(["allocate", project, name, port])
+            .assert()
+            .success();
+    }
+
     pm_cmd(&config_path)
-        .args(["suggest", "--json"])
+        .args(["query", "team-a-*"])
         .assert()
         .success()
-        .stdout(predicate::str::starts_with("["));
-}
+        .stdout("team-a-api.api=3000\nteam-a-web.web=8000\n");
 
-#[test]
-fn test_suggest_multiple_json() {
-    let (_temp_dir, config_path) = setup_temp_config();
+    let output = pm_cmd(&config_path)
+        .args(["query", "*-web", "web", "--json=v1"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["data"]["team-a-web"][0]["port"], 8000);
+    assert_eq!(json["data"]["team-b-web"][0]["name"], "web");
+    assert!(json["data"].get("team-a-api").is_none());
 
-    // Suggest multiple with JSON output
     pm_cmd(&config_path)
-        .args(["suggest", "3", "--json"])
+        .args(["query", "team-c-*"])
         .assert()
-        .success()
-        .stdout(predicate::str::starts_with("["));
+        .failure();
 }
 
 #[test]
-fn test_config_json() {
+fn test_env() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // Config with JSON output
     pm_cmd(&config_path)
-        .args(["config", "--json"])
+        .args(["allocate", "webapp", "web", "8080"])
         .assert()
-        .success()
-        .stdout(predicate::str::contains("\"ranges\""))
-        .stdout(predicate::str::contains("\"web\""));
-}
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "admin-ui", "8081"])
+        .assert()
+        .success();
 
-#[test]
-fn test_config_path_json() {
-    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["env", "webapp"])
+        .assert()
+        .success()
+        .stdout("ADMIN_UI_PORT=8081\nWEB_PORT=8080\n");
 
-    // Config with --path and JSON output
     pm_cmd(&config_path)
-        .args(["config", "--path", "--json"])
+        .args(["env", "webapp", "--export"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("\"config_file\""))
-        .stdout(predicate::str::contains("\"ranges\""));
-}
+        .stdout(predicate::str::contains("export WEB_PORT=8080"));
 
-// ============================================================================
-// List Filtering Tests
-// ============================================================================
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "admin_ui", "8082"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["env", "webapp"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("$ADMIN_UI_PORT"));
+}
 
+#[cfg(unix)]
 #[test]
-fn test_list_active_only() {
-    let (_temp_dir, config_path) = setup_temp_config();
+fn test_run() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = temp_dir.path().join("webapp");
+    std::fs::create_dir(&project_dir).unwrap();
 
-    // Allocate a port (will be idle since nothing is running on it)
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "18080"])
+        .args(["allocate", "webapp", "web", "8080"])
         .assert()
         .success();
-
-    // List with --active should not show idle ports
     pm_cmd(&config_path)
-        .args(["list", "--active"])
+        .arg("link")
+        .arg(&project_dir)
+        .arg("webapp")
         .assert()
         .success();
+
+    for project in [&["webapp"][..], &["."], &[]] {
+        pm_cmd(&config_path)
+            .arg("run")
+            .args(project)
+            .args(["--", "sh", "-c", "echo $WEB_PORT"])
+            .current_dir(&project_dir)
+            .assert()
+            .success()
+            .stdout("8080\n");
+    }
+
+    // The command's exit code is passed on
+    pm_cmd(&config_path)
+        .args(["run", "webapp", "--", "sh", "-c", "exit 3"])
+        .assert()
+        .code(3);
+
+    pm_cmd(&config_path)
+        .args(["run", ".", "--", "true"])
+        .current_dir(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No project linked"));
 }
 
 #[test]
-fn test_list_active_json() {
-    let (_temp_dir, config_path) = setup_temp_config();
+fn test_hook() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = temp_dir.path().join("webapp");
+    std::fs::create_dir(&project_dir).unwrap();
 
-    // Allocate a port
     pm_cmd(&config_path)
-        .args(["allocate", "webapp", "web", "18081"])
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .arg("link")
+        .arg(&project_dir)
+        .arg("webapp")
         .assert()
         .success();
 
-    // List with --active --json
     pm_cmd(&config_path)
-        .args(["list", "--active", "--json"])
+        .args(["hook", "zsh"])
         .assert()
         .success()
-        .stdout(predicate::str::starts_with("["));
+        .stdout(predicate::str::contains("chpwd_functions"));
+
+    // Entering the project exports its ports; leaving unsets them
+    pm_cmd(&config_path)
+        .args(["hook", "bash", "--apply"])
+        .current_dir(&project_dir)
+        .env_remove("PM_HOOK_VARS")
+        .assert()
+        .success()
+        .stdout("export WEB_PORT='8080';\nexport PM_HOOK_VARS='WEB_PORT';\n");
+    pm_cmd(&config_path)
+        .args(["hook", "fish", "--apply"])
+        .current_dir(temp_dir.path())
+        .env("PM_HOOK_VARS", "WEB_PORT")
+        .assert()
+        .success()
+        .stdout("set -e WEB_PORT;\nset -e PM_HOOK_VARS;\n");
 }
 
 #[test]
-fn test_list_unassigned() {
+fn test_complete_allocate_port() {
     let (_temp_dir, config_path) = setup_temp_config();
 
-    // List unassigned ports (ports listening but not in registry)
     pm_cmd(&config_path)
-        .args(["list", "--unassigned"])
+        .args(["allocate", "webapp", "web", "8000"])
         .assert()
         .success();
-}
-
-#[test]
-fn test_list_unassigned_json() {
-    let (_temp_dir, config_path) = setup_temp_config();
 
-    // List unassigned ports with JSON
     pm_cmd(&config_path)
-        .args(["list", "--unassigned", "--json"])
+        .env("COMPLETE", "fish")
+        .args(["--", "pm", "allocate", "webapp", "api", "300"])
         .assert()
         .success()
-        .stdout(predicate::str::starts_with("["));
+        .stdout(predicate::str::contains(
+            "3000\tfree in api range 3000-3999\n3001\t",
+        ));
+    // Allocated ports aren't offered
+    pm_cmd(&config_path)
+        .env("COMPLETE", "bash")
+        .env("_CLAP_COMPLETE_INDEX", "4")
+        .args(["--", "pm", "allocate", "webapp", "web", "800"])
+        .assert()
+        .success()
+        .stdout("8001\n8002\n8003\n8004\n8005");
 }
 
-// ============================================================================
-// Concurrent Access Tests
-// ============================================================================
-
 #[test]
-fn test_concurrent_allocations_no_duplicates() {
+fn test_complete_machine_interface() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    for (project, name) in [("webapp", "web"), ("webapp", "api"), ("backend", "db")] {
+        pm_cmd(&config_path)
+            .args(["allocate", project, name])
+            .assert()
+            .success();
+    }
+
+    pm_cmd(&config_path)
+        .args(["_complete", "zsh", "2", "--", "pm", "free", "w"])
+        .assert()
+        .success()
+        .stdout("webapp:2 ports\n");
+    // The cursor may be past the last word
+    pm_cmd(&config_path)
+        .args(["_complete", "bash", "3", "--", "pm", "export", "nginx"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("backend\nwebapp\n"));
+    pm_cmd(&config_path)
+        .args(["_complete", "nu", "1", "--", "pm", "ho"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""value":"hold""#));
+    // Ports are offered as under COMPLETE=<shell>
+    pm_cmd(&config_path)
+        .args([
+            "_complete",
+            "fish",
+            "4",
+            "--",
+            "pm",
+            "allocate",
+            "webapp",
+            "worker",
+            "90",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\tfree in default range"));
+}
+
+#[test]
+fn test_get() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["get", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout("8080\n");
+
+    // Missing names are allocated once, then returned as-is
+    let output = pm_cmd(&config_path)
+        .args(["get", "webapp", "admin", "--type", "db"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let port = String::from_utf8(output.stdout).unwrap();
+    let n: u16 = port.trim().parse().unwrap();
+    assert!((5400..=5499).contains(&n), "{n} not in the db range");
+
+    pm_cmd(&config_path)
+        .args(["get", "webapp", "admin"])
+        .assert()
+        .success()
+        .stdout(port);
+
+    pm_cmd(&config_path)
+        .args(["get", "webapp", "x=y"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid name"));
+}
+
+#[test]
+fn test_hold() {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::process::Stdio;
+
+    let (_temp_dir, config_path) = setup_temp_config();
+    let port = TcpListener::bind("0.0.0.0:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+        .to_string();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", &port])
+        .assert()
+        .success();
+
+    let mut holder = Command::cargo_bin("pm")
+        .unwrap()
+        .env("PM_CONFIG_PATH", &config_path)
+        .env_remove("PM_CONFIG_DIR")
+        .env("PM_SYSTEM_REGISTRY", "")
+        .args(["hold", "webapp", "web"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(holder.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert!(line.starts_with(&format!("Holding webapp.web = {port} until")));
+
+    // Nothing else can bind the port, another hold included
+    assert!(TcpListener::bind(format!("0.0.0.0:{port}")).is_err());
+    pm_cmd(&config_path)
+        .args(["hold", "webapp", "web"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("can't bind it"));
+
+    pm_cmd(&config_path)
+        .args(["hold", "webapp", "web", "--release"])
+        .assert()
+        .success()
+        .stdout(format!("Released webapp.web = {port}\n"));
+    assert!(holder.wait().unwrap().success());
+    assert!(TcpListener::bind(format!("0.0.0.0:{port}")).is_ok());
+    pm_cmd(&config_path)
+        .args(["hold", "webapp", "web", "--release"])
+        .assert()
+        .success()
+        .stdout("webapp.web is not held\n");
+}
+
+// ============================================================================
+// Status Command Tests
+// ============================================================================
+
+#[test]
+fn test_status_runs() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Status command should run successfully (output depends on system state)
+    pm_cmd(&config_path).args(["status"]).assert().success();
+}
+
+#[test]
+fn test_doctor() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .arg("doctor")
+        .assert()
+        .stdout(predicate::str::contains("(not created yet)"))
+        .stdout(predicate::str::contains("Port detection: "));
+
+    std::fs::write(&config_path, "[projects.webapp\n").unwrap();
+    pm_cmd(&config_path)
+        .arg("doctor")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(": invalid"));
+}
+
+#[cfg(not(target_os = "macos"))]
+#[test]
+fn test_allocate_require_detection() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Without detection, allocating still works unless detection is required
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "--require-detection"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Platform not supported"));
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web"])
+        .assert()
+        .success()
+        .stderr("");
+}
+
+#[cfg(not(target_os = "macos"))]
+#[test]
+fn test_config_strict_detection() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["config", "--strict-detection", "true"])
+        .assert()
+        .success()
+        .stdout("Commands fail when listening ports can't be detected\n");
+    for args in [
+        vec!["allocate", "webapp", "web"],
+        vec!["get", "webapp", "web"],
+        vec!["suggest"],
+    ] {
+        pm_cmd(&config_path)
+            .args(&args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Platform not supported"));
+    }
+    let output = pm_cmd(&config_path)
+        .args(["config", "--json=v0"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["strict_detection"], true);
+
+    pm_cmd(&config_path)
+        .args(["config", "--strict-detection", "false"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web"])
+        .assert()
+        .success();
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[test]
+fn test_allocate_avoid_lingering() {
+    use std::net::{TcpListener, TcpStream};
+
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Closing the accepted side first leaves it in TIME_WAIT on the port
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port().to_string();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (accepted, _) = listener.accept().unwrap();
+    drop(accepted);
+    drop(client);
+    drop(listener);
+
+    pm_cmd(&config_path)
+        .args([
+            "allocate",
+            "webapp",
+            "web",
+            &port,
+            "--avoid-lingering",
+            "60",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("connections in TIME_WAIT"));
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", &port])
+        .assert()
+        .success();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_wsl_host_listeners() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (temp_dir, config_path) = setup_temp_config();
+
+    // Stand-ins for the Windows tools WSL interop would run
+    let bin = temp_dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let tools = [
+        (
+            "netstat.exe",
+            "  TCP    0.0.0.0:8000    0.0.0.0:0    LISTENING    8120",
+        ),
+        ("tasklist.exe", r#""node.exe","8120","Console","1","45 K""#),
+    ];
+    for (name, output) in tools {
+        let tool = bin.join(name);
+        fs::write(&tool, format!("#!/bin/sh\ncat <<'EOF'\n{output}\nEOF\n")).unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let wsl = |args: &[&str]| {
+        let mut cmd = pm_cmd(&config_path);
+        cmd.env("PATH", &path)
+            .env("WSL_DISTRO_NAME", "Ubuntu")
+            .env("PM_WSL_HOST", "1")
+            .args(args);
+        cmd
+    };
+
+    wsl(&["status", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\"process\": \"node.exe (Windows)\"",
+        ));
+    // A port taken on the Windows side isn't suggested
+    wsl(&["suggest", "--type", "web"])
+        .assert()
+        .success()
+        .stdout("8001\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_status_remote() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    // A stand-in ssh that runs the remote command here, where `ss` lists
+    // two nginx workers
+    let bin = temp_dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let tools = [
+        ("ssh", "#!/bin/sh\nexec sh -c \"$2\"\n"),
+        (
+            "ss",
+            "#!/bin/sh\ncat <<'EOF'\n\
+             LISTEN 0 511 0.0.0.0:8080 0.0.0.0:* \
+             users:((\"nginx\",pid=812,fd=6),(\"nginx\",pid=813,fd=6))\n\
+             EOF\n",
+        ),
+    ];
+    for (name, script) in tools {
+        let tool = bin.join(name);
+        fs::write(&tool, script).unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    pm_cmd(&config_path)
+        .env("PATH", format!("{}:/usr/bin:/bin", bin.display()))
+        .args(["status", "--remote", "pi@raspberry", "-o", "short"])
+        .assert()
+        .success()
+        .stdout("webapp.web 8080 nginx(812)\n");
+    pm_cmd(&config_path)
+        .args(["status", "--remote=-oProxyCommand=x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a host name"));
+}
+
+// Detection is unsupported here, so the agent answers with why
+#[cfg(not(target_os = "macos"))]
+#[test]
+fn test_status_agent() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let (_temp_dir, config_path) = setup_temp_config();
+    let mut agent = Command::cargo_bin("pm")
+        .unwrap()
+        .args(["agent", "--listen", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(agent.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let address = line.trim().rsplit(' ').next().unwrap().to_string();
+
+    let output = pm_cmd(&config_path)
+        .args(["status", "--agent", &address, "--agent", "127.0.0.1:1"])
+        .output()
+        .unwrap();
+    agent.kill().unwrap();
+    agent.wait().unwrap();
+
+    // Unreachable and failing agents are warnings, not errors
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!(
+        "Not showing listeners on {address}: Failed to run 'pm agent at {address}': \
+         Port detection error: Platform not supported"
+    )));
+    assert!(stderr.contains("Not showing listeners on 127.0.0.1:1"));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "No listening ports detected.\n"
+    );
+}
+
+#[test]
+fn test_snapshot() {
+    let (temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+    let output = pm_cmd(&config_path).arg("snapshot").output().unwrap();
+    assert!(output.status.success());
+    let mut json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["apiVersion"], "v1");
+    assert!(json["data"]["captured_at"].is_string());
+    assert_eq!(json["data"]["registry"]["projects"]["webapp"]["web"], 8000);
+
+    // Read back elsewhere, with listeners as another machine saw them
+    json["data"]["listeners"] = serde_json::json!([
+        {"port": 8000, "pid": 42, "pids": [42, 43], "process": "nginx"},
+        {"port": 9100, "pid": null, "process": null}
+    ]);
+    let snapshot = temp_dir.path().join("snapshot.json");
+    fs::write(&snapshot, json.to_string()).unwrap();
+    let (_other_dir, other_config) = setup_temp_config();
+    let output = pm_cmd(&other_config)
+        .args(["status", "--json=v0", "--snapshot"])
+        .arg(&snapshot)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let ports: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(ports[0]["project"], "webapp");
+    assert_eq!(ports[0]["pids"], serde_json::json!([42, 43]));
+    assert_eq!(ports[1]["port"], 9100);
+
+    fs::write(&snapshot, "{}").unwrap();
+    pm_cmd(&config_path)
+        .args(["status", "--snapshot"])
+        .arg(&snapshot)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to parse JSON file"));
+}
+
+#[test]
+fn test_check_against() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let (_their_dir, their_config) = setup_temp_config();
+    pm_cmd(&their_config)
+        .args(["allocate", "shop", "web", "8000"])
+        .assert()
+        .success();
+    let output = pm_cmd(&their_config).arg("snapshot").output().unwrap();
+    let mut json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    json["data"]["listeners"] = serde_json::json!([
+        {"port": 5432, "pid": 42, "process": "postgres"}
+    ]);
+    let snapshot = temp_dir.path().join("snapshot.json");
+    fs::write(&snapshot, json.to_string()).unwrap();
+
+    for (name, port) in [("web", "8000"), ("db", "5432"), ("api", "3000")] {
+        pm_cmd(&config_path)
+            .args(["allocate", "webapp", name, port])
+            .assert()
+            .success();
+    }
+
+    let output = pm_cmd(&config_path)
+        .args(["check-against", "--json=v1"])
+        .arg(&snapshot)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json["data"],
+        serde_json::json!([
+            {"project": "webapp", "name": "db", "port": 5432, "listener": "postgres (PID 42)"},
+            {"project": "webapp", "name": "web", "port": 8000, "allocated_by": "shop.web"}
+        ])
+    );
+
+    // An exported registry works too
+    let output = pm_cmd(&their_config)
+        .args(["export", "registry"])
+        .output()
+        .unwrap();
+    let export = temp_dir.path().join("theirs.json");
+    fs::write(&export, &output.stdout).unwrap();
+    pm_cmd(&config_path)
+        .arg("check-against")
+        .arg(&export)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("shop.web"))
+        .stderr(predicate::str::contains("1 allocation(s) collide"));
+
+    pm_cmd(&their_config)
+        .arg("check-against")
+        .arg(&export)
+        .assert()
+        .failure();
+    let (_empty_dir, empty_config) = setup_temp_config();
+    pm_cmd(&empty_config)
+        .arg("check-against")
+        .arg(&export)
+        .assert()
+        .success()
+        .stdout("No collisions\n");
+}
+
+#[test]
+fn test_debug_bundle() {
+    use std::io::Read;
+
+    let (temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "acme-shop", "web", "8000", "--note", "payments"])
+        .assert()
+        .success();
+
+    let read_bundle = |redact: bool| {
+        let bundle = temp_dir.path().join("bundle.tar.gz");
+        let mut cmd = pm_cmd(&config_path);
+        cmd.args(["debug-bundle", "--output"]).arg(&bundle);
+        if redact {
+            cmd.arg("--redact");
+        }
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::starts_with("Wrote "));
+
+        let file = fs::File::open(&bundle).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let mut files = std::collections::BTreeMap::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().display().to_string();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            files.insert(name, content);
+        }
+        files
+    };
+
+    let files = read_bundle(false);
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        [
+            "pm-debug/changes.txt",
+            "pm-debug/lsof.txt",
+            "pm-debug/platform.txt",
+            "pm-debug/registry.toml",
+            "pm-debug/snapshot.json",
+        ]
+    );
+    assert!(files["pm-debug/registry.toml"].contains("acme-shop"));
+    assert!(files["pm-debug/platform.txt"].starts_with("pm "));
+    let snapshot: serde_json::Value =
+        serde_json::from_str(&files["pm-debug/snapshot.json"]).unwrap();
+    assert_eq!(
+        snapshot["registry"]["projects"]["acme-shop"]["web"]["port"],
+        8000
+    );
+
+    let files = read_bundle(true);
+    for content in files.values() {
+        assert!(!content.contains("acme-shop"));
+        assert!(!content.contains("payments"));
+    }
+    assert!(files["pm-debug/registry.toml"].contains("[projects.project-1.web]"));
+}
+
+// ============================================================================
+// Suggest Command Tests
+// ============================================================================
+
+#[test]
+fn test_suggest_default() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Suggest outputs just the port number (for scripting)
+    pm_cmd(&config_path)
+        .args(["suggest"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("9")); // default range 9000-9999
+}
+
+#[test]
+fn test_suggest_multiple() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["suggest", "3"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_suggest_by_type() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Suggest web ports (8000-8999 range)
+    pm_cmd(&config_path)
+        .args(["suggest", "--type", "web"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("8"));
+}
+
+#[test]
+fn test_suggest_explain() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    fs::write(
+        &config_path,
+        "[defaults.ranges]\nweb = { range = [8000, 8999], exclude = [8001] }\n\n\
+         [projects.webapp]\nweb = 8000\n",
+    )
+    .unwrap();
+
+    pm_cmd(&config_path)
+        .args(["suggest", "--type", "web", "--explain"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Suggesting from the 'web' range 8000-8999 except 8001",
+        ))
+        .stdout(predicate::str::contains("allocated to webapp.web"))
+        .stdout(predicate::str::contains("excluded by the range"))
+        .stdout(predicate::str::contains("8002").and(predicate::str::contains("suggested")));
+
+    let output = pm_cmd(&config_path)
+        .args(["suggest", "--type", "web", "--explain", "--json=v0"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["type"], "web");
+    assert_eq!(
+        json["candidates"],
+        serde_json::json!([
+            {"port": 8000, "suggested": false, "reason": "allocated", "owner": "webapp.web"},
+            {"port": 8001, "suggested": false, "reason": "excluded"},
+            {"port": 8002, "suggested": true},
+        ])
+    );
+}
+
+#[test]
+fn test_verbose_logs_to_stderr() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success()
+        .stderr("");
+
+    // Logs explain the pick without touching stdout
+    pm_cmd(&config_path)
+        .args(["-vv", "suggest", "--type", "web"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("range=8000-8999"))
+        .stderr(predicate::str::contains(
+            "skipped: already allocated port=8000",
+        ));
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api", "3000", "-v"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("acquired registry lock"))
+        .stderr(predicate::str::contains("registry + api = 3000"));
+
+    pm_cmd(&config_path)
+        .args(["-q", "-v", "list"])
+        .assert()
+        .failure();
+}
+
+// ============================================================================
+// Error Case Tests
+// ============================================================================
+
+#[test]
+fn test_allocate_duplicate_port() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate a port
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    // Try to allocate the same port to another project
+    pm_cmd(&config_path)
+        .args(["allocate", "other", "web", "8080"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already allocated"));
+}
+
+#[test]
+fn test_allocate_same_name_twice() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate a port
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    // Try to allocate the same name again
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8081"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+}
+
+#[test]
+fn test_free_nonexistent_project() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["free", "nonexistent", "web"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not found"));
+}
+
+#[test]
+fn test_query_nonexistent_project() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["query", "nonexistent"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not found"));
+}
+
+#[test]
+fn test_invalid_port_number() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "0"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_invalid_port_too_large() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "99999"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_allocate_expect_process() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args([
+            "allocate",
+            "webapp",
+            "web",
+            "8080",
+            "--expect-process",
+            "node(",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid process pattern 'node('"));
+
+    pm_cmd(&config_path)
+        .args([
+            "allocate",
+            "webapp",
+            "web",
+            "8080",
+            "--expect-process",
+            "node.*vite",
+        ])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["list", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\"expect_process\": \"node.*vite\"",
+        ));
+}
+
+#[test]
+fn test_allocate_note_and_url() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args([
+            "allocate",
+            "webapp",
+            "admin",
+            "8443",
+            "--note",
+            "payments sandbox",
+            "--url-template",
+            "https://localhost:{port}/admin",
+        ])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["list", "--long"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("NOTE"))
+        .stdout(predicate::str::contains("payments sandbox"))
+        .stdout(predicate::str::contains("https://localhost:8443/admin"));
+    pm_cmd(&config_path)
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("payments sandbox").not());
+
+    let output = pm_cmd(&config_path)
+        .args(["list", "--json=v0"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["note"], "payments sandbox");
+    assert_eq!(json[0]["url"], "https://localhost:8443/admin");
+    assert!(json[1].get("url").is_none());
+
+    // Replacing the port keeps the note and template
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "admin", "8444", "--replace"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["open", "webapp", "admin", "--print"])
+        .assert()
+        .success()
+        .stdout("https://localhost:8444/admin\n");
+    pm_cmd(&config_path)
+        .args(["open", "webapp", "web", "--print"])
+        .assert()
+        .success()
+        .stdout("http://localhost:8080\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_open_uses_browser() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .env("BROWSER", "echo")
+        .args(["open", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout("http://localhost:8080\n");
+
+    pm_cmd(&config_path)
+        .env("BROWSER", "false")
+        .args(["open", "webapp", "web"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to run 'false'"));
+}
+
+#[test]
+fn test_urls() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args([
+            "allocate",
+            "webapp",
+            "admin",
+            "8443",
+            "--url-template",
+            "https://{host}:{port}/admin",
+        ])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "db", "5432", "--host", "devbox"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["urls", "webapp"])
+        .assert()
+        .success()
+        .stdout(
+            "admin=https://localhost:8443/admin\n\
+             db=http://devbox:5432\n\
+             web=http://localhost:8080\n",
+        );
+    pm_cmd(&config_path)
+        .args(["urls", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout("http://localhost:8080\n");
+
+    let output = pm_cmd(&config_path)
+        .args(["urls", "webapp", "--json=v1"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["data"][0]["name"], "admin");
+    assert_eq!(json["data"][0]["port"], 8443);
+    assert_eq!(json["data"][0]["url"], "https://localhost:8443/admin");
+}
+
+#[test]
+fn test_host_substitution() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "db", "5432", "--host", "devbox"])
+        .assert()
+        .success();
+
+    // Allocations on other machines keep their own host
+    pm_cmd(&config_path)
+        .args(["urls", "webapp", "--host", "10.0.0.5"])
+        .assert()
+        .success()
+        .stdout("db=http://devbox:5432\nweb=http://10.0.0.5:8080\n");
+    pm_cmd(&config_path)
+        .args(["env", "webapp", "--host", "10.0.0.5"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("WEB_PORT=8080\n"))
+        .stdout(predicate::str::contains("WEB_URL=http://10.0.0.5:8080\n"));
+    pm_cmd(&config_path)
+        .args(["export", "nginx", "webapp", "--host", "10.0.0.5"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("proxy_pass http://10.0.0.5:8080;"));
+    pm_cmd(&config_path)
+        .args(["urls", "webapp", "--host", "not a host"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_allocate_replace() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080", "--group", "stack"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8081"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8081", "--replace"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Reallocated webapp.web: 8080 -> 8081",
+        ));
+
+    pm_cmd(&config_path)
+        .args(["query", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("8081"));
+
+    // Metadata not given again is kept
+    pm_cmd(&config_path)
+        .args(["list", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"group\": \"stack\""));
+
+    // Nothing to replace is a plain allocation
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api", "3000", "--replace"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.api = 3000"));
+}
+
+#[test]
+fn test_allocate_with_type() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // "admin" has no range of its own, so it would land in the default one
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "admin", "--type", "web"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.admin = 8000"));
+
+    let registry = std::fs::read_to_string(&config_path).unwrap();
+    assert!(registry.contains("type = \"web\""));
+
+    // The stored type still picks the range when reallocating
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "admin", "--replace"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Reallocated webapp.admin: 8000 -> 8001",
+        ));
+}
+
+#[test]
+fn test_type_rules() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    std::fs::write(
+        &config_path,
+        "[[defaults.type_rules]]\nname = \"frontend*\"\ntype = \"web\"\n\n\
+         [[defaults.type_rules]]\nregex = \"-grpc$\"\ntype = \"api\"\n",
+    )
+    .unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "shop", "frontend-admin"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Allocated shop.frontend-admin = 8000",
+        ));
+    pm_cmd(&config_path)
+        .args(["allocate", "shop", "users-grpc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Allocated shop.users-grpc = 3000"));
+    // --type wins over the rules
+    pm_cmd(&config_path)
+        .args(["allocate", "shop", "frontend-db", "--type", "db"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Allocated shop.frontend-db = 5400",
+        ));
+
+    pm_cmd(&config_path)
+        .args(["config"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("frontend* -> web"))
+        .stdout(predicate::str::contains("/-grpc$/ -> api"));
+}
+
+#[test]
+fn test_quotas() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    std::fs::write(
+        &config_path,
+        "[defaults.quotas]\nper_project = 1\nper_user = 2\n",
+    )
+    .unwrap();
+
+    pm_cmd(&config_path)
+        .env("USER", "alice")
+        .args(["allocate", "webapp", "web"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .env("USER", "alice")
+        .args(["allocate", "webapp", "api"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "project 'webapp' already has 1 ports allocated",
+        ));
+    pm_cmd(&config_path)
+        .env("USER", "alice")
+        .args(["allocate", "shop", "web"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .env("USER", "alice")
+        .args(["allocate", "blog", "web"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("user 'alice'"));
+    pm_cmd(&config_path)
+        .env("USER", "bob")
+        .args(["allocate", "blog", "web"])
+        .assert()
+        .success();
+
+    let registry = std::fs::read_to_string(&config_path).unwrap();
+    assert!(registry.contains("owner = \"alice\""));
+    pm_cmd(&config_path)
+        .args(["config"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("per user: 2"));
+}
+
+#[test]
+fn test_allocate_if_absent() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    for _ in 0..2 {
+        pm_cmd(&config_path)
+            .args(["allocate", "webapp", "web", "8080", "--if-absent"])
+            .assert()
+            .success();
+    }
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "--if-absent"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "webapp.web = 8080 (already allocated)",
+        ));
+
+    // A different explicit port is still an error
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8081", "--if-absent"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "--if-absent", "--replace"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_allocate_invalid_names() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "a b", "x=y", "8080"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid name 'x=y'"));
+
+    pm_cmd(&config_path)
+        .args(["allocate", "a b", "web", "8080"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid name 'a b'"));
+
+    // Nothing was written
+    pm_cmd(&config_path)
+        .args(["query", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn test_alias_invalid_name() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["alias", "webapp", "web", "web/api"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid character '/'"));
+}
+
+#[test]
+fn test_registry_with_legacy_names_loads() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    std::fs::write(&config_path, "[projects.\"a b\"]\n\"web.v2\" = 8080\n").unwrap();
+
+    // Names older versions allowed still load and can be renamed
+    pm_cmd(&config_path)
+        .args(["query", "a b"])
+        .assert()
+        .success()
+        .stdout("web.v2=8080\n");
+    pm_cmd(&config_path)
+        .args(["config", "validate"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "invalid character '.' [invalid-name]",
+        ));
+    pm_cmd(&config_path)
+        .args(["rename-port", "a b", "web.v2", "web-v2"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web.v2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid character '.'"));
+}
+
+#[test]
+fn test_config_invalid_range_format() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["config", "--set", "invalid"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("format"));
+}
+
+#[test]
+fn test_json_errors() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    let output = pm_cmd(&config_path)
+        .args(["query", "nope", "--json=v1"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let json: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(json["apiVersion"], "v1");
+    assert_eq!(json["error"]["code"], "E_PROJECT_NOT_FOUND");
+    assert_eq!(json["error"]["kind"], "not_found");
+    assert!(json["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("'nope' not found"));
+    assert!(!json["error"]["hints"].as_array().unwrap().is_empty());
+
+    let output = pm_cmd(&config_path)
+        .args(["query", "nope", "--json"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(json["error"]["code"], "E_PROJECT_NOT_FOUND");
+    assert!(json.get("apiVersion").is_none());
+
+    // Without --json, errors stay plain text, even if a command pm runs gets one
+    pm_cmd(&config_path)
+        .args(["query", "nope"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with("Error: "));
+    pm_cmd(&config_path)
+        .args(["run", "nope", "--", "tool", "--json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with("Error: "));
+}
+
+#[test]
+fn test_explain() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["explain", "e_port_in_use"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "E_PORT_IN_USE: Something is already listening on the port\n",
+        ))
+        .stdout(predicate::str::contains("To fix:"));
+
+    pm_cmd(&config_path)
+        .args(["explain"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("E_PROJECT_NOT_FOUND"));
+
+    pm_cmd(&config_path)
+        .args(["explain", "E_NOPE"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown error code 'E_NOPE'"));
+}
+
+// ============================================================================
+// Alias Tests
+// ============================================================================
+
+#[test]
+fn test_allocate_alias() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["a", "webapp", "web", "8080"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.web = 8080"));
+}
+
+#[test]
+fn test_free_alias() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate first
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    // Free using alias
+    pm_cmd(&config_path)
+        .args(["f", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Freed"));
+}
+
+#[test]
+fn test_list_alias() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path).args(["ls"]).assert().success();
+}
+
+#[test]
+fn test_query_alias() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate first
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["q", "webapp"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("8080"));
+}
+
+// ============================================================================
+// Persistence Tests
+// ============================================================================
+
+#[test]
+fn test_config_persists() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate a port
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    // Check the config file was created and contains the allocation
+    let content = fs::read_to_string(&config_path).unwrap();
+    assert!(content.contains("webapp"));
+    assert!(content.contains("8080"));
+}
+
+#[test]
+fn test_config_dir_env() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let config_dir = temp_dir.path().join("pm");
+
+    // PM_CONFIG_DIR takes precedence over PM_CONFIG_PATH
+    pm_cmd(&config_path)
+        .env("PM_CONFIG_DIR", &config_dir)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+    assert!(config_dir.join("registry.toml").exists());
+    assert!(config_dir.join(".registry.lock").exists());
+    assert!(!std::path::Path::new(&config_path).exists());
+
+    pm_cmd(&config_path)
+        .env("PM_CONFIG_DIR", &config_dir)
+        .args(["config", "--path"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("registry.toml"))
+        .stdout(predicate::str::contains("pm"));
+}
+
+#[test]
+fn test_config_flag() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let config_dir = temp_dir.path().join("pm");
+    let other = temp_dir.path().join("other");
+    fs::create_dir(&other).unwrap();
+
+    // --config wins over both environment variables; relative paths resolve
+    // against the current directory
+    pm_cmd(&config_path)
+        .env("PM_CONFIG_DIR", &config_dir)
+        .current_dir(&other)
+        .args(["--config", "team.toml", "allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+    assert!(other.join("team.toml").exists());
+    assert!(other.join(".registry.lock").exists());
+    assert!(!config_dir.exists());
+    assert!(!std::path::Path::new(&config_path).exists());
+
+    // Global, so it also works after the subcommand
+    pm_cmd(&config_path)
+        .args(["query", "webapp", "web", "-c"])
+        .arg(other.join("team.toml"))
+        .assert()
+        .success()
+        .stdout("8080\n");
+    pm_cmd(&config_path)
+        .args(["query", "webapp", "web"])
+        .assert()
+        .failure();
+}
+
+/// Runs git in `dir` with a fixed identity and returns its stdout.
+fn git_in(dir: &std::path::Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {args:?} failed");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Like `pm_cmd`, with a git identity for git-sync commits.
+fn pm_git_cmd(config_path: &std::path::Path) -> assert_cmd::Command {
+    let mut cmd = pm_cmd(&config_path.to_string_lossy());
+    cmd.env("GIT_AUTHOR_NAME", "pm test")
+        .env("GIT_AUTHOR_EMAIL", "pm@example.com")
+        .env("GIT_COMMITTER_NAME", "pm test")
+        .env("GIT_COMMITTER_EMAIL", "pm@example.com");
+    cmd
+}
+
+#[test]
+fn test_git_sync() {
+    let temp_dir = TempDir::new().unwrap();
+    let laptop = temp_dir.path().join("laptop");
+    let remote = temp_dir.path().join("remote.git");
+    fs::create_dir(&laptop).unwrap();
+    fs::create_dir(&remote).unwrap();
+    git_in(&remote, &["init", "--quiet", "--bare"]);
+    let laptop_registry = laptop.join("registry.toml");
+
+    pm_git_cmd(&laptop_registry)
+        .args(["sync"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pm config --git-sync true"));
+
+    pm_git_cmd(&laptop_registry)
+        .args(["config", "--git-sync", "true"])
+        .assert()
+        .success();
+    pm_git_cmd(&laptop_registry)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+
+    // Each change is a commit describing it; auxiliary files stay untracked
+    let message = git_in(&laptop, &["log", "-1", "--format=%B"]);
+    assert!(message.starts_with("pm allocate\n"));
+    assert!(message.contains("+ webapp.web = 8000"));
+    assert_eq!(
+        git_in(&laptop, &["ls-files"]),
+        ".gitignore\nregistry.toml\n"
+    );
+    assert_eq!(git_in(&laptop, &["status", "--porcelain"]), "");
+
+    pm_git_cmd(&laptop_registry)
+        .args(["sync"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pm sync --remote"));
+    pm_git_cmd(&laptop_registry)
+        .args(["sync", "--remote"])
+        .arg(&remote)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Synced registry with"));
+
+    // A teammate clones the registry, changes it, and syncs back
+    let desktop = temp_dir.path().join("desktop");
+    git_in(
+        temp_dir.path(),
+        &["clone", "--quiet", &remote.to_string_lossy(), "desktop"],
+    );
+    let desktop_registry = desktop.join("registry.toml");
+    pm_git_cmd(&desktop_registry)
+        .args(["query", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout("8000\n");
+    pm_git_cmd(&desktop_registry)
+        .args(["allocate", "api", "http", "3000"])
+        .assert()
+        .success();
+    pm_git_cmd(&desktop_registry)
+        .args(["sync"])
+        .assert()
+        .success();
+
+    pm_git_cmd(&laptop_registry)
+        .args(["allocate", "webapp", "db", "5432"])
+        .assert()
+        .success();
+    pm_git_cmd(&laptop_registry)
+        .args(["sync"])
+        .assert()
+        .success();
+    pm_git_cmd(&laptop_registry)
+        .args(["query", "api", "http"])
+        .assert()
+        .success()
+        .stdout("3000\n");
+    pm_git_cmd(&laptop_registry)
+        .args(["query", "webapp", "db"])
+        .assert()
+        .success()
+        .stdout("5432\n");
+
+    // Both take port 9000; git merges the two cleanly, but the result is
+    // invalid, so the pull is undone
+    pm_git_cmd(&desktop_registry)
+        .args(["sync"])
+        .assert()
+        .success();
+    pm_git_cmd(&desktop_registry)
+        .args(["allocate", "zoo", "web", "9000"])
+        .assert()
+        .success();
+    pm_git_cmd(&desktop_registry)
+        .args(["sync"])
+        .assert()
+        .success();
+    pm_git_cmd(&laptop_registry)
+        .args(["allocate", "aardvark", "web", "9000"])
+        .assert()
+        .success();
+    let head = git_in(&laptop, &["rev-parse", "HEAD"]);
+    pm_git_cmd(&laptop_registry)
+        .args(["sync"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("duplicate-port"))
+        .stderr(predicate::str::contains("Undid the pull"));
+    assert_eq!(git_in(&laptop, &["rev-parse", "HEAD"]), head);
+    pm_git_cmd(&laptop_registry)
+        .args(["query", "zoo"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_git_sync_refuses_foreign_repo() {
+    let temp_dir = TempDir::new().unwrap();
+    let dotfiles = temp_dir.path().join("dotfiles");
+    fs::create_dir(&dotfiles).unwrap();
+    git_in(&dotfiles, &["init", "--quiet"]);
+    let registry = dotfiles.join("registry.toml");
+
+    pm_git_cmd(&registry)
+        .args(["config", "--git-sync", "true"])
+        .assert()
+        .success();
+    pm_git_cmd(&registry)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("git repository pm didn't create"));
+    pm_git_cmd(&registry)
+        .args(["sync"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("PM_CONFIG_PATH"));
+    assert!(!dotfiles.join(".gitignore").exists());
+}
+
+#[test]
+fn test_profiles() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let work = temp_dir
+        .path()
+        .join("profiles")
+        .join("work")
+        .join("registry.toml");
+
+    pm_cmd(&config_path)
+        .args(["allocate", "home", "web", "18080"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["profile", "create", "work"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created profile work"));
+    assert!(work.exists());
+
+    pm_cmd(&config_path)
+        .args(["--profile", "work", "allocate", "office", "web", "18081"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["query", "--all"])
+        .assert()
+        .success()
+        .stdout("home.web=18080\n");
+
+    // Switching changes the default for later commands
+    pm_cmd(&config_path)
+        .args(["profile", "switch", "work"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["query", "--all"])
+        .assert()
+        .success()
+        .stdout("office.web=18081\n");
+    pm_cmd(&config_path)
+        .args(["profile", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("  default"))
+        .stdout(predicate::str::contains("* work"));
+    pm_cmd(&config_path)
+        .args(["--profile", "default", "query", "--all"])
+        .assert()
+        .success()
+        .stdout("home.web=18080\n");
+
+    pm_cmd(&config_path)
+        .args(["profile", "switch", "default"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["query", "--all"])
+        .assert()
+        .success()
+        .stdout("home.web=18080\n");
+}
+
+#[test]
+fn test_profile_errors() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["--profile", "nope", "list"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Profile 'nope' not found"));
+    pm_cmd(&config_path)
+        .args(["profile", "switch", "nope"])
+        .assert()
+        .failure();
+    pm_cmd(&config_path)
+        .args(["profile", "create", "../escape"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid name"));
+
+    pm_cmd(&config_path)
+        .args(["profile", "create", "work"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["profile", "create", "work"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+}
+
+#[test]
+fn test_system_registry_overlay() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let system_path = temp_dir.path().join("system.toml");
+    fs::write(
+        &system_path,
+        "[defaults.ranges]\ngpu = [7100, 7199]\n\n[projects.shared]\ndb = 5432\n",
+    )
+    .unwrap();
+    let pm = || {
+        let mut cmd = pm_cmd(&config_path);
+        cmd.env("PM_SYSTEM_REGISTRY", &system_path);
+        cmd
+    };
+
+    // Reserved ports can't be taken
+    pm().args(["allocate", "webapp", "db", "5432"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already allocated to shared.db"));
+
+    // System ranges are available and reservations are visible
+    pm().args(["allocate", "webapp", "gpu"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("7100"));
+    pm().args(["query", "shared", "db"])
+        .assert()
+        .success()
+        .stdout("5432\n");
+    pm().args(["list", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"system\": true"));
+    pm().args(["config"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("System registry:"));
+
+    // The user registry never receives system entries
+    let content = fs::read_to_string(&config_path).unwrap();
+    assert!(!content.contains("5432"));
+    assert!(!content.contains("7199"));
+
+    // A broken system registry is skipped with a warning
+    fs::write(&system_path, "not toml [").unwrap();
+    pm().args(["query", "webapp", "gpu"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Ignoring system registry"));
+}
+
+#[test]
+fn test_torn_journal_is_discarded() {
+    let (temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    // A process that died while writing the journal never touched the registry
+    let journal = temp_dir.path().join(".registry.journal");
+    fs::write(&journal, "{\"version\":1,\"before\":null,\"af").unwrap();
+
+    pm_cmd(&config_path)
+        .args(["query", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout("8080\n")
+        .stderr(predicate::str::contains(
+            "Discarded an incomplete registry write",
+        ));
+    assert!(!journal.exists());
+}
+
+#[test]
+fn test_watch_registry() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    let mut watcher = Command::cargo_bin("pm").unwrap();
+    let mut child = watcher
+        .env("PM_CONFIG_PATH", &config_path)
+        .env_remove("PM_CONFIG_DIR")
+        .env("PM_SYSTEM_REGISTRY", "")
+        .args(["watch-registry", "--json=v0"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let _ = tx.send(line.unwrap());
+        }
+    });
+
+    // Give the watcher time to start, then change the registry
+    std::thread::sleep(Duration::from_millis(500));
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api", "3000"])
+        .assert()
+        .success();
+    let line = rx.recv_timeout(Duration::from_secs(10));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    let line = line.expect("no change reported");
+    assert_eq!(
+        line,
+        r#"{"added":[{"project":"webapp","name":"api","port":3000}],"removed":[],"changed":[]}"#
+    );
+}
+
+#[test]
+fn test_ide() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    let (_temp_dir, config_path) = setup_temp_config();
+    let mut ide = Command::cargo_bin("pm").unwrap();
+    let mut child = ide
+        .env("PM_CONFIG_PATH", &config_path)
+        .env_remove("PM_CONFIG_DIR")
+        .env("PM_SYSTEM_REGISTRY", "")
+        .arg("ide")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let message: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+            let _ = tx.send(message);
+        }
+    });
+    // Collects messages until one matches, failing after a timeout
+    let wait_for = |pred: &dyn Fn(&serde_json::Value) -> bool| {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let message = rx.recv_timeout(remaining).expect("message not received");
+            if pred(&message) {
+                return message;
+            }
+        }
+    };
+
+    let status = wait_for(&|m| m["method"] == "v1.status");
+    assert_eq!(status["params"]["ports"], serde_json::json!([]));
+
+    // Give the registry watcher time to start
+    std::thread::sleep(Duration::from_millis(500));
+    writeln!(
+        stdin,
+        r#"{{"jsonrpc":"2.0","id":1,"method":"v1.allocate","params":{{"project":"webapp","name":"api","port":3000}}}}"#
+    )
+    .unwrap();
+    let response = wait_for(&|m| m["id"] == 1);
+    assert_eq!(
+        response["result"],
+        serde_json::json!({"project": "webapp", "name": "api", "port": 3000, "previous": null})
+    );
+    let changed = wait_for(&|m| m["method"] == "v1.changed");
+    assert_eq!(changed["params"]["change"]["added"][0]["port"], 3000);
+
+    writeln!(
+        stdin,
+        r#"{{"jsonrpc":"2.0","id":2,"method":"v1.query","params":{{"project":"missing"}}}}"#
+    )
+    .unwrap();
+    let response = wait_for(&|m| m["id"] == 2);
+    assert_eq!(response["error"]["code"], -32001);
+    assert_eq!(response["error"]["data"]["kind"], "not_found");
+
+    // Closing stdin ends the session
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn test_multiple_projects() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate ports to multiple projects
+    pm_cmd(&config_path)
+        .args(["allocate", "frontend", "web", "8080"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "backend", "api", "3000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "database", "db", "5432"])
+        .assert()
+        .success();
+
+    // Query each project
+    pm_cmd(&config_path)
+        .args(["query", "frontend", "web"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("8080"));
+
+    pm_cmd(&config_path)
+        .args(["query", "backend", "api"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3000"));
+
+    pm_cmd(&config_path)
+        .args(["query", "database", "db"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("5432"));
+}
+
+// ============================================================================
+// JSON Output Tests
+// ============================================================================
+
+#[test]
+fn test_list_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate a port first
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    // List with JSON output
+    pm_cmd(&config_path)
+        .args(["list", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"project\""))
+        .stdout(predicate::str::contains("\"port\""))
+        .stdout(predicate::str::contains("8080"))
+        .stdout(predicate::str::contains("webapp"));
+}
+
+#[test]
+fn test_list_json_empty() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // List with JSON output when empty
+    pm_cmd(&config_path)
+        .args(["list", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[]"));
+}
+
+#[test]
+fn test_query_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate a port first
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    // Query with JSON output
+    pm_cmd(&config_path)
+        .args(["query", "webapp", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"name\""))
+        .stdout(predicate::str::contains("\"port\""))
+        .stdout(predicate::str::contains("8080"));
+}
+
+#[test]
+fn test_status_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Status with JSON output
+    pm_cmd(&config_path)
+        .args(["status", "--json=v1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{\n  \"apiVersion\": \"v1\""));
+}
+
+#[test]
+fn test_status_full() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Status with --full flag shows DIRECTORY column
+    pm_cmd(&config_path)
+        .args(["status", "--full"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_status_full_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Status with --full --json includes cwd field
+    pm_cmd(&config_path)
+        .args(["status", "--full", "--json=v1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{\n  \"apiVersion\": \"v1\""));
+}
+
+#[test]
+fn test_suggest_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // v2 lists each port with the range it came from
+    let output = pm_cmd(&config_path)
+        .args(["suggest", "--type", "web", "--json=v2"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["apiVersion"], "v2");
+    assert_eq!(
+        json["data"],
+        serde_json::json!([{"port": 8000, "type": "web", "range": [8000, 8999]}])
+    );
+
+    // A type without a range uses the default one
+    let output = pm_cmd(&config_path)
+        .args(["suggest", "--type", "nope", "--json=v2"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["data"][0]["type"], "default");
+
+    // Earlier versions, including a bare --json, keep bare numbers
+    let output = pm_cmd(&config_path)
+        .args(["suggest", "--type", "web", "--json=v1"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["data"], serde_json::json!([8000]));
+    pm_cmd(&config_path)
+        .args(["suggest", "--type", "web", "--json"])
+        .assert()
+        .success()
+        .stdout("[\n  8000\n]\n");
+}
+
+#[test]
+fn test_suggest_multiple_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Suggest multiple with JSON output
+    pm_cmd(&config_path)
+        .args(["suggest", "3", "--json=v2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{\n  \"apiVersion\": \"v2\""));
+}
+
+#[test]
+fn test_config_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Config with JSON output
+    pm_cmd(&config_path)
+        .args(["config", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"ranges\""))
+        .stdout(predicate::str::contains("\"web\""));
+}
+
+#[test]
+fn test_json_pinned_v1() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    let output = pm_cmd(&config_path)
+        .args(["query", "webapp", "--json=v1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["apiVersion"], "v1");
+    assert_eq!(json["data"][0]["name"], "web");
+    assert_eq!(json["data"][0]["port"], 8080);
+}
+
+#[test]
+fn test_json_v0_is_bare() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["suggest", "--json=v0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("["));
+
+    pm_cmd(&config_path)
+        .args(["config", "--json=v0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{\n  \"ranges\""));
+}
+
+#[test]
+fn test_json_unknown_version() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["list", "--json=v9"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_config_path_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Config with --path and JSON output
+    pm_cmd(&config_path)
+        .args(["config", "--path", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"config_file\""))
+        .stdout(predicate::str::contains("\"ranges\""));
+}
+
+// ============================================================================
+// List Filtering Tests
+// ============================================================================
+
+#[test]
+fn test_list_active_only() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate a port (will be idle since nothing is running on it)
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "18080"])
+        .assert()
+        .success();
+
+    // List with --active should not show idle ports
+    pm_cmd(&config_path)
+        .args(["list", "--active"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_list_active_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // Allocate a port
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "18081"])
+        .assert()
+        .success();
+
+    // List with --active --json
+    pm_cmd(&config_path)
+        .args(["list", "--active", "--json=v1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{\n  \"apiVersion\": \"v1\""));
+}
+
+#[test]
+fn test_list_unassigned() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // List unassigned ports (ports listening but not in registry)
+    pm_cmd(&config_path)
+        .args(["list", "--unassigned"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_list_unassigned_json() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // List unassigned ports with JSON
+    pm_cmd(&config_path)
+        .args(["list", "--unassigned", "--json=v1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{\n  \"apiVersion\": \"v1\""));
+}
+
+// ============================================================================
+// Concurrent Access Tests
+// ============================================================================
+
+#[test]
+fn test_concurrent_allocations_no_duplicates() {
     let (_temp_dir, config_path) = setup_temp_config();
 
     // Spawn multiple processes concurrently trying to allocate ports
@@ -714,47 +3387,1988 @@ fn test_concurrent_allocations_no_duplicates() {
         handles.push(handle);
     }
 
-    // Wait for all threads and collect results
-    let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    // Wait for all threads and collect results
+    let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    // All should succeed
+    for result in &results {
+        assert!(result.status.success(), "Allocation failed: {:?}", result);
+    }
+
+    // Verify all ports are unique using list command
+    pm_cmd(&config_path)
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("project0"))
+        .stdout(predicate::str::contains("project1"))
+        .stdout(predicate::str::contains("project2"))
+        .stdout(predicate::str::contains("project3"))
+        .stdout(predicate::str::contains("project4"));
+
+    // Read the config file and verify no duplicate ports
+    let content = fs::read_to_string(&config_path).unwrap();
+    // Match port allocations (single number) not range definitions (arrays)
+    let ports: Vec<u16> = content
+        .lines()
+        .filter_map(|line| {
+            let trimmed = line.trim();
+            if trimmed.starts_with("web = ") && !trimmed.contains('[') {
+                // Extract the port number (e.g., "web = 8000" -> 8000)
+                trimmed.strip_prefix("web = ")?.parse().ok()
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Should have 5 unique port assignments
+    assert_eq!(ports.len(), 5, "Expected 5 port assignments");
+
+    // Check all ports are unique
+    let mut unique_ports = ports.clone();
+    unique_ports.sort();
+    unique_ports.dedup();
+    assert_eq!(unique_ports.len(), 5, "All ports should be unique");
+}
+
+// ============================================================================
+// Group Tests
+// ============================================================================
+
+#[test]
+fn test_free_group_across_projects() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080", "--group", "sprint-42"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "backend", "api", "3001", "--group", "sprint-42"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api", "3000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["free", "--group", "sprint-42"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Freed webapp.web (was 8080)"))
+        .stdout(predicate::str::contains("Freed backend.api (was 3001)"));
+
+    // Ungrouped allocation is untouched
+    pm_cmd(&config_path)
+        .args(["query", "webapp"])
+        .assert()
+        .success()
+        .stdout("api=3000\n");
+}
+
+#[test]
+fn test_free_unknown_group() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["free", "--group", "nope"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No allocations found in group"));
+}
+
+#[test]
+fn test_free_orphans() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    for (project, name, port) in [
+        ("webapp", "api", "3000"),
+        ("webapp", "web", "8080"),
+        ("infra", "api", "3001"),
+    ] {
+        pm_cmd(&config_path)
+            .args(["allocate", project, name, port])
+            .assert()
+            .success();
+    }
+    pm_cmd(&config_path)
+        .args(["lock", "infra", "api"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["config", "--set", "api=4000-4099"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["free", "--orphans", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "webapp.api = 3000 is outside every configured range",
+        ))
+        .stdout(predicate::str::contains("webapp.web").not());
+
+    pm_cmd(&config_path)
+        .args(["free", "--orphans", "--migrate", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Moved webapp.api: 3000 -> 4000"))
+        .stdout(predicate::str::contains("Left infra.api on 3001 (locked)"));
+
+    // Locked orphans are left unless --force
+    pm_cmd(&config_path)
+        .args(["free", "--orphans", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Left infra.api on 3001 (locked)"));
+    pm_cmd(&config_path)
+        .args(["free", "--orphans", "--yes", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Freed infra.api (was 3001)"));
+
+    pm_cmd(&config_path)
+        .args(["free", "--orphans"])
+        .assert()
+        .success()
+        .stdout("No allocations outside the configured ranges\n");
+}
+
+#[test]
+fn test_free_orphan_flags_need_orphans() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    for args in [
+        &["free", "webapp", "web", "--dry-run"][..],
+        &["free", "webapp", "--yes", "--migrate"],
+        &["free", "--group", "sprint-42", "--yes"],
+    ] {
+        pm_cmd(&config_path).args(args).assert().failure();
+    }
+    pm_cmd(&config_path)
+        .args(["query", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout("8080\n");
+}
+
+// ============================================================================
+// Scheduled Free Tests
+// ============================================================================
+
+#[test]
+fn test_scheduled_free() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "demo", "web", "8080"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "demo", "api", "3000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["free", "demo", "web", "--after", "2h"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "Scheduled demo.web (8080) to be freed at ",
+        ));
+    pm_cmd(&config_path)
+        .args(["free", "demo", "api", "--at", "2020-01-01T00:00:00Z"])
+        .assert()
+        .success();
+
+    // Scheduling doesn't free anything yet
+    let output = pm_cmd(&config_path)
+        .args(["list", "--json=v0"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert_eq!(json[0]["free_at"], "2020-01-01T00:00:00+00:00");
+
+    pm_cmd(&config_path)
+        .args(["gc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "Freed demo.api (was 3000, scheduled for ",
+        ));
+    pm_cmd(&config_path)
+        .args(["gc"])
+        .assert()
+        .success()
+        .stdout("Nothing to free\n");
+    pm_cmd(&config_path)
+        .args(["query", "demo", "web"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_gc_dry_run_and_ttl() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "demo", "web", "8080", "--ttl", "1s"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "demo", "api", "3000", "--ttl", "3d"])
+        .assert()
+        .success();
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+
+    pm_cmd(&config_path)
+        .args(["gc", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Would free demo.web (was 8080, scheduled for ",
+        ))
+        .stdout(predicate::str::contains(
+            "Would free 1 allocation: 1 scheduled",
+        ));
+    pm_cmd(&config_path)
+        .args(["query", "demo", "web"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["gc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Freed 1 allocation: 1 scheduled"));
+    pm_cmd(&config_path)
+        .args(["query", "demo"])
+        .assert()
+        .success()
+        .stdout("api=3000\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_gc_frees_when_bound_process_exits() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    let pid = child.id().to_string();
+    child.wait().unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "demo", "web", "8080", "--pid", &pid])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "demo", "api", "3000", "--pid", "1"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["gc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Freed demo.web (was 8080, process {pid} exited)"
+        )))
+        .stdout(predicate::str::contains("1 process exited"));
+    pm_cmd(&config_path)
+        .args(["query", "demo"])
+        .assert()
+        .success()
+        .stdout("api=3000\n");
+}
+
+#[test]
+fn test_allocate_rejects_non_positive_pid() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    // kill(0) and kill(-1) address process groups, so liveness checks on
+    // them would never fail
+    for pid in ["--pid=0", "--pid=-1"] {
+        pm_cmd(&config_path)
+            .args(["allocate", "demo", "web", "8080", pid])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--pid"));
+    }
+    pm_cmd(&config_path)
+        .args(["query", "demo"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_usage() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .arg("usage")
+        .assert()
+        .success()
+        .stdout("No allocation changes recorded yet\n");
+    for args in [
+        ["allocate", "demo", "web", "8080"],
+        ["allocate", "demo", "api", "3000"],
+        ["allocate", "shop", "db", "5400"],
+    ] {
+        pm_cmd(&config_path).args(args).assert().success();
+    }
+    pm_cmd(&config_path)
+        .args(["free", "demo", "web"])
+        .assert()
+        .success();
+
+    let output = pm_cmd(&config_path)
+        .args(["usage", "--json=v0"])
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["allocated"], 3);
+    assert_eq!(report["freed"], 1);
+    assert_eq!(report["peak"], 3);
+    assert_eq!(report["projects"][0]["project"], "demo");
+    assert_eq!(report["projects"][0]["peak"], 2);
+    assert!(report["projects"][0]["average_lifetime_secs"].is_u64());
+    assert!(report["projects"][1]["average_lifetime_secs"].is_null());
+}
+
+#[test]
+fn test_config_stale_after_days() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["config", "--stale-after-days", "30"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["config"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("idle for 30 days"));
+    pm_cmd(&config_path)
+        .args(["config", "--stale-after-days", "0"])
+        .assert()
+        .success()
+        .stdout("pm gc no longer frees idle allocations\n");
+    assert!(!fs::read_to_string(&config_path)
+        .unwrap()
+        .contains("stale_after_days"));
+}
+
+#[test]
+fn test_scheduled_free_rejects_bad_times() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["free", "demo", "--after", "2 weeks"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a duration"));
+    pm_cmd(&config_path)
+        .args(["free", "demo", "--at", "6pm"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a time"));
+    pm_cmd(&config_path)
+        .args(["free", "demo", "--at", "18:00", "--force"])
+        .assert()
+        .failure();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_install_service_systemd() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (temp_dir, config_path) = setup_temp_config();
+    let xdg = temp_dir.path().join("xdg");
+    let units = xdg.join("systemd").join("user");
+
+    // Stand-in systemctl that records its arguments
+    let bin = temp_dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let log = temp_dir.path().join("systemctl.log");
+    let systemctl = bin.join("systemctl");
+    fs::write(
+        &systemctl,
+        format!("#!/bin/sh\necho \"$@\" >> '{}'\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&systemctl, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let service = |args: &[&str]| {
+        let mut cmd = pm_cmd(&config_path);
+        cmd.env("XDG_CONFIG_HOME", &xdg)
+            .env("PATH", &path)
+            .args(args);
+        cmd
+    };
+
+    service(&["install-service", "--print"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "# {}",
+            units.join("pm-gc.service").display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "Environment=\"PM_CONFIG_PATH={config_path}\""
+        )))
+        .stdout(predicate::str::contains("OnUnitActiveSec=15min"));
+    assert!(!units.exists());
+
+    service(&["install-service", "--interval", "5"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("pm gc will run every 5 minutes"));
+    assert!(fs::read_to_string(units.join("pm-gc.service"))
+        .unwrap()
+        .contains(" gc\n"));
+    assert!(fs::read_to_string(units.join("pm-gc.timer"))
+        .unwrap()
+        .contains("OnUnitActiveSec=5min"));
+
+    service(&["uninstall-service"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed"));
+    assert!(!units.join("pm-gc.service").exists());
+    assert!(!units.join("pm-gc.timer").exists());
+    assert_eq!(
+        fs::read_to_string(&log).unwrap(),
+        "--user daemon-reload\n--user enable --now pm-gc.timer\n\
+         --user disable --now pm-gc.timer\n--user daemon-reload\n"
+    );
+
+    service(&["uninstall-service"])
+        .assert()
+        .success()
+        .stdout("No pm service installed\n");
+}
+
+// ============================================================================
+// Port Alias Tests
+// ============================================================================
+
+#[test]
+fn test_alias_query() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "myapp", "web", "8080"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["alias", "myapp", "web", "frontend"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Aliased myapp.frontend -> web (8080)",
+        ));
+
+    pm_cmd(&config_path)
+        .args(["query", "myapp", "frontend"])
+        .assert()
+        .success()
+        .stdout("8080\n");
+
+    pm_cmd(&config_path)
+        .args(["unalias", "myapp", "frontend"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["query", "myapp", "frontend"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_alias_resolves_project_like_query() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = temp_dir.path().join("checkout");
+    fs::create_dir(&project_dir).unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "myapp", "web", "8080"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .arg("link")
+        .arg(&project_dir)
+        .arg("myapp")
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["alias", ".", "web", "frontend"])
+        .current_dir(&project_dir)
+        .assert()
+        .success()
+        .stdout("Aliased myapp.frontend -> web (8080)\n");
+
+    pm_cmd(&config_path)
+        .args(["config", "--ignore-case", "true"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["unalias", "MyApp", "frontend"])
+        .assert()
+        .success()
+        .stdout("Removed alias myapp.frontend (was web)\n");
+}
+
+#[test]
+fn test_free_alias_refused() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "myapp", "web", "8080"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["alias", "myapp", "web", "frontend"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["free", "myapp", "frontend"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is an alias for 'web'"));
+}
+
+// ============================================================================
+// Lock Tests
+// ============================================================================
+
+#[test]
+fn test_locked_port_requires_force() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "infra", "registry", "5000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["lock", "infra", "registry"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Locked infra.registry (5000)"));
+
+    pm_cmd(&config_path)
+        .args(["free", "infra"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is locked"));
+
+    pm_cmd(&config_path)
+        .args(["free", "infra", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Freed infra.registry (was 5000)"));
+}
+
+#[test]
+fn test_unlock_then_free() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "infra", "registry", "5000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["lock", "infra"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["unlock", "infra"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Unlocked infra.registry (5000)"));
+
+    pm_cmd(&config_path)
+        .args(["free", "infra", "registry"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_lock_resolves_project_like_free() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = temp_dir.path().join("checkout");
+    fs::create_dir(&project_dir).unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "infra", "registry", "5000"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .arg("link")
+        .arg(&project_dir)
+        .arg("infra")
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["lock", ".", "registry"])
+        .current_dir(&project_dir)
+        .assert()
+        .success()
+        .stdout("Locked infra.registry (5000)\n");
+
+    pm_cmd(&config_path)
+        .args(["config", "--ignore-case", "true"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["unlock", "INFRA"])
+        .assert()
+        .success()
+        .stdout("Unlocked infra.registry (5000)\n");
+}
+
+// ============================================================================
+// Prompt Tests
+// ============================================================================
+
+#[test]
+fn test_prompt_linked_directory() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = fs::canonicalize(temp_dir.path()).unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "18080"])
+        .assert()
+        .success();
+
+    // Link the directory by hand-editing the registry
+    let mut content = fs::read_to_string(&config_path).unwrap();
+    content.push_str(&format!(
+        "\n[links]\n{:?} = \"webapp\"\n",
+        project_dir.display().to_string()
+    ));
+    fs::write(&config_path, content).unwrap();
+
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args(["prompt"])
+        .assert()
+        .success()
+        .stdout("web:18080✗\n");
+}
+
+#[test]
+fn test_prompt_outside_project_is_silent() {
+    let (temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .current_dir(temp_dir.path())
+        .args(["prompt"])
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn test_tmux_segment() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "18080"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["tmux", "webapp"])
+        .assert()
+        .success()
+        .stdout("webapp #[dim]web:18080#[default]\n");
+
+    pm_cmd(&config_path)
+        .args(["tmux", "webapp", "--plain"])
+        .assert()
+        .success()
+        .stdout("webapp web:18080✗\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tmux_set_env() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "18080"])
+        .assert()
+        .success();
+
+    // Stand-in tmux that records its arguments
+    let bin = temp_dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let log = temp_dir.path().join("tmux.log");
+    let tmux = bin.join("tmux");
+    fs::write(
+        &tmux,
+        format!("#!/bin/sh\necho \"$@\" >> '{}'\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&tmux, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    pm_cmd(&config_path)
+        .env("PATH", path)
+        .args(["tmux", "webapp", "--set-env", "-t", "dev"])
+        .assert()
+        .success()
+        .stdout("Set WEB_PORT=18080 in tmux\n");
+
+    assert_eq!(
+        fs::read_to_string(&log).unwrap(),
+        "set-environment -t dev WEB_PORT 18080\n"
+    );
+}
+
+// ============================================================================
+// Directory Link Tests
+// ============================================================================
+
+#[test]
+fn test_link_infers_project() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = fs::canonicalize(temp_dir.path()).unwrap();
+
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args(["link", ".", "webapp"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-> webapp"));
+
+    // Single argument is the port name; the project comes from the link
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args(["allocate", "web"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.web ="));
+
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args(["allocate", ".", "api", "3000"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.api = 3000"));
+
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args(["query", ".", "api"])
+        .assert()
+        .success()
+        .stdout("3000\n");
+
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args(["query"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("api=3000"));
+
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args(["free", ".", "api"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Freed webapp.api (was 3000)"));
+}
+
+#[test]
+fn test_query_unlinked_directory_fails() {
+    let (temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .current_dir(temp_dir.path())
+        .args(["query"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No project linked to"));
+}
+
+#[test]
+fn test_unlink() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = fs::canonicalize(temp_dir.path()).unwrap();
+
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args(["link", ".", "webapp"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["link"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-> webapp"));
+
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args(["unlink", "."])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(was webapp)"));
+}
+
+#[test]
+fn test_monorepo_subpath_links() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let repo = fs::canonicalize(temp_dir.path()).unwrap().join("mono");
+    let api_dir = repo.join("packages").join("api");
+    fs::create_dir_all(api_dir.join("src")).unwrap();
+    fs::create_dir(repo.join(".git")).unwrap();
+
+    pm_cmd(&config_path)
+        .current_dir(&api_dir)
+        .args(["link", ".", "api", "--repo"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Linked packages/api -> api"));
+
+    assert!(fs::read_to_string(repo.join(".pm.toml"))
+        .unwrap()
+        .contains("\"packages/api\" = \"api\""));
+
+    pm_cmd(&config_path)
+        .args(["allocate", "api", "http", "3000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .current_dir(api_dir.join("src"))
+        .args(["query", ".", "http"])
+        .assert()
+        .success()
+        .stdout("3000\n");
+}
+
+// =============================================================================
+// Export Tests
+// =============================================================================
+
+#[test]
+fn test_export_caddy() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args([
+            "allocate",
+            "webapp",
+            "web",
+            "8000",
+            "--hostname",
+            "webapp.localhost",
+        ])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api", "3000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["export", "caddy", "webapp"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "http://webapp.localhost {\n    reverse_proxy 127.0.0.1:8000\n}",
+        ))
+        .stdout(predicate::str::contains("http://api.webapp.localhost {"));
+}
+
+#[test]
+fn test_export_rejects_invalid_hostnames() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000", "--hostname", "a.test;"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid hostname for webapp.web"));
+
+    // A hand-edited or imported registry isn't exported either
+    fs::write(
+        &config_path,
+        "[projects.webapp.web]\nport = 8000\nhostname = \"a.test { }\\nhttp://b.test\"\n",
+    )
+    .unwrap();
+    for proxy in ["nginx", "caddy"] {
+        pm_cmd(&config_path)
+            .args(["export", proxy])
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains("Invalid hostname for webapp.web"));
+    }
+    pm_cmd(&config_path)
+        .args(["config", "validate"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("invalid-hostname"));
+}
+
+#[test]
+fn test_export_nginx_all_projects() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "backend", "api", "3001"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["export", "nginx"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "server_name web.webapp.localhost;",
+        ))
+        .stdout(predicate::str::contains(
+            "proxy_pass http://127.0.0.1:3001;",
+        ));
+}
+
+#[test]
+fn test_export_unknown_project() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["export", "nginx", "nonexistent"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_export_procfile() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args([
+            "allocate",
+            "webapp",
+            "web",
+            "8000",
+            "--command",
+            "npm run dev -- --port {port}",
+        ])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "db", "5432"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["export", "overmind", "webapp"])
+        .assert()
+        .success()
+        .stdout("web: npm run dev -- --port 8000\n");
+}
+
+// =============================================================================
+// Import Tests
+// =============================================================================
+
+fn write_scan_fixture(dir: &std::path::Path) {
+    fs::write(
+        dir.join("package.json"),
+        r#"{"scripts": {"dev": "next dev -p 3001", "build": "next build"}}"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("docker-compose.yml"),
+        "services:\n  db:\n    image: postgres\n    ports:\n      - \"5433:5432\"\n",
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_import_scan_dry_run() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = temp_dir.path().join("shop");
+    fs::create_dir(&project_dir).unwrap();
+    write_scan_fixture(&project_dir);
+
+    pm_cmd(&config_path)
+        .args(["import", "scan", "--dry-run"])
+        .arg(&project_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "shop.dev = 3001 (package.json: \"dev\": \"next dev -p 3001\")",
+        ))
+        .stdout(predicate::str::contains(
+            "shop.db = 5433 (docker-compose.yml",
+        ));
+
+    pm_cmd(&config_path)
+        .args(["query", "shop"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_import_scan_confirms_each_finding() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = temp_dir.path().join("shop");
+    fs::create_dir(&project_dir).unwrap();
+    write_scan_fixture(&project_dir);
+
+    // Accept the first finding (dev), decline the second (db)
+    pm_cmd(&config_path)
+        .args(["import", "scan", "--project", "storefront"])
+        .arg(&project_dir)
+        .write_stdin("y\nn\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Allocated storefront.dev = 3001"));
+
+    pm_cmd(&config_path)
+        .args(["query", "storefront"])
+        .assert()
+        .success()
+        .stdout("dev=3001\n");
+}
+
+#[test]
+fn test_import_scan_skips_allocated_ports() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = temp_dir.path().join("shop");
+    fs::create_dir(&project_dir).unwrap();
+    write_scan_fixture(&project_dir);
+
+    pm_cmd(&config_path)
+        .args(["allocate", "other", "db", "5433"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["import", "scan", "--yes"])
+        .arg(&project_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Skipping 5433 (docker-compose.yml: - \"5433:5432\"): already allocated to other.db",
+        ))
+        .stdout(predicate::str::contains("Allocated shop.dev = 3001"));
+}
+
+#[test]
+fn test_import_netstat() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let listing = temp_dir.path().join("ss.txt");
+    fs::write(
+        &listing,
+        "\
+State  Recv-Q Send-Q Local Address:Port  Peer Address:Port Process
+LISTEN 0      4096       127.0.0.1:5432       0.0.0.0:*     users:((\"postgres\",pid=812,fd=5))
+LISTEN 0      511          0.0.0.0:3000       0.0.0.0:*     users:((\"node\",pid=901,fd=20))
+LISTEN 0      511             [::]:3000          [::]:*     users:((\"node\",pid=901,fd=21))
+",
+    )
+    .unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "shop", "web", "3000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["import", "netstat", "--as-status", "--json"])
+        .arg(&listing)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"project\": \"shop\""))
+        .stdout(predicate::str::contains("\"process\": \"postgres\""));
+
+    pm_cmd(&config_path)
+        .args(["import", "netstat", "--project", "box", "--yes"])
+        .arg(&listing)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Skipping 3000 (node): already allocated to shop.web",
+        ))
+        .stdout(predicate::str::contains("Allocated box.postgres = 5432"));
+
+    pm_cmd(&config_path)
+        .args(["query", "box"])
+        .assert()
+        .success()
+        .stdout("postgres=5432\n");
+}
+
+#[test]
+fn test_export_devcontainer_updates_file() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let devcontainer = temp_dir
+        .path()
+        .join(".devcontainer")
+        .join("devcontainer.json");
+    fs::create_dir(devcontainer.parent().unwrap()).unwrap();
+    fs::write(
+        &devcontainer,
+        "{\n  // base image\n  \"image\": \"node:20\",\n  \"forwardPorts\": [9229],\n}\n",
+    )
+    .unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .current_dir(temp_dir.path())
+        .args(["export", "devcontainer", "webapp"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("with 1 port(s) from webapp"));
+
+    let updated: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&devcontainer).unwrap()).unwrap();
+    assert_eq!(updated["image"], "node:20");
+    assert_eq!(updated["forwardPorts"], serde_json::json!([9229, 8000]));
+    assert_eq!(updated["portsAttributes"]["8000"]["label"], "web");
+}
+
+#[test]
+fn test_export_devcontainer_creates_file() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let devcontainer = temp_dir.path().join("devcontainer.json");
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["export", "devcontainer", "webapp", "--file"])
+        .arg(&devcontainer)
+        .assert()
+        .success();
+
+    let created: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&devcontainer).unwrap()).unwrap();
+    assert_eq!(created["forwardPorts"], serde_json::json!([8000]));
+}
+
+#[test]
+fn test_export_import_registry() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let export_file = temp_dir.path().join("registry.json");
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000", "--note", "frontend"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["alias", "webapp", "web", "frontend"])
+        .assert()
+        .success();
+
+    let output = pm_cmd(&config_path)
+        .args(["export", "registry", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let exported: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(exported["apiVersion"], "v1");
+    assert_eq!(exported["data"]["projects"]["webapp"]["web"]["port"], 8000);
+    fs::write(&export_file, &output.stdout).unwrap();
+
+    // Import into a fresh registry on "another machine"
+    let (_other_dir, other_config) = setup_temp_config();
+    pm_cmd(&other_config)
+        .args(["allocate", "old", "web", "8500"])
+        .assert()
+        .success();
+    pm_cmd(&other_config)
+        .args(["import", "registry"])
+        .arg(&export_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Replaced registry with 1 project(s) and 1 port(s)",
+        ));
+
+    pm_cmd(&other_config)
+        .args(["query", "webapp", "frontend"])
+        .assert()
+        .success()
+        .stdout("8000\n");
+    pm_cmd(&other_config)
+        .args(["query", "old", "web"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_import_registry_merge() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let import_file = temp_dir.path().join("import.json");
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+
+    fs::write(
+        &import_file,
+        r#"{"projects": {"webapp": {"web": 8000}, "api": {"http": 3000}}}"#,
+    )
+    .unwrap();
+    pm_cmd(&config_path)
+        .args(["import", "registry", "--merge"])
+        .arg(&import_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported 1 new entries"))
+        .stdout(predicate::str::contains("(1 already present)"));
+
+    pm_cmd(&config_path)
+        .args(["query", "api", "http"])
+        .assert()
+        .success()
+        .stdout("3000\n");
+
+    // Conflicts are all reported and nothing is imported
+    fs::write(
+        &import_file,
+        r#"{"projects": {"webapp": {"web": 8001}, "other": {"http": 3000}, "new": {"web": 4000}}}"#,
+    )
+    .unwrap();
+    pm_cmd(&config_path)
+        .args(["import", "registry", "--merge"])
+        .arg(&import_file)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "projects.webapp.web: is 8000 here but 8001 in the import",
+        ))
+        .stderr(predicate::str::contains(
+            "projects.other.http: port 3000 is already allocated to api.http",
+        ))
+        .stderr(predicate::str::contains("2 conflict(s)"));
+
+    pm_cmd(&config_path)
+        .args(["query", "new", "web"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_import_registry_invalid() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let import_file = temp_dir.path().join("import.json");
+
+    fs::write(
+        &import_file,
+        r#"{"projects": {"webapp": {"web": 8000}, "api": {"http": 8000}}}"#,
+    )
+    .unwrap();
+    pm_cmd(&config_path)
+        .args(["import", "registry"])
+        .arg(&import_file)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("duplicate-port"));
+
+    fs::write(&import_file, "{not json").unwrap();
+    pm_cmd(&config_path)
+        .args(["import", "registry"])
+        .arg(&import_file)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to parse JSON"));
+
+    // Names are checked as in the TOML registry, whether replacing or merging
+    fs::write(
+        &import_file,
+        r#"{"projects": {"a b": {"web": 8000}, "webapp": {"x=y": 8001}}}"#,
+    )
+    .unwrap();
+    for args in [
+        &["import", "registry"][..],
+        &["import", "registry", "--merge"],
+    ] {
+        pm_cmd(&config_path)
+            .args(args)
+            .arg(&import_file)
+            .assert()
+            .failure()
+            .stdout(predicate::str::contains("projects.a b"))
+            .stdout(predicate::str::contains("projects.webapp.x=y"));
+    }
+
+    pm_cmd(&config_path)
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("webapp").not());
+}
+
+#[test]
+fn test_merge() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let other = temp_dir.path().join("other.toml");
+    fs::write(
+        &other,
+        "[projects.webapp]\nweb = 8000\n\n[projects.api]\nhttp = 3000\nweb = 8001\n",
+    )
+    .unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "shop", "web", "8001"])
+        .assert()
+        .success();
+
+    // api.web collides with shop.web; keep both
+    pm_cmd(&config_path)
+        .args(["merge", "--reassign"])
+        .arg(&other)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("+ api.http = 3000"))
+        .stdout(predicate::str::contains("~ api.web: 8001 -> 8002"));
+    pm_cmd(&config_path)
+        .args(["query", "shop", "web"])
+        .assert()
+        .success()
+        .stdout("8001\n");
+
+    // Without an answer, collisions keep ours
+    pm_cmd(&config_path)
+        .args(["merge"])
+        .arg(&other)
+        .write_stdin("")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("= kept ours for api.web"));
+}
+
+#[test]
+fn test_merge_asks_for_collisions() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let other = temp_dir.path().join("other.toml");
+    fs::write(&other, "[projects.api]\nweb = 8000\n").unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["merge"])
+        .arg(&other)
+        .write_stdin("t\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "api.web is 8000 in theirs, but 8000 is webapp.web here",
+        ))
+        .stdout(predicate::str::contains("~ webapp.web: 8000 -> 8001"))
+        .stdout(predicate::str::contains("+ api.web = 8000"));
+
+    pm_cmd(&config_path)
+        .args(["merge", "--ours", "--theirs"])
+        .arg(&other)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_diff() {
+    let (temp_dir, config_path) = setup_temp_config();
+
+    for (project, name, port) in [("webapp", "web", "8000"), ("webapp", "api", "3000")] {
+        pm_cmd(&config_path)
+            .args(["allocate", project, name, port])
+            .assert()
+            .success();
+    }
+    let old = temp_dir.path().join("old.toml");
+    std::fs::copy(&config_path, &old).unwrap();
+    let export = pm_cmd(&config_path)
+        .args(["export", "registry"])
+        .output()
+        .unwrap();
+    let old_json = temp_dir.path().join("old.json");
+    std::fs::write(&old_json, export.stdout).unwrap();
+
+    pm_cmd(&config_path)
+        .args(["free", "webapp", "api"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8100", "--replace"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["config", "--set", "web=8000-8199"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .arg("diff")
+        .arg(&old)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"removed +│ webapp\.api +│ 3000").unwrap())
+        .stdout(predicate::str::is_match(r"changed +│ webapp\.web +│ 8000 +│ 8100").unwrap())
+        .stdout(predicate::str::is_match(r"range web +│ 8000-8999 +│ 8000-8199").unwrap());
+
+    let output = pm_cmd(&config_path)
+        .arg("diff")
+        .arg(&old_json)
+        .arg("current")
+        .arg("--json=v1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["data"]["removed"][0]["name"], "api");
+    assert_eq!(json["data"]["changed"][0]["new"], 8100);
+    assert_eq!(json["data"]["ranges"][0]["type"], "web");
+
+    pm_cmd(&config_path)
+        .arg("diff")
+        .arg(&old)
+        .arg(&old_json)
+        .assert()
+        .success()
+        .stdout("No differences\n");
+}
+
+// =============================================================================
+// Schema Tests
+// =============================================================================
+
+/// Returns the `required` and `properties` keys of a schema definition.
+fn schema_keys(schema: &serde_json::Value, definition: &str) -> (Vec<String>, Vec<String>) {
+    let def = &schema["definitions"][definition];
+    let required = def["required"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_str().unwrap().to_string())
+        .collect();
+    let properties = def["properties"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .cloned()
+        .collect();
+    (required, properties)
+}
 
-    // All should succeed
-    for result in &results {
-        assert!(result.status.success(), "Allocation failed: {:?}", result);
+#[test]
+fn test_schema_list_matches_output() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080", "--group", "g"])
+        .assert()
+        .success();
+
+    let schema_out = pm_cmd(&config_path)
+        .args(["schema", "list"])
+        .output()
+        .unwrap();
+    assert!(schema_out.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&schema_out.stdout).unwrap();
+    assert_eq!(schema["properties"]["data"]["type"], "array");
+    let (required, properties) = schema_keys(&schema, "AllocatedPortInfo");
+
+    let list_out = pm_cmd(&config_path)
+        .args(["list", "--json=v1"])
+        .output()
+        .unwrap();
+    let list: serde_json::Value = serde_json::from_slice(&list_out.stdout).unwrap();
+    let entry = list["data"][0].as_object().unwrap();
+
+    for key in &required {
+        assert!(entry.contains_key(key), "missing required key {key}");
+    }
+    for key in entry.keys() {
+        assert!(properties.contains(key), "undocumented key {key}");
     }
+}
+
+#[test]
+fn test_schema_config() {
+    let (_temp_dir, config_path) = setup_temp_config();
 
-    // Verify all ports are unique using list command
     pm_cmd(&config_path)
-        .args(["list"])
+        .args(["schema", "config"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("project0"))
-        .stdout(predicate::str::contains("project1"))
-        .stdout(predicate::str::contains("project2"))
-        .stdout(predicate::str::contains("project3"))
-        .stdout(predicate::str::contains("project4"));
+        .stdout(predicate::str::contains("\"apiVersion\""))
+        .stdout(predicate::str::contains("\"RangeInfo\""));
 
-    // Read the config file and verify no duplicate ports
-    let content = fs::read_to_string(&config_path).unwrap();
-    // Match port allocations (single number) not range definitions (arrays)
-    let ports: Vec<u16> = content
-        .lines()
-        .filter_map(|line| {
-            let trimmed = line.trim();
-            if trimmed.starts_with("web = ") && !trimmed.contains('[') {
-                // Extract the port number (e.g., "web = 8000" -> 8000)
-                trimmed.strip_prefix("web = ")?.parse().ok()
-            } else {
-                None
-            }
-        })
+    pm_cmd(&config_path)
+        .args(["schema", "config", "--api-version", "v0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"title\": \"ConfigInfo\""))
+        .stdout(predicate::str::contains("apiVersion").not());
+}
+
+#[test]
+fn test_schema_rejects_unknown_format() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["schema", "nonexistent"])
+        .assert()
+        .failure();
+}
+
+// =============================================================================
+// Docs Generation Tests
+// =============================================================================
+
+#[test]
+fn test_gen_docs_markdown() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["gen-docs", "--markdown"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("## `pm allocate`"))
+        .stdout(predicate::str::contains("## `pm gen-docs`"));
+}
+
+#[test]
+fn test_gen_docs_man_pages() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let out_dir = temp_dir.path().join("man");
+
+    pm_cmd(&config_path)
+        .args(["gen-docs", "--man", "--out-dir"])
+        .arg(&out_dir)
+        .assert()
+        .success();
+
+    assert!(out_dir.join("pm.1").exists());
+    assert!(out_dir.join("pm-allocate.1").exists());
+}
+
+#[test]
+fn test_gen_docs_requires_format() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path).args(["gen-docs"]).assert().failure();
+}
+
+// =============================================================================
+// Config Edit Tests
+// =============================================================================
+
+/// Writes an executable "editor" that replaces the edited file with `content`.
+#[cfg(unix)]
+fn fake_editor(dir: &std::path::Path, content: &str) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let replacement = dir.join("replacement.toml");
+    fs::write(&replacement, content).unwrap();
+
+    let editor = dir.join("editor.sh");
+    fs::write(
+        &editor,
+        format!("#!/bin/sh\ncp '{}' \"$1\"\n", replacement.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+    editor
+}
+
+#[cfg(unix)]
+#[test]
+fn test_config_edit_saves_valid_changes() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let editor = fake_editor(temp_dir.path(), "[projects.webapp]\nweb = 8080\n");
+
+    pm_cmd(&config_path)
+        .env("VISUAL", &editor)
+        .args(["config", "edit"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Saved"));
+
+    pm_cmd(&config_path)
+        .args(["query", "webapp", "web"])
+        .assert()
+        .success()
+        .stdout("8080\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_config_edit_rejects_duplicate_ports() {
+    let (temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+    let before = fs::read_to_string(&config_path).unwrap();
+
+    let editor = fake_editor(
+        temp_dir.path(),
+        "[projects.webapp]\nweb = 8080\n\n[projects.other]\nweb = 8080\n",
+    );
+
+    // Empty stdin declines the offer to edit again
+    pm_cmd(&config_path)
+        .env("VISUAL", &editor)
+        .args(["config", "edit"])
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("duplicate-port"))
+        .stderr(predicate::str::contains("Registry not saved"));
+
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), before);
+}
+
+#[test]
+fn test_config_edit_conflicts_with_flags() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["config", "--path", "edit"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_config_validate_ok() {
+    let (_temp_dir, config_path) = setup_temp_config();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8080"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["config", "validate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with(": ok\n"));
+}
+
+#[test]
+fn test_config_validate_file_reports_all_problems() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let file = temp_dir.path().join("team.toml");
+    fs::write(
+        &file,
+        "[defaults.ranges]\nweb = [8999, 8000]\n\n[projects.\"web app\"]\nweb = 70000\n",
+    )
+    .unwrap();
+
+    let output = pm_cmd(&config_path)
+        .args(["config", "validate", "--json=v1"])
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["data"]["valid"], false);
+    let codes: Vec<_> = report["data"]["diagnostics"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| d["code"].as_str().unwrap().to_string())
         .collect();
+    assert_eq!(
+        codes,
+        vec!["inverted-range", "invalid-name", "invalid-port"]
+    );
+}
 
-    // Should have 5 unique port assignments
-    assert_eq!(ports.len(), 5, "Expected 5 port assignments");
+#[test]
+fn test_config_validate_duplicate_ports() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let file = temp_dir.path().join("team.toml");
+    fs::write(
+        &file,
+        "[projects.a]\nweb = 8080\n\n[projects.b]\nweb = 8080\n",
+    )
+    .unwrap();
 
-    // Check all ports are unique
-    let mut unique_ports = ports.clone();
-    unique_ports.sort();
-    unique_ports.dedup();
-    assert_eq!(unique_ports.len(), 5, "All ports should be unique");
+    pm_cmd(&config_path)
+        .args(["config", "validate"])
+        .arg(&file)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "port 8080 is assigned to both a.web and b.web [duplicate-port]",
+        ))
+        .stderr(predicate::str::contains("has 1 problem(s)"));
+}
+
+#[test]
+fn test_config_repair() {
+    let (temp_dir, config_path) = setup_temp_config();
+    fs::write(
+        &config_path,
+        "\
+[projects.webapp]
+web = 8000
+api = 30x0
+
+[projects.shop
+web = 8100
+",
+    )
+    .unwrap();
+
+    pm_cmd(&config_path)
+        .args(["list", "--json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("\"code\":\"E_REGISTRY_CORRUPT\""))
+        .stderr(predicate::str::contains("pm config repair"));
+
+    pm_cmd(&config_path)
+        .args(["config", "repair", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "dropped line 3 in [projects.webapp]: api = 30x0",
+        ))
+        .stdout(predicate::str::contains(
+            "dropped lines 5-6: [projects.shop",
+        ))
+        .stdout(predicate::str::contains("Kept 1 project with 1 port"));
+    pm_cmd(&config_path).args(["list"]).assert().failure();
+
+    pm_cmd(&config_path)
+        .args(["config", "repair"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("The broken registry was saved to"));
+    pm_cmd(&config_path)
+        .args(["query", "webapp"])
+        .assert()
+        .success()
+        .stdout("web=8000\n");
+    let backups = fs::read_dir(temp_dir.path())
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .contains(".broken-")
+        })
+        .count();
+    assert_eq!(backups, 1);
+
+    pm_cmd(&config_path)
+        .args(["config", "repair"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ok, nothing to repair"));
+}
+
+#[test]
+fn test_load_skips_broken_sections() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    fs::write(
+        &config_path,
+        "[projects.webapp]\nweb = 8000\n\n[projects.shop]\nweb = 0\n",
+    )
+    .unwrap();
+
+    pm_cmd(&config_path)
+        .args(["query", "webapp"])
+        .assert()
+        .success()
+        .stdout("web=8000\n")
+        .stderr(predicate::str::contains("Leaving out projects.shop"));
+
+    // Saving would lose projects.shop, so changes wait for a repair
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pm config repair"));
+    assert!(fs::read_to_string(&config_path)
+        .unwrap()
+        .contains("[projects.shop]"));
+}
+
+#[test]
+fn test_allocate_lints_arguments() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "api", "webapp"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'pm allocate webapp api'"));
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api", "03000"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("leading zero"))
+        .stderr(predicate::str::contains("Pass --no-lint"));
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api", "30003000"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("3000 typed twice"));
+    pm_cmd(&config_path)
+        .args(["allocate", "08080", "web"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Project '08080' looks like a port",
+        ));
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "api", "03000", "--no-lint"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Allocated webapp.api = 3000"));
+}
+
+#[test]
+fn test_rename_port() {
+    let (_temp_dir, config_path) = setup_temp_config();
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000", "--note", "storefront"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["alias", "webapp", "web", "frontend"])
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["rename-port", "webapp", "web", "site"])
+        .assert()
+        .success()
+        .stdout("Renamed webapp.web to webapp.site (8000)\n");
+    pm_cmd(&config_path)
+        .args(["query", "webapp"])
+        .assert()
+        .success()
+        .stdout("site=8000\n");
+    pm_cmd(&config_path)
+        .args(["query", "webapp", "frontend"])
+        .assert()
+        .success()
+        .stdout("8000\n");
+    pm_cmd(&config_path)
+        .args(["list", "--long"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("storefront"));
+
+    pm_cmd(&config_path)
+        .args(["rename-port", "webapp", "web", "api"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("web"));
+}
+
+#[test]
+fn test_rename_port_resolves_project_like_free() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = temp_dir.path().join("checkout");
+    fs::create_dir(&project_dir).unwrap();
+
+    pm_cmd(&config_path)
+        .args(["allocate", "webapp", "web", "8000"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .arg("link")
+        .arg(&project_dir)
+        .arg("webapp")
+        .assert()
+        .success();
+
+    pm_cmd(&config_path)
+        .args(["rename-port", ".", "web", "www"])
+        .current_dir(&project_dir)
+        .assert()
+        .success()
+        .stdout("Renamed webapp.web to webapp.www (8000)\n");
+
+    // The message names the project as stored, not as typed
+    pm_cmd(&config_path)
+        .args(["config", "--ignore-case", "true"])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["rename-port", "WEBAPP", "www", "site"])
+        .assert()
+        .success()
+        .stdout("Renamed webapp.www to webapp.site (8000)\n");
+}
+
+#[test]
+fn test_init_presets_leave_a_valid_registry() {
+    let (temp_dir, config_path) = setup_temp_config();
+    let project_dir = temp_dir.path().join("shop");
+    fs::create_dir(&project_dir).unwrap();
+
+    pm_cmd(&config_path)
+        .current_dir(&project_dir)
+        .args([
+            "init",
+            "--ranges",
+            "python,jvm,databases,node,messaging,grpc",
+            "--template",
+            "fullstack",
+        ])
+        .assert()
+        .success();
+    pm_cmd(&config_path)
+        .args(["config", "validate"])
+        .assert()
+        .success();
 }